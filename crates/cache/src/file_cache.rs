@@ -0,0 +1,620 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::key_for_logical_plan;
+use crate::QueryResultCache;
+use crate::{CachedQueryResult, CachedQueryResultStream};
+use crate::{FileCacheIoSnafu, FileCacheIpcSnafu, FileCacheTaskSnafu, Result};
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use async_stream::stream;
+use async_trait::async_trait;
+use datafusion::error::DataFusionError;
+use datafusion::execution::SendableRecordBatchStream;
+use datafusion::logical_expr::LogicalPlan;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::sql::TableReference;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const INDEX_FILE_NAME: &str = "index.json";
+const RESULT_FILE_EXTENSION: &str = "arrow";
+
+/// Changes to the index are written to `index.json` at most this often, and when the cache is dropped.
+const INDEX_WRITE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The number of record batches read ahead of the consumer of a cached result.
+const READ_AHEAD_BATCHES: usize = 2;
+
+/// A results cache that stores each query result as an Arrow IPC file, so cached results
+/// survive restarts and don't have to fit in memory.
+///
+/// An index of the cached results is kept in memory and persisted to `index.json` in the cache
+/// directory. Results are evicted by least recent use once the files exceed the maximum size.
+///
+/// The index is written at most once per [`INDEX_WRITE_INTERVAL`]. Result files are removed as soon
+/// as they are evicted or invalidated, and index entries without a file are dropped on startup, so
+/// an index that is behind after a crash never serves a stale result.
+pub struct FileCache {
+    dir: PathBuf,
+    cache_max_size: u64,
+    ttl: Duration,
+    index: Arc<Mutex<Index>>,
+    next_write_id: AtomicU64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Index {
+    entries: HashMap<u64, IndexEntry>,
+
+    /// Whether the entries changed since the index was last written.
+    #[serde(skip)]
+    dirty: bool,
+
+    #[serde(skip)]
+    written_at: Option<Instant>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    size_bytes: u64,
+    /// Milliseconds since the epoch
+    created_at: u64,
+    /// Milliseconds since the epoch
+    last_accessed_at: u64,
+    input_tables: Vec<CachedTableReference>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct CachedTableReference {
+    catalog: Option<String>,
+    schema: Option<String>,
+    table: String,
+}
+
+impl From<&TableReference> for CachedTableReference {
+    fn from(table_reference: &TableReference) -> Self {
+        Self {
+            catalog: table_reference.catalog().map(ToString::to_string),
+            schema: table_reference.schema().map(ToString::to_string),
+            table: table_reference.table().to_string(),
+        }
+    }
+}
+
+impl From<&CachedTableReference> for TableReference {
+    fn from(table_reference: &CachedTableReference) -> Self {
+        let table = table_reference.table.as_str();
+        match (&table_reference.catalog, &table_reference.schema) {
+            (Some(catalog), Some(schema)) => {
+                TableReference::full(catalog.as_str(), schema.as_str(), table)
+            }
+            (None, Some(schema)) => TableReference::partial(schema.as_str(), table),
+            _ => TableReference::bare(table),
+        }
+    }
+}
+
+impl Index {
+    fn size_bytes(&self) -> u64 {
+        self.entries.values().map(|entry| entry.size_bytes).sum()
+    }
+
+    /// Removes the least recently used entries until the cache fits in `max_size`, returning
+    /// their keys.
+    fn evict_to_fit(&mut self, max_size: u64) -> Vec<u64> {
+        let mut size = self.size_bytes();
+        if size <= max_size {
+            return vec![];
+        }
+
+        let mut entries: Vec<(u64, u64, u64)> = self
+            .entries
+            .iter()
+            .map(|(key, entry)| (entry.last_accessed_at, *key, entry.size_bytes))
+            .collect();
+        entries.sort_unstable();
+
+        let mut evicted = vec![];
+        for (_, key, size_bytes) in entries {
+            if size <= max_size {
+                break;
+            }
+            self.entries.remove(&key);
+            size = size.saturating_sub(size_bytes);
+            evicted.push(key);
+        }
+
+        evicted
+    }
+}
+
+impl FileCache {
+    /// Opens the cache in `dir`, creating the directory if it doesn't exist.
+    ///
+    /// Results that expired or whose files are missing are removed from the index, and files
+    /// that aren't in the index are deleted.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the cache directory can't be created or read.
+    pub fn try_new(dir: impl Into<PathBuf>, cache_max_size: u64, ttl: Duration) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).context(FileCacheIoSnafu { path: dir.clone() })?;
+
+        let mut index = read_index(&dir);
+        let now = current_time_millis();
+        index
+            .entries
+            .retain(|key, entry| !is_expired(entry, ttl, now) && result_path(&dir, *key).is_file());
+
+        let entries = fs::read_dir(&dir).context(FileCacheIoSnafu { path: dir.clone() })?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_indexed = path
+                .file_stem()
+                .and_then(|stem| u64::from_str_radix(&stem.to_string_lossy(), 16).ok())
+                .is_some_and(|key| index.entries.contains_key(&key));
+            let is_result_file = path
+                .extension()
+                .is_some_and(|ext| ext == RESULT_FILE_EXTENSION || ext == "tmp");
+            if !is_indexed && is_result_file {
+                remove_file(&path);
+            }
+        }
+
+        for key in index.evict_to_fit(cache_max_size) {
+            remove_file(&result_path(&dir, key));
+        }
+        write_index(&dir, &index)?;
+
+        Ok(Self {
+            dir,
+            cache_max_size,
+            ttl,
+            index: Arc::new(Mutex::new(index)),
+            next_write_id: AtomicU64::new(0),
+        })
+    }
+
+    fn lock_index(&self) -> MutexGuard<'_, Index> {
+        // The index is never left inconsistent while locked, so a poisoned lock can be reused.
+        self.index
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn remove_entries(&self, keys: &[u64]) {
+        for key in keys {
+            remove_file(&result_path(&self.dir, *key));
+        }
+    }
+
+    async fn get_key(&self, key: u64) -> Result<Option<CachedQueryResultStream>> {
+        let input_tables = {
+            let mut index = self.lock_index();
+            let now = current_time_millis();
+            let Some(entry) = index.entries.get_mut(&key) else {
+                return Ok(None);
+            };
+            if is_expired(entry, self.ttl, now) {
+                index.entries.remove(&key);
+                index.dirty = true;
+                drop(index);
+                self.remove_entries(&[key]);
+                return Ok(None);
+            }
+            entry.last_accessed_at = now;
+            let input_tables = entry.input_tables.clone();
+            index.dirty = true;
+            input_tables
+        };
+
+        let path = result_path(&self.dir, key);
+        let reader = tokio::task::spawn_blocking(move || open_result(&path))
+            .await
+            .context(FileCacheTaskSnafu)?;
+
+        match reader {
+            Ok(reader) => Ok(Some(CachedQueryResultStream {
+                data: stream_result(reader),
+                input_tables: Arc::new(input_tables.iter().map(TableReference::from).collect()),
+            })),
+            Err(e) => {
+                tracing::warn!("Removing unreadable cached query result {key:016x}: {e}");
+                let mut index = self.lock_index();
+                index.entries.remove(&key);
+                index.dirty = true;
+                drop(index);
+                self.remove_entries(&[key]);
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Drop for FileCache {
+    fn drop(&mut self) {
+        let mut index = self.lock_index();
+        if let Err(e) = flush_index(&self.dir, &mut index, true) {
+            tracing::warn!("Failed to write results cache index: {e}");
+        }
+    }
+}
+
+fn current_time_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
+
+fn is_expired(entry: &IndexEntry, ttl: Duration, now: u64) -> bool {
+    u128::from(now.saturating_sub(entry.created_at)) >= ttl.as_millis()
+}
+
+fn result_path(dir: &Path, key: u64) -> PathBuf {
+    dir.join(format!("{key:016x}.{RESULT_FILE_EXTENSION}"))
+}
+
+fn remove_file(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!(
+                "Failed to remove cached query result {}: {e}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Reads the index of the cache directory, starting over if it's missing or unreadable.
+fn read_index(dir: &Path) -> Index {
+    let path = dir.join(INDEX_FILE_NAME);
+    let Ok(file) = File::open(&path) else {
+        return Index::default();
+    };
+
+    match serde_json::from_reader(BufReader::new(file)) {
+        Ok(index) => index,
+        Err(e) => {
+            tracing::warn!(
+                "Failed to read results cache index {}, clearing the cache: {e}",
+                path.display()
+            );
+            Index::default()
+        }
+    }
+}
+
+/// Writes the index if it changed since it was last written, unless it was written less than
+/// [`INDEX_WRITE_INTERVAL`] ago and `force` isn't set.
+fn flush_index(dir: &Path, index: &mut Index, force: bool) -> Result<()> {
+    let recently_written = index
+        .written_at
+        .is_some_and(|written_at| written_at.elapsed() < INDEX_WRITE_INTERVAL);
+    if !index.dirty || (recently_written && !force) {
+        return Ok(());
+    }
+
+    write_index(dir, index)?;
+    index.dirty = false;
+    index.written_at = Some(Instant::now());
+    Ok(())
+}
+
+fn write_index(dir: &Path, index: &Index) -> Result<()> {
+    let path = dir.join(INDEX_FILE_NAME);
+    let tmp_path = path.with_extension("json.tmp");
+    let file = File::create(&tmp_path).context(FileCacheIoSnafu {
+        path: tmp_path.clone(),
+    })?;
+    serde_json::to_writer(BufWriter::new(file), index)
+        .map_err(std::io::Error::from)
+        .context(FileCacheIoSnafu {
+            path: tmp_path.clone(),
+        })?;
+    fs::rename(&tmp_path, &path).context(FileCacheIoSnafu { path })
+}
+
+/// Writes the result to the temporary file `tmp_path`, which is renamed to `path` once complete,
+/// returning its size.
+fn write_result(path: &Path, tmp_path: PathBuf, result: &CachedQueryResult) -> Result<u64> {
+    let file = File::create(&tmp_path).context(FileCacheIoSnafu {
+        path: tmp_path.clone(),
+    })?;
+
+    let write = || -> Result<(), arrow::error::ArrowError> {
+        let mut writer = FileWriter::try_new(BufWriter::new(file), &result.schema)?;
+        for batch in result.records.iter() {
+            writer.write(batch)?;
+        }
+        writer.finish()
+    };
+    if let Err(e) = write() {
+        remove_file(&tmp_path);
+        return Err(e).context(FileCacheIpcSnafu { path: tmp_path });
+    }
+
+    fs::rename(&tmp_path, path).context(FileCacheIoSnafu {
+        path: path.to_path_buf(),
+    })?;
+    let metadata = fs::metadata(path).context(FileCacheIoSnafu {
+        path: path.to_path_buf(),
+    })?;
+
+    Ok(metadata.len())
+}
+
+fn open_result(path: &Path) -> Result<FileReader<BufReader<File>>> {
+    let file = File::open(path).context(FileCacheIoSnafu {
+        path: path.to_path_buf(),
+    })?;
+    FileReader::try_new(BufReader::new(file), None).context(FileCacheIpcSnafu {
+        path: path.to_path_buf(),
+    })
+}
+
+/// Reads the record batches of a result file on a blocking task, as they are consumed.
+fn stream_result(reader: FileReader<BufReader<File>>) -> SendableRecordBatchStream {
+    let schema = reader.schema();
+    let (tx, mut rx) = tokio::sync::mpsc::channel(READ_AHEAD_BATCHES);
+    tokio::task::spawn_blocking(move || {
+        for batch in reader {
+            let batch = batch.map_err(|e| DataFusionError::ArrowError(e, None));
+            // The receiver is dropped when the consumer stops reading the result.
+            if tx.blocking_send(batch).is_err() {
+                break;
+            }
+        }
+    });
+
+    let batches = stream! {
+        while let Some(batch) = rx.recv().await {
+            yield batch;
+        }
+    };
+    Box::pin(RecordBatchStreamAdapter::new(schema, batches))
+}
+
+#[async_trait]
+impl QueryResultCache for FileCache {
+    async fn get(&self, plan: &LogicalPlan) -> Result<Option<CachedQueryResultStream>> {
+        self.get_key(key_for_logical_plan(plan)).await
+    }
+
+    async fn put(&self, plan: &LogicalPlan, result: CachedQueryResult) -> Result<()> {
+        self.put_key(key_for_logical_plan(plan), result).await
+    }
+
+    async fn put_key(&self, plan_key: u64, result: CachedQueryResult) -> Result<()> {
+        let path = result_path(&self.dir, plan_key);
+        let write_id = self.next_write_id.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = path.with_extension(format!("{write_id}.tmp"));
+        let input_tables = result
+            .input_tables
+            .iter()
+            .map(CachedTableReference::from)
+            .collect();
+        let size_bytes =
+            tokio::task::spawn_blocking(move || write_result(&path, tmp_path, &result))
+                .await
+                .context(FileCacheTaskSnafu)??;
+
+        if size_bytes > self.cache_max_size {
+            remove_file(&result_path(&self.dir, plan_key));
+            return Ok(());
+        }
+
+        let index = Arc::clone(&self.index);
+        let dir = self.dir.clone();
+        let cache_max_size = self.cache_max_size;
+        tokio::task::spawn_blocking(move || {
+            let mut index = index
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            let now = current_time_millis();
+            index.entries.insert(
+                plan_key,
+                IndexEntry {
+                    size_bytes,
+                    created_at: now,
+                    last_accessed_at: now,
+                    input_tables,
+                },
+            );
+            for key in index.evict_to_fit(cache_max_size) {
+                remove_file(&result_path(&dir, key));
+            }
+            index.dirty = true;
+            flush_index(&dir, &mut index, false)
+        })
+        .await
+        .context(FileCacheTaskSnafu)?
+    }
+
+    async fn invalidate_for_table(&self, table_name: TableReference) -> Result<()> {
+        let table_name = CachedTableReference::from(&table_name);
+        let index = Arc::clone(&self.index);
+        let dir = self.dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut index = index
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            let invalidated: HashSet<u64> = index
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.input_tables.contains(&table_name))
+                .map(|(key, _)| *key)
+                .collect();
+            if invalidated.is_empty() {
+                return Ok(());
+            }
+
+            index.entries.retain(|key, _| !invalidated.contains(key));
+            for key in invalidated {
+                remove_file(&result_path(&dir, key));
+            }
+            index.dirty = true;
+            flush_index(&dir, &mut index, false)
+        })
+        .await
+        .context(FileCacheTaskSnafu)?
+    }
+
+    fn size_bytes(&self) -> u64 {
+        self.lock_index().size_bytes()
+    }
+
+    fn item_count(&self) -> u64 {
+        self.lock_index().entries.len() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int64Array, RecordBatch};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use futures::TryStreamExt;
+
+    fn make_result(table: &str, num_rows: i64) -> CachedQueryResult {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![Arc::new(Int64Array::from_iter_values(0..num_rows))],
+        )
+        .expect("valid record batch");
+
+        CachedQueryResult {
+            records: Arc::new(vec![batch]),
+            schema,
+            input_tables: Arc::new(HashSet::from([TableReference::partial("public", table)])),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_cache_survives_restarts() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let ttl = Duration::from_secs(60);
+
+        let cache = FileCache::try_new(dir.path(), 1024 * 1024, ttl).expect("valid cache");
+        cache
+            .put_key(1, make_result("customer", 10))
+            .await
+            .expect("result is cached");
+        assert_eq!(cache.item_count(), 1);
+        assert!(cache.size_bytes() > 0);
+        drop(cache);
+
+        let cache = FileCache::try_new(dir.path(), 1024 * 1024, ttl).expect("valid cache");
+        let cached = cache
+            .get_key(1)
+            .await
+            .expect("cache is readable")
+            .expect("result is cached");
+        assert!(cached
+            .input_tables
+            .contains(&TableReference::partial("public", "customer")));
+        let records: Vec<RecordBatch> = cached.data.try_collect().await.expect("valid result");
+        assert_eq!(records[0].num_rows(), 10);
+        assert!(cache.get_key(2).await.expect("cache is readable").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_cache_batches_index_writes() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let ttl = Duration::from_secs(60);
+
+        let cache = FileCache::try_new(dir.path(), 1024 * 1024, ttl).expect("valid cache");
+        for key in 1..=3 {
+            cache
+                .put_key(key, make_result("customer", 10))
+                .await
+                .expect("result is cached");
+        }
+
+        // Only the first put is written right away, the others when the cache is dropped.
+        assert_eq!(read_index(dir.path()).entries.len(), 1);
+        drop(cache);
+        assert_eq!(read_index(dir.path()).entries.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_file_cache_invalidate_for_table() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let cache = FileCache::try_new(dir.path(), 1024 * 1024, Duration::from_secs(60))
+            .expect("valid cache");
+        cache
+            .put_key(1, make_result("customer", 10))
+            .await
+            .expect("result is cached");
+        cache
+            .put_key(2, make_result("orders", 10))
+            .await
+            .expect("result is cached");
+
+        cache
+            .invalidate_for_table(TableReference::partial("public", "customer"))
+            .await
+            .expect("cache is invalidated");
+
+        assert!(cache.get_key(1).await.expect("cache is readable").is_none());
+        assert!(cache.get_key(2).await.expect("cache is readable").is_some());
+        assert!(!result_path(dir.path(), 1).exists());
+    }
+
+    #[tokio::test]
+    async fn test_file_cache_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let ttl = Duration::from_secs(60);
+        let size = {
+            let cache = FileCache::try_new(dir.path(), u64::MAX, ttl).expect("valid cache");
+            cache
+                .put_key(1, make_result("customer", 1000))
+                .await
+                .expect("result is cached");
+            cache.size_bytes()
+        };
+
+        let cache = FileCache::try_new(dir.path(), size * 2, ttl).expect("valid cache");
+        cache
+            .put_key(2, make_result("customer", 1000))
+            .await
+            .expect("result is cached");
+        cache.lock_index().entries.entry(2).and_modify(|entry| {
+            entry.last_accessed_at = 0;
+        });
+        cache
+            .put_key(3, make_result("customer", 1000))
+            .await
+            .expect("result is cached");
+
+        assert_eq!(cache.item_count(), 2);
+        assert!(cache.get_key(1).await.expect("cache is readable").is_some());
+        assert!(cache.get_key(2).await.expect("cache is readable").is_none());
+        assert!(cache.get_key(3).await.expect("cache is readable").is_some());
+    }
+}
@@ -20,6 +20,7 @@ use std::fmt::Formatter;
 use std::hash::DefaultHasher;
 use std::hash::Hash;
 use std::hash::Hasher;
+use std::path::PathBuf;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::SystemTime;
@@ -29,15 +30,19 @@ use arrow::array::RecordBatch;
 use arrow::datatypes::Schema;
 use async_trait::async_trait;
 use byte_unit::Byte;
+use datafusion::error::DataFusionError;
 use datafusion::execution::SendableRecordBatchStream;
 use datafusion::logical_expr::LogicalPlan;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::sql::TableReference;
+use file_cache::FileCache;
 use fundu::ParseError;
 use lru_cache::LruCache;
 use metrics::atomics::AtomicU64;
 use snafu::{ResultExt, Snafu};
 use spicepod::component::runtime::ResultsCache;
 
+mod file_cache;
 mod lru_cache;
 mod utils;
 
@@ -57,6 +62,26 @@ pub enum Error {
         source: moka::PredicateError,
         table_name: Arc<str>,
     },
+
+    #[snafu(display(
+        "Unknown cache_type value: {cache_type}. Supported values are 'memory' and 'file'."
+    ))]
+    UnknownCacheType { cache_type: String },
+
+    #[snafu(display("Failed to access results cache file {}: {source}", path.display()))]
+    FileCacheIo {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Failed to read or write cached results in {}: {source}", path.display()))]
+    FileCacheIpc {
+        source: arrow::error::ArrowError,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Results cache file task failed: {source}"))]
+    FileCacheTask { source: tokio::task::JoinError },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -80,9 +105,34 @@ pub struct CachedQueryResult {
     pub input_tables: Arc<HashSet<TableReference>>,
 }
 
+/// A query result read from the cache. Its record batches are streamed, as they may not be held in memory.
+pub struct CachedQueryResultStream {
+    pub data: SendableRecordBatchStream,
+    pub input_tables: Arc<HashSet<TableReference>>,
+}
+
+impl From<CachedQueryResult> for CachedQueryResultStream {
+    fn from(result: CachedQueryResult) -> Self {
+        let records = result
+            .records
+            .iter()
+            .cloned()
+            .map(Ok::<_, DataFusionError>)
+            .collect::<Vec<_>>();
+        Self {
+            data: Box::pin(RecordBatchStreamAdapter::new(
+                result.schema,
+                futures::stream::iter(records),
+            )),
+            input_tables: result.input_tables,
+        }
+    }
+}
+
+/// A storage backend for cached query results, selected with `cache_type`.
 #[async_trait]
 pub trait QueryResultCache {
-    async fn get(&self, plan: &LogicalPlan) -> Result<Option<CachedQueryResult>>;
+    async fn get(&self, plan: &LogicalPlan) -> Result<Option<CachedQueryResultStream>>;
     async fn put(&self, plan: &LogicalPlan, result: CachedQueryResult) -> Result<()>;
     async fn put_key(&self, key: u64, result: CachedQueryResult) -> Result<()>;
     async fn invalidate_for_table(&self, table_name: TableReference) -> Result<()>;
@@ -90,8 +140,40 @@ pub trait QueryResultCache {
     fn item_count(&self) -> u64;
 }
 
+const DEFAULT_FILE_CACHE_DIR: &str = ".spice/cache/results";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheType {
+    /// Results are kept in memory and lost on restarts.
+    Memory,
+    /// Results are stored as Arrow IPC files in `cache_dir` and survive restarts.
+    File,
+}
+
+impl TryFrom<Option<&str>> for CacheType {
+    type Error = Error;
+
+    fn try_from(cache_type: Option<&str>) -> Result<Self> {
+        match cache_type {
+            None | Some("memory") => Ok(CacheType::Memory),
+            Some("file") => Ok(CacheType::File),
+            Some(cache_type) => UnknownCacheTypeSnafu { cache_type }.fail(),
+        }
+    }
+}
+
+impl Display for CacheType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheType::Memory => write!(f, "memory"),
+            CacheType::File => write!(f, "file"),
+        }
+    }
+}
+
 pub struct QueryResultsCacheProvider {
     cache: Arc<dyn QueryResultCache + Send + Sync>,
+    cache_type: CacheType,
     cache_max_size: u64,
     ttl: std::time::Duration,
     metrics_reported_last_time: AtomicU64,
@@ -116,8 +198,22 @@ impl QueryResultsCacheProvider {
             None => std::time::Duration::from_secs(1),
         };
 
+        let cache_type = CacheType::try_from(config.cache_type.as_deref())?;
+        let cache: Arc<dyn QueryResultCache + Send + Sync> = match cache_type {
+            CacheType::Memory => Arc::new(LruCache::new(cache_max_size, ttl)),
+            CacheType::File => Arc::new(FileCache::try_new(
+                config
+                    .cache_dir
+                    .as_deref()
+                    .unwrap_or(DEFAULT_FILE_CACHE_DIR),
+                cache_max_size,
+                ttl,
+            )?),
+        };
+
         let cache_provider = QueryResultsCacheProvider {
-            cache: Arc::new(LruCache::new(cache_max_size, ttl)),
+            cache,
+            cache_type,
             cache_max_size,
             ttl,
             metrics_reported_last_time: AtomicU64::new(0),
@@ -133,7 +229,7 @@ impl QueryResultsCacheProvider {
     /// # Errors
     ///
     /// Will return `Err` if method fails to access the cache
-    pub async fn get(&self, plan: &LogicalPlan) -> Result<Option<CachedQueryResult>> {
+    pub async fn get(&self, plan: &LogicalPlan) -> Result<Option<CachedQueryResultStream>> {
         metrics::counter!("results_cache_request_count").increment(1);
         match self.cache.get(plan).await {
             Ok(Some(cached_result)) => {
@@ -233,7 +329,8 @@ impl Display for QueryResultsCacheProvider {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "max size: {:.2}, item ttl: {:?}",
+            "type: {}, max size: {:.2}, item ttl: {:?}",
+            self.cache_type,
             Byte::from_u64(self.cache_max_size).get_adjusted_unit(byte_unit::Unit::MiB),
             self.ttl
         )
@@ -297,4 +394,31 @@ mod tests {
 
         assert!(cache_provider.cache_is_enabled_for_plan(&logical_plan));
     }
+
+    #[test]
+    fn test_cache_type() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let cache_provider = QueryResultsCacheProvider::try_new(
+            &ResultsCache {
+                cache_type: Some("file".to_string()),
+                cache_dir: Some(dir.path().to_string_lossy().to_string()),
+                ..Default::default()
+            },
+            Box::new([]),
+        )
+        .expect("valid cache provider");
+        assert_eq!(cache_provider.cache_type, CacheType::File);
+        assert!(dir.path().join("index.json").is_file());
+
+        assert!(matches!(
+            QueryResultsCacheProvider::try_new(
+                &ResultsCache {
+                    cache_type: Some("redis".to_string()),
+                    ..Default::default()
+                },
+                Box::new([]),
+            ),
+            Err(Error::UnknownCacheType { .. })
+        ));
+    }
 }
@@ -15,6 +15,7 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 use crate::CachedQueryResult;
+use crate::CachedQueryResultStream;
 use crate::FailedToInvalidateCacheSnafu;
 use crate::QueryResultCache;
 use crate::Result;
@@ -65,10 +66,10 @@ impl LruCache {
 
 #[async_trait]
 impl QueryResultCache for LruCache {
-    async fn get(&self, plan: &LogicalPlan) -> Result<Option<CachedQueryResult>> {
+    async fn get(&self, plan: &LogicalPlan) -> Result<Option<CachedQueryResultStream>> {
         let key = key_for_logical_plan(plan);
         match self.cache.get(&key).await {
-            Some(value) => Ok(Some(value)),
+            Some(value) => Ok(Some(value.into())),
             None => Ok(None),
         }
     }
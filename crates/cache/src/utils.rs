@@ -18,8 +18,9 @@ use std::{collections::HashSet, sync::Arc};
 
 use arrow::array::RecordBatch;
 use datafusion::{
-    execution::SendableRecordBatchStream, logical_expr::LogicalPlan,
-    physical_plan::stream::RecordBatchStreamAdapter, sql::TableReference,
+    common::tree_node::TreeNodeRecursion, execution::SendableRecordBatchStream,
+    logical_expr::LogicalPlan, physical_plan::stream::RecordBatchStreamAdapter,
+    sql::TableReference,
 };
 
 use crate::{CachedQueryResult, QueryResultsCacheProvider};
@@ -74,19 +75,20 @@ pub fn to_cached_record_batch_stream(
     ))
 }
 
+/// Returns the tables scanned by a logical plan, including tables scanned by subqueries in expressions (i.e. `WHERE id IN (SELECT ...)`).
 #[must_use]
 pub fn get_logical_plan_input_tables(plan: &LogicalPlan) -> HashSet<TableReference> {
     let mut table_names: HashSet<TableReference> = HashSet::new();
-    let mut plan_stack = vec![plan];
 
-    while let Some(current_plan) = plan_stack.pop() {
+    // The visitor never returns an error, so the result can be ignored.
+    let _ = plan.apply_with_subqueries(|current_plan| {
         if let LogicalPlan::TableScan(source, ..) = current_plan {
             // Clones of TableReferences are cheap - all fields are Arcs
             table_names.insert(source.table_name.clone());
         }
 
-        plan_stack.extend(current_plan.inputs());
-    }
+        Ok(TreeNodeRecursion::Continue)
+    });
 
     table_names
 }
@@ -223,6 +225,20 @@ pub(crate) mod tests {
         assert_eq!(table_names, expected);
     }
 
+    #[tokio::test]
+    async fn test_collect_table_names_subquery_in_where_clause() {
+        let sql = "SELECT * FROM customer \
+                   WHERE id IN (SELECT customer_id FROM orders) \
+                   AND EXISTS (SELECT 1 FROM state WHERE state.id = customer.id)";
+        let logical_plan = parse_sql_to_logical_plan(sql).await;
+
+        let table_names = get_logical_plan_input_tables(&logical_plan);
+
+        let expected: HashSet<TableReference> =
+            HashSet::from(["customer".into(), "orders".into(), "state".into()]);
+        assert_eq!(table_names, expected);
+    }
+
     fn create_session_context() -> SessionContext {
         let config = SessionConfig::new().with_information_schema(true);
         let ctx = SessionContext::new_with_config(config);
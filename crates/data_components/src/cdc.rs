@@ -17,14 +17,21 @@ limitations under the License.
 use std::{fmt::Display, sync::Arc};
 
 use arrow::{
-    array::{Array, ListArray, RecordBatch, StringArray, StructArray},
+    array::{
+        Array, ArrayRef, ListArray, ListBuilder, RecordBatch, StringArray, StringBuilder,
+        StructArray,
+    },
     datatypes::{DataType, Field, Schema, SchemaRef},
+    error::ArrowError,
 };
 use futures::stream::BoxStream;
 use snafu::prelude::*;
 
 pub type ChangesStream = BoxStream<'static, Result<ChangeEnvelope, StreamError>>;
 
+/// A stream of record batches to append to a dataset, e.g. from an event stream.
+pub type AppendStream = BoxStream<'static, Result<AppendEnvelope, StreamError>>;
+
 #[derive(Debug, Snafu)]
 pub enum CommitError {
     #[snafu(display("Unable to commit change: {source}"))]
@@ -37,12 +44,33 @@ pub enum CommitError {
 pub enum ChangeBatchError {
     #[snafu(display("Schema didn't match expected change batch format {detail} schema={schema}"))]
     SchemaMismatch { detail: String, schema: SchemaRef },
+
+    #[snafu(display("Unable to build change batch: {source}"))]
+    UnableToBuildChangeBatch { source: ArrowError },
 }
 
 #[derive(Debug)]
 pub enum StreamError {
     Kafka(String),
     SerdeJsonError(String),
+    Decode(String),
+    IncompatibleSchemaChange(String),
+    Postgres(String),
+    MySql(String),
+}
+
+impl StreamError {
+    /// Whether a change couldn't be read, rather than the source being unavailable for a while.
+    /// The stream must stop at such a change, as committing the changes after it would skip it.
+    #[must_use]
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            StreamError::SerdeJsonError(_)
+                | StreamError::Decode(_)
+                | StreamError::IncompatibleSchemaChange(_)
+        )
+    }
 }
 
 impl std::error::Error for StreamError {}
@@ -52,6 +80,12 @@ impl std::fmt::Display for StreamError {
         match self {
             StreamError::Kafka(e) => write!(f, "Kafka error: {e}"),
             StreamError::SerdeJsonError(e) => write!(f, "Serde JSON error: {e}"),
+            StreamError::Decode(e) => write!(f, "Unable to decode change: {e}"),
+            StreamError::IncompatibleSchemaChange(e) => {
+                write!(f, "Incompatible schema change: {e}")
+            }
+            StreamError::Postgres(e) => write!(f, "Postgres error: {e}"),
+            StreamError::MySql(e) => write!(f, "MySQL error: {e}"),
         }
     }
 }
@@ -80,6 +114,26 @@ impl ChangeEnvelope {
     }
 }
 
+pub struct AppendEnvelope {
+    committer: Box<dyn CommitChange + Send>,
+    pub record_batch: RecordBatch,
+}
+
+impl AppendEnvelope {
+    #[must_use]
+    pub fn new(committer: Box<dyn CommitChange + Send>, record_batch: RecordBatch) -> Self {
+        Self {
+            committer,
+            record_batch,
+        }
+    }
+
+    /// Commits the record batch once it was appended to the dataset.
+    pub fn commit(self) -> Result<(), CommitError> {
+        self.committer.commit()
+    }
+}
+
 /// The Arrow schema that represents a `ChangeEvent`
 #[must_use]
 pub fn changes_schema(table_schema: &Schema) -> Schema {
@@ -165,6 +219,44 @@ impl ChangeBatch {
         })
     }
 
+    /// Creates a `ChangeBatch` that applies the same operation to every row of `data`.
+    pub fn try_from_rows(
+        op: &ChangeOperation,
+        primary_keys: &[String],
+        data: RecordBatch,
+    ) -> Result<Self, ChangeBatchError> {
+        let num_rows = data.num_rows();
+        let schema = changes_schema(&data.schema());
+
+        let op = op.to_string();
+        let ops = StringArray::from(vec![op.as_str(); num_rows]);
+
+        let mut primary_keys_builder = ListBuilder::new(StringBuilder::new())
+            .with_field(Arc::new(Field::new("item", DataType::Utf8, false)));
+        for _ in 0..num_rows {
+            if primary_keys.is_empty() {
+                primary_keys_builder.append(false);
+            } else {
+                for key in primary_keys {
+                    primary_keys_builder.values().append_value(key);
+                }
+                primary_keys_builder.append(true);
+            }
+        }
+
+        let record = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(ops) as ArrayRef,
+                Arc::new(primary_keys_builder.finish()),
+                Arc::new(StructArray::from(data)),
+            ],
+        )
+        .context(UnableToBuildChangeBatchSnafu)?;
+
+        Self::try_new(record)
+    }
+
     #[must_use]
     pub fn op(&self, row: usize) -> ChangeOperation {
         let Some(op_col) = self
@@ -16,6 +16,8 @@ limitations under the License.
 
 pub mod arrow;
 pub mod change_event;
+pub mod decoder;
+pub mod schema_registry;
 
 #[cfg(test)]
 mod tests;
@@ -150,7 +150,7 @@ pub struct Source {
     pub xmin: Option<serde_json::Value>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Schema {
     #[serde(rename = "type")]
     pub schema_type: String,
@@ -0,0 +1,335 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Decoding of Debezium change events serialized as JSON, or as Avro or Protobuf with their schema
+//! resolved from a schema registry.
+//!
+//! Avro and Protobuf change events are converted to the same [`ChangeEvent`] that the Kafka Connect
+//! JSON converter produces, so that they share the conversion to Arrow.
+
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use arrow::datatypes::Schema;
+use snafu::prelude::*;
+
+use super::arrow::convert_fields_to_arrow_schema;
+use super::change_event::{self, ChangeEvent, ChangeEventKey};
+use super::schema_registry::{self, SchemaRegistry, SchemaType};
+
+mod avro;
+mod protobuf;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("The {format} message format requires a schema registry URL"))]
+    MissingSchemaRegistry { format: MessageFormat },
+
+    #[snafu(display("{source}"))]
+    SchemaRegistry { source: schema_registry::Error },
+
+    #[snafu(display("Schema {id} is a {actual:?} schema, expected a {expected} schema"))]
+    UnexpectedSchemaType {
+        id: u32,
+        expected: MessageFormat,
+        actual: SchemaType,
+    },
+
+    #[snafu(display("Unable to deserialize JSON message: {source}"))]
+    UnableToDeserializeJson { source: serde_json::Error },
+
+    #[snafu(display("Unable to parse Avro schema {id}: {source}"))]
+    UnableToParseAvroSchema { id: u32, source: apache_avro::Error },
+
+    #[snafu(display("Unsupported Avro schema: {message}"))]
+    UnsupportedAvroSchema { message: String },
+
+    #[snafu(display("Unable to decode Avro message: {source}"))]
+    UnableToDecodeAvro { source: apache_avro::Error },
+
+    #[snafu(display("Unable to load Protobuf schema {id}: {message}"))]
+    UnableToLoadProtobufSchema { id: u32, message: String },
+
+    #[snafu(display("Protobuf schema {id} has no message at index {indexes:?}"))]
+    ProtobufMessageNotFound { id: u32, indexes: Vec<i64> },
+
+    #[snafu(display("Invalid Protobuf message indexes"))]
+    InvalidProtobufMessageIndexes,
+
+    #[snafu(display("Unable to decode Protobuf message: {source}"))]
+    UnableToDecodeProtobuf { source: prost::DecodeError },
+
+    #[snafu(display("The change event schema has no 'after' field"))]
+    MissingAfterSchema,
+
+    #[snafu(display("Unable to convert the change event schema to Arrow: {source}"))]
+    UnableToConvertSchema { source: super::arrow::Error },
+
+    #[snafu(display(
+        "Schema {schema_id} is incompatible with the dataset: {changes}. Recreate the accelerated dataset to use the new schema."
+    ))]
+    IncompatibleSchemaChange { schema_id: u32, changes: String },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    Json,
+    Avro,
+    Protobuf,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "avro" => Ok(Self::Avro),
+            "protobuf" => Ok(Self::Protobuf),
+            _ => Err(format!("Unknown message format: {s}")),
+        }
+    }
+}
+
+impl Display for MessageFormat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Avro => write!(f, "avro"),
+            Self::Protobuf => write!(f, "protobuf"),
+        }
+    }
+}
+
+pub struct ChangeEventDecoder {
+    message_format: MessageFormat,
+    format: Format,
+    /// The schemas that were already checked to be compatible with the dataset.
+    compatible_schema_ids: Mutex<HashSet<u32>>,
+}
+
+enum Format {
+    /// JSON messages embed their schema.
+    Json,
+    Avro(SchemaRegistry, avro::Schemas),
+    Protobuf(SchemaRegistry, protobuf::Schemas),
+}
+
+impl ChangeEventDecoder {
+    pub fn try_new(
+        message_format: MessageFormat,
+        registry: Option<SchemaRegistry>,
+    ) -> Result<Self> {
+        let format = match (message_format, registry) {
+            (MessageFormat::Json, _) => Format::Json,
+            (MessageFormat::Avro, Some(registry)) => {
+                Format::Avro(registry, avro::Schemas::default())
+            }
+            (MessageFormat::Protobuf, Some(registry)) => {
+                Format::Protobuf(registry, protobuf::Schemas::default())
+            }
+            (format, None) => return MissingSchemaRegistrySnafu { format }.fail(),
+        };
+
+        Ok(Self {
+            message_format,
+            format,
+            compatible_schema_ids: Mutex::new(HashSet::new()),
+        })
+    }
+
+    #[must_use]
+    pub fn message_format(&self) -> MessageFormat {
+        self.message_format
+    }
+
+    pub async fn decode_key(&self, message: &[u8]) -> Result<ChangeEventKey> {
+        match self.decode_with_registry(message).await? {
+            Some((schema, payload, _)) => Ok(ChangeEventKey { schema, payload }),
+            None => serde_json::from_slice(message).context(UnableToDeserializeJsonSnafu),
+        }
+    }
+
+    /// Decodes a change event, along with the ID of its registry schema if it has one.
+    pub async fn decode_value(&self, message: &[u8]) -> Result<(ChangeEvent, Option<u32>)> {
+        match self.decode_with_registry(message).await? {
+            Some((schema, payload, schema_id)) => {
+                let payload =
+                    serde_json::from_value(payload).context(UnableToDeserializeJsonSnafu)?;
+                Ok((ChangeEvent { schema, payload }, Some(schema_id)))
+            }
+            None => Ok((
+                serde_json::from_slice(message).context(UnableToDeserializeJsonSnafu)?,
+                None,
+            )),
+        }
+    }
+
+    /// Decodes a change event for a dataset with `table_schema`. The first time a registry schema
+    /// is seen, it is checked to be compatible with the dataset.
+    pub async fn decode_change(
+        &self,
+        message: &[u8],
+        table_schema: &Schema,
+    ) -> Result<ChangeEvent> {
+        let (event, schema_id) = self.decode_value(message).await?;
+        if let Some(schema_id) = schema_id {
+            self.check_compatibility(schema_id, &event, table_schema)?;
+        }
+        Ok(event)
+    }
+
+    /// Decodes a message serialized with a registry schema into its Kafka Connect schema, its JSON
+    /// payload and its schema ID, or returns `None` for JSON messages.
+    pub async fn decode_with_registry(
+        &self,
+        message: &[u8],
+    ) -> Result<Option<(change_event::Schema, serde_json::Value, u32)>> {
+        let (schema, payload, schema_id) = match &self.format {
+            Format::Json => return Ok(None),
+            Format::Avro(registry, schemas) => {
+                let (schema_id, payload) =
+                    schema_registry::split_wire_format(message).context(SchemaRegistrySnafu)?;
+                let (schema, payload) = schemas.get(registry, schema_id).await?.decode(payload)?;
+                (schema, payload, schema_id)
+            }
+            Format::Protobuf(registry, schemas) => {
+                let (schema_id, payload) =
+                    schema_registry::split_wire_format(message).context(SchemaRegistrySnafu)?;
+                let (schema, payload) = schemas
+                    .get(registry, schema_id)
+                    .await?
+                    .decode(schema_id, payload)?;
+                (schema, payload, schema_id)
+            }
+        };
+        Ok(Some((schema, payload, schema_id)))
+    }
+
+    /// Reports columns of the dataset that were removed or changed type or nullability in the
+    /// schema of a change event. Columns that were added are ignored with a warning, as the dataset
+    /// schema is fixed when it is first loaded.
+    fn check_compatibility(
+        &self,
+        schema_id: u32,
+        event: &ChangeEvent,
+        table_schema: &Schema,
+    ) -> Result<()> {
+        if self
+            .compatible_schema_ids
+            .lock()
+            .is_ok_and(|ids| ids.contains(&schema_id))
+        {
+            return Ok(());
+        }
+
+        let fields = event.get_schema_fields().context(MissingAfterSchemaSnafu)?;
+        let schema = convert_fields_to_arrow_schema(fields).context(UnableToConvertSchemaSnafu)?;
+
+        let changes = schema_changes(table_schema, &schema);
+        ensure!(
+            changes.is_empty(),
+            IncompatibleSchemaChangeSnafu {
+                schema_id,
+                changes: changes.join(", "),
+            }
+        );
+
+        let added: Vec<&str> = schema
+            .fields()
+            .iter()
+            .filter(|field| table_schema.field_with_name(field.name()).is_err())
+            .map(|field| field.name().as_str())
+            .collect();
+        if !added.is_empty() {
+            tracing::warn!(
+                "Schema {schema_id} adds the columns {} which are not in the dataset and will be ignored. Recreate the accelerated dataset to include them.",
+                added.join(", ")
+            );
+        }
+
+        if let Ok(mut ids) = self.compatible_schema_ids.lock() {
+            ids.insert(schema_id);
+        }
+        Ok(())
+    }
+}
+
+/// The changes from `table_schema` to `schema` that prevent its values from being written to the
+/// dataset.
+fn schema_changes(table_schema: &Schema, schema: &Schema) -> Vec<String> {
+    let mut changes = Vec::new();
+    for field in table_schema.fields() {
+        let Ok(new_field) = schema.field_with_name(field.name()) else {
+            changes.push(format!("column '{}' was removed", field.name()));
+            continue;
+        };
+        if new_field.data_type() != field.data_type() {
+            changes.push(format!(
+                "column '{}' changed type from {} to {}",
+                field.name(),
+                field.data_type(),
+                new_field.data_type()
+            ));
+        } else if new_field.is_nullable() && !field.is_nullable() {
+            changes.push(format!("column '{}' became nullable", field.name()));
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::{DataType, Field};
+
+    #[test]
+    fn test_schema_changes() {
+        let table_schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("price", DataType::Float64, true),
+            Field::new("qty", DataType::Int32, false),
+        ]);
+
+        let compatible = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("price", DataType::Float64, true),
+            Field::new("qty", DataType::Int32, false),
+            Field::new("added", DataType::Utf8, true),
+        ]);
+        assert!(schema_changes(&table_schema, &compatible).is_empty());
+
+        let incompatible = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("price", DataType::Float64, true),
+            Field::new("qty", DataType::Int32, true),
+        ]);
+        assert_eq!(
+            schema_changes(&table_schema, &incompatible),
+            vec![
+                "column 'id' changed type from Int32 to Int64",
+                "column 'name' was removed",
+                "column 'qty' became nullable",
+            ]
+        );
+    }
+}
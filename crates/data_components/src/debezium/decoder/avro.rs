@@ -0,0 +1,469 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Avro change events, as written by the Confluent Avro converter.
+//!
+//! The converter keeps the Kafka Connect schema in the Avro schema (e.g. `connect.name`), which is
+//! used to build the same [`change_event::Schema`] that the JSON converter embeds in each message.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use apache_avro::types::Value as AvroValue;
+use base64::prelude::*;
+use serde_json::{Map, Value};
+use snafu::prelude::*;
+
+use super::{
+    MessageFormat, Result, SchemaRegistrySnafu, UnableToDecodeAvroSnafu,
+    UnableToDeserializeJsonSnafu, UnableToParseAvroSchemaSnafu, UnexpectedSchemaTypeSnafu,
+    UnsupportedAvroSchemaSnafu,
+};
+use crate::debezium::change_event;
+use crate::debezium::schema_registry::{SchemaRegistry, SchemaType};
+
+/// The Avro schemas that were fetched from the registry, by ID.
+#[derive(Default)]
+pub(super) struct Schemas {
+    schemas: RwLock<HashMap<u32, Arc<AvroSchema>>>,
+}
+
+impl Schemas {
+    pub(super) async fn get(&self, registry: &SchemaRegistry, id: u32) -> Result<Arc<AvroSchema>> {
+        if let Some(schema) = self
+            .schemas
+            .read()
+            .ok()
+            .and_then(|schemas| schemas.get(&id).cloned())
+        {
+            return Ok(schema);
+        }
+
+        let registered = registry.schema(id).await.context(SchemaRegistrySnafu)?;
+        ensure!(
+            registered.schema_type == SchemaType::Avro,
+            UnexpectedSchemaTypeSnafu {
+                id,
+                expected: MessageFormat::Avro,
+                actual: registered.schema_type,
+            }
+        );
+
+        let schema = Arc::new(AvroSchema::try_new(id, &registered.schema)?);
+        if let Ok(mut schemas) = self.schemas.write() {
+            schemas.insert(id, Arc::clone(&schema));
+        }
+        Ok(schema)
+    }
+}
+
+pub(super) struct AvroSchema {
+    schema: apache_avro::Schema,
+    connect_schema: change_event::Schema,
+}
+
+impl AvroSchema {
+    fn try_new(id: u32, schema: &str) -> Result<Self> {
+        let json: Value = serde_json::from_str(schema).context(UnableToDeserializeJsonSnafu)?;
+        let connect_schema = ConnectSchemaBuilder::default().schema(&json)?;
+        let schema =
+            apache_avro::Schema::parse_str(schema).context(UnableToParseAvroSchemaSnafu { id })?;
+
+        Ok(Self {
+            schema,
+            connect_schema,
+        })
+    }
+
+    /// Decodes an Avro datum into its Kafka Connect schema and JSON payload.
+    pub(super) fn decode(&self, mut datum: &[u8]) -> Result<(change_event::Schema, Value)> {
+        let value = apache_avro::from_avro_datum(&self.schema, &mut datum, None)
+            .context(UnableToDecodeAvroSnafu)?;
+        Ok((self.connect_schema.clone(), to_json(value)))
+    }
+}
+
+/// Builds a Kafka Connect schema from an Avro schema, resolving references to named types.
+#[derive(Default)]
+struct ConnectSchemaBuilder<'a> {
+    named: HashMap<String, &'a Value>,
+}
+
+impl<'a> ConnectSchemaBuilder<'a> {
+    fn schema(&mut self, avro: &'a Value) -> Result<change_event::Schema> {
+        let field = self.field(avro, None)?;
+        ensure!(
+            field.field_type == "struct",
+            UnsupportedAvroSchemaSnafu {
+                message: "the schema of a change event must be a record",
+            }
+        );
+
+        Ok(change_event::Schema {
+            schema_type: field.field_type,
+            fields: field.fields.unwrap_or_default(),
+            optional: field.optional,
+            name: field.name.unwrap_or_default(),
+        })
+    }
+
+    fn field(&mut self, avro: &'a Value, namespace: Option<&str>) -> Result<change_event::Field> {
+        match avro {
+            Value::String(name) => match name.as_str() {
+                "boolean" => Ok(connect_field("boolean")),
+                "int" => Ok(connect_field("int32")),
+                "long" => Ok(connect_field("int64")),
+                "float" => Ok(connect_field("float")),
+                "double" => Ok(connect_field("double")),
+                "bytes" => Ok(connect_field("bytes")),
+                "string" => Ok(connect_field("string")),
+                name => {
+                    let full_name = match namespace {
+                        Some(namespace) if !name.contains('.') => format!("{namespace}.{name}"),
+                        _ => name.to_string(),
+                    };
+                    let named = self
+                        .named
+                        .get(&full_name)
+                        .or_else(|| self.named.get(name))
+                        .copied()
+                        .context(UnsupportedAvroSchemaSnafu {
+                            message: format!("unknown type {name}"),
+                        })?;
+                    self.field(named, namespace)
+                }
+            },
+            // Kafka Connect optional fields are unions with null.
+            Value::Array(union) => {
+                let variants: Vec<&Value> = union
+                    .iter()
+                    .filter(|variant| variant.as_str() != Some("null"))
+                    .collect();
+                let [variant] = *variants.as_slice() else {
+                    return UnsupportedAvroSchemaSnafu {
+                        message: format!("union {avro}"),
+                    }
+                    .fail();
+                };
+                let mut field = self.field(variant, namespace)?;
+                field.optional = variants.len() < union.len();
+                Ok(field)
+            }
+            Value::Object(object) => self.complex_field(avro, object, namespace),
+            _ => UnsupportedAvroSchemaSnafu {
+                message: format!("type {avro}"),
+            }
+            .fail(),
+        }
+    }
+
+    fn complex_field(
+        &mut self,
+        avro: &'a Value,
+        object: &'a Map<String, Value>,
+        namespace: Option<&str>,
+    ) -> Result<change_event::Field> {
+        let avro_type = object.get("type").context(UnsupportedAvroSchemaSnafu {
+            message: format!("missing type in {avro}"),
+        })?;
+
+        let mut field = match avro_type.as_str() {
+            Some("record") => {
+                let (name, namespace) = self.register(avro, object, namespace);
+                let fields = object
+                    .get("fields")
+                    .and_then(Value::as_array)
+                    .context(UnsupportedAvroSchemaSnafu {
+                        message: format!("record {name} has no fields"),
+                    })?
+                    .iter()
+                    .map(|field| {
+                        let field_name = field.get("name").and_then(Value::as_str).context(
+                            UnsupportedAvroSchemaSnafu {
+                                message: format!("record {name} has a field without a name"),
+                            },
+                        )?;
+                        let field_type = field.get("type").context(UnsupportedAvroSchemaSnafu {
+                            message: format!("field {field_name} of record {name} has no type"),
+                        })?;
+
+                        let mut connect = self.field(field_type, namespace.as_deref())?;
+                        connect.field = Some(field_name.to_string());
+                        Ok(connect)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                change_event::Field {
+                    fields: Some(fields),
+                    name: Some(name),
+                    ..connect_field("struct")
+                }
+            }
+            Some("enum") => {
+                self.register(avro, object, namespace);
+                connect_field("string")
+            }
+            Some("fixed") => {
+                self.register(avro, object, namespace);
+                connect_field("bytes")
+            }
+            Some("array") => {
+                let items = object.get("items").context(UnsupportedAvroSchemaSnafu {
+                    message: format!("array {avro} has no items"),
+                })?;
+                change_event::Field {
+                    items: Some(Box::new(self.field(items, namespace)?)),
+                    ..connect_field("array")
+                }
+            }
+            Some("map") => connect_field("map"),
+            // A primitive type with attributes, or a nested type.
+            _ => self.field(avro_type, namespace)?,
+        };
+
+        // The Kafka Connect schema kept by the Avro converter.
+        if object.get("connect.type").and_then(Value::as_str) == Some("int16") {
+            field.field_type = "int16".to_string();
+        }
+        if let Some(name) = object.get("connect.name").and_then(Value::as_str) {
+            field.name = Some(name.to_string());
+        }
+        if let Some(version) = object.get("connect.version").and_then(Value::as_i64) {
+            field.version = Some(version);
+        }
+        if let Some(parameters) = object.get("connect.parameters").and_then(Value::as_object) {
+            field.parameters = Some(
+                parameters
+                    .iter()
+                    .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                    .collect(),
+            );
+        }
+
+        // Avro logical types without a Kafka Connect schema.
+        match object.get("logicalType").and_then(Value::as_str) {
+            Some("decimal") => {
+                field.name = Some("org.apache.kafka.connect.data.Decimal".to_string());
+                let parameters = field.parameters.get_or_insert_with(HashMap::new);
+                for (attribute, parameter) in [
+                    ("scale", "scale"),
+                    ("precision", "connect.decimal.precision"),
+                ] {
+                    if let Some(value) = object.get(attribute).and_then(Value::as_i64) {
+                        parameters
+                            .entry(parameter.to_string())
+                            .or_insert_with(|| value.to_string());
+                    }
+                }
+            }
+            Some(logical_type) if field.name.is_none() => {
+                field.name = match logical_type {
+                    "date" => Some("io.debezium.time.Date".to_string()),
+                    "time-millis" => Some("io.debezium.time.Time".to_string()),
+                    "time-micros" => Some("io.debezium.time.MicroTime".to_string()),
+                    "timestamp-micros" => Some("io.debezium.time.MicroTimestamp".to_string()),
+                    _ => None,
+                };
+            }
+            _ => {}
+        }
+
+        Ok(field)
+    }
+
+    /// Registers a named type, returning its full name and namespace.
+    fn register(
+        &mut self,
+        avro: &'a Value,
+        object: &'a Map<String, Value>,
+        namespace: Option<&str>,
+    ) -> (String, Option<String>) {
+        let name = object
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let (full_name, namespace) = match name.rsplit_once('.') {
+            Some((namespace, _)) => (name.to_string(), Some(namespace.to_string())),
+            None => {
+                let namespace = object
+                    .get("namespace")
+                    .and_then(Value::as_str)
+                    .or(namespace)
+                    .filter(|namespace| !namespace.is_empty());
+                match namespace {
+                    Some(namespace) => (format!("{namespace}.{name}"), Some(namespace.to_string())),
+                    None => (name.to_string(), None),
+                }
+            }
+        };
+
+        self.named.insert(full_name.clone(), avro);
+        (full_name, namespace)
+    }
+}
+
+fn connect_field(field_type: &str) -> change_event::Field {
+    change_event::Field {
+        field_type: field_type.to_string(),
+        fields: None,
+        optional: false,
+        name: None,
+        field: None,
+        version: None,
+        parameters: None,
+        items: None,
+    }
+}
+
+/// Converts an Avro value to JSON as the Kafka Connect JSON converter would, e.g. bytes and
+/// decimals are base64 encoded.
+fn to_json(value: AvroValue) -> Value {
+    match value {
+        AvroValue::Null => Value::Null,
+        AvroValue::Boolean(b) => b.into(),
+        AvroValue::Int(i) | AvroValue::Date(i) | AvroValue::TimeMillis(i) => i.into(),
+        AvroValue::Long(i)
+        | AvroValue::TimeMicros(i)
+        | AvroValue::TimestampMillis(i)
+        | AvroValue::TimestampMicros(i) => i.into(),
+        AvroValue::Float(f) => f64::from(f).into(),
+        AvroValue::Double(f) => f.into(),
+        AvroValue::Bytes(bytes) | AvroValue::Fixed(_, bytes) => {
+            BASE64_STANDARD.encode(bytes).into()
+        }
+        AvroValue::Decimal(decimal) => Vec::<u8>::try_from(&decimal)
+            .map_or(Value::Null, |bytes| BASE64_STANDARD.encode(bytes).into()),
+        AvroValue::String(s) | AvroValue::Enum(_, s) => s.into(),
+        AvroValue::Uuid(uuid) => uuid.to_string().into(),
+        AvroValue::Union(_, value) => to_json(*value),
+        AvroValue::Array(values) => values.into_iter().map(to_json).collect(),
+        AvroValue::Map(values) => Value::Object(
+            values
+                .into_iter()
+                .map(|(key, value)| (key, to_json(value)))
+                .collect(),
+        ),
+        AvroValue::Record(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key, to_json(value)))
+                .collect(),
+        ),
+        value => Value::try_from(value).unwrap_or(Value::Null),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apache_avro::types::Record;
+
+    const VALUE_SCHEMA: &str = r#"{
+        "type": "record",
+        "name": "Envelope",
+        "namespace": "server.public.orders",
+        "fields": [
+            {"name": "before", "type": ["null", {
+                "type": "record",
+                "name": "Value",
+                "fields": [
+                    {"name": "id", "type": "int"},
+                    {"name": "qty", "type": {"type": "int", "connect.type": "int16"}},
+                    {"name": "note", "type": ["null", "string"], "default": null},
+                    {"name": "created", "type": {"type": "int", "connect.version": 1, "connect.name": "io.debezium.time.Date"}},
+                    {"name": "price", "type": {"type": "bytes", "scale": 2, "precision": 10, "logicalType": "decimal"}}
+                ]
+            }], "default": null},
+            {"name": "after", "type": ["null", "Value"], "default": null},
+            {"name": "op", "type": "string"},
+            {"name": "ts_ms", "type": ["null", "long"], "default": null}
+        ]
+    }"#;
+
+    #[test]
+    fn test_connect_schema() {
+        let schema = AvroSchema::try_new(1, VALUE_SCHEMA).expect("valid schema");
+        assert_eq!(schema.connect_schema.name, "server.public.orders.Envelope");
+
+        let after = &schema.connect_schema.fields[1];
+        assert_eq!(after.field.as_deref(), Some("after"));
+        assert!(after.optional);
+
+        let fields = after.fields.as_ref().expect("after is a struct");
+        let types: Vec<(&str, &str, bool)> = fields
+            .iter()
+            .map(|f| {
+                (
+                    f.field.as_deref().unwrap_or_default(),
+                    f.field_type.as_str(),
+                    f.optional,
+                )
+            })
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                ("id", "int32", false),
+                ("qty", "int16", false),
+                ("note", "string", true),
+                ("created", "int32", false),
+                ("price", "bytes", false),
+            ]
+        );
+        assert_eq!(fields[3].name.as_deref(), Some("io.debezium.time.Date"));
+        let parameters = fields[4].parameters.as_ref().expect("decimal parameters");
+        assert_eq!(parameters["scale"], "2");
+        assert_eq!(parameters["connect.decimal.precision"], "10");
+    }
+
+    #[test]
+    fn test_decode() {
+        let schema = AvroSchema::try_new(1, VALUE_SCHEMA).expect("valid schema");
+
+        let value_schema = match &schema.schema {
+            apache_avro::Schema::Record(record) => match &record.fields[0].schema {
+                apache_avro::Schema::Union(union) => union.variants()[1].clone(),
+                _ => panic!("before is a union"),
+            },
+            _ => panic!("schema is a record"),
+        };
+        let mut value = Record::new(&value_schema).expect("value is a record");
+        value.put("id", 1);
+        value.put("qty", 2);
+        value.put("note", AvroValue::Union(0, Box::new(AvroValue::Null)));
+        value.put("created", 19_000);
+        value.put("price", AvroValue::Decimal(vec![0x01, 0x00].into()));
+
+        let mut envelope = Record::new(&schema.schema).expect("envelope is a record");
+        envelope.put("before", AvroValue::Union(0, Box::new(AvroValue::Null)));
+        envelope.put("after", AvroValue::Union(1, Box::new(value.into())));
+        envelope.put("op", "c");
+        envelope.put("ts_ms", AvroValue::Union(1, Box::new(AvroValue::Long(5))));
+
+        let datum = apache_avro::to_avro_datum(&schema.schema, envelope).expect("valid datum");
+        let (_, payload) = schema.decode(&datum).expect("decoded datum");
+
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "before": null,
+                "after": {"id": 1, "qty": 2, "note": null, "created": 19_000, "price": "AQA="},
+                "op": "c",
+                "ts_ms": 5,
+            })
+        );
+    }
+}
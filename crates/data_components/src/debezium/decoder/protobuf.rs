@@ -0,0 +1,489 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Protobuf change events, as written by the Confluent Protobuf converter.
+//!
+//! After the schema ID, the converter writes the indexes of the message type in the schema's file
+//! descriptor, followed by the encoded message.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use base64::prelude::*;
+use prost::Message;
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, FieldDescriptor, FileDescriptor, Kind, MapKey,
+    MessageDescriptor, ReflectMessage, Value as ProtobufValue,
+};
+use serde_json::Value;
+use snafu::prelude::*;
+
+use super::{
+    Error, InvalidProtobufMessageIndexesSnafu, MessageFormat, ProtobufMessageNotFoundSnafu, Result,
+    SchemaRegistrySnafu, UnableToDecodeProtobufSnafu, UnableToLoadProtobufSchemaSnafu,
+    UnexpectedSchemaTypeSnafu,
+};
+use crate::debezium::change_event;
+use crate::debezium::schema_registry::{RegisteredSchema, SchemaRegistry, SchemaType};
+
+/// Nested messages deeper than this are not expanded, to support recursive message types.
+const MAX_DEPTH: usize = 32;
+
+/// The Protobuf schemas that were fetched from the registry, by ID.
+#[derive(Default)]
+pub(super) struct Schemas {
+    schemas: RwLock<HashMap<u32, ProtobufSchema>>,
+}
+
+impl Schemas {
+    pub(super) async fn get(&self, registry: &SchemaRegistry, id: u32) -> Result<ProtobufSchema> {
+        if let Some(schema) = self
+            .schemas
+            .read()
+            .ok()
+            .and_then(|schemas| schemas.get(&id).cloned())
+        {
+            return Ok(schema);
+        }
+
+        let registered = registry.schema(id).await.context(SchemaRegistrySnafu)?;
+        ensure!(
+            registered.schema_type == SchemaType::Protobuf,
+            UnexpectedSchemaTypeSnafu {
+                id,
+                expected: MessageFormat::Protobuf,
+                actual: registered.schema_type,
+            }
+        );
+
+        let name = format!("schema_{id}.proto");
+        let mut files = vec![file_descriptor_proto(id, &registered, &name)?];
+
+        // Fetch the schemas imported by the schema, and the schemas they import.
+        let mut references = registered.references.clone();
+        let mut fetched = HashSet::new();
+        while let Some(reference) = references.pop() {
+            if !fetched.insert(reference.name.clone()) {
+                continue;
+            }
+            let imported = registry
+                .referenced_schema(&reference)
+                .await
+                .context(SchemaRegistrySnafu)?;
+            files.push(file_descriptor_proto(id, &imported, &reference.name)?);
+            references.extend(imported.references);
+        }
+
+        let mut pool = DescriptorPool::global();
+        files.retain(|file| pool.get_file_by_name(file.name()).is_none());
+        pool.add_file_descriptor_protos(files)
+            .map_err(|e| Error::UnableToLoadProtobufSchema {
+                id,
+                message: e.to_string(),
+            })?;
+
+        let schema = ProtobufSchema(pool.get_file_by_name(&name).context(
+            UnableToLoadProtobufSchemaSnafu {
+                id,
+                message: format!("{name} was not added"),
+            },
+        )?);
+        if let Ok(mut schemas) = self.schemas.write() {
+            schemas.insert(id, schema.clone());
+        }
+        Ok(schema)
+    }
+}
+
+/// Decodes a serialized `FileDescriptorProto` from the registry, named `name` so that it can be
+/// imported by the other files.
+fn file_descriptor_proto(
+    id: u32,
+    registered: &RegisteredSchema,
+    name: &str,
+) -> Result<prost_types::FileDescriptorProto> {
+    let bytes = BASE64_STANDARD.decode(&registered.schema).map_err(|e| {
+        Error::UnableToLoadProtobufSchema {
+            id,
+            message: e.to_string(),
+        }
+    })?;
+    let mut file = prost_types::FileDescriptorProto::decode(bytes.as_slice()).map_err(|e| {
+        Error::UnableToLoadProtobufSchema {
+            id,
+            message: e.to_string(),
+        }
+    })?;
+    file.name = Some(name.to_string());
+    Ok(file)
+}
+
+#[derive(Clone)]
+pub(super) struct ProtobufSchema(FileDescriptor);
+
+impl ProtobufSchema {
+    /// Decodes a message into its Kafka Connect schema and JSON payload.
+    pub(super) fn decode(
+        &self,
+        schema_id: u32,
+        message: &[u8],
+    ) -> Result<(change_event::Schema, Value)> {
+        let (indexes, message) =
+            message_indexes(message).context(InvalidProtobufMessageIndexesSnafu)?;
+        let descriptor =
+            self.message_descriptor(&indexes)
+                .context(ProtobufMessageNotFoundSnafu {
+                    id: schema_id,
+                    indexes,
+                })?;
+
+        let decoded = DynamicMessage::decode(descriptor.clone(), message)
+            .context(UnableToDecodeProtobufSnafu)?;
+
+        let schema = change_event::Schema {
+            schema_type: "struct".to_string(),
+            fields: descriptor
+                .fields()
+                .map(|field| connect_field(&field, 0))
+                .collect(),
+            optional: false,
+            name: descriptor.full_name().to_string(),
+        };
+        Ok((schema, message_to_json(&decoded)))
+    }
+
+    /// Finds a message type by its index in the file, followed by the indexes of its nested types.
+    fn message_descriptor(&self, indexes: &[i64]) -> Option<MessageDescriptor> {
+        let (first, nested) = indexes.split_first()?;
+        let mut descriptor = self.0.messages().nth(usize::try_from(*first).ok()?)?;
+        for index in nested {
+            let child = descriptor
+                .child_messages()
+                .nth(usize::try_from(*index).ok()?)?;
+            descriptor = child;
+        }
+        Some(descriptor)
+    }
+}
+
+/// Reads the message indexes that prefix a message. A single `0` is shorthand for the first
+/// message type in the file.
+fn message_indexes(message: &[u8]) -> Option<(Vec<i64>, &[u8])> {
+    let (count, mut message) = read_zigzag_varint(message)?;
+    if count == 0 {
+        return Some((vec![0], message));
+    }
+
+    let count = usize::try_from(count).ok()?;
+    let mut indexes = Vec::new();
+    for _ in 0..count {
+        let (index, rest) = read_zigzag_varint(message)?;
+        indexes.push(index);
+        message = rest;
+    }
+    Some((indexes, message))
+}
+
+#[allow(clippy::cast_possible_wrap)]
+fn read_zigzag_varint(bytes: &[u8]) -> Option<(i64, &[u8])> {
+    let mut value: u64 = 0;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            let decoded = ((value >> 1) as i64) ^ -((value & 1) as i64);
+            return Some((decoded, &bytes[i + 1..]));
+        }
+    }
+    None
+}
+
+fn connect_field(field: &FieldDescriptor, depth: usize) -> change_event::Field {
+    let mut connect = if field.is_map() {
+        connect_type("map")
+    } else if field.is_list() {
+        change_event::Field {
+            items: Some(Box::new(kind_field(&field.kind(), depth))),
+            ..connect_type("array")
+        }
+    } else {
+        kind_field(&field.kind(), depth)
+    };
+
+    connect.field = Some(field.name().to_string());
+    connect.optional = connect.optional || field.supports_presence();
+    connect
+}
+
+fn kind_field(kind: &Kind, depth: usize) -> change_event::Field {
+    match kind {
+        Kind::Double => connect_type("double"),
+        Kind::Float => connect_type("float"),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => connect_type("int32"),
+        Kind::Int64
+        | Kind::Sint64
+        | Kind::Sfixed64
+        | Kind::Uint32
+        | Kind::Fixed32
+        | Kind::Uint64
+        | Kind::Fixed64 => connect_type("int64"),
+        Kind::Bool => connect_type("boolean"),
+        Kind::String | Kind::Enum(_) => connect_type("string"),
+        Kind::Bytes => connect_type("bytes"),
+        Kind::Message(message) => {
+            if message.full_name() == "google.protobuf.Timestamp" {
+                return change_event::Field {
+                    name: Some("io.debezium.time.MicroTimestamp".to_string()),
+                    ..connect_type("int64")
+                };
+            }
+            if let Some(value) = wrapped_value(message) {
+                let mut field = kind_field(&value.kind(), depth);
+                field.optional = true;
+                return field;
+            }
+
+            let fields = if depth < MAX_DEPTH {
+                message
+                    .fields()
+                    .map(|field| connect_field(&field, depth + 1))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            change_event::Field {
+                fields: Some(fields),
+                name: Some(message.full_name().to_string()),
+                ..connect_type("struct")
+            }
+        }
+    }
+}
+
+fn connect_type(field_type: &str) -> change_event::Field {
+    change_event::Field {
+        field_type: field_type.to_string(),
+        fields: None,
+        optional: false,
+        name: None,
+        field: None,
+        version: None,
+        parameters: None,
+        items: None,
+    }
+}
+
+/// The `value` field of the well-known wrapper types, e.g. `google.protobuf.Int32Value`, which
+/// are used for nullable scalars.
+fn wrapped_value(message: &MessageDescriptor) -> Option<FieldDescriptor> {
+    let name = message.full_name();
+    if name.starts_with("google.protobuf.") && name.ends_with("Value") {
+        message.get_field_by_name("value")
+    } else {
+        None
+    }
+}
+
+fn message_to_json(message: &DynamicMessage) -> Value {
+    let descriptor = message.descriptor();
+
+    if descriptor.full_name() == "google.protobuf.Timestamp" {
+        let field = |name: &str| {
+            message
+                .get_field_by_name(name)
+                .and_then(|value| value.as_i64().or_else(|| value.as_i32().map(i64::from)))
+                .unwrap_or_default()
+        };
+        return field("seconds")
+            .saturating_mul(1_000_000)
+            .saturating_add(field("nanos") / 1_000)
+            .into();
+    }
+    if let Some(value) = wrapped_value(&descriptor) {
+        return value_to_json(&message.get_field(&value), &value.kind());
+    }
+
+    Value::Object(
+        descriptor
+            .fields()
+            .map(|field| {
+                let value = if field.supports_presence() && !message.has_field(&field) {
+                    Value::Null
+                } else {
+                    value_to_json(&message.get_field(&field), &field.kind())
+                };
+                (field.name().to_string(), value)
+            })
+            .collect(),
+    )
+}
+
+fn value_to_json(value: &ProtobufValue, kind: &Kind) -> Value {
+    match value {
+        ProtobufValue::Bool(b) => (*b).into(),
+        ProtobufValue::I32(i) => (*i).into(),
+        ProtobufValue::I64(i) => (*i).into(),
+        ProtobufValue::U32(i) => (*i).into(),
+        ProtobufValue::U64(i) => (*i).into(),
+        ProtobufValue::F32(f) => f64::from(*f).into(),
+        ProtobufValue::F64(f) => (*f).into(),
+        ProtobufValue::String(s) => s.clone().into(),
+        ProtobufValue::Bytes(bytes) => BASE64_STANDARD.encode(bytes).into(),
+        ProtobufValue::EnumNumber(number) => kind
+            .as_enum()
+            .and_then(|descriptor| descriptor.get_value(*number))
+            .map_or_else(|| (*number).into(), |value| value.name().into()),
+        ProtobufValue::Message(message) => message_to_json(message),
+        ProtobufValue::List(values) => values
+            .iter()
+            .map(|value| value_to_json(value, kind))
+            .collect(),
+        ProtobufValue::Map(values) => {
+            let value_kind = kind
+                .as_message()
+                .map(|entry| entry.map_entry_value_field().kind());
+            Value::Object(
+                values
+                    .iter()
+                    .map(|(key, value)| {
+                        let value = value_kind
+                            .as_ref()
+                            .map_or(Value::Null, |kind| value_to_json(value, kind));
+                        (map_key_to_string(key), value)
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+fn map_key_to_string(key: &MapKey) -> String {
+    match key {
+        MapKey::Bool(b) => b.to_string(),
+        MapKey::I32(i) => i.to_string(),
+        MapKey::I64(i) => i.to_string(),
+        MapKey::U32(i) => i.to_string(),
+        MapKey::U64(i) => i.to_string(),
+        MapKey::String(s) => s.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::{
+        field_descriptor_proto::{Label, Type},
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    };
+
+    fn field(name: &str, number: i32, field_type: Type) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional.into()),
+            r#type: Some(field_type.into()),
+            ..Default::default()
+        }
+    }
+
+    fn schema() -> ProtobufSchema {
+        let file = FileDescriptorProto {
+            name: Some("schema_1.proto".to_string()),
+            package: Some("server.public.orders".to_string()),
+            dependency: vec!["google/protobuf/timestamp.proto".to_string()],
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("Key".to_string()),
+                    field: vec![field("id", 1, Type::Int32)],
+                    ..Default::default()
+                },
+                DescriptorProto {
+                    name: Some("Value".to_string()),
+                    field: vec![
+                        field("id", 1, Type::Int32),
+                        field("name", 2, Type::String),
+                        FieldDescriptorProto {
+                            type_name: Some(".google.protobuf.Timestamp".to_string()),
+                            ..field("created", 3, Type::Message)
+                        },
+                    ],
+                    ..Default::default()
+                },
+            ],
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        };
+
+        let mut pool = DescriptorPool::global();
+        pool.add_file_descriptor_proto(file)
+            .expect("valid file descriptor");
+        ProtobufSchema(
+            pool.get_file_by_name("schema_1.proto")
+                .expect("file was added"),
+        )
+    }
+
+    #[test]
+    fn test_message_indexes() {
+        assert_eq!(message_indexes(&[0, 42]), Some((vec![0], [42].as_slice())));
+        // Two indexes, 1 and 2, zigzag encoded.
+        assert_eq!(
+            message_indexes(&[4, 2, 4, 42]),
+            Some((vec![1, 2], [42].as_slice()))
+        );
+        assert_eq!(message_indexes(&[0x80]), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        let schema = schema();
+        let descriptor = schema.message_descriptor(&[1]).expect("message descriptor");
+
+        let mut message = DynamicMessage::new(descriptor);
+        message.set_field_by_name("id", ProtobufValue::I32(7));
+        message.set_field_by_name("name", ProtobufValue::String("widget".to_string()));
+        let mut created = DynamicMessage::new(
+            DescriptorPool::global()
+                .get_message_by_name("google.protobuf.Timestamp")
+                .expect("well-known type"),
+        );
+        created.set_field_by_name("seconds", ProtobufValue::I64(2));
+        created.set_field_by_name("nanos", ProtobufValue::I32(3_000));
+        message.set_field_by_name("created", ProtobufValue::Message(created));
+
+        let mut bytes = vec![2, 2];
+        bytes.extend(message.encode_to_vec());
+
+        let (connect_schema, payload) = schema.decode(1, &bytes).expect("decoded message");
+        assert_eq!(connect_schema.name, "server.public.orders.Value");
+        let types: Vec<(&str, bool)> = connect_schema
+            .fields
+            .iter()
+            .map(|f| (f.field_type.as_str(), f.optional))
+            .collect();
+        assert_eq!(
+            types,
+            vec![("int32", false), ("string", false), ("int64", true)]
+        );
+        assert_eq!(
+            connect_schema.fields[2].name.as_deref(),
+            Some("io.debezium.time.MicroTimestamp")
+        );
+        assert_eq!(
+            payload,
+            serde_json::json!({"id": 7, "name": "widget", "created": 2_000_003})
+        );
+    }
+}
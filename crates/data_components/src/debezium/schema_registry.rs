@@ -0,0 +1,189 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! A client for a Confluent-compatible schema registry, and the registry's wire format.
+//!
+//! Messages serialized with a registry schema are prefixed with a magic byte (`0`) and the
+//! big-endian 4 byte ID of the schema. See
+//! <https://docs.confluent.io/platform/current/schema-registry/fundamentals/serdes-develop/index.html#wire-format>.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Schema registry request to {url} failed: {source}"))]
+    RequestFailed { url: String, source: reqwest::Error },
+
+    #[snafu(display(
+        "The message is not in the schema registry wire format: expected a magic byte of 0 followed by a 4 byte schema ID"
+    ))]
+    InvalidWireFormat,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SchemaType {
+    Avro,
+    Protobuf,
+    Json,
+}
+
+/// A reference from a schema to another schema registered under `subject`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SchemaReference {
+    pub name: String,
+    pub subject: String,
+    pub version: i32,
+}
+
+/// A schema as returned by the registry. Protobuf schemas are requested in the `serialized`
+/// format, i.e. a base64 encoded `FileDescriptorProto`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisteredSchema {
+    /// The registry omits the type of Avro schemas.
+    #[serde(default = "default_schema_type")]
+    pub schema_type: SchemaType,
+    pub schema: String,
+    #[serde(default)]
+    pub references: Vec<SchemaReference>,
+}
+
+fn default_schema_type() -> SchemaType {
+    SchemaType::Avro
+}
+
+pub struct SchemaRegistry {
+    url: String,
+    username: Option<String>,
+    password: Option<SecretString>,
+    client: reqwest::Client,
+    schemas: RwLock<HashMap<u32, Arc<RegisteredSchema>>>,
+}
+
+impl SchemaRegistry {
+    #[must_use]
+    pub fn new(url: &str, username: Option<String>, password: Option<SecretString>) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            username,
+            password,
+            client: reqwest::Client::new(),
+            schemas: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Gets the schema with the given ID. Schemas are immutable, so they are only fetched once.
+    pub async fn schema(&self, id: u32) -> Result<Arc<RegisteredSchema>> {
+        if let Some(schema) = self
+            .schemas
+            .read()
+            .ok()
+            .and_then(|schemas| schemas.get(&id).cloned())
+        {
+            return Ok(schema);
+        }
+
+        let schema: Arc<RegisteredSchema> = Arc::new(
+            self.get(&format!("/schemas/ids/{id}?format=serialized"))
+                .await?,
+        );
+        if let Ok(mut schemas) = self.schemas.write() {
+            schemas.insert(id, Arc::clone(&schema));
+        }
+        Ok(schema)
+    }
+
+    /// Gets a schema referenced by another schema.
+    pub async fn referenced_schema(&self, reference: &SchemaReference) -> Result<RegisteredSchema> {
+        self.get(&format!(
+            "/subjects/{}/versions/{}?format=serialized",
+            reference.subject, reference.version
+        ))
+        .await
+    }
+
+    async fn get(&self, path: &str) -> Result<RegisteredSchema> {
+        let url = format!("{}{path}", self.url);
+        tracing::debug!("Fetching schema from {url}");
+
+        let mut request = self
+            .client
+            .get(&url)
+            .header("Accept", "application/vnd.schemaregistry.v1+json");
+        if let Some(username) = &self.username {
+            request = request.basic_auth(
+                username,
+                self.password
+                    .as_ref()
+                    .map(|p| p.expose_secret().to_string()),
+            );
+        }
+
+        request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context(RequestFailedSnafu { url: url.clone() })?
+            .json()
+            .await
+            .context(RequestFailedSnafu { url })
+    }
+}
+
+/// Splits a message in the schema registry wire format into its schema ID and its payload.
+pub fn split_wire_format(message: &[u8]) -> Result<(u32, &[u8])> {
+    match message {
+        [0, a, b, c, d, payload @ ..] => Ok((u32::from_be_bytes([*a, *b, *c, *d]), payload)),
+        _ => InvalidWireFormatSnafu.fail(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_wire_format() {
+        let (id, payload) = split_wire_format(&[0, 0, 0, 1, 2, 42]).expect("valid wire format");
+        assert_eq!(id, 258);
+        assert_eq!(payload, &[42]);
+
+        assert!(split_wire_format(&[1, 0, 0, 0, 1]).is_err());
+        assert!(split_wire_format(&[0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_registered_schema() {
+        let schema: RegisteredSchema =
+            serde_json::from_str(r#"{"schema": "\"string\""}"#).expect("valid registered schema");
+        assert_eq!(schema.schema_type, SchemaType::Avro);
+
+        let schema: RegisteredSchema = serde_json::from_str(
+            r#"{"schemaType": "PROTOBUF", "schema": "", "references": [{"name": "a.proto", "subject": "a", "version": 1}]}"#,
+        )
+        .expect("valid registered schema");
+        assert_eq!(schema.schema_type, SchemaType::Protobuf);
+        assert_eq!(schema.references[0].subject, "a");
+    }
+}
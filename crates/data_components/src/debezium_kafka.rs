@@ -18,7 +18,7 @@ use crate::{
     cdc::{self, ChangeEnvelope, ChangesStream},
     debezium::{
         arrow::changes,
-        change_event::{ChangeEvent, ChangeEventKey},
+        decoder::{self, ChangeEventDecoder},
     },
     kafka::KafkaConsumer,
 };
@@ -41,11 +41,17 @@ pub struct DebeziumKafka {
     primary_keys: Vec<String>,
     constraints: Option<Constraints>,
     consumer: &'static KafkaConsumer,
+    decoder: Arc<ChangeEventDecoder>,
 }
 
 impl DebeziumKafka {
     #[must_use]
-    pub fn new(schema: SchemaRef, primary_keys: Vec<String>, consumer: KafkaConsumer) -> Self {
+    pub fn new(
+        schema: SchemaRef,
+        primary_keys: Vec<String>,
+        consumer: KafkaConsumer,
+        decoder: Arc<ChangeEventDecoder>,
+    ) -> Self {
         let Ok(df_schema) = DFSchema::try_from(Arc::clone(&schema)) else {
             unreachable!("DFSchema::try_from is infallible as of DataFusion 38")
         };
@@ -69,6 +75,7 @@ impl DebeziumKafka {
             primary_keys,
             constraints,
             consumer: Box::leak(Box::new(consumer)),
+            decoder,
         }
     }
 
@@ -81,23 +88,33 @@ impl DebeziumKafka {
     pub fn stream_changes(&self) -> ChangesStream {
         let schema = Arc::clone(&self.schema);
         let primary_keys = self.primary_keys.clone();
-        let stream = self
-            .consumer
-            .stream_json::<ChangeEventKey, ChangeEvent>()
-            .map(move |msg| {
-                let schema = Arc::clone(&schema);
-                let pk = primary_keys.clone();
+        let decoder = Arc::clone(&self.decoder);
+        let stream = self.consumer.stream_bytes().then(move |msg| {
+            let schema = Arc::clone(&schema);
+            let pk = primary_keys.clone();
+            let decoder = Arc::clone(&decoder);
+            async move {
                 let Ok(msg) = msg else {
                     return Err(cdc::StreamError::Kafka(
                         "Unable to read message".to_string(),
                     ));
                 };
 
-                let val = msg.value();
-                changes::to_change_batch(&schema, &pk, val)
+                let val =
+                    decoder
+                        .decode_change(msg.value(), &schema)
+                        .await
+                        .map_err(|e| match e {
+                            decoder::Error::IncompatibleSchemaChange { .. } => {
+                                cdc::StreamError::IncompatibleSchemaChange(e.to_string())
+                            }
+                            e => cdc::StreamError::Decode(e.to_string()),
+                        })?;
+                changes::to_change_batch(&schema, &pk, &val)
                     .map(|rb| ChangeEnvelope::new(Box::new(msg), rb))
                     .map_err(|e| cdc::StreamError::SerdeJsonError(e.to_string()))
-            });
+            }
+        });
 
         Box::pin(stream)
     }
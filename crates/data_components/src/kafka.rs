@@ -14,13 +14,15 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::{fmt, str::FromStr, time::Duration};
+
 use futures::{Stream, StreamExt};
 use rdkafka::{
     config::RDKafkaLogLevel,
     consumer::{Consumer, StreamConsumer},
     message::BorrowedMessage,
     util::get_rdkafka_version,
-    ClientConfig, Message, Offset,
+    ClientConfig, Message, Offset, TopicPartitionList,
 };
 use serde::de::DeserializeOwned;
 use snafu::prelude::*;
@@ -55,10 +57,64 @@ pub enum Error {
 
     #[snafu(display("The metadata for topic {topic} was not found."))]
     MetadataTopicNotFound { topic: String },
+
+    #[snafu(display("Unable to seek Kafka topic '{topic}' to timestamp {timestamp}: {source}"))]
+    UnableToSeekToTimestamp {
+        topic: String,
+        timestamp: i64,
+        source: rdkafka::error::KafkaError,
+    },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Where a new consumer group starts reading a topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StartOffset {
+    #[default]
+    Earliest,
+    Latest,
+    /// The first message at or after a timestamp, in milliseconds since the Unix epoch.
+    Timestamp(i64),
+}
+
+impl FromStr for StartOffset {
+    type Err = String;
+
+    /// Parses `earliest`, `latest`, or a timestamp as RFC 3339 or milliseconds since the Unix
+    /// epoch.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "earliest" => Ok(Self::Earliest),
+            "latest" => Ok(Self::Latest),
+            _ => s
+                .parse::<i64>()
+                .ok()
+                .or_else(|| {
+                    chrono::DateTime::parse_from_rfc3339(s)
+                        .ok()
+                        .map(|timestamp| timestamp.timestamp_millis())
+                })
+                .map(Self::Timestamp)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid start offset '{s}'. Expected 'earliest', 'latest' or a timestamp."
+                    )
+                }),
+        }
+    }
+}
+
+impl fmt::Display for StartOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Earliest => write!(f, "earliest"),
+            Self::Latest => write!(f, "latest"),
+            Self::Timestamp(timestamp) => write!(f, "{timestamp}"),
+        }
+    }
+}
+
 pub struct KafkaConsumer {
     group_id: String,
     consumer: StreamConsumer,
@@ -76,6 +132,22 @@ impl KafkaConsumer {
         Self::create(Self::generate_group_id(dataset), brokers)
     }
 
+    /// Creates a consumer for a new consumer group that starts reading at `start_offset`.
+    ///
+    /// Consumers that start at a timestamp must call [`KafkaConsumer::seek_to_timestamp`] instead
+    /// of [`KafkaConsumer::subscribe`].
+    pub fn create_with_start_offset(
+        dataset: &str,
+        brokers: String,
+        start_offset: StartOffset,
+    ) -> Result<Self> {
+        let offset_reset = match start_offset {
+            StartOffset::Latest => "latest",
+            StartOffset::Earliest | StartOffset::Timestamp(_) => "earliest",
+        };
+        Self::create_with_offset_reset(Self::generate_group_id(dataset), brokers, offset_reset)
+    }
+
     #[must_use]
     pub fn group_id(&self) -> &str {
         &self.group_id
@@ -122,6 +194,122 @@ impl KafkaConsumer {
         })
     }
 
+    /// Receive a message from the Kafka topic without deserializing its key and value.
+    pub async fn next_bytes(&self) -> Result<Option<KafkaMessage<Vec<u8>, Vec<u8>>>> {
+        let mut stream = Box::pin(self.stream_bytes());
+        stream.next().await.transpose()
+    }
+
+    /// Stream messages from the Kafka topic without deserializing their keys and values, e.g. for
+    /// formats that require a schema to be fetched before decoding.
+    pub fn stream_bytes(&self) -> impl Stream<Item = Result<KafkaMessage<Vec<u8>, Vec<u8>>>> {
+        self.consumer.stream().filter_map(move |msg| async move {
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(e) => return Some(Err(Error::UnableToReceiveMessage { source: e })),
+            };
+
+            let key = msg.key()?.to_vec();
+            let value = msg.payload()?.to_vec();
+
+            Some(Ok(KafkaMessage::new(&self.consumer, msg, key, value)))
+        })
+    }
+
+    /// Receive the payloads of up to `max` messages from the Kafka topic, e.g. to infer a schema.
+    ///
+    /// Waits up to `first_timeout` for the first payload, then stops once no further message is
+    /// received within `idle_timeout`. No payloads are returned if the topic has no message with a
+    /// payload within `first_timeout`.
+    pub async fn sample_payloads(
+        &self,
+        max: usize,
+        first_timeout: Duration,
+        idle_timeout: Duration,
+    ) -> Result<Vec<Vec<u8>>> {
+        let mut stream = Box::pin(self.stream_messages());
+        let mut payloads = Vec::new();
+        while payloads.len() < max {
+            let timeout = if payloads.is_empty() {
+                first_timeout
+            } else {
+                idle_timeout
+            };
+            let Ok(Some(msg)) = tokio::time::timeout(timeout, stream.next()).await else {
+                break;
+            };
+            if let Some(payload) = msg?.payload() {
+                payloads.push(payload.to_vec());
+            }
+        }
+        Ok(payloads)
+    }
+
+    /// Stream messages from the Kafka topic, including messages without a key or payload.
+    pub fn stream_messages(&self) -> impl Stream<Item = Result<BorrowedMessage<'_>>> {
+        self.consumer
+            .stream()
+            .map(|msg| msg.context(UnableToReceiveMessageSnafu))
+    }
+
+    /// Assigns all partitions of the topic to this consumer, starting at the first message at or
+    /// after `timestamp` (in milliseconds since the Unix epoch).
+    pub fn seek_to_timestamp(&self, topic: &str, timestamp: i64) -> Result<()> {
+        let metadata = self
+            .consumer
+            .fetch_metadata(Some(topic), Duration::from_secs(10))
+            .context(UnableToSeekToTimestampSnafu { topic, timestamp })?;
+
+        let topic_metadata = metadata
+            .topics()
+            .iter()
+            .find(|t| t.name() == topic)
+            .context(MetadataTopicNotFoundSnafu {
+                topic: topic.to_string(),
+            })?;
+
+        let mut partitions = TopicPartitionList::new();
+        for partition_metadata in topic_metadata.partitions() {
+            partitions
+                .add_partition_offset(topic, partition_metadata.id(), Offset::Offset(timestamp))
+                .context(UnableToSeekToTimestampSnafu { topic, timestamp })?;
+        }
+
+        // Partitions without a message after the timestamp are set to their end.
+        let offsets = self
+            .consumer
+            .offsets_for_times(partitions, Duration::from_secs(10))
+            .context(UnableToSeekToTimestampSnafu { topic, timestamp })?;
+
+        self.consumer
+            .assign(&offsets)
+            .context(UnableToSeekToTimestampSnafu { topic, timestamp })
+    }
+
+    /// The offsets to store once a batch of messages was processed, i.e. the offset after the last
+    /// message of each partition.
+    pub fn offsets_after(&self, messages: &[BorrowedMessage<'_>]) -> Result<KafkaOffsets<'_>> {
+        let mut offsets = TopicPartitionList::new();
+        for msg in messages {
+            // Messages of a partition are received in order
+            let next = Offset::Offset(msg.offset() + 1);
+            if offsets
+                .find_partition(msg.topic(), msg.partition())
+                .is_some()
+            {
+                offsets.set_partition_offset(msg.topic(), msg.partition(), next)
+            } else {
+                offsets.add_partition_offset(msg.topic(), msg.partition(), next)
+            }
+            .context(UnableToCommitMessageSnafu)?;
+        }
+
+        Ok(KafkaOffsets {
+            consumer: &self.consumer,
+            offsets,
+        })
+    }
+
     pub fn restart_topic(&self, topic: &str) -> Result<()> {
         let mut assignment = self
             .consumer
@@ -175,14 +363,22 @@ impl KafkaConsumer {
     }
 
     fn create(group_id: String, brokers: String) -> Result<Self> {
+        Self::create_with_offset_reset(group_id, brokers, "smallest")
+    }
+
+    fn create_with_offset_reset(
+        group_id: String,
+        brokers: String,
+        offset_reset: &str,
+    ) -> Result<Self> {
         let (_, version) = get_rdkafka_version();
         tracing::debug!("rd_kafka_version: {}", version);
 
         let consumer: StreamConsumer = ClientConfig::new()
             .set("group.id", group_id.clone())
             .set("bootstrap.servers", brokers)
-            // For new consumer groups, start reading at the beginning of the topic
-            .set("auto.offset.reset", "smallest")
+            // Where new consumer groups start reading the topic
+            .set("auto.offset.reset", offset_reset)
             // Commit offsets automatically
             .set("enable.auto.commit", "true")
             // Commit offsets every 5 seconds
@@ -242,3 +438,39 @@ impl<K, V> CommitChange for KafkaMessage<'_, K, V> {
         Ok(())
     }
 }
+
+/// The next offsets to consume for a batch of messages, stored once the batch was processed.
+pub struct KafkaOffsets<'a> {
+    consumer: &'a StreamConsumer,
+    offsets: TopicPartitionList,
+}
+
+impl CommitChange for KafkaOffsets<'_> {
+    fn commit(&self) -> Result<(), CommitError> {
+        self.consumer
+            .store_offsets(&self.offsets)
+            .context(UnableToCommitMessageSnafu)
+            .boxed()
+            .map_err(|e| cdc::CommitError::UnableToCommitChange { source: e })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_start_offset() {
+        assert_eq!("earliest".parse(), Ok(StartOffset::Earliest));
+        assert_eq!("latest".parse(), Ok(StartOffset::Latest));
+        assert_eq!(
+            "1700000000000".parse(),
+            Ok(StartOffset::Timestamp(1_700_000_000_000))
+        );
+        assert_eq!(
+            "2023-11-14T22:13:20Z".parse(),
+            Ok(StartOffset::Timestamp(1_700_000_000_000))
+        );
+        assert!("yesterday".parse::<StartOffset>().is_err());
+    }
+}
@@ -0,0 +1,337 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! An append-only table of the messages of a Kafka topic, e.g. an event stream.
+
+use crate::{
+    cdc::{self, AppendEnvelope, AppendStream},
+    debezium::{
+        self,
+        decoder::{self, ChangeEventDecoder, MessageFormat},
+    },
+    kafka::KafkaConsumer,
+};
+use arrow::{
+    array::{
+        ArrayRef, Int32Builder, Int64Builder, RecordBatch, StringBuilder,
+        TimestampMillisecondBuilder,
+    },
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    error::ArrowError,
+};
+use async_stream::stream;
+use async_trait::async_trait;
+use datafusion::{
+    datasource::{TableProvider, TableType},
+    error::Result as DataFusionResult,
+    execution::context::SessionState,
+    logical_expr::Expr,
+    physical_plan::{empty::EmptyExec, ExecutionPlan},
+};
+use futures::StreamExt;
+use rdkafka::message::{BorrowedMessage, Message};
+use snafu::prelude::*;
+use std::{any::Any, sync::Arc};
+
+pub const KEY_COLUMN: &str = "_kafka_key";
+pub const PARTITION_COLUMN: &str = "_kafka_partition";
+pub const OFFSET_COLUMN: &str = "_kafka_offset";
+pub const TIMESTAMP_COLUMN: &str = "_kafka_timestamp";
+
+/// Messages that were already received are appended in batches of up to this many messages.
+const MAX_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to decode Kafka message: {source}"))]
+    UnableToDecodeMessage { source: decoder::Error },
+
+    #[snafu(display("Unable to deserialize JSON message from Kafka: {source}"))]
+    UnableToDeserializeJson { source: serde_json::Error },
+
+    #[snafu(display("Unable to infer the schema of the JSON message: {source}"))]
+    UnableToInferSchema { source: ArrowError },
+
+    #[snafu(display("Unable to convert the message schema to Arrow: {source}"))]
+    UnableToConvertSchema { source: debezium::arrow::Error },
+
+    #[snafu(display("Unable to convert messages to Arrow: {source}"))]
+    UnableToConvertMessages { source: debezium::arrow::Error },
+
+    #[snafu(display("Unable to build record batch from messages: {source}"))]
+    UnableToBuildRecordBatch { source: ArrowError },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Infers the schema of the values of a topic from a sample of its messages.
+///
+/// JSON schemas are merged from all sampled messages. Avro and protobuf messages have a registry
+/// schema, so the schema of the latest sampled message is used.
+pub async fn infer_value_schema(
+    decoder: &ChangeEventDecoder,
+    payloads: &[Vec<u8>],
+) -> Result<Schema> {
+    if let Some(latest) = payloads.last() {
+        if let Some((schema, _, _)) = decoder
+            .decode_with_registry(latest)
+            .await
+            .context(UnableToDecodeMessageSnafu)?
+        {
+            return debezium::arrow::convert_fields_to_arrow_schema(schema.fields.iter().collect())
+                .context(UnableToConvertSchemaSnafu);
+        }
+    }
+
+    let values = payloads
+        .iter()
+        .map(|payload| serde_json::from_slice(payload).context(UnableToDeserializeJsonSnafu))
+        .collect::<Result<Vec<serde_json::Value>>>()?;
+    arrow::json::reader::infer_json_schema_from_iterator(values.into_iter().map(Ok))
+        .context(UnableToInferSchemaSnafu)
+}
+
+pub struct KafkaTable {
+    schema: SchemaRef,
+    value_schema: SchemaRef,
+    consumer: &'static KafkaConsumer,
+    decoder: Arc<ChangeEventDecoder>,
+}
+
+impl KafkaTable {
+    /// Creates a table with the columns of `value_schema` followed by the key, partition, offset
+    /// and timestamp of each message.
+    #[must_use]
+    pub fn new(
+        value_schema: SchemaRef,
+        consumer: KafkaConsumer,
+        decoder: Arc<ChangeEventDecoder>,
+    ) -> Self {
+        let mut fields = value_schema.fields().to_vec();
+        fields.extend([
+            Arc::new(Field::new(KEY_COLUMN, DataType::Utf8, true)),
+            Arc::new(Field::new(PARTITION_COLUMN, DataType::Int32, false)),
+            Arc::new(Field::new(OFFSET_COLUMN, DataType::Int64, false)),
+            Arc::new(Field::new(
+                TIMESTAMP_COLUMN,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                true,
+            )),
+        ]);
+
+        Self {
+            schema: Arc::new(Schema::new(fields)),
+            value_schema,
+            consumer: Box::leak(Box::new(consumer)),
+            decoder,
+        }
+    }
+
+    /// Streams the messages of the topic as record batches. The offsets of a batch are committed
+    /// once it is appended to the dataset.
+    ///
+    /// Messages are decoded one by one. The stream ends after the error of a message that can't
+    /// be received or decoded, so that no offset past it is committed. The messages received
+    /// before it are still appended.
+    #[must_use]
+    pub fn stream_appends(&self) -> AppendStream {
+        let consumer = self.consumer;
+        let schema = Arc::clone(&self.schema);
+        let value_schema = Arc::clone(&self.value_schema);
+        let decoder = Arc::clone(&self.decoder);
+
+        Box::pin(stream! {
+            let mut chunks = consumer.stream_messages().ready_chunks(MAX_BATCH_SIZE);
+            while let Some(chunk) = chunks.next().await {
+                let mut messages = Vec::with_capacity(chunk.len());
+                let mut values = Vec::with_capacity(chunk.len());
+                let mut error = None;
+
+                for msg in chunk {
+                    let msg = match msg {
+                        Ok(msg) => msg,
+                        Err(e) => {
+                            error = Some(cdc::StreamError::Kafka(e.to_string()));
+                            break;
+                        }
+                    };
+
+                    match decode_value(&decoder, &msg).await {
+                        Ok(value) => {
+                            messages.push(msg);
+                            values.push(value);
+                        }
+                        Err(e) => {
+                            error = Some(cdc::StreamError::Decode(format!(
+                                "{e} (partition {}, offset {})",
+                                msg.partition(),
+                                msg.offset()
+                            )));
+                            break;
+                        }
+                    }
+                }
+
+                if !messages.is_empty() {
+                    let envelope =
+                        to_record_batch(&schema, &value_schema, &decoder, &messages, values)
+                            .map_err(|e| cdc::StreamError::Decode(e.to_string()))
+                            .and_then(|record_batch| {
+                                let offsets = consumer
+                                    .offsets_after(&messages)
+                                    .map_err(|e| cdc::StreamError::Kafka(e.to_string()))?;
+                                Ok(AppendEnvelope::new(Box::new(offsets), record_batch))
+                            });
+                    let failed = envelope.is_err();
+                    yield envelope;
+                    if failed {
+                        return;
+                    }
+                }
+
+                if let Some(error) = error {
+                    yield Err(error);
+                    return;
+                }
+            }
+        })
+    }
+}
+
+/// Decodes the value of a message, or returns `None` for tombstones.
+async fn decode_value(
+    decoder: &ChangeEventDecoder,
+    msg: &BorrowedMessage<'_>,
+) -> Result<Option<serde_json::Value>> {
+    let Some(payload) = msg.payload() else {
+        return Ok(None);
+    };
+
+    let value = match decoder
+        .decode_with_registry(payload)
+        .await
+        .context(UnableToDecodeMessageSnafu)?
+    {
+        Some((_, value, _)) => value,
+        None => serde_json::from_slice(payload).context(UnableToDeserializeJsonSnafu)?,
+    };
+    Ok(Some(value))
+}
+
+fn to_record_batch(
+    schema: &SchemaRef,
+    value_schema: &SchemaRef,
+    decoder: &ChangeEventDecoder,
+    messages: &[BorrowedMessage<'_>],
+    values: Vec<Option<serde_json::Value>>,
+) -> Result<RecordBatch> {
+    let mut keys = StringBuilder::new();
+    let mut partitions = Int32Builder::new();
+    let mut offsets = Int64Builder::new();
+    let mut timestamps = TimestampMillisecondBuilder::new();
+
+    // Tombstones have no value to append, but their offsets are still committed
+    let values = messages
+        .iter()
+        .zip(values)
+        .filter_map(|(msg, value)| {
+            let value = value?;
+            keys.append_option(msg.key().map(String::from_utf8_lossy));
+            partitions.append_value(msg.partition());
+            offsets.append_value(msg.offset());
+            timestamps.append_option(msg.timestamp().to_millis());
+            Some(value)
+        })
+        .collect::<Vec<_>>();
+
+    let values = match decoder.message_format() {
+        MessageFormat::Json => {
+            let mut json_decoder = arrow::json::ReaderBuilder::new(Arc::clone(value_schema))
+                .build_decoder()
+                .context(UnableToBuildRecordBatchSnafu)?;
+            json_decoder
+                .serialize(&values)
+                .context(UnableToBuildRecordBatchSnafu)?;
+            json_decoder
+                .flush()
+                .context(UnableToBuildRecordBatchSnafu)?
+                .unwrap_or_else(|| RecordBatch::new_empty(Arc::clone(value_schema)))
+        }
+        MessageFormat::Avro | MessageFormat::Protobuf => {
+            debezium::arrow::to_record_batch(values, value_schema)
+                .context(UnableToConvertMessagesSnafu)?
+        }
+    };
+
+    let mut columns: Vec<ArrayRef> = values.columns().to_vec();
+    columns.push(Arc::new(keys.finish()));
+    columns.push(Arc::new(partitions.finish()));
+    columns.push(Arc::new(offsets.finish()));
+    columns.push(Arc::new(timestamps.finish()));
+
+    RecordBatch::try_new(Arc::clone(schema), columns).context(UnableToBuildRecordBatchSnafu)
+}
+
+#[async_trait]
+impl TableProvider for KafkaTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        _projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(EmptyExec::new(Arc::clone(&self.schema))) as Arc<dyn ExecutionPlan>)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_infer_json_value_schema() {
+        let decoder = ChangeEventDecoder::try_new(MessageFormat::Json, None).expect("JSON decoder");
+        let payloads = [
+            br#"{"id": 1, "name": "a"}"#.to_vec(),
+            br#"{"id": 2, "name": "b", "price": 1.5}"#.to_vec(),
+        ];
+        let schema = infer_value_schema(&decoder, &payloads)
+            .await
+            .expect("inferred schema");
+
+        assert_eq!(
+            schema,
+            Schema::new(vec![
+                Field::new("id", DataType::Int64, true),
+                Field::new("name", DataType::Utf8, true),
+                Field::new("price", DataType::Float64, true),
+            ])
+        );
+    }
+}
@@ -41,6 +41,8 @@ pub mod flight;
 pub mod flightsql;
 #[cfg(feature = "debezium")]
 pub mod kafka;
+#[cfg(feature = "debezium")]
+pub mod kafka_table;
 #[cfg(feature = "mysql")]
 pub mod mysql;
 #[cfg(feature = "odbc")]
@@ -22,6 +22,8 @@ use std::sync::Arc;
 
 use crate::Read;
 
+pub mod binlog;
+
 #[async_trait]
 impl Read for MySQLTableFactory {
     async fn table_provider(
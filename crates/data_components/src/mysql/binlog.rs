@@ -0,0 +1,1212 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Streams the changes of a `MySQL` table from the row-based binary log, as a replica would.
+//!
+//! The server needs `binlog_format = ROW` and `binlog_row_image = FULL`. Positions are tracked by
+//! GTID when the server has `gtid_mode = ON`, so they survive a failover, and by binary log file
+//! and offset otherwise. `ENUM` and `SET` columns are replicated as their numeric index.
+
+use crate::cdc::{
+    self, ChangeBatch, ChangeEnvelope, ChangeOperation, ChangesStream, CommitChange, CommitError,
+    StreamError,
+};
+use arrow::{
+    array::{
+        new_null_array, ArrayRef, BinaryArray, Float32Array, Float64Array, Int64Array, RecordBatch,
+        StringArray, TimestampMicrosecondArray, UInt64Array,
+    },
+    compute::{cast_with_options, CastOptions},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    error::ArrowError,
+};
+use async_stream::stream;
+use async_trait::async_trait;
+use datafusion::{
+    common::{Constraints, DFSchema},
+    datasource::{TableProvider, TableType},
+    error::{DataFusionError, Result as DataFusionResult},
+    execution::{context::SessionState, SendableRecordBatchStream},
+    logical_expr::{Expr, TableProviderFilterPushDown},
+    physical_plan::ExecutionPlan,
+    prelude::SessionContext,
+    sql::{
+        sqlparser::ast::{Ident, TableConstraint},
+        TableReference,
+    },
+};
+use futures::StreamExt;
+use mysql_async::{
+    binlog::{
+        events::{EventData, RowsEventData},
+        row::BinlogRow,
+        value::BinlogValue,
+    },
+    prelude::Queryable,
+    BinlogStream, BinlogStreamRequest, Conn, Opts, OptsBuilder, Row, Sid, SslOpts, Value,
+};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+    fmt::{Display, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often the confirmed binary log position is checkpointed.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid MySQL connection string: {source}"))]
+    InvalidConnectionString { source: mysql_async::UrlError },
+
+    #[snafu(display("No database is specified for the table {table}. Set the 'db' parameter or qualify the table name."))]
+    MissingDatabase { table: String },
+
+    #[snafu(display("Unable to connect to MySQL: {source}"))]
+    UnableToConnect { source: mysql_async::Error },
+
+    #[snafu(display("Binary log query failed: {source}"))]
+    QueryFailed { source: mysql_async::Error },
+
+    #[snafu(display("Unable to read the binary log status. Is the binary log enabled?"))]
+    BinlogStatusUnavailable,
+
+    #[snafu(display(
+        "Streaming changes requires '{setting}' to be '{expected}', but it is '{value}'"
+    ))]
+    UnsupportedBinlogSetting {
+        setting: String,
+        value: String,
+        expected: String,
+    },
+
+    #[snafu(display("Invalid GTID set '{gtid_set}'"))]
+    InvalidGtidSet { gtid_set: String },
+
+    #[snafu(display("Unable to read the binary log: {source}"))]
+    UnableToReadBinlog { source: mysql_async::Error },
+
+    #[snafu(display("Unable to decode binary log event: {source}"))]
+    UnableToDecodeEvent { source: std::io::Error },
+
+    #[snafu(display("Unable to convert column '{column}' to {data_type}: {source}"))]
+    UnableToConvertColumn {
+        column: String,
+        data_type: DataType,
+        source: ArrowError,
+    },
+
+    #[snafu(display("Unable to convert replicated rows: {source}"))]
+    UnableToConvertRows { source: ArrowError },
+
+    #[snafu(display("{source}"))]
+    UnableToBuildChangeBatch { source: cdc::ChangeBatchError },
+
+    #[snafu(display("Unable to snapshot the table: {source}"))]
+    UnableToSnapshotTable { source: DataFusionError },
+
+    #[snafu(display("Unable to save the binary log checkpoint: {source}"))]
+    UnableToSaveCheckpoint {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<Error> for StreamError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::UnableToDecodeEvent { .. }
+            | Error::UnableToConvertColumn { .. }
+            | Error::UnableToConvertRows { .. }
+            | Error::UnableToBuildChangeBatch { .. } => StreamError::Decode(e.to_string()),
+            _ => StreamError::MySql(e.to_string()),
+        }
+    }
+}
+
+/// A position in the binary log. Streams resume after the GTID set if there is one, or after the
+/// file offset otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinlogPosition {
+    pub filename: String,
+    pub position: u64,
+    pub gtid_set: Option<String>,
+}
+
+/// Persists the binary log position up to which changes were written, so a restarted stream
+/// resumes after it.
+#[async_trait]
+pub trait BinlogCheckpoint: Send + Sync {
+    /// The position saved by the last checkpoint, if any.
+    async fn load(&self) -> Option<BinlogPosition>;
+
+    async fn save(
+        &self,
+        position: &BinlogPosition,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// A set of GTIDs, e.g. `3e11fa47-71ca-11e1-9e33-c80aa9429562:1-5:7`, as a list of inclusive
+/// transaction ranges for each source UUID.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GtidSet(BTreeMap<String, Vec<(u64, u64)>>);
+
+impl GtidSet {
+    /// Adds a transaction, merging it with adjacent ranges.
+    pub fn add(&mut self, uuid: &str, gno: u64) {
+        let ranges = self.0.entry(uuid.to_lowercase()).or_default();
+        ranges.push((gno, gno));
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+        for &(start, end) in ranges.iter() {
+            match merged.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        *ranges = merged;
+    }
+
+    fn sids(&self) -> Result<Vec<Sid<'static>>> {
+        self.to_string()
+            .split(',')
+            .filter(|sid| !sid.is_empty())
+            .map(|sid| {
+                Sid::from_str(sid).ok().context(InvalidGtidSetSnafu {
+                    gtid_set: sid.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl FromStr for GtidSet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || InvalidGtidSetSnafu {
+            gtid_set: s.to_string(),
+        };
+
+        let mut gtid_set = Self::default();
+        for sid in s.split(',').map(str::trim).filter(|sid| !sid.is_empty()) {
+            let mut parts = sid.split(':');
+            let uuid = parts.next().context(invalid())?.to_lowercase();
+            let ranges = gtid_set.0.entry(uuid).or_default();
+            for range in parts {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                let start = start.parse().ok().with_context(invalid)?;
+                let end = end.parse().ok().with_context(invalid)?;
+                ranges.push((start, end));
+            }
+        }
+
+        Ok(gtid_set)
+    }
+}
+
+impl Display for GtidSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (uuid, ranges)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{uuid}")?;
+            for (start, end) in ranges {
+                if start == end {
+                    write!(f, ":{start}")?;
+                } else {
+                    write!(f, ":{start}-{end}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_uuid(bytes: [u8; 16]) -> String {
+    let hex = bytes.iter().fold(String::with_capacity(32), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    });
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Builds the connection options from the parameters of the `mysql` connector.
+#[allow(clippy::implicit_hasher)]
+pub fn connection_opts(params: &HashMap<String, SecretString>) -> Result<Opts> {
+    let param = |name: &str| params.get(name).map(|value| value.expose_secret().clone());
+
+    let builder = if let Some(connection_string) = param("connection_string") {
+        OptsBuilder::from_opts(
+            Opts::from_url(&connection_string).context(InvalidConnectionStringSnafu)?,
+        )
+    } else {
+        let mut builder = OptsBuilder::default()
+            .user(param("user"))
+            .pass(param("pass"))
+            .db_name(param("db"));
+        if let Some(host) = param("host") {
+            builder = builder.ip_or_hostname(host);
+        }
+        if let Some(port) = param("tcp_port").and_then(|port| port.parse().ok()) {
+            builder = builder.tcp_port(port);
+        }
+        builder
+    };
+
+    let ssl_opts = match param("sslmode").as_deref() {
+        Some("disabled") => None,
+        sslmode => {
+            let mut ssl_opts =
+                SslOpts::default().with_danger_accept_invalid_certs(sslmode == Some("preferred"));
+            if let Some(root_cert) = param("sslrootcert") {
+                ssl_opts = ssl_opts.with_root_certs(vec![PathBuf::from(root_cert).into()]);
+            }
+            Some(ssl_opts)
+        }
+    };
+
+    Ok(builder.ssl_opts(ssl_opts).into())
+}
+
+/// A replica server ID for a dataset when none is configured. It has to be unique among the
+/// replicas of the server.
+#[must_use]
+pub fn default_server_id(dataset_name: &str) -> u32 {
+    // FNV-1a, in the upper half of the ID range to avoid the IDs usually given to servers.
+    let hash = dataset_name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    hash | 0x8000_0000
+}
+
+/// The columns of the primary key of a `MySQL` table, in key order.
+pub async fn primary_keys(opts: &Opts, table_reference: &TableReference) -> Result<Vec<String>> {
+    let (database, table) = database_and_table(opts, table_reference)?;
+    let mut conn = Conn::new(opts.clone())
+        .await
+        .context(UnableToConnectSnafu)?;
+    let primary_keys = conn
+        .exec(
+            "SELECT COLUMN_NAME FROM information_schema.KEY_COLUMN_USAGE \
+             WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ? AND CONSTRAINT_NAME = 'PRIMARY' \
+             ORDER BY ORDINAL_POSITION",
+            (database, table),
+        )
+        .await
+        .context(QueryFailedSnafu)?;
+    conn.disconnect().await.ok();
+
+    Ok(primary_keys)
+}
+
+fn database_and_table(opts: &Opts, table_reference: &TableReference) -> Result<(String, String)> {
+    let database = match table_reference.schema() {
+        Some(schema) => schema.to_string(),
+        None => opts
+            .db_name()
+            .context(MissingDatabaseSnafu {
+                table: table_reference.to_string(),
+            })?
+            .to_string(),
+    };
+
+    Ok((database, table_reference.table().to_string()))
+}
+
+/// Reads the changes of a table from the binary log.
+#[derive(Clone)]
+pub struct Binlog {
+    opts: Opts,
+    database: String,
+    table: String,
+    server_id: u32,
+    checkpoint: Arc<dyn BinlogCheckpoint>,
+}
+
+/// Where a changes stream starts to read the binary log.
+enum StartPosition {
+    /// After the position of the last checkpoint.
+    Checkpoint(BinlogPosition),
+    /// After the current position of the binary log, once the table was read in full.
+    Snapshot(BinlogPosition),
+}
+
+impl Binlog {
+    pub fn try_new(
+        opts: Opts,
+        table_reference: &TableReference,
+        server_id: u32,
+        checkpoint: Arc<dyn BinlogCheckpoint>,
+    ) -> Result<Self> {
+        let (database, table) = database_and_table(&opts, table_reference)?;
+
+        Ok(Self {
+            opts,
+            database,
+            table,
+            server_id,
+            checkpoint,
+        })
+    }
+
+    async fn connect(&self) -> Result<Conn> {
+        Conn::new(self.opts.clone())
+            .await
+            .context(UnableToConnectSnafu)
+    }
+
+    async fn start_position(&self) -> Result<StartPosition> {
+        let mut conn = self.connect().await?;
+        for (setting, expected) in [("binlog_format", "ROW"), ("binlog_row_image", "FULL")] {
+            let value: Option<String> = conn
+                .query_first(format!("SELECT @@GLOBAL.{setting}"))
+                .await
+                .context(QueryFailedSnafu)?;
+            let value = value.unwrap_or_default();
+            ensure!(
+                value.eq_ignore_ascii_case(expected),
+                UnsupportedBinlogSettingSnafu {
+                    setting,
+                    value,
+                    expected,
+                }
+            );
+        }
+
+        if let Some(position) = self.checkpoint.load().await {
+            conn.disconnect().await.ok();
+            return Ok(StartPosition::Checkpoint(position));
+        }
+
+        let position = current_position(&mut conn).await?;
+        conn.disconnect().await.ok();
+
+        Ok(StartPosition::Snapshot(position))
+    }
+
+    /// The names of the columns of the table, in the order of the binary log row images.
+    async fn columns(&self) -> Result<Vec<String>> {
+        let mut conn = self.connect().await?;
+        let columns = conn
+            .exec(
+                "SELECT COLUMN_NAME FROM information_schema.COLUMNS \
+                 WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ? ORDER BY ORDINAL_POSITION",
+                (&self.database, &self.table),
+            )
+            .await
+            .context(QueryFailedSnafu)?;
+        conn.disconnect().await.ok();
+
+        Ok(columns)
+    }
+
+    async fn binlog_stream(&self, position: &StreamPosition) -> Result<BinlogStream> {
+        let conn = self.connect().await?;
+        let request = BinlogStreamRequest::new(self.server_id);
+        let request = match &position.gtid_set {
+            Some(gtid_set) => request.with_gtid().with_gtid_set(gtid_set.sids()?),
+            None => request
+                .with_filename(position.filename.as_bytes())
+                .with_pos(position.position),
+        };
+
+        conn.get_binlog_stream(request)
+            .await
+            .context(UnableToReadBinlogSnafu)
+    }
+
+    async fn confirm(&self, position: &BinlogPosition) -> Result<()> {
+        self.checkpoint
+            .save(position)
+            .await
+            .context(UnableToSaveCheckpointSnafu)
+    }
+}
+
+/// The current position of the binary log, including the executed GTIDs if the server uses them.
+async fn current_position(conn: &mut Conn) -> Result<BinlogPosition> {
+    // `SHOW MASTER STATUS` was renamed in MySQL 8.2
+    let status: Option<Row> = match conn.query_first("SHOW BINARY LOG STATUS").await {
+        Ok(status) => status,
+        Err(_) => conn
+            .query_first("SHOW MASTER STATUS")
+            .await
+            .context(QueryFailedSnafu)?,
+    };
+    let status = status.context(BinlogStatusUnavailableSnafu)?;
+
+    let filename: String = status.get("File").context(BinlogStatusUnavailableSnafu)?;
+    let position: u64 = status
+        .get("Position")
+        .context(BinlogStatusUnavailableSnafu)?;
+
+    // MariaDB has a different GTID format, and is tracked by file offset.
+    let gtid_mode: Option<String> = conn
+        .query_first("SELECT @@GLOBAL.gtid_mode")
+        .await
+        .unwrap_or_default();
+    let gtid_set = match gtid_mode.as_deref() {
+        Some("ON") => status.get::<String, _>("Executed_Gtid_Set"),
+        _ => None,
+    };
+
+    Ok(BinlogPosition {
+        filename,
+        position,
+        gtid_set,
+    })
+}
+
+/// The position of a stream, with the GTID set parsed to add the transactions as they're read.
+#[derive(Clone)]
+struct StreamPosition {
+    filename: String,
+    position: u64,
+    gtid_set: Option<GtidSet>,
+}
+
+impl TryFrom<BinlogPosition> for StreamPosition {
+    type Error = Error;
+
+    fn try_from(position: BinlogPosition) -> Result<Self> {
+        Ok(Self {
+            filename: position.filename,
+            position: position.position,
+            gtid_set: position.gtid_set.as_deref().map(str::parse).transpose()?,
+        })
+    }
+}
+
+impl From<&StreamPosition> for BinlogPosition {
+    fn from(position: &StreamPosition) -> Self {
+        Self {
+            filename: position.filename.clone(),
+            position: position.position,
+            gtid_set: position.gtid_set.as_ref().map(ToString::to_string),
+        }
+    }
+}
+
+/// A `MySQL` table whose changes are streamed from the binary log.
+///
+/// All columns are nullable, like the table of a Postgres replication stream.
+pub struct MySQLBinlogTable {
+    base_table: Arc<dyn TableProvider>,
+    schema: SchemaRef,
+    primary_keys: Vec<String>,
+    constraints: Option<Constraints>,
+    binlog: Binlog,
+}
+
+impl MySQLBinlogTable {
+    #[must_use]
+    pub fn new(
+        base_table: Arc<dyn TableProvider>,
+        primary_keys: Vec<String>,
+        binlog: Binlog,
+    ) -> Self {
+        let base_schema = base_table.schema();
+        let schema = Arc::new(Schema::new(
+            base_schema
+                .fields()
+                .iter()
+                .map(|field| field.as_ref().clone().with_nullable(true))
+                .collect::<Vec<Field>>(),
+        ));
+
+        let Ok(df_schema) = DFSchema::try_from(Arc::clone(&schema)) else {
+            unreachable!("DFSchema::try_from is infallible as of DataFusion 38")
+        };
+        let constraints = Constraints::new_from_table_constraints(
+            &[TableConstraint::PrimaryKey {
+                name: None,
+                index_name: None,
+                index_type: None,
+                columns: primary_keys
+                    .iter()
+                    .map(|col| Ident::new(col.clone()))
+                    .collect(),
+                index_options: vec![],
+                characteristics: None,
+            }],
+            &Arc::new(df_schema),
+        )
+        .ok();
+
+        Self {
+            base_table,
+            schema,
+            primary_keys,
+            constraints,
+            binlog,
+        }
+    }
+
+    /// Streams the changes of the table. A stream without a checkpoint first reads the whole
+    /// table as `Read` changes.
+    ///
+    /// The table is read after the binary log position is taken, so the changes replayed from
+    /// the binary log may already be part of the snapshot. Replaying them by primary key
+    /// converges to the same rows.
+    #[must_use]
+    #[allow(clippy::too_many_lines)]
+    pub fn stream_changes(&self) -> ChangesStream {
+        let base_table = Arc::clone(&self.base_table);
+        let schema = Arc::clone(&self.schema);
+        let primary_keys = self.primary_keys.clone();
+        let binlog = self.binlog.clone();
+
+        Box::pin(stream! {
+            // The position after the last transaction whose changes were all written.
+            let confirmed: Arc<Mutex<Option<BinlogPosition>>> = Arc::new(Mutex::new(None));
+
+            // The position after the last transaction sent downstream.
+            let mut position = loop {
+                let start_position = match binlog.start_position().await {
+                    Ok(start_position) => start_position,
+                    Err(e) => {
+                        yield Err(StreamError::from(e));
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                        continue;
+                    }
+                };
+
+                let position = match start_position {
+                    StartPosition::Checkpoint(position) => position,
+                    StartPosition::Snapshot(position) => {
+                        // The position is only confirmed once every batch of the snapshot was written.
+                        let written = Arc::new(AtomicUsize::new(0));
+                        let mut num_batches = 0;
+                        let mut snapshot_complete = true;
+                        match snapshot(&base_table).await {
+                            Ok(mut batches) => {
+                                while let Some(batch) = batches.next().await {
+                                    let change = batch
+                                        .context(UnableToSnapshotTableSnafu)
+                                        .and_then(|batch| {
+                                            RecordBatch::try_new(Arc::clone(&schema), batch.columns().to_vec())
+                                                .context(UnableToConvertRowsSnafu)
+                                        })
+                                        .and_then(|batch| {
+                                            ChangeBatch::try_from_rows(&ChangeOperation::Read, &primary_keys, batch)
+                                                .context(UnableToBuildChangeBatchSnafu)
+                                        });
+                                    match change {
+                                        Ok(change) => {
+                                            num_batches += 1;
+                                            yield Ok(ChangeEnvelope::new(
+                                                Box::new(SnapshotCommitter::new(&written)),
+                                                change,
+                                            ));
+                                        }
+                                        Err(e) => {
+                                            snapshot_complete = false;
+                                            yield Err(StreamError::from(e));
+                                            break;
+                                        }
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                snapshot_complete = false;
+                                yield Err(StreamError::from(e));
+                            }
+                        }
+
+                        // The table is read again from a new position.
+                        if !snapshot_complete || written.load(Ordering::SeqCst) != num_batches {
+                            tokio::time::sleep(RETRY_INTERVAL).await;
+                            continue;
+                        }
+                        PositionCommitter::new(Some(position.clone()), &confirmed).confirm();
+                        position
+                    }
+                };
+
+                match StreamPosition::try_from(position) {
+                    Ok(position) => break position,
+                    Err(e) => {
+                        yield Err(StreamError::from(e));
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                    }
+                }
+            };
+
+            // The position after the last transaction whose changes were sent downstream, until
+            // they were written.
+            let mut unwritten: Option<BinlogPosition> = None;
+            let mut checkpointed: Option<BinlogPosition> = None;
+            let mut last_checkpoint = Instant::now();
+            loop {
+                let columns = match binlog.columns().await {
+                    Ok(columns) => columns,
+                    Err(e) => {
+                        yield Err(StreamError::from(e));
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                        continue;
+                    }
+                };
+
+                let mut binlog_stream = match binlog.binlog_stream(&position).await {
+                    Ok(binlog_stream) => binlog_stream,
+                    Err(e) => {
+                        yield Err(StreamError::from(e));
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                        continue;
+                    }
+                };
+
+                let mut transaction: Vec<Result<ChangeBatch>> = Vec::new();
+                let mut transaction_gtid: Option<(String, u64)> = None;
+                loop {
+                    let event = tokio::time::timeout(CHECKPOINT_INTERVAL, binlog_stream.next()).await;
+
+                    let confirmed_position = confirmed.lock().ok().and_then(|confirmed| confirmed.clone());
+                    if confirmed_position != checkpointed
+                        && (event.is_err() || last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL)
+                    {
+                        if let Some(confirmed_position) = &confirmed_position {
+                            match binlog.confirm(confirmed_position).await {
+                                Ok(()) => checkpointed = Some(confirmed_position.clone()),
+                                Err(e) => yield Err(StreamError::from(e)),
+                            }
+                        }
+                        last_checkpoint = Instant::now();
+                    }
+
+                    let event = match event {
+                        Ok(Some(Ok(event))) => event,
+                        // No events were written in the checkpoint interval.
+                        Err(_) => continue,
+                        Ok(Some(Err(e))) => {
+                            yield Err(StreamError::from(Error::UnableToReadBinlog { source: e }));
+                            break;
+                        }
+                        Ok(None) => break,
+                    };
+
+                    let committed = match event.read_data() {
+                        Ok(Some(EventData::RotateEvent(rotate_event))) => {
+                            position.filename = rotate_event.name().into_owned();
+                            position.position = rotate_event.position();
+                            false
+                        }
+                        Ok(Some(EventData::GtidEvent(gtid_event))) => {
+                            transaction_gtid = Some((format_uuid(gtid_event.sid()), gtid_event.gno()));
+                            transaction.clear();
+                            false
+                        }
+                        Ok(Some(EventData::QueryEvent(query_event))) => {
+                            // DDL statements are transactions of their own.
+                            if query_event.query() == "BEGIN" {
+                                transaction.clear();
+                                false
+                            } else {
+                                true
+                            }
+                        }
+                        Ok(Some(EventData::XidEvent(_))) => true,
+                        Ok(Some(EventData::RowsEvent(rows_event))) => {
+                            if let Some(table_map_event) = binlog_stream.get_tme(rows_event.table_id()) {
+                                if table_map_event.database_name() == binlog.database.as_str()
+                                    && table_map_event.table_name() == binlog.table.as_str()
+                                {
+                                    let rows = rows_event.rows(table_map_event).map(|rows| rows.context(UnableToDecodeEventSnafu));
+                                    for rows in rows {
+                                        transaction.extend(match rows {
+                                            Ok((before, after)) => to_change_batches(&schema, &primary_keys, &columns, &rows_event, before, after),
+                                            Err(e) => vec![Err(e)],
+                                        });
+                                    }
+                                }
+                            }
+                            false
+                        }
+                        Ok(_) => false,
+                        Err(e) => {
+                            transaction.push(Err(Error::UnableToDecodeEvent { source: e }));
+                            false
+                        }
+                    };
+
+                    if !committed {
+                        continue;
+                    }
+
+                    position.position = u64::from(event.header().log_pos());
+                    if let (Some(gtid_set), Some((uuid, gno))) = (&mut position.gtid_set, transaction_gtid.take()) {
+                        gtid_set.add(&uuid, gno);
+                    }
+                    let transaction_position = BinlogPosition::from(&position);
+
+                    // Only the last change of a transaction confirms it.
+                    let num_changes = transaction.len();
+                    if num_changes == 0 {
+                        // Transactions without changes of the table are confirmed as they are, once
+                        // the changes sent before them were written.
+                        let confirmed_position = confirmed.lock().ok().and_then(|confirmed| confirmed.clone());
+                        if unwritten.is_none() || unwritten == confirmed_position {
+                            unwritten = None;
+                            PositionCommitter::new(Some(transaction_position), &confirmed).confirm();
+                        }
+                        continue;
+                    }
+                    unwritten = Some(transaction_position.clone());
+                    let mut envelopes = Vec::with_capacity(num_changes);
+                    for (i, change) in transaction.drain(..).enumerate() {
+                        let commit_position = (i + 1 == num_changes).then(|| transaction_position.clone());
+                        envelopes.push(change.map(|change| {
+                            ChangeEnvelope::new(Box::new(PositionCommitter::new(commit_position, &confirmed)), change)
+                        }));
+                    }
+                    for envelope in envelopes {
+                        yield envelope.map_err(StreamError::from);
+                    }
+                }
+
+                // The connection was closed, resume after the last transaction sent downstream.
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        })
+    }
+}
+
+async fn snapshot(base_table: &Arc<dyn TableProvider>) -> Result<SendableRecordBatchStream> {
+    SessionContext::new()
+        .read_table(Arc::clone(base_table))
+        .context(UnableToSnapshotTableSnafu)?
+        .execute_stream()
+        .await
+        .context(UnableToSnapshotTableSnafu)
+}
+
+/// Converts a changed row of the table to change batches. An update of the primary key is
+/// converted to a delete of the old row followed by an update.
+fn to_change_batches(
+    schema: &SchemaRef,
+    primary_keys: &[String],
+    columns: &[String],
+    rows_event: &RowsEventData<'_>,
+    before: Option<BinlogRow>,
+    after: Option<BinlogRow>,
+) -> Vec<Result<ChangeBatch>> {
+    match (rows_event, before, after) {
+        (RowsEventData::WriteRowsEvent(_) | RowsEventData::WriteRowsEventV1(_), _, Some(after)) => {
+            vec![to_change_batch(
+                schema,
+                primary_keys,
+                columns,
+                &ChangeOperation::Create,
+                &after,
+            )]
+        }
+        (
+            RowsEventData::DeleteRowsEvent(_) | RowsEventData::DeleteRowsEventV1(_),
+            Some(before),
+            _,
+        ) => vec![to_change_batch(
+            schema,
+            primary_keys,
+            columns,
+            &ChangeOperation::Delete,
+            &before,
+        )],
+        (
+            RowsEventData::UpdateRowsEvent(_)
+            | RowsEventData::UpdateRowsEventV1(_)
+            | RowsEventData::PartialUpdateRowsEvent(_),
+            before,
+            Some(after),
+        ) => {
+            let key_changed = before.as_ref().is_some_and(|before| {
+                primary_keys.iter().any(|key| {
+                    columns
+                        .iter()
+                        .position(|column| column == key)
+                        .is_some_and(|i| before.as_ref(i) != after.as_ref(i))
+                })
+            });
+
+            let mut changes = vec![];
+            if let (true, Some(before)) = (key_changed, before) {
+                changes.push(to_change_batch(
+                    schema,
+                    primary_keys,
+                    columns,
+                    &ChangeOperation::Delete,
+                    &before,
+                ));
+            }
+            changes.push(to_change_batch(
+                schema,
+                primary_keys,
+                columns,
+                &ChangeOperation::Update,
+                &after,
+            ));
+            changes
+        }
+        _ => vec![],
+    }
+}
+
+/// Converts a row image of the binary log to a change batch. Columns that aren't part of the
+/// image are null.
+fn to_change_batch(
+    schema: &SchemaRef,
+    primary_keys: &[String],
+    columns: &[String],
+    op: &ChangeOperation,
+    row: &BinlogRow,
+) -> Result<ChangeBatch> {
+    let arrays = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let value = columns
+                .iter()
+                .position(|column| column == field.name())
+                .and_then(|i| row.as_ref(i));
+            to_array(value, field)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let data =
+        RecordBatch::try_new(Arc::clone(schema), arrays).context(UnableToConvertRowsSnafu)?;
+
+    ChangeBatch::try_from_rows(op, primary_keys, data).context(UnableToBuildChangeBatchSnafu)
+}
+
+fn to_array(value: Option<&BinlogValue<'_>>, field: &Field) -> Result<ArrayRef> {
+    let convert_context = |_: &mut ArrowError| UnableToConvertColumnSnafu {
+        column: field.name().clone(),
+        data_type: field.data_type().clone(),
+    };
+
+    let data_type = field.data_type();
+    let array: ArrayRef = match value {
+        None | Some(BinlogValue::Value(Value::NULL)) => return Ok(new_null_array(data_type, 1)),
+        Some(BinlogValue::Value(value)) => {
+            value_to_array(value, data_type).with_context(convert_context)?
+        }
+        Some(BinlogValue::Jsonb(value)) => {
+            let json = serde_json::Value::try_from(value.clone())
+                .map_err(|e| ArrowError::ParseError(e.to_string()))
+                .with_context(convert_context)?;
+            Arc::new(StringArray::from(vec![json.to_string()]))
+        }
+        Some(BinlogValue::JsonDiff(_)) => {
+            return Err(ArrowError::NotYetImplemented(
+                "Partial JSON updates aren't supported. Set 'binlog_row_value_options' to ''."
+                    .to_string(),
+            ))
+            .with_context(convert_context)
+        }
+    };
+
+    cast_with_options(
+        &array,
+        data_type,
+        &CastOptions {
+            safe: false,
+            ..CastOptions::default()
+        },
+    )
+    .with_context(convert_context)
+}
+
+/// Converts a binary log value to an array that can be cast to `data_type`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn value_to_array(value: &Value, data_type: &DataType) -> Result<ArrayRef, ArrowError> {
+    let array: ArrayRef = match (value, data_type) {
+        // `TIMESTAMP` columns are sent as seconds since the epoch.
+        (Value::Int(seconds), DataType::Timestamp(_, timezone)) => Arc::new(
+            TimestampMicrosecondArray::from(vec![seconds * 1_000_000])
+                .with_timezone_opt(timezone.clone()),
+        ),
+        (Value::Bytes(bytes), DataType::Timestamp(_, timezone)) if !bytes.contains(&b'-') => {
+            let text =
+                std::str::from_utf8(bytes).map_err(|e| ArrowError::ParseError(e.to_string()))?;
+            // The fraction has six digits, e.g. `1719792000.500000`.
+            let (seconds, fraction) = text.split_once('.').unwrap_or((text, ""));
+            let micros = format!("{fraction:0<6}");
+            let parse = |part: &str| {
+                part.parse::<i64>()
+                    .map_err(|e| ArrowError::ParseError(format!("Invalid timestamp '{text}': {e}")))
+            };
+            let micros = parse(micros.get(..6).unwrap_or_default())?;
+            Arc::new(
+                TimestampMicrosecondArray::from(vec![parse(seconds)? * 1_000_000 + micros])
+                    .with_timezone_opt(timezone.clone()),
+            )
+        }
+        // Unsigned integers are sent as signed integers of the column width.
+        (Value::Int(value), DataType::UInt8) => {
+            Arc::new(UInt64Array::from(vec![u64::from(*value as u8)]))
+        }
+        (Value::Int(value), DataType::UInt16) => {
+            Arc::new(UInt64Array::from(vec![u64::from(*value as u16)]))
+        }
+        (Value::Int(value), DataType::UInt32) => {
+            Arc::new(UInt64Array::from(vec![u64::from(*value as u32)]))
+        }
+        (Value::Int(value), DataType::UInt64) => Arc::new(UInt64Array::from(vec![*value as u64])),
+        (Value::Int(value), _) => Arc::new(Int64Array::from(vec![*value])),
+        (Value::UInt(value), _) => Arc::new(UInt64Array::from(vec![*value])),
+        (Value::Float(value), _) => Arc::new(Float32Array::from(vec![*value])),
+        (Value::Double(value), _) => Arc::new(Float64Array::from(vec![*value])),
+        (
+            Value::Bytes(bytes),
+            DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_),
+        ) => Arc::new(BinaryArray::from(vec![bytes.as_slice()])),
+        (Value::Bytes(bytes), _) => {
+            let text =
+                std::str::from_utf8(bytes).map_err(|e| ArrowError::ParseError(e.to_string()))?;
+            Arc::new(StringArray::from(vec![text]))
+        }
+        (Value::Date(year, month, day, ..), DataType::Date32 | DataType::Date64) => Arc::new(
+            StringArray::from(vec![format!("{year:04}-{month:02}-{day:02}")]),
+        ),
+        (Value::Date(year, month, day, hour, minute, second, micros), _) => {
+            Arc::new(StringArray::from(vec![format!(
+                "{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02}.{micros:06}"
+            )]))
+        }
+        (Value::Time(negative, days, hours, minutes, seconds, micros), _) => {
+            let sign = if *negative { "-" } else { "" };
+            let hours = days * 24 + u32::from(*hours);
+            Arc::new(StringArray::from(vec![format!(
+                "{sign}{hours:02}:{minutes:02}:{seconds:02}.{micros:06}"
+            )]))
+        }
+        (Value::NULL, _) => new_null_array(data_type, 1),
+    };
+
+    Ok(array)
+}
+
+/// Confirms the transaction of a change once it's written, if it's the last change of it.
+struct PositionCommitter {
+    position: Option<BinlogPosition>,
+    confirmed: Arc<Mutex<Option<BinlogPosition>>>,
+}
+
+impl PositionCommitter {
+    fn new(
+        position: Option<BinlogPosition>,
+        confirmed: &Arc<Mutex<Option<BinlogPosition>>>,
+    ) -> Self {
+        Self {
+            position,
+            confirmed: Arc::clone(confirmed),
+        }
+    }
+
+    fn confirm(&self) {
+        if let (Some(position), Ok(mut confirmed)) = (&self.position, self.confirmed.lock()) {
+            *confirmed = Some(position.clone());
+        }
+    }
+}
+
+impl CommitChange for PositionCommitter {
+    fn commit(&self) -> Result<(), CommitError> {
+        self.confirm();
+        Ok(())
+    }
+}
+
+/// Counts the snapshot batches that were written, so the position is only confirmed once all were.
+struct SnapshotCommitter {
+    written: Arc<AtomicUsize>,
+}
+
+impl SnapshotCommitter {
+    fn new(written: &Arc<AtomicUsize>) -> Self {
+        Self {
+            written: Arc::clone(written),
+        }
+    }
+}
+
+impl CommitChange for SnapshotCommitter {
+    fn commit(&self) -> Result<(), CommitError> {
+        self.written.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TableProvider for MySQLBinlogTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn constraints(&self) -> Option<&Constraints> {
+        self.constraints.as_ref()
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        self.base_table.supports_filters_pushdown(filters)
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        self.base_table
+            .scan(state, projection, filters, limit)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Date32Array, Int32Array, StructArray};
+    use mysql_async::consts::ColumnType;
+    use mysql_async::Column;
+
+    #[test]
+    fn test_gtid_set() {
+        let mut gtid_set: GtidSet =
+            "3E11FA47-71CA-11E1-9E33-C80AA9429562:1-5:7,\n4e11fa47-71ca-11e1-9e33-c80aa9429562:3"
+                .parse()
+                .expect("valid GTID set");
+        gtid_set.add("3e11fa47-71ca-11e1-9e33-c80aa9429562", 6);
+        gtid_set.add("5e11fa47-71ca-11e1-9e33-c80aa9429562", 1);
+        assert_eq!(
+            gtid_set.to_string(),
+            "3e11fa47-71ca-11e1-9e33-c80aa9429562:1-7,4e11fa47-71ca-11e1-9e33-c80aa9429562:3,5e11fa47-71ca-11e1-9e33-c80aa9429562:1"
+        );
+        assert_eq!(gtid_set.sids().expect("valid SIDs").len(), 3);
+
+        assert!("3e11fa47-71ca-11e1-9e33-c80aa9429562:x"
+            .parse::<GtidSet>()
+            .is_err());
+        assert_eq!(
+            format_uuid([
+                0x3e, 0x11, 0xfa, 0x47, 0x71, 0xca, 0x11, 0xe1, 0x9e, 0x33, 0xc8, 0x0a, 0xa9, 0x42,
+                0x95, 0x62
+            ]),
+            "3e11fa47-71ca-11e1-9e33-c80aa9429562"
+        );
+    }
+
+    #[test]
+    fn test_to_change_batch() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::UInt32, true),
+            Field::new("created", DataType::Date32, true),
+            Field::new(
+                "updated",
+                DataType::Timestamp(arrow::datatypes::TimeUnit::Microsecond, None),
+                true,
+            ),
+            Field::new("added_later", DataType::Int32, true),
+        ]));
+        let columns = vec![
+            "id".to_string(),
+            "created".to_string(),
+            "updated".to_string(),
+        ];
+        let row = BinlogRow::new(
+            vec![
+                Some(BinlogValue::Value(Value::Int(-1))),
+                Some(BinlogValue::Value(Value::Date(2024, 7, 1, 0, 0, 0, 0))),
+                Some(BinlogValue::Value(Value::Bytes(b"1719792000.5".to_vec()))),
+            ],
+            columns
+                .iter()
+                .map(|name| Column::new(ColumnType::MYSQL_TYPE_LONG).with_name(name.as_bytes()))
+                .collect::<Vec<_>>()
+                .into(),
+        );
+
+        let change = to_change_batch(
+            &schema,
+            &["id".to_string()],
+            &columns,
+            &ChangeOperation::Create,
+            &row,
+        )
+        .expect("valid change");
+        assert_eq!(change.op(0).to_string(), "c");
+
+        let data = StructArray::from(change.data(0));
+        let id = data
+            .column(0)
+            .as_any()
+            .downcast_ref::<arrow::array::UInt32Array>()
+            .expect("uint32 column");
+        assert_eq!(id.value(0), u32::MAX);
+        let created = data
+            .column(1)
+            .as_any()
+            .downcast_ref::<Date32Array>()
+            .expect("date32 column");
+        assert_eq!(created.value(0), 19905);
+        let updated = data
+            .column(2)
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .expect("timestamp column");
+        assert_eq!(updated.value(0), 1_719_792_000_500_000);
+        assert!(data.column(3).is_null(0));
+        assert!(data
+            .column(3)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .is_some());
+    }
+}
@@ -33,6 +33,8 @@ use datafusion_table_providers::{
     util,
 };
 
+pub mod replication;
+
 #[async_trait]
 impl Read for PostgresTableFactory {
     async fn table_provider(
@@ -0,0 +1,1076 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Streams the changes of a Postgres table from a logical replication slot, without Kafka.
+//!
+//! The slot is read with `pg_logical_slot_peek_binary_changes` and the `pgoutput` plugin. The slot
+//! is only advanced once the changes were written to the accelerator and the confirmed LSN was
+//! saved with a [`ReplicationCheckpoint`], so a restarted stream resumes where it stopped.
+
+use crate::cdc::{
+    self, ChangeBatch, ChangeEnvelope, ChangeOperation, ChangesStream, CommitChange, CommitError,
+    StreamError,
+};
+use arrow::{
+    array::{ArrayRef, BinaryArray, RecordBatch, StringArray},
+    compute::{cast_with_options, CastOptions},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    error::ArrowError,
+};
+use async_stream::stream;
+use async_trait::async_trait;
+use datafusion::{
+    common::{Constraints, DFSchema},
+    datasource::{TableProvider, TableType},
+    error::{DataFusionError, Result as DataFusionResult},
+    execution::{context::SessionState, SendableRecordBatchStream},
+    logical_expr::{Expr, TableProviderFilterPushDown},
+    physical_plan::ExecutionPlan,
+    prelude::SessionContext,
+    sql::{
+        sqlparser::ast::{Ident, TableConstraint},
+        TableReference,
+    },
+};
+use datafusion_table_providers::sql::db_connection_pool::{
+    dbconnection::postgresconn::PostgresConnection, postgrespool::PostgresConnectionPool,
+};
+use futures::StreamExt;
+use pgoutput::{Message, Relation, TupleValue};
+use snafu::prelude::*;
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio_postgres::Client;
+
+pub mod pgoutput;
+
+/// The number of changes to read from the slot at once. Postgres only stops reading at the end
+/// of a transaction, so larger transactions are read in full.
+const MAX_CHANGES_PER_POLL: i32 = 1000;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Postgres replication slot names are limited to 63 characters.
+const MAX_SLOT_NAME_LENGTH: usize = 63;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to connect to Postgres: {source}"))]
+    UnableToConnect {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("Replication query failed: {source}"))]
+    QueryFailed { source: tokio_postgres::Error },
+
+    #[snafu(display("Invalid LSN '{lsn}'"))]
+    InvalidLsn { lsn: String },
+
+    #[snafu(display("Unable to decode replication message: {source}"))]
+    UnableToDecodeMessage { source: pgoutput::Error },
+
+    #[snafu(display("Unable to convert column '{column}' to {data_type}: {source}"))]
+    UnableToConvertColumn {
+        column: String,
+        data_type: DataType,
+        source: ArrowError,
+    },
+
+    #[snafu(display("Unable to convert replicated rows: {source}"))]
+    UnableToConvertRows { source: ArrowError },
+
+    #[snafu(display("Column '{column}' has an unchanged TOAST value which isn't replicated. Set 'REPLICA IDENTITY FULL' on the table to replicate it."))]
+    UnchangedToastValue { column: String },
+
+    #[snafu(display("{source}"))]
+    UnableToBuildChangeBatch { source: cdc::ChangeBatchError },
+
+    #[snafu(display("Unable to snapshot the table: {source}"))]
+    UnableToSnapshotTable { source: DataFusionError },
+
+    #[snafu(display("Unable to save the replication checkpoint: {source}"))]
+    UnableToSaveCheckpoint {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<Error> for StreamError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::UnableToDecodeMessage { .. }
+            | Error::UnableToConvertColumn { .. }
+            | Error::UnableToConvertRows { .. }
+            | Error::UnchangedToastValue { .. }
+            | Error::UnableToBuildChangeBatch { .. } => StreamError::Decode(e.to_string()),
+            _ => StreamError::Postgres(e.to_string()),
+        }
+    }
+}
+
+/// A location in the Postgres write-ahead log, formatted as `16/B374D848`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Lsn(u64);
+
+impl From<u64> for Lsn {
+    fn from(lsn: u64) -> Self {
+        Self(lsn)
+    }
+}
+
+impl From<Lsn> for u64 {
+    fn from(lsn: Lsn) -> Self {
+        lsn.0
+    }
+}
+
+impl FromStr for Lsn {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |part: &str| u32::from_str_radix(part, 16).ok();
+        let (high, low) = s
+            .split_once('/')
+            .and_then(|(high, low)| Some((parse(high)?, parse(low)?)))
+            .context(InvalidLsnSnafu { lsn: s })?;
+
+        Ok(Self((u64::from(high) << 32) | u64::from(low)))
+    }
+}
+
+impl Display for Lsn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 & 0xFFFF_FFFF)
+    }
+}
+
+/// Persists the LSN up to which changes were written, so a restarted stream resumes after it.
+#[async_trait]
+pub trait ReplicationCheckpoint: Send + Sync {
+    /// The LSN saved by the last checkpoint, if any.
+    async fn load(&self) -> Option<Lsn>;
+
+    async fn save(&self, lsn: Lsn) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// The replication slot name used for a dataset when none is configured.
+#[must_use]
+pub fn default_slot_name(dataset_name: &str) -> String {
+    let mut slot_name: String = format!("spice_{dataset_name}")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    slot_name.truncate(MAX_SLOT_NAME_LENGTH);
+    slot_name
+}
+
+/// The columns of the primary key of a Postgres table, in key order.
+pub async fn primary_keys(
+    pool: &PostgresConnectionPool,
+    table_reference: &TableReference,
+) -> Result<Vec<String>> {
+    let conn = pool
+        .connect_direct()
+        .await
+        .boxed()
+        .context(UnableToConnectSnafu)?;
+    let rows = conn
+        .conn
+        .query(
+            "SELECT a.attname::text FROM pg_index i \
+             JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey) \
+             WHERE i.indrelid = $1::text::regclass AND i.indisprimary \
+             ORDER BY array_position(i.indkey::int2[], a.attnum)",
+            &[&table_reference.to_quoted_string()],
+        )
+        .await
+        .context(QueryFailedSnafu)?;
+
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Drops the replication slot of a dataset that was removed, if it exists.
+pub async fn drop_slot(pool: &PostgresConnectionPool, slot_name: &str) -> Result<()> {
+    let conn = pool
+        .connect_direct()
+        .await
+        .boxed()
+        .context(UnableToConnectSnafu)?;
+    conn.conn
+        .execute(
+            "SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots \
+             WHERE slot_name = $1",
+            &[&slot_name],
+        )
+        .await
+        .context(QueryFailedSnafu)?;
+
+    Ok(())
+}
+
+/// Drops the publication of a dataset that was removed, if it exists.
+pub async fn drop_publication(pool: &PostgresConnectionPool, publication: &str) -> Result<()> {
+    let conn = pool
+        .connect_direct()
+        .await
+        .boxed()
+        .context(UnableToConnectSnafu)?;
+    conn.conn
+        .batch_execute(&format!(
+            "DROP PUBLICATION IF EXISTS {}",
+            quote_identifier(publication)
+        ))
+        .await
+        .context(QueryFailedSnafu)?;
+
+    Ok(())
+}
+
+/// The connection a changes stream reads its replication slot with. It's kept between polls and
+/// only replaced after an error.
+struct SlotConnection {
+    pool: Arc<PostgresConnectionPool>,
+    conn: Option<PostgresConnection>,
+}
+
+impl SlotConnection {
+    fn new(pool: Arc<PostgresConnectionPool>) -> Self {
+        Self { pool, conn: None }
+    }
+
+    async fn client(&mut self) -> Result<&Client> {
+        let conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => self
+                .pool
+                .connect_direct()
+                .await
+                .boxed()
+                .context(UnableToConnectSnafu)?,
+        };
+        Ok(&*self.conn.insert(conn).conn)
+    }
+
+    /// Reconnects before the next query, as the connection may be broken.
+    fn reset(&mut self) {
+        self.conn = None;
+    }
+}
+
+/// Reads the changes of a table from a logical replication slot with the `pgoutput` plugin.
+#[derive(Clone)]
+pub struct Replication {
+    pool: Arc<PostgresConnectionPool>,
+    table_reference: TableReference,
+    slot_name: String,
+    publication: String,
+    checkpoint: Arc<dyn ReplicationCheckpoint>,
+}
+
+/// Where a changes stream starts to read the replication slot.
+enum StartPosition {
+    /// After the LSN of the last checkpoint.
+    Checkpoint(Lsn),
+    /// After the LSN at which the slot was created, once the table was read in full.
+    Snapshot(Lsn),
+}
+
+impl Replication {
+    #[must_use]
+    pub fn new(
+        pool: Arc<PostgresConnectionPool>,
+        table_reference: TableReference,
+        slot_name: String,
+        publication: String,
+        checkpoint: Arc<dyn ReplicationCheckpoint>,
+    ) -> Self {
+        Self {
+            pool,
+            table_reference,
+            slot_name,
+            publication,
+            checkpoint,
+        }
+    }
+
+    async fn start_position(&self, conn: &mut SlotConnection) -> Result<StartPosition> {
+        if let Some(lsn) = self.checkpoint.load().await {
+            if self.slot_exists(conn).await? {
+                return Ok(StartPosition::Checkpoint(lsn));
+            }
+            tracing::warn!(
+                "Replication slot {} no longer exists. Reading {} from the start.",
+                self.slot_name,
+                self.table_reference
+            );
+        }
+
+        Ok(StartPosition::Snapshot(self.create_slot(conn).await?))
+    }
+
+    async fn slot_exists(&self, conn: &mut SlotConnection) -> Result<bool> {
+        let row = conn
+            .client()
+            .await?
+            .query_opt(
+                "SELECT 1 FROM pg_replication_slots WHERE slot_name = $1",
+                &[&self.slot_name],
+            )
+            .await
+            .context(QueryFailedSnafu)?;
+
+        Ok(row.is_some())
+    }
+
+    /// Creates the publication if it doesn't exist, and (re)creates the replication slot.
+    /// Returns the LSN from which the slot streams changes.
+    async fn create_slot(&self, conn: &mut SlotConnection) -> Result<Lsn> {
+        let publication_exists = conn
+            .client()
+            .await?
+            .query_opt(
+                "SELECT 1 FROM pg_publication WHERE pubname = $1",
+                &[&self.publication],
+            )
+            .await
+            .context(QueryFailedSnafu)?
+            .is_some();
+        if !publication_exists {
+            tracing::info!(
+                "Creating publication {} for {}",
+                self.publication,
+                self.table_reference
+            );
+            conn.client()
+                .await?
+                .batch_execute(&format!(
+                    "CREATE PUBLICATION {} FOR TABLE {}",
+                    quote_identifier(&self.publication),
+                    self.table_reference.to_quoted_string()
+                ))
+                .await
+                .context(QueryFailedSnafu)?;
+        }
+
+        if self.slot_exists(conn).await? {
+            tracing::info!(
+                "Recreating replication slot {} as no changes were checkpointed for it",
+                self.slot_name
+            );
+            conn.client()
+                .await?
+                .execute("SELECT pg_drop_replication_slot($1)", &[&self.slot_name])
+                .await
+                .context(QueryFailedSnafu)?;
+        }
+
+        let row = conn
+            .client()
+            .await?
+            .query_one(
+                "SELECT lsn::text FROM pg_create_logical_replication_slot($1, 'pgoutput')",
+                &[&self.slot_name],
+            )
+            .await
+            .context(QueryFailedSnafu)?;
+
+        row.get::<_, &str>(0).parse()
+    }
+
+    /// Reads the pending changes of the slot without consuming them.
+    async fn peek_changes(&self, conn: &mut SlotConnection) -> Result<Vec<Message>> {
+        let rows = conn
+            .client()
+            .await?
+            .query(
+                "SELECT data FROM pg_logical_slot_peek_binary_changes($1, NULL, $2, \
+                 'proto_version', '1', 'publication_names', $3)",
+                &[
+                    &self.slot_name,
+                    &MAX_CHANGES_PER_POLL,
+                    &quote_identifier(&self.publication),
+                ],
+            )
+            .await
+            .context(QueryFailedSnafu)?;
+
+        rows.iter()
+            .map(|row| Message::parse(row.get(0)))
+            .collect::<Result<_, _>>()
+            .context(UnableToDecodeMessageSnafu)
+    }
+
+    /// Saves the checkpoint, then lets the slot release the changes up to `lsn`.
+    async fn confirm(&self, conn: &mut SlotConnection, lsn: Lsn) -> Result<()> {
+        self.checkpoint
+            .save(lsn)
+            .await
+            .context(UnableToSaveCheckpointSnafu)?;
+
+        conn.client()
+            .await?
+            .execute(
+                "SELECT pg_replication_slot_advance($1, $2::text::pg_lsn)",
+                &[&self.slot_name, &lsn.to_string()],
+            )
+            .await
+            .context(QueryFailedSnafu)?;
+
+        Ok(())
+    }
+
+    fn is_replicated_table(&self, relation: &Relation) -> bool {
+        match &self.table_reference {
+            TableReference::Bare { table } => relation.name == table.as_ref(),
+            TableReference::Partial { schema, table }
+            | TableReference::Full { schema, table, .. } => {
+                relation.namespace == schema.as_ref() && relation.name == table.as_ref()
+            }
+        }
+    }
+}
+
+/// A Postgres table whose changes are streamed from a logical replication slot.
+///
+/// All columns are nullable, as deleted rows only carry the values of their replica identity.
+pub struct PostgresReplicationTable {
+    base_table: Arc<dyn TableProvider>,
+    schema: SchemaRef,
+    primary_keys: Vec<String>,
+    constraints: Option<Constraints>,
+    replication: Replication,
+}
+
+impl PostgresReplicationTable {
+    #[must_use]
+    pub fn new(
+        base_table: Arc<dyn TableProvider>,
+        primary_keys: Vec<String>,
+        replication: Replication,
+    ) -> Self {
+        let base_schema = base_table.schema();
+        let schema = Arc::new(Schema::new(
+            base_schema
+                .fields()
+                .iter()
+                .map(|field| field.as_ref().clone().with_nullable(true))
+                .collect::<Vec<Field>>(),
+        ));
+
+        let Ok(df_schema) = DFSchema::try_from(Arc::clone(&schema)) else {
+            unreachable!("DFSchema::try_from is infallible as of DataFusion 38")
+        };
+        let constraints = Constraints::new_from_table_constraints(
+            &[TableConstraint::PrimaryKey {
+                name: None,
+                index_name: None,
+                index_type: None,
+                columns: primary_keys
+                    .iter()
+                    .map(|col| Ident::new(col.clone()))
+                    .collect(),
+                index_options: vec![],
+                characteristics: None,
+            }],
+            &Arc::new(df_schema),
+        )
+        .ok();
+
+        Self {
+            base_table,
+            schema,
+            primary_keys,
+            constraints,
+            replication,
+        }
+    }
+
+    /// Streams the changes of the table. A stream without a checkpoint first reads the whole
+    /// table as `Read` changes.
+    ///
+    /// The table is read after the replication slot is created, so the changes replayed from
+    /// the slot may already be part of the snapshot. Replaying them by primary key converges to
+    /// the same rows.
+    #[must_use]
+    pub fn stream_changes(&self) -> ChangesStream {
+        let base_table = Arc::clone(&self.base_table);
+        let schema = Arc::clone(&self.schema);
+        let primary_keys = self.primary_keys.clone();
+        let replication = self.replication.clone();
+
+        Box::pin(stream! {
+            let mut connection = SlotConnection::new(Arc::clone(&replication.pool));
+
+            // The end LSN of the last transaction whose changes were all written.
+            let confirmed = Arc::new(AtomicU64::new(0));
+
+            // The end LSN of the last transaction sent downstream.
+            let mut streamed = loop {
+                let start_position = match replication.start_position(&mut connection).await {
+                    Ok(start_position) => start_position,
+                    Err(e) => {
+                        connection.reset();
+                        yield Err(StreamError::from(e));
+                        tokio::time::sleep(POLL_INTERVAL).await;
+                        continue;
+                    }
+                };
+
+                let lsn = match start_position {
+                    StartPosition::Checkpoint(lsn) => {
+                        confirmed.fetch_max(lsn.into(), Ordering::SeqCst);
+                        break lsn;
+                    }
+                    StartPosition::Snapshot(lsn) => lsn,
+                };
+
+                // The slot is only confirmed once every batch of the snapshot was written.
+                let written = Arc::new(AtomicUsize::new(0));
+                let mut num_batches = 0;
+                let mut snapshot_complete = true;
+                match snapshot(&base_table).await {
+                    Ok(mut batches) => {
+                        while let Some(batch) = batches.next().await {
+                            let change = batch
+                                .context(UnableToSnapshotTableSnafu)
+                                .and_then(|batch| {
+                                    RecordBatch::try_new(Arc::clone(&schema), batch.columns().to_vec())
+                                        .context(UnableToConvertRowsSnafu)
+                                })
+                                .and_then(|batch| {
+                                    ChangeBatch::try_from_rows(&ChangeOperation::Read, &primary_keys, batch)
+                                        .context(UnableToBuildChangeBatchSnafu)
+                                });
+                            match change {
+                                Ok(change) => {
+                                    num_batches += 1;
+                                    yield Ok(ChangeEnvelope::new(
+                                        Box::new(SnapshotCommitter::new(&written)),
+                                        change,
+                                    ));
+                                }
+                                Err(e) => {
+                                    snapshot_complete = false;
+                                    yield Err(StreamError::from(e));
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        snapshot_complete = false;
+                        yield Err(StreamError::from(e));
+                    }
+                }
+
+                if snapshot_complete && written.load(Ordering::SeqCst) == num_batches {
+                    confirmed.fetch_max(lsn.into(), Ordering::SeqCst);
+                    break lsn;
+                }
+
+                // The slot is recreated and the table read again.
+                tokio::time::sleep(POLL_INTERVAL).await;
+            };
+
+            // The end LSN of the last transaction whose changes were sent downstream.
+            let mut sent = streamed;
+            let mut confirmed_lsn = Lsn::default();
+            let mut relations: HashMap<u32, Relation> = HashMap::new();
+            loop {
+                let lsn = Lsn::from(confirmed.load(Ordering::SeqCst));
+                if lsn > confirmed_lsn {
+                    match replication.confirm(&mut connection, lsn).await {
+                        Ok(()) => confirmed_lsn = lsn,
+                        Err(e) => {
+                            connection.reset();
+                            yield Err(StreamError::from(e));
+                        }
+                    }
+                }
+
+                let messages = match replication.peek_changes(&mut connection).await {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        connection.reset();
+                        yield Err(StreamError::from(e));
+                        tokio::time::sleep(POLL_INTERVAL).await;
+                        continue;
+                    }
+                };
+
+                let mut envelopes = Vec::new();
+                let mut transaction = Vec::new();
+                let mut skip_transaction = false;
+                let mut read_new_transactions = false;
+                for message in messages {
+                    match message {
+                        Message::Begin { final_lsn, .. } => {
+                            // The slot is only advanced once changes are confirmed, so it returns
+                            // transactions that were already sent again.
+                            skip_transaction = Lsn::from(final_lsn) < streamed;
+                            transaction.clear();
+                        }
+                        Message::Commit { end_lsn, .. } => {
+                            if skip_transaction {
+                                continue;
+                            }
+                            read_new_transactions = true;
+                            streamed = Lsn::from(end_lsn);
+
+                            // Only the last change of a transaction confirms it.
+                            let num_changes = transaction.len();
+                            if num_changes > 0 {
+                                sent = streamed;
+                            }
+                            for (i, change) in transaction.drain(..).enumerate() {
+                                let commit_lsn = (i + 1 == num_changes).then_some(streamed);
+                                envelopes.push(change.map(|change| {
+                                    ChangeEnvelope::new(Box::new(LsnCommitter::new(commit_lsn, &confirmed)), change)
+                                }));
+                            }
+                        }
+                        Message::Relation(relation) => {
+                            relations.insert(relation.id, relation);
+                        }
+                        Message::Other(_) => {}
+                        change => {
+                            if !skip_transaction {
+                                transaction.extend(to_change_batches(
+                                    &replication,
+                                    &schema,
+                                    &primary_keys,
+                                    &relations,
+                                    change,
+                                ));
+                            }
+                        }
+                    }
+                }
+
+                if envelopes.is_empty() {
+                    // No change of the table was read, so the transactions are confirmed as they
+                    // are, once the changes sent before them were written.
+                    if confirmed.load(Ordering::SeqCst) >= u64::from(sent) {
+                        confirmed.fetch_max(streamed.into(), Ordering::SeqCst);
+                    }
+                    if !read_new_transactions {
+                        tokio::time::sleep(POLL_INTERVAL).await;
+                    }
+                    continue;
+                }
+
+                for envelope in envelopes {
+                    yield envelope.map_err(StreamError::from);
+                }
+            }
+        })
+    }
+}
+
+async fn snapshot(base_table: &Arc<dyn TableProvider>) -> Result<SendableRecordBatchStream> {
+    SessionContext::new()
+        .read_table(Arc::clone(base_table))
+        .context(UnableToSnapshotTableSnafu)?
+        .execute_stream()
+        .await
+        .context(UnableToSnapshotTableSnafu)
+}
+
+/// Converts a row change of the replicated table to change batches. Changes of other tables
+/// in the publication are ignored.
+fn to_change_batches(
+    replication: &Replication,
+    schema: &SchemaRef,
+    primary_keys: &[String],
+    relations: &HashMap<u32, Relation>,
+    change: Message,
+) -> Vec<Result<ChangeBatch>> {
+    let relation_id = match &change {
+        Message::Insert { relation_id, .. }
+        | Message::Update { relation_id, .. }
+        | Message::Delete { relation_id, .. } => *relation_id,
+        Message::Truncate { relation_ids } => {
+            let Some(relation) = relation_ids
+                .iter()
+                .filter_map(|id| relations.get(id))
+                .find(|relation| replication.is_replicated_table(relation))
+            else {
+                return vec![];
+            };
+            return vec![to_change_batch(
+                schema,
+                primary_keys,
+                relation,
+                &ChangeOperation::Truncate,
+                &[],
+            )];
+        }
+        _ => return vec![],
+    };
+
+    let Some(relation) = relations
+        .get(&relation_id)
+        .filter(|relation| replication.is_replicated_table(relation))
+    else {
+        return vec![];
+    };
+
+    match change {
+        Message::Insert { new, .. } => vec![to_change_batch(
+            schema,
+            primary_keys,
+            relation,
+            &ChangeOperation::Create,
+            &new,
+        )],
+        Message::Update { old, new, .. } => {
+            let Some(old) = old else {
+                return vec![to_change_batch(
+                    schema,
+                    primary_keys,
+                    relation,
+                    &ChangeOperation::Update,
+                    &new,
+                )];
+            };
+
+            // Unchanged TOAST values are only available in the old row, with 'REPLICA IDENTITY FULL'.
+            let new: Vec<TupleValue> = new
+                .into_iter()
+                .zip(old.iter())
+                .map(|(new, old)| match (new, old) {
+                    (TupleValue::UnchangedToast, TupleValue::Text(old)) => {
+                        TupleValue::Text(old.clone())
+                    }
+                    (new, _) => new,
+                })
+                .collect();
+
+            let key_changed = relation
+                .columns
+                .iter()
+                .enumerate()
+                .any(|(i, column)| column.is_key && old.get(i) != new.get(i));
+            let mut changes = vec![];
+            if key_changed {
+                changes.push(to_change_batch(
+                    schema,
+                    primary_keys,
+                    relation,
+                    &ChangeOperation::Delete,
+                    &old,
+                ));
+            }
+            changes.push(to_change_batch(
+                schema,
+                primary_keys,
+                relation,
+                &ChangeOperation::Update,
+                &new,
+            ));
+            changes
+        }
+        Message::Delete { old, .. } => vec![to_change_batch(
+            schema,
+            primary_keys,
+            relation,
+            &ChangeOperation::Delete,
+            &old,
+        )],
+        _ => vec![],
+    }
+}
+
+/// Converts a replicated row, whose values are sent in their text representation, to a change
+/// batch. Columns that aren't part of the relation are null.
+fn to_change_batch(
+    schema: &SchemaRef,
+    primary_keys: &[String],
+    relation: &Relation,
+    op: &ChangeOperation,
+    row: &[TupleValue],
+) -> Result<ChangeBatch> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let value = match relation
+                .columns
+                .iter()
+                .position(|column| column.name == *field.name())
+                .and_then(|i| row.get(i))
+            {
+                Some(TupleValue::Text(text)) => Some(text.as_str()),
+                Some(TupleValue::UnchangedToast) => {
+                    return UnchangedToastValueSnafu {
+                        column: field.name().clone(),
+                    }
+                    .fail()
+                }
+                Some(TupleValue::Null) | None => None,
+            };
+            to_array(value, field)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let data =
+        RecordBatch::try_new(Arc::clone(schema), columns).context(UnableToConvertRowsSnafu)?;
+
+    ChangeBatch::try_from_rows(op, primary_keys, data).context(UnableToBuildChangeBatchSnafu)
+}
+
+fn to_array(value: Option<&str>, field: &Field) -> Result<ArrayRef> {
+    let convert_context = |_: &mut ArrowError| UnableToConvertColumnSnafu {
+        column: field.name().clone(),
+        data_type: field.data_type().clone(),
+    };
+
+    let array: ArrayRef = match field.data_type() {
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => {
+            let bytes = value
+                .map(decode_bytea)
+                .transpose()
+                .with_context(convert_context)?;
+            Arc::new(BinaryArray::from_iter([bytes]))
+        }
+        _ => Arc::new(StringArray::from_iter([value])),
+    };
+
+    cast_with_options(
+        &array,
+        field.data_type(),
+        &CastOptions {
+            safe: false,
+            ..CastOptions::default()
+        },
+    )
+    .with_context(convert_context)
+}
+
+/// Decodes a `bytea` value from its hex text representation, e.g. `\x0aff`.
+fn decode_bytea(text: &str) -> Result<Vec<u8>, ArrowError> {
+    let invalid = || ArrowError::ParseError(format!("Invalid bytea value '{text}'"));
+    let hex = text.strip_prefix("\\x").ok_or_else(invalid)?;
+    if hex.len() % 2 != 0 {
+        return Err(invalid());
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Confirms the transaction of a change once it's written, if it's the last change of it.
+struct LsnCommitter {
+    lsn: Option<Lsn>,
+    confirmed: Arc<AtomicU64>,
+}
+
+impl LsnCommitter {
+    fn new(lsn: Option<Lsn>, confirmed: &Arc<AtomicU64>) -> Self {
+        Self {
+            lsn,
+            confirmed: Arc::clone(confirmed),
+        }
+    }
+}
+
+impl CommitChange for LsnCommitter {
+    fn commit(&self) -> Result<(), CommitError> {
+        if let Some(lsn) = self.lsn {
+            self.confirmed.fetch_max(lsn.into(), Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+/// Counts the snapshot batches that were written, so the slot is only confirmed once all were.
+struct SnapshotCommitter {
+    written: Arc<AtomicUsize>,
+}
+
+impl SnapshotCommitter {
+    fn new(written: &Arc<AtomicUsize>) -> Self {
+        Self {
+            written: Arc::clone(written),
+        }
+    }
+}
+
+impl CommitChange for SnapshotCommitter {
+    fn commit(&self) -> Result<(), CommitError> {
+        self.written.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TableProvider for PostgresReplicationTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn constraints(&self) -> Option<&Constraints> {
+        self.constraints.as_ref()
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        self.base_table.supports_filters_pushdown(filters)
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        self.base_table
+            .scan(state, projection, filters, limit)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array, StructArray};
+    use pgoutput::RelationColumn;
+
+    #[test]
+    fn test_parse_lsn() {
+        let lsn: Lsn = "16/B374D848".parse().expect("valid LSN");
+        assert_eq!(u64::from(lsn), 0x16_B374_D848);
+        assert_eq!(lsn.to_string(), "16/B374D848");
+        assert!("0/0".parse::<Lsn>().expect("valid LSN") < lsn);
+        assert!("16B374D848".parse::<Lsn>().is_err());
+        assert!("16/G".parse::<Lsn>().is_err());
+    }
+
+    #[test]
+    fn test_default_slot_name() {
+        assert_eq!(default_slot_name("public.Orders"), "spice_public_orders");
+        assert_eq!(
+            default_slot_name(&"a".repeat(100)).len(),
+            MAX_SLOT_NAME_LENGTH
+        );
+    }
+
+    #[test]
+    fn test_to_change_batch() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("payload", DataType::Binary, true),
+            Field::new("added_later", DataType::Utf8, true),
+        ]));
+        let relation = Relation {
+            id: 1,
+            namespace: "public".to_string(),
+            name: "orders".to_string(),
+            columns: vec![
+                RelationColumn {
+                    name: "id".to_string(),
+                    type_oid: 23,
+                    is_key: true,
+                },
+                RelationColumn {
+                    name: "payload".to_string(),
+                    type_oid: 17,
+                    is_key: false,
+                },
+            ],
+        };
+
+        let change = to_change_batch(
+            &schema,
+            &["id".to_string()],
+            &relation,
+            &ChangeOperation::Delete,
+            &[
+                TupleValue::Text("42".to_string()),
+                TupleValue::Text("\\x0aff".to_string()),
+            ],
+        )
+        .expect("valid change");
+        assert_eq!(change.op(0).to_string(), "d");
+        assert_eq!(change.primary_keys(0), vec!["id".to_string()]);
+
+        let data = StructArray::from(change.data(0));
+        let id = data
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .expect("int32 column");
+        assert_eq!(id.value(0), 42);
+        let payload = data
+            .column(1)
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .expect("binary column");
+        assert_eq!(payload.value(0), [0x0a, 0xff]);
+        assert!(data.column(2).is_null(0));
+
+        assert!(matches!(
+            to_change_batch(
+                &schema,
+                &["id".to_string()],
+                &relation,
+                &ChangeOperation::Update,
+                &[
+                    TupleValue::Text("42".to_string()),
+                    TupleValue::UnchangedToast
+                ],
+            ),
+            Err(Error::UnchangedToastValue { .. })
+        ));
+    }
+}
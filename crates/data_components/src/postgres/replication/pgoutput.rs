@@ -0,0 +1,384 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Decodes the messages of the `pgoutput` logical decoding plugin (protocol version 1).
+//!
+//! See <https://www.postgresql.org/docs/current/protocol-logicalrep-message-formats.html>.
+
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unexpected end of pgoutput message"))]
+    UnexpectedEndOfMessage,
+
+    #[snafu(display("Unknown pgoutput tuple data kind '{kind}'"))]
+    UnknownTupleDataKind { kind: char },
+
+    #[snafu(display("Unexpected pgoutput tuple type '{kind}' in '{message_type}' message"))]
+    UnexpectedTupleType { kind: char, message_type: char },
+
+    #[snafu(display("Invalid UTF-8 in pgoutput message: {source}"))]
+    InvalidText { source: std::string::FromUtf8Error },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Begin {
+        final_lsn: u64,
+        xid: u32,
+    },
+    Commit {
+        commit_lsn: u64,
+        end_lsn: u64,
+    },
+    Relation(Relation),
+    Insert {
+        relation_id: u32,
+        new: Vec<TupleValue>,
+    },
+    Update {
+        relation_id: u32,
+        /// The replica identity columns (`K`) or the full old row (`O`), only sent if the
+        /// replica identity changed or the table uses `REPLICA IDENTITY FULL`.
+        old: Option<Vec<TupleValue>>,
+        new: Vec<TupleValue>,
+    },
+    Delete {
+        relation_id: u32,
+        old: Vec<TupleValue>,
+    },
+    Truncate {
+        relation_ids: Vec<u32>,
+    },
+    /// Origin, type and logical decoding messages, which don't affect the replicated rows.
+    Other(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relation {
+    pub id: u32,
+    pub namespace: String,
+    pub name: String,
+    pub columns: Vec<RelationColumn>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelationColumn {
+    pub name: String,
+    pub type_oid: u32,
+    /// Whether the column is part of the replica identity, usually the primary key.
+    pub is_key: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TupleValue {
+    Null,
+    /// A `TOAST`ed value that didn't change, and therefore isn't sent.
+    UnchangedToast,
+    Text(String),
+}
+
+impl Message {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader { data };
+        let message_type = reader.u8()?;
+        let message = match message_type {
+            b'B' => {
+                let final_lsn = reader.u64()?;
+                reader.u64()?; // commit timestamp
+                Self::Begin {
+                    final_lsn,
+                    xid: reader.u32()?,
+                }
+            }
+            b'C' => {
+                reader.u8()?; // flags, currently unused
+                Self::Commit {
+                    commit_lsn: reader.u64()?,
+                    end_lsn: reader.u64()?,
+                }
+            }
+            b'R' => {
+                let id = reader.u32()?;
+                let namespace = reader.string()?;
+                let name = reader.string()?;
+                reader.u8()?; // replica identity setting
+                let num_columns = reader.u16()?;
+                let mut columns = Vec::with_capacity(num_columns.into());
+                for _ in 0..num_columns {
+                    let flags = reader.u8()?;
+                    let name = reader.string()?;
+                    let type_oid = reader.u32()?;
+                    reader.u32()?; // type modifier
+                    columns.push(RelationColumn {
+                        name,
+                        type_oid,
+                        is_key: flags & 1 == 1,
+                    });
+                }
+                Self::Relation(Relation {
+                    id,
+                    namespace,
+                    name,
+                    columns,
+                })
+            }
+            b'I' => {
+                let relation_id = reader.u32()?;
+                reader.expect_tuple_type(b"N", message_type)?;
+                Self::Insert {
+                    relation_id,
+                    new: reader.tuple()?,
+                }
+            }
+            b'U' => {
+                let relation_id = reader.u32()?;
+                let old = if reader.expect_tuple_type(b"KON", message_type)? == b'N' {
+                    None
+                } else {
+                    let old = reader.tuple()?;
+                    reader.expect_tuple_type(b"N", message_type)?;
+                    Some(old)
+                };
+                Self::Update {
+                    relation_id,
+                    old,
+                    new: reader.tuple()?,
+                }
+            }
+            b'D' => {
+                let relation_id = reader.u32()?;
+                reader.expect_tuple_type(b"KO", message_type)?;
+                Self::Delete {
+                    relation_id,
+                    old: reader.tuple()?,
+                }
+            }
+            b'T' => {
+                let num_relations = reader.u32()?;
+                reader.u8()?; // CASCADE / RESTART IDENTITY options
+                let relation_ids = (0..num_relations)
+                    .map(|_| reader.u32())
+                    .collect::<Result<Vec<_>>>()?;
+                Self::Truncate { relation_ids }
+            }
+            other => Self::Other(other),
+        };
+
+        Ok(message)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(self.data.len() >= len, UnexpectedEndOfMessageSnafu);
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_be_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    /// Reads a null-terminated string.
+    fn string(&mut self) -> Result<String> {
+        let len = self
+            .data
+            .iter()
+            .position(|b| *b == 0)
+            .context(UnexpectedEndOfMessageSnafu)?;
+        let bytes = self.take(len)?.to_vec();
+        self.take(1)?;
+        String::from_utf8(bytes).context(InvalidTextSnafu)
+    }
+
+    fn expect_tuple_type(&mut self, expected: &[u8], message_type: u8) -> Result<u8> {
+        let kind = self.u8()?;
+        ensure!(
+            expected.contains(&kind),
+            UnexpectedTupleTypeSnafu {
+                kind: char::from(kind),
+                message_type: char::from(message_type),
+            }
+        );
+        Ok(kind)
+    }
+
+    fn tuple(&mut self) -> Result<Vec<TupleValue>> {
+        let num_columns = self.u16()?;
+        let mut values = Vec::with_capacity(num_columns.into());
+        for _ in 0..num_columns {
+            let value = match self.u8()? {
+                b'n' => TupleValue::Null,
+                b'u' => TupleValue::UnchangedToast,
+                b't' => {
+                    let len = self.u32()? as usize;
+                    let text =
+                        String::from_utf8(self.take(len)?.to_vec()).context(InvalidTextSnafu)?;
+                    TupleValue::Text(text)
+                }
+                kind => {
+                    return UnknownTupleDataKindSnafu {
+                        kind: char::from(kind),
+                    }
+                    .fail()
+                }
+            };
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuple(values: &[Option<&str>]) -> Vec<u8> {
+        let mut data = u16::try_from(values.len())
+            .expect("valid length")
+            .to_be_bytes()
+            .to_vec();
+        for value in values {
+            match value {
+                Some(value) => {
+                    data.push(b't');
+                    data.extend_from_slice(
+                        &u32::try_from(value.len())
+                            .expect("valid length")
+                            .to_be_bytes(),
+                    );
+                    data.extend_from_slice(value.as_bytes());
+                }
+                None => data.push(b'n'),
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_relation() {
+        let mut data = vec![b'R'];
+        data.extend_from_slice(&16384_u32.to_be_bytes());
+        data.extend_from_slice(b"public\0orders\0");
+        data.push(b'd');
+        data.extend_from_slice(&2_u16.to_be_bytes());
+        for (flags, name, oid) in [(1_u8, "id", 23_u32), (0, "note", 25)] {
+            data.push(flags);
+            data.extend_from_slice(name.as_bytes());
+            data.push(0);
+            data.extend_from_slice(&oid.to_be_bytes());
+            data.extend_from_slice(&(-1_i32).to_be_bytes());
+        }
+
+        let message = Message::parse(&data).expect("valid message");
+        assert_eq!(
+            message,
+            Message::Relation(Relation {
+                id: 16384,
+                namespace: "public".to_string(),
+                name: "orders".to_string(),
+                columns: vec![
+                    RelationColumn {
+                        name: "id".to_string(),
+                        type_oid: 23,
+                        is_key: true,
+                    },
+                    RelationColumn {
+                        name: "note".to_string(),
+                        type_oid: 25,
+                        is_key: false,
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_row_changes() {
+        let mut insert = vec![b'I'];
+        insert.extend_from_slice(&16384_u32.to_be_bytes());
+        insert.push(b'N');
+        insert.extend_from_slice(&tuple(&[Some("1"), None]));
+        assert_eq!(
+            Message::parse(&insert).expect("valid message"),
+            Message::Insert {
+                relation_id: 16384,
+                new: vec![TupleValue::Text("1".to_string()), TupleValue::Null],
+            }
+        );
+
+        let mut update = vec![b'U'];
+        update.extend_from_slice(&16384_u32.to_be_bytes());
+        update.push(b'K');
+        update.extend_from_slice(&tuple(&[Some("1"), None]));
+        update.push(b'N');
+        update.extend_from_slice(&tuple(&[Some("2"), Some("moved")]));
+        assert_eq!(
+            Message::parse(&update).expect("valid message"),
+            Message::Update {
+                relation_id: 16384,
+                old: Some(vec![TupleValue::Text("1".to_string()), TupleValue::Null]),
+                new: vec![
+                    TupleValue::Text("2".to_string()),
+                    TupleValue::Text("moved".to_string())
+                ],
+            }
+        );
+
+        let mut delete = vec![b'D'];
+        delete.extend_from_slice(&16384_u32.to_be_bytes());
+        delete.push(b'N');
+        assert!(matches!(
+            Message::parse(&delete),
+            Err(Error::UnexpectedTupleType {
+                kind: 'N',
+                message_type: 'D'
+            })
+        ));
+
+        assert!(matches!(
+            Message::parse(&insert[..insert.len() - 1]),
+            Err(Error::UnexpectedEndOfMessage)
+        ));
+    }
+}
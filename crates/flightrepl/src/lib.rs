@@ -316,8 +316,14 @@ async fn get_and_display_nql_records(
     let start_time = Instant::now();
 
     let resp = send_nsql_request(&Client::new(), endpoint, query, LlmRuntime::Openai).await?;
+    let mut resp: serde_json::Value = serde_json::from_str(&resp)?;
 
-    let jsonl_resp = json_array_to_jsonl(&resp)?;
+    // Show the SQL generated for the question before its results.
+    if let Some(sql) = resp["sql"].as_str() {
+        println!("{}", Colour::Fixed(8).paint(sql));
+    }
+
+    let jsonl_resp = json_array_to_jsonl(resp["results"].take())?;
 
     let (schema, _) = arrow_json::reader::infer_json_schema(jsonl_resp.as_bytes(), None)?;
 
@@ -336,9 +342,11 @@ async fn get_and_display_nql_records(
     Ok(())
 }
 
-/// Convert a JSON array string to a JSONL string.
-fn json_array_to_jsonl(json_array_str: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let json_array: Vec<serde_json::Value> = serde_json::from_str(json_array_str)?;
+/// Convert a JSON array to a JSONL string.
+fn json_array_to_jsonl(
+    json_array: serde_json::Value,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let json_array: Vec<serde_json::Value> = serde_json::from_value(json_array)?;
 
     let jsonl_strings: Vec<String> = json_array
         .into_iter()
@@ -20,7 +20,10 @@ use candle_transformers::{generation::LogitsProcessor, models::quantized_llama::
 use snafu::ResultExt;
 use tokenizers::Tokenizer;
 
-use super::{Chat, Error as ChatError, FailedToLoadModelSnafu, FailedToLoadTokenizerSnafu, Result};
+use super::{
+    Chat, Error as ChatError, FailedToLoadModelSnafu, FailedToLoadTokenizerSnafu,
+    FailedToRunModelSnafu, Result,
+};
 use candle_core::{quantized::gguf_file, Tensor};
 
 struct InferenceHyperparams {
@@ -57,13 +60,18 @@ pub struct CandleLlama {
 
 #[async_trait]
 impl Chat for CandleLlama {
-    async fn run(&mut self, prompt: String) -> Result<Option<String>> {
+    async fn run(&self, prompt: String) -> Result<Option<String>> {
         // tknzr.clone() is bad
-        Self::perform_inference(
-            prompt,
-            TokenOutputStream::new(self.tknzr.clone()),
-            &mut self.mdl.clone(),
-        )
+        let tknzr = self.tknzr.clone();
+        let mut mdl = self.mdl.clone();
+
+        // Inference is CPU-bound, so it runs on a blocking thread to not stall concurrent requests.
+        tokio::task::spawn_blocking(move || {
+            Self::perform_inference(prompt, TokenOutputStream::new(tknzr), &mut mdl)
+        })
+        .await
+        .boxed()
+        .context(FailedToRunModelSnafu)?
         .context(FailedToLoadModelSnafu)
     }
 }
@@ -14,12 +14,14 @@ limitations under the License.
 #![allow(clippy::borrowed_box)]
 #![allow(clippy::needless_pass_by_value)]
 
-use super::{message_to_content, Chat, Error as ChatError, FailedToRunModelSnafu, Result};
+use super::{
+    completion_to_choice, request_to_prompt, Chat, Error as ChatError, FailedToRunModelSnafu,
+    Result,
+};
 use async_openai::{
     error::{ApiError, OpenAIError},
     types::{
-        ChatChoice, ChatCompletionResponseMessage, CompletionUsage, CreateChatCompletionRequest,
-        CreateChatCompletionResponse, Role,
+        ChatChoice, CompletionUsage, CreateChatCompletionRequest, CreateChatCompletionResponse,
     },
 };
 use async_stream::stream;
@@ -269,7 +271,7 @@ impl MistralLlama {
         })
     }
 
-    async fn run_internal(&mut self, prompt: String) -> Result<Option<(String, Usage)>> {
+    async fn run_internal(&self, prompt: String) -> Result<Option<(String, Usage)>> {
         let (snd, mut rcv) = channel::<MistralResponse>(10_000);
         tracing::trace!("Sending request to pipeline");
         self.pipeline
@@ -306,13 +308,13 @@ impl MistralLlama {
 
 #[async_trait]
 impl Chat for MistralLlama {
-    async fn health(&mut self) -> Result<()> {
+    async fn health(&self) -> Result<()> {
         // If [`MistralLlama`] is instantiated successfully, it is healthy.
         Ok(())
     }
 
     async fn stream<'a>(
-        &mut self,
+        &self,
         prompt: String,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<Option<String>>> + Send>>> {
         let (snd, mut rcv) = channel::<MistralResponse>(1000);
@@ -375,7 +377,7 @@ impl Chat for MistralLlama {
         })))
     }
 
-    async fn run(&mut self, prompt: String) -> Result<Option<String>> {
+    async fn run(&self, prompt: String) -> Result<Option<String>> {
         match self.run_internal(prompt).await? {
             Some((response, _usage)) => Ok(Some(response)),
             None => Ok(None),
@@ -384,16 +386,11 @@ impl Chat for MistralLlama {
 
     #[allow(deprecated, clippy::cast_possible_truncation)]
     async fn chat_request(
-        &mut self,
+        &self,
         req: CreateChatCompletionRequest,
     ) -> Result<CreateChatCompletionResponse, OpenAIError> {
         let model_id = req.model.clone();
-        let prompt = req
-            .messages
-            .iter()
-            .map(message_to_content)
-            .collect::<Vec<String>>()
-            .join("\n");
+        let prompt = request_to_prompt(&req);
         let (choices, usage): (Vec<ChatChoice>, Option<Usage>) =
             match self.run_internal(prompt).await.map_err(|e| {
                 OpenAIError::ApiError(ApiError {
@@ -404,17 +401,7 @@ impl Chat for MistralLlama {
                 })
             })? {
                 Some((resp, usage)) => {
-                    let choice = vec![ChatChoice {
-                        message: ChatCompletionResponseMessage {
-                            content: Some(resp),
-                            tool_calls: None,
-                            role: Role::System,
-                            function_call: None,
-                        },
-                        index: 0,
-                        finish_reason: None,
-                        logprobs: None,
-                    }];
+                    let choice = vec![completion_to_choice(&req, resp)];
                     (choice, Some(usage))
                 }
                 None => (vec![], None),
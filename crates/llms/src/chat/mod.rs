@@ -28,8 +28,9 @@ use async_openai::{
         ChatCompletionRequestSystemMessage, ChatCompletionRequestToolMessage,
         ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
         ChatCompletionResponseMessage, ChatCompletionResponseStream,
-        ChatCompletionStreamResponseDelta, CreateChatCompletionRequest,
-        CreateChatCompletionResponse, CreateChatCompletionStreamResponse, Role,
+        ChatCompletionStreamResponseDelta, ChatCompletionTool, ChatCompletionToolChoiceOption,
+        CreateChatCompletionRequest, CreateChatCompletionResponse,
+        CreateChatCompletionStreamResponse, FinishReason, Role,
     },
 };
 
@@ -39,6 +40,8 @@ pub mod candle;
 #[cfg(feature = "mistralrs")]
 pub mod mistral;
 
+pub mod tools;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum LlmRuntime {
@@ -118,10 +121,17 @@ pub fn message_to_content(message: &ChatCompletionRequestMessage) -> String {
         ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
             content,
             ..
-        })
-        | ChatCompletionRequestMessage::Tool(ChatCompletionRequestToolMessage {
-            content, ..
         }) => content.clone(),
+        ChatCompletionRequestMessage::Tool(ChatCompletionRequestToolMessage {
+            content,
+            tool_call_id,
+            ..
+        }) => format!("Result of tool call {tool_call_id}: {content}"),
+        ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+            content: None,
+            tool_calls: Some(tool_calls),
+            ..
+        }) => tools::format_tool_calls(tool_calls),
         ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
             content,
             ..
@@ -133,13 +143,63 @@ pub fn message_to_content(message: &ChatCompletionRequestMessage) -> String {
     }
 }
 
+/// Convert a [`CreateChatCompletionRequest`] to a single prompt, for models without native
+/// support for chat messages. The tools of the request, if any, are described at its start.
+#[must_use]
+pub fn request_to_prompt(req: &CreateChatCompletionRequest) -> String {
+    let messages = req
+        .messages
+        .iter()
+        .map(message_to_content)
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    match offered_tools(req) {
+        Some(tools) => format!("{}\n{messages}", tools::tools_prompt(tools)),
+        None => messages,
+    }
+}
+
+/// Convert the completion of a prompt from [`request_to_prompt`] to a [`ChatChoice`], with the
+/// tool calls it requested if the request offered tools.
+#[must_use]
+#[allow(deprecated)]
+pub fn completion_to_choice(req: &CreateChatCompletionRequest, completion: String) -> ChatChoice {
+    let tool_calls = offered_tools(req).and_then(|_| tools::parse_tool_calls(&completion));
+    let finish_reason = tool_calls.as_ref().map(|_| FinishReason::ToolCalls);
+
+    ChatChoice {
+        message: ChatCompletionResponseMessage {
+            content: if tool_calls.is_some() {
+                None
+            } else {
+                Some(completion)
+            },
+            tool_calls,
+            role: Role::System,
+            function_call: None,
+        },
+        index: 0,
+        finish_reason,
+        logprobs: None,
+    }
+}
+
+fn offered_tools(req: &CreateChatCompletionRequest) -> Option<&[ChatCompletionTool]> {
+    match (&req.tools, &req.tool_choice) {
+        (_, Some(ChatCompletionToolChoiceOption::None)) | (None, _) => None,
+        (Some(tools), _) if tools.is_empty() => None,
+        (Some(tools), _) => Some(tools),
+    }
+}
+
 #[async_trait]
 pub trait Chat: Sync + Send {
-    async fn run(&mut self, prompt: String) -> Result<Option<String>>;
+    async fn run(&self, prompt: String) -> Result<Option<String>>;
 
     /// A basic health check to ensure the model can process future [`Self::run`] requests.
     /// Default implementation is a basic call to [`Self::run`].
-    async fn health(&mut self) -> Result<()> {
+    async fn health(&self) -> Result<()> {
         self.run("health".to_string())
             .await
             .boxed()
@@ -148,7 +208,7 @@ pub trait Chat: Sync + Send {
     }
 
     async fn stream<'a>(
-        &mut self,
+        &self,
         prompt: String,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<Option<String>>> + Send>>> {
         let resp = self.run(prompt).await;
@@ -157,16 +217,11 @@ pub trait Chat: Sync + Send {
 
     #[allow(deprecated)]
     async fn chat_stream(
-        &mut self,
+        &self,
         req: CreateChatCompletionRequest,
     ) -> Result<ChatCompletionResponseStream, OpenAIError> {
         let model_id = req.model.clone();
-        let prompt = req
-            .messages
-            .iter()
-            .map(message_to_content)
-            .collect::<Vec<String>>()
-            .join("\n");
+        let prompt = request_to_prompt(&req);
 
         let mut stream = self.stream(prompt).await.map_err(|e| {
             OpenAIError::ApiError(ApiError {
@@ -223,16 +278,11 @@ pub trait Chat: Sync + Send {
     /// implementation will be constructed based on the trait's [`run`] method.
     #[allow(deprecated)]
     async fn chat_request(
-        &mut self,
+        &self,
         req: CreateChatCompletionRequest,
     ) -> Result<CreateChatCompletionResponse, OpenAIError> {
         let model_id = req.model.clone();
-        let prompt = req
-            .messages
-            .iter()
-            .map(message_to_content)
-            .collect::<Vec<String>>()
-            .join("\n");
+        let prompt = request_to_prompt(&req);
         let choices: Vec<ChatChoice> = match self.run(prompt).await.map_err(|e| {
             OpenAIError::ApiError(ApiError {
                 message: e.to_string(),
@@ -241,17 +291,7 @@ pub trait Chat: Sync + Send {
                 code: None,
             })
         })? {
-            Some(resp) => vec![ChatChoice {
-                message: ChatCompletionResponseMessage {
-                    content: Some(resp),
-                    tool_calls: None,
-                    role: Role::System,
-                    function_call: None,
-                },
-                index: 0,
-                finish_reason: None,
-                logprobs: None,
-            }],
+            Some(resp) => vec![completion_to_choice(&req, resp)],
             None => vec![],
         };
 
@@ -0,0 +1,136 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+     https://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Tool calling for models without native support for it. The tools of a request are described
+//! in the prompt, and the model is asked to reply with a JSON object to call them.
+
+use async_openai::types::{
+    ChatCompletionMessageToolCall, ChatCompletionTool, ChatCompletionToolType, FunctionCall,
+};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize)]
+struct ToolCalls {
+    tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Describes the tools and how to call them, to prepend to the prompt.
+#[must_use]
+pub fn tools_prompt(tools: &[ChatCompletionTool]) -> String {
+    let descriptions: Vec<String> = tools
+        .iter()
+        .map(|tool| {
+            let mut description = format!("- {}", tool.function.name);
+            if let Some(text) = &tool.function.description {
+                description.push_str(&format!(": {text}"));
+            }
+            if let Some(parameters) = &tool.function.parameters {
+                description.push_str(&format!("\n  Parameters (JSON schema): {parameters}"));
+            }
+            description
+        })
+        .collect();
+
+    format!(
+        "You can call the following tools:\n{}\n\nTo call tools, reply with only a JSON object of the form \
+         {{\"tool_calls\": [{{\"name\": \"<tool name>\", \"arguments\": {{<arguments>}}}}]}}. \
+         The results of the tools will be provided in the next message. \
+         If no tool is needed, reply to the user directly.\n",
+        descriptions.join("\n")
+    )
+}
+
+/// Parses the tool calls of a completion that replied to a [`tools_prompt`], if it called any.
+#[must_use]
+pub fn parse_tool_calls(completion: &str) -> Option<Vec<ChatCompletionMessageToolCall>> {
+    // Models often wrap the JSON object in a markdown code block.
+    let start = completion.find('{')?;
+    let end = completion.rfind('}')?;
+    let calls: ToolCalls = serde_json::from_str(completion.get(start..=end)?).ok()?;
+    if calls.tool_calls.is_empty() {
+        return None;
+    }
+
+    Some(
+        calls
+            .tool_calls
+            .into_iter()
+            .map(|call| ChatCompletionMessageToolCall {
+                id: format!(
+                    "call_{}",
+                    thread_rng()
+                        .sample_iter(&Alphanumeric)
+                        .take(10)
+                        .map(char::from)
+                        .collect::<String>()
+                ),
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionCall {
+                    name: call.name,
+                    arguments: match call.arguments {
+                        Value::String(arguments) => arguments,
+                        arguments => arguments.to_string(),
+                    },
+                },
+            })
+            .collect(),
+    )
+}
+
+/// Formats tool calls the way the model is asked to reply in a [`tools_prompt`], so previous
+/// calls can be included in later prompts.
+#[must_use]
+pub fn format_tool_calls(tool_calls: &[ChatCompletionMessageToolCall]) -> String {
+    let calls = ToolCalls {
+        tool_calls: tool_calls
+            .iter()
+            .map(|call| ToolCall {
+                name: call.function.name.clone(),
+                arguments: serde_json::from_str(&call.function.arguments)
+                    .unwrap_or_else(|_| Value::String(call.function.arguments.clone())),
+            })
+            .collect(),
+    };
+
+    serde_json::to_string(&calls).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tool_calls() {
+        let completion = "```json\n{\"tool_calls\": [{\"name\": \"sql\", \"arguments\": {\"query\": \"SELECT 1\"}}]}\n```";
+        let tool_calls = parse_tool_calls(completion).expect("tool calls");
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].function.name, "sql");
+        assert_eq!(tool_calls[0].function.arguments, r#"{"query":"SELECT 1"}"#);
+        assert_eq!(
+            format_tool_calls(&tool_calls),
+            r#"{"tool_calls":[{"name":"sql","arguments":{"query":"SELECT 1"}}]}"#
+        );
+
+        assert!(parse_tool_calls("There are 3 datasets.").is_none());
+        assert!(parse_tool_calls("{\"answer\": 42}").is_none());
+    }
+}
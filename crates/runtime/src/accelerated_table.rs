@@ -20,12 +20,15 @@ use std::{any::Any, sync::Arc, time::Duration};
 use crate::component::dataset::acceleration::{RefreshMode, ZeroResultsAction};
 use crate::component::dataset::TimeFormat;
 use crate::datafusion::SPICE_RUNTIME_SCHEMA;
+use crate::embeddings::full_text::FullTextIndex;
+use crate::embeddings::index::EmbeddingIndex;
+use crate::embeddings::SearchIndex;
 use arrow::array::UInt64Array;
 use arrow::datatypes::SchemaRef;
 use arrow::error::ArrowError;
 use async_trait::async_trait;
 use cache::QueryResultsCacheProvider;
-use data_components::cdc::ChangesStream;
+use data_components::cdc::{AppendStream, ChangesStream};
 use data_components::delete::get_deletion_provider;
 use datafusion::error::{DataFusionError, Result as DataFusionResult};
 use datafusion::execution::context::SessionState;
@@ -67,6 +70,11 @@ pub enum Error {
         source: datafusion::error::DataFusionError,
     },
 
+    #[snafu(display("Unable to read append stream: {source}"))]
+    UnableToReadAppendStream {
+        source: data_components::cdc::StreamError,
+    },
+
     #[snafu(display("Unable to create MemTable from data update: {source}"))]
     UnableToCreateMemTableFromUpdate {
         source: datafusion::error::DataFusionError,
@@ -96,6 +104,11 @@ pub enum Error {
         source: datafusion::error::DataFusionError,
     },
 
+    #[snafu(display("Failed to compute embeddings for changed data: {source}"))]
+    FailedToEmbedData {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
     #[snafu(display("The accelerated table does not support delete operations"))]
     AcceleratedTableDoesntSupportDelete {},
 
@@ -136,6 +149,7 @@ pub struct AcceleratedTable {
     zero_results_action: ZeroResultsAction,
     refresh_params: Arc<RwLock<refresh::Refresh>>,
     refresher: Arc<refresh::Refresher>,
+    search_indexes: Vec<Arc<dyn SearchIndex>>,
 }
 
 fn validate_refresh_data_window(
@@ -167,6 +181,8 @@ pub struct Builder {
     zero_results_action: ZeroResultsAction,
     cache_provider: Option<Arc<QueryResultsCacheProvider>>,
     changes_stream: Option<ChangesStream>,
+    append_stream: Option<AppendStream>,
+    search_indexes: Vec<Arc<dyn SearchIndex>>,
 }
 
 impl Builder {
@@ -185,6 +201,8 @@ impl Builder {
             zero_results_action: ZeroResultsAction::default(),
             cache_provider: None,
             changes_stream: None,
+            append_stream: None,
+            search_indexes: Vec::new(),
         }
     }
 
@@ -206,6 +224,12 @@ impl Builder {
         self
     }
 
+    /// Set the vector and full-text indexes to maintain over the accelerated table
+    pub fn search_indexes(&mut self, search_indexes: Vec<Arc<dyn SearchIndex>>) -> &mut Self {
+        self.search_indexes = search_indexes;
+        self
+    }
+
     /// Set the changes stream for the accelerated table
     ///
     /// # Panics
@@ -217,6 +241,18 @@ impl Builder {
         self
     }
 
+    /// Set the append stream for the accelerated table, used instead of scanning the federated
+    /// table
+    ///
+    /// # Panics
+    ///
+    /// Panics if the refresh mode isn't `RefreshMode::Append`.
+    pub fn append_stream(&mut self, append_stream: AppendStream) -> &mut Self {
+        assert!(self.refresh.mode == RefreshMode::Append);
+        self.append_stream = Some(append_stream);
+        self
+    }
+
     /// Build the accelerated table
     ///
     /// # Panics
@@ -227,7 +263,12 @@ impl Builder {
 
         let (acceleration_refresh_mode, refresh_trigger) = match self.refresh.mode {
             RefreshMode::Append => {
-                if self.refresh.time_column.is_none() {
+                if let Some(append_stream) = self.append_stream {
+                    (
+                        refresh::AccelerationRefreshMode::AppendStream(append_stream),
+                        None,
+                    )
+                } else if self.refresh.time_column.is_none() {
                     (refresh::AccelerationRefreshMode::Append(None), None)
                 } else {
                     let (start_refresh, on_start_refresh) = mpsc::channel::<()>(1);
@@ -264,6 +305,7 @@ impl Builder {
             Arc::clone(&self.accelerator),
         );
         refresher.cache_provider(self.cache_provider.clone());
+        refresher.search_indexes(self.search_indexes.clone());
 
         let refresh_handle = refresher
             .start(acceleration_refresh_mode, ready_sender)
@@ -273,12 +315,19 @@ impl Builder {
         let mut handlers = vec![];
         handlers.push(refresh_handle);
 
+        for index in &self.search_indexes {
+            handlers.push(tokio::spawn(
+                Arc::clone(index).start(Arc::clone(&self.accelerator)),
+            ));
+        }
+
         if let Some(retention) = self.retention {
             let retention_check_handle = tokio::spawn(AcceleratedTable::start_retention_check(
                 self.dataset_name.clone(),
                 Arc::clone(&self.accelerator),
                 retention,
                 self.cache_provider.clone(),
+                self.search_indexes.clone(),
             ));
             handlers.push(retention_check_handle);
         }
@@ -292,6 +341,7 @@ impl Builder {
                 zero_results_action: self.zero_results_action,
                 refresh_params,
                 refresher,
+                search_indexes: self.search_indexes,
             },
             is_ready,
         )
@@ -344,6 +394,39 @@ impl AcceleratedTable {
         Arc::clone(&self.accelerator)
     }
 
+    /// Schedule a rebuild of the search indexes, after data was written to the accelerator
+    /// outside of a refresh.
+    pub fn mark_search_indexes_stale(&self) {
+        for index in &self.search_indexes {
+            index.mark_stale();
+        }
+    }
+
+    /// Get the vector index over the embeddings of `column`, if the column is indexed.
+    #[must_use]
+    pub fn embedding_index(&self, column: &str) -> Option<Arc<EmbeddingIndex>> {
+        self.search_indexes
+            .iter()
+            .filter_map(|index| {
+                Arc::clone(index)
+                    .as_any_arc()
+                    .downcast::<EmbeddingIndex>()
+                    .ok()
+            })
+            .find(|index| index.column() == column)
+    }
+
+    /// Get the full-text index over the dataset's text columns, if any.
+    #[must_use]
+    pub fn full_text_index(&self) -> Option<Arc<FullTextIndex>> {
+        self.search_indexes.iter().find_map(|index| {
+            Arc::clone(index)
+                .as_any_arc()
+                .downcast::<FullTextIndex>()
+                .ok()
+        })
+    }
+
     pub async fn update_refresh_sql(&self, refresh_sql: Option<String>) -> Result<()> {
         let dataset_name = &self.dataset_name;
 
@@ -366,6 +449,7 @@ impl AcceleratedTable {
         accelerator: Arc<dyn TableProvider>,
         retention: Retention,
         cache_provider: Option<Arc<QueryResultsCacheProvider>>,
+        search_indexes: Vec<Arc<dyn SearchIndex>>,
     ) {
         let time_column = retention.time_column;
         let retention_period = retention.period;
@@ -450,6 +534,10 @@ impl AcceleratedTable {
                                             tracing::error!("Failed to invalidate cached results for dataset {}: {e}", &dataset_name);
                                         }
                                     }
+
+                                    for index in &search_indexes {
+                                        index.mark_stale();
+                                    }
                                 }
                             }
                         };
@@ -21,9 +21,10 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use crate::accelerated_table::refresh_task::RefreshTask;
 use crate::component::dataset::acceleration::RefreshMode;
 use crate::component::dataset::TimeFormat;
+use crate::embeddings::SearchIndex;
 use arrow::datatypes::Schema;
 use cache::QueryResultsCacheProvider;
-use data_components::cdc::ChangesStream;
+use data_components::cdc::{AppendStream, ChangesStream};
 use datafusion::common::TableReference;
 use datafusion::datasource::TableProvider;
 use futures::future::BoxFuture;
@@ -208,6 +209,7 @@ impl Default for Refresh {
 pub(crate) enum AccelerationRefreshMode {
     Full(Receiver<()>),
     Append(Option<Receiver<()>>),
+    AppendStream(AppendStream),
     Changes(ChangesStream),
 }
 
@@ -217,6 +219,7 @@ pub struct Refresher {
     refresh: Arc<RwLock<Refresh>>,
     accelerator: Arc<dyn TableProvider>,
     cache_provider: Option<Arc<QueryResultsCacheProvider>>,
+    search_indexes: Vec<Arc<dyn SearchIndex>>,
     refresh_task_runner: RefreshTaskRunner,
 }
 
@@ -240,6 +243,7 @@ impl Refresher {
             refresh,
             accelerator,
             cache_provider: None,
+            search_indexes: Vec::new(),
             refresh_task_runner,
         }
     }
@@ -252,6 +256,11 @@ impl Refresher {
         self
     }
 
+    pub fn search_indexes(&mut self, search_indexes: Vec<Arc<dyn SearchIndex>>) -> &mut Self {
+        self.search_indexes = search_indexes;
+        self
+    }
+
     pub(crate) async fn start(
         &mut self,
         acceleration_refresh_mode: AccelerationRefreshMode,
@@ -264,9 +273,12 @@ impl Refresher {
                 if let (Some(receiver), Some(_)) = (receiver, time_column) {
                     receiver
                 } else {
-                    return self.start_streaming_append(ready_sender);
+                    return self.start_streaming_append(None, ready_sender);
                 }
             }
+            AccelerationRefreshMode::AppendStream(stream) => {
+                return self.start_streaming_append(Some(stream), ready_sender);
+            }
             AccelerationRefreshMode::Full(receiver) => receiver,
             AccelerationRefreshMode::Changes(stream) => {
                 return self.start_changes_stream(stream, ready_sender);
@@ -280,6 +292,7 @@ impl Refresher {
         let refresh = Arc::clone(&self.refresh);
 
         let cache_provider = self.cache_provider.clone();
+        let search_indexes = self.search_indexes.clone();
 
         let refresh_check_interval = self.refresh.read().await.check_interval;
 
@@ -322,6 +335,10 @@ impl Refresher {
                                     tracing::error!("Failed to invalidate cached results for dataset {}: {e}", &dataset_name.to_string());
                                 }
                             }
+
+                            for index in &search_indexes {
+                                index.mark_stale();
+                            }
                         }
 
                         if let Some(refresh_check_interval) = refresh_check_interval {
@@ -335,20 +352,24 @@ impl Refresher {
 
     fn start_streaming_append(
         &mut self,
+        append_stream: Option<AppendStream>,
         ready_sender: oneshot::Sender<()>,
     ) -> tokio::task::JoinHandle<()> {
-        let refresh_task = Arc::new(RefreshTask::new(
-            self.dataset_name.clone(),
-            Arc::clone(&self.federated),
-            Arc::clone(&self.refresh),
-            Arc::clone(&self.accelerator),
-        ));
+        let refresh_task = Arc::new(
+            RefreshTask::new(
+                self.dataset_name.clone(),
+                Arc::clone(&self.federated),
+                Arc::clone(&self.refresh),
+                Arc::clone(&self.accelerator),
+            )
+            .with_search_indexes(self.search_indexes.clone()),
+        );
 
         let cache_provider = self.cache_provider.clone();
 
         tokio::spawn(async move {
             if let Err(err) = refresh_task
-                .start_streaming_append(cache_provider, Some(ready_sender))
+                .start_streaming_append(append_stream, cache_provider, Some(ready_sender))
                 .await
             {
                 tracing::error!("Append refresh failed with error: {err}");
@@ -361,12 +382,15 @@ impl Refresher {
         changes_stream: ChangesStream,
         ready_sender: oneshot::Sender<()>,
     ) -> tokio::task::JoinHandle<()> {
-        let refresh_task = Arc::new(RefreshTask::new(
-            self.dataset_name.clone(),
-            Arc::clone(&self.federated),
-            Arc::clone(&self.refresh),
-            Arc::clone(&self.accelerator),
-        ));
+        let refresh_task = Arc::new(
+            RefreshTask::new(
+                self.dataset_name.clone(),
+                Arc::clone(&self.federated),
+                Arc::clone(&self.refresh),
+                Arc::clone(&self.accelerator),
+            )
+            .with_search_indexes(self.search_indexes.clone()),
+        );
 
         let cache_provider = self.cache_provider.clone();
 
@@ -21,6 +21,7 @@ use arrow::{
 };
 use async_stream::stream;
 use cache::QueryResultsCacheProvider;
+use data_components::cdc::{AppendEnvelope, AppendStream};
 use datafusion_table_providers::util::retriable_error::{
     check_and_mark_retriable_error, is_retriable_error,
 };
@@ -30,6 +31,8 @@ use util::fibonacci_backoff::FibonacciBackoffBuilder;
 use util::{retry, RetryError};
 
 use crate::dataupdate::StreamingDataUpdateExecutionPlan;
+use crate::embeddings::table::EmbeddingTable;
+use crate::embeddings::SearchIndex;
 use crate::{
     component::dataset::acceleration::RefreshMode,
     dataconnector::get_data,
@@ -64,6 +67,9 @@ use super::refresh::Refresh;
 
 mod changes;
 
+/// A data update to append, along with the envelope to commit once it is written.
+type AppendUpdate = (Option<SystemTime>, DataUpdate, Option<AppendEnvelope>);
+
 #[derive(Debug, Clone, Default)]
 struct RefreshStat {
     pub num_rows: usize,
@@ -75,6 +81,7 @@ pub struct RefreshTask {
     federated: Arc<dyn TableProvider>,
     refresh: Arc<RwLock<Refresh>>,
     accelerator: Arc<dyn TableProvider>,
+    search_indexes: Vec<Arc<dyn SearchIndex>>,
 }
 
 impl RefreshTask {
@@ -90,18 +97,42 @@ impl RefreshTask {
             federated,
             refresh,
             accelerator,
+            search_indexes: Vec::new(),
         }
     }
 
+    /// Set the search indexes to rebuild after data is written to the accelerator.
+    #[must_use]
+    pub fn with_search_indexes(mut self, search_indexes: Vec<Arc<dyn SearchIndex>>) -> Self {
+        self.search_indexes = search_indexes;
+        self
+    }
+
+    /// Appends data to the accelerator as it streams from the federated table, or from the
+    /// `append_stream` of the data connector if it has one.
     pub async fn start_streaming_append(
         &self,
+        append_stream: Option<AppendStream>,
         cache_provider: Option<Arc<QueryResultsCacheProvider>>,
         ready_sender: Option<oneshot::Sender<()>>,
     ) -> super::Result<()> {
         self.mark_dataset_status(status::ComponentStatus::Refreshing)
             .await;
 
-        let mut stream = Box::pin(self.get_append_stream());
+        self.reuse_materialized_embeddings().await;
+
+        // Appends with offsets must not be skipped, as the offsets of later appends would be
+        // committed past them.
+        let committed = append_stream.is_some();
+        let mut stream = match append_stream {
+            Some(append_stream) => self.get_committed_append_stream(append_stream).boxed(),
+            None => self
+                .get_append_stream()
+                .map(|update| {
+                    update.map(|(start_time, data_update)| (start_time, data_update, None))
+                })
+                .boxed(),
+        };
 
         let dataset_name = self.dataset_name.clone();
 
@@ -109,34 +140,51 @@ impl RefreshTask {
 
         while let Some(update) = stream.next().await {
             match update {
-                Ok((start_time, data_update)) => {
+                Ok((start_time, data_update, append_envelope)) => {
                     // write_data_update updates dataset status and logs errors so we don't do this here
                     if self
                         .write_data_update(start_time, data_update)
                         .await
-                        .is_ok()
+                        .is_err()
                     {
-                        if let Some(ready_sender) = ready_sender.take() {
-                            ready_sender.send(()).ok();
+                        if committed {
+                            tracing::error!("Stopped streaming appends for dataset {dataset_name}, as an append couldn't be written. The dataset will continue from the last committed append when it is reloaded.");
+                            break;
                         }
+                        continue;
+                    }
 
-                        if let Some(cache_provider) = &cache_provider {
-                            if let Err(e) = cache_provider
-                                .invalidate_for_table(dataset_name.clone())
-                                .await
-                            {
-                                tracing::error!(
-                                    "Failed to invalidate cached results for dataset {}: {e}",
-                                    &dataset_name.to_string()
-                                );
-                            }
+                    if let Some(ready_sender) = ready_sender.take() {
+                        ready_sender.send(()).ok();
+                    }
+
+                    if let Some(append_envelope) = append_envelope {
+                        if let Err(e) = append_envelope.commit() {
+                            tracing::warn!("Failed to commit an append for dataset {dataset_name}. It will be appended again when the dataset is reloaded: {e}");
                         }
                     }
+
+                    if let Some(cache_provider) = &cache_provider {
+                        if let Err(e) = cache_provider
+                            .invalidate_for_table(dataset_name.clone())
+                            .await
+                        {
+                            tracing::error!(
+                                "Failed to invalidate cached results for dataset {}: {e}",
+                                &dataset_name.to_string()
+                            );
+                        }
+                    }
+
+                    self.mark_search_indexes_stale();
                 }
                 Err(e) => {
                     tracing::error!("Error getting update for dataset {dataset_name}: {e}");
                     self.mark_dataset_status(status::ComponentStatus::Error)
                         .await;
+                    if committed {
+                        break;
+                    }
                 }
             }
         }
@@ -198,6 +246,8 @@ impl RefreshTask {
 
         let start_time = SystemTime::now();
 
+        self.reuse_materialized_embeddings().await;
+
         let get_data_update_result = match mode {
             RefreshMode::Full => self.get_full_update().await,
             RefreshMode::Append => self.get_incremental_append_update().await,
@@ -309,6 +359,8 @@ impl RefreshTask {
             self.trace_dataset_loaded(start_time, refresh_stat.num_rows, refresh_stat.memory_size);
         }
 
+        self.record_materialized_embeddings(overwrite).await;
+
         self.mark_dataset_status(status::ComponentStatus::Ready)
             .await;
 
@@ -449,6 +501,36 @@ impl RefreshTask {
         }
     }
 
+    /// Converts the record batches of a data connector's append stream to data updates, along with
+    /// the envelope to commit once the update is written.
+    fn get_committed_append_stream(
+        &self,
+        append_stream: AppendStream,
+    ) -> impl Stream<Item = super::Result<AppendUpdate>> + '_ {
+        let schema = self.federated.schema();
+
+        append_stream.then(move |append_envelope| {
+            let schema = Arc::clone(&schema);
+            async move {
+                let append_envelope =
+                    append_envelope.context(super::UnableToReadAppendStreamSnafu)?;
+                let data = self
+                    .embed_change(append_envelope.record_batch.clone())
+                    .await?;
+
+                Ok((
+                    None,
+                    DataUpdate {
+                        schema,
+                        data: vec![data],
+                        update_type: UpdateType::Append,
+                    },
+                    Some(append_envelope),
+                ))
+            }
+        })
+    }
+
     fn trace_dataset_loaded(&self, start_time: SystemTime, num_rows: usize, memory_size: usize) {
         if let Ok(elapse) = util::humantime_elapsed(start_time) {
             let dataset_name = &self.dataset_name;
@@ -714,6 +796,32 @@ impl RefreshTask {
         }
     }
 
+    /// Reuse the embeddings already written to the accelerator, so that only new or changed rows
+    /// are embedded when data is read from the federated table.
+    async fn reuse_materialized_embeddings(&self) {
+        if let Some(embedding_table) = self.federated.as_any().downcast_ref::<EmbeddingTable>() {
+            embedding_table
+                .reuse_materialized_embeddings(Arc::clone(&self.accelerator))
+                .await;
+        }
+    }
+
+    /// Record which model and chunking the embeddings written to the accelerator were computed
+    /// with, so that they aren't reused once the dataset's embeddings are configured differently.
+    async fn record_materialized_embeddings(&self, overwritten: bool) {
+        if let Some(embedding_table) = self.federated.as_any().downcast_ref::<EmbeddingTable>() {
+            embedding_table
+                .record_materialized_embeddings(overwritten)
+                .await;
+        }
+    }
+
+    fn mark_search_indexes_stale(&self) {
+        for index in &self.search_indexes {
+            index.mark_stale();
+        }
+    }
+
     async fn mark_dataset_status(&self, status: status::ComponentStatus) {
         status::update_dataset(&self.dataset_name, status);
 
@@ -15,6 +15,7 @@ limitations under the License.
 */
 
 use super::RefreshTask;
+use crate::embeddings::table::EmbeddingTable;
 use crate::{dataupdate::StreamingDataUpdateExecutionPlan, status};
 use arrow::array::{Int32Array, Int64Array, RecordBatch, StringArray};
 use arrow::datatypes::DataType;
@@ -71,6 +72,8 @@ impl RefreshTask {
 
         let mut ready_sender = ready_sender;
 
+        self.reuse_materialized_embeddings().await;
+
         while let Some(update) = changes_stream.next().await {
             match update {
                 Ok(change_envelope) => {
@@ -79,6 +82,8 @@ impl RefreshTask {
                         .await
                     {
                         Ok(()) => {
+                            self.record_materialized_embeddings(false).await;
+
                             if let Some(ready_sender) = ready_sender.take() {
                                 ready_sender.send(()).ok();
                             }
@@ -98,11 +103,16 @@ impl RefreshTask {
                                     );
                                 }
                             }
+
+                            self.mark_search_indexes_stale();
                         }
                         Err(e) => {
+                            // The change isn't committed, and neither are the ones after it, so
+                            // the stream resumes from it when the dataset is reloaded.
                             self.mark_dataset_status(status::ComponentStatus::Error)
                                 .await;
-                            tracing::error!("Error writing change for {dataset_name}: {e}");
+                            tracing::error!("Error writing change for {dataset_name}: {e}. Stopped streaming changes.");
+                            break;
                         }
                     }
                 }
@@ -110,6 +120,10 @@ impl RefreshTask {
                     tracing::error!("Changes stream error for {dataset_name}: {e}");
                     self.mark_dataset_status(status::ComponentStatus::Error)
                         .await;
+                    if e.is_fatal() {
+                        tracing::error!("Stopped streaming changes for {dataset_name}, as a change couldn't be read. The dataset resumes from its last written change when it is reloaded.");
+                        break;
+                    }
                 }
             }
         }
@@ -153,7 +167,7 @@ impl RefreshTask {
                         .context(crate::accelerated_table::FailedToWriteDataSnafu)?;
                 }
                 ChangeOperation::Create | ChangeOperation::Update | ChangeOperation::Read => {
-                    let inner_data: RecordBatch = change_batch.data(row);
+                    let inner_data = self.embed_change(change_batch.data(row)).await?;
                     let primary_keys = change_batch.primary_keys(row);
                     let ctx = SessionContext::new();
                     let session_state = ctx.state();
@@ -182,7 +196,22 @@ impl RefreshTask {
                         .await
                         .context(crate::accelerated_table::FailedToWriteDataSnafu)?;
                 }
-                _ => {
+                ChangeOperation::Truncate => {
+                    tracing::info!("Truncating data for {dataset_name}");
+
+                    let ctx = SessionContext::new();
+                    let session_state = ctx.state();
+
+                    let delete_plan = deletion_provider
+                        .delete_from(&session_state, &[lit(true)])
+                        .await
+                        .context(crate::accelerated_table::FailedToWriteDataSnafu)?;
+
+                    collect(delete_plan, ctx.task_ctx())
+                        .await
+                        .context(crate::accelerated_table::FailedToWriteDataSnafu)?;
+                }
+                ChangeOperation::Unknown(_) => {
                     tracing::error!("Unknown change operation {op} for {dataset_name}");
                 }
             }
@@ -191,6 +220,21 @@ impl RefreshTask {
         Ok(())
     }
 
+    /// Compute the embedding columns of changed or appended rows if the dataset has embeddings.
+    /// Only rows whose embedded values changed are sent to the embedding model.
+    pub(super) async fn embed_change(
+        &self,
+        data: RecordBatch,
+    ) -> crate::accelerated_table::Result<RecordBatch> {
+        match self.federated.as_any().downcast_ref::<EmbeddingTable>() {
+            Some(embedding_table) => embedding_table
+                .embed_batch(&data)
+                .await
+                .context(crate::accelerated_table::FailedToEmbedDataSnafu),
+            None => Ok(data),
+        }
+    }
+
     fn get_primary_key_log_fmt(
         data: &RecordBatch,
         primary_keys: &[String],
@@ -0,0 +1,345 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose, Engine};
+use dashmap::DashMap;
+use secrecy::{ExposeSecret, SecretString};
+use snafu::prelude::*;
+use spicepod::component::runtime::AuthConfig;
+use uuid::Uuid;
+
+use crate::secrets::Secrets;
+
+pub mod access_control;
+pub mod api_key;
+pub mod basic;
+pub mod jwt;
+pub mod policy;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to read JWKS file {path}: {source}"))]
+    UnableToReadJwksFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Unable to parse JWKS file {path}: {source}"))]
+    UnableToParseJwks {
+        path: String,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Invalid key in JWKS file {path}: {source}"))]
+    InvalidJwk {
+        path: String,
+        source: jsonwebtoken::errors::Error,
+    },
+
+    #[snafu(display("Invalid table pattern {pattern} in runtime.auth.grants: {source}"))]
+    InvalidGrantPattern {
+        pattern: String,
+        source: globset::Error,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// How long a session token issued by a Flight handshake stays valid.
+const SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The identity of an authenticated caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    pub name: Arc<str>,
+    pub roles: Vec<Arc<str>>,
+
+    /// Claims carried by the credentials, i.e. the payload of a JWT. Empty for other credential types.
+    pub claims: HashMap<String, serde_json::Value>,
+}
+
+impl Principal {
+    #[must_use]
+    pub fn new(name: impl Into<Arc<str>>, roles: Vec<Arc<str>>) -> Self {
+        Self {
+            name: name.into(),
+            roles,
+            claims: HashMap::new(),
+        }
+    }
+
+    /// The principal used for every request when authentication is not configured.
+    #[must_use]
+    pub fn anonymous() -> Self {
+        Self::new("anonymous", vec![])
+    }
+
+    #[must_use]
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r.as_ref() == role)
+    }
+}
+
+/// Credentials presented by a client.
+pub enum Credentials {
+    Basic {
+        username: String,
+        password: SecretString,
+    },
+    Bearer(SecretString),
+}
+
+impl Credentials {
+    /// Parses the value of an `Authorization` header, i.e. `Bearer <token>` or `Basic <base64(username:password)>`.
+    #[must_use]
+    pub fn from_authorization_header(value: &str) -> Option<Self> {
+        let (scheme, credentials) = value.trim().split_once(' ')?;
+        let credentials = credentials.trim();
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            return Some(Credentials::Bearer(SecretString::new(
+                credentials.to_string(),
+            )));
+        }
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = general_purpose::STANDARD.decode(credentials).ok()?;
+            let decoded = String::from_utf8(decoded).ok()?;
+            let (username, password) = decoded.split_once(':')?;
+            return Some(Credentials::Basic {
+                username: username.to_string(),
+                password: SecretString::new(password.to_string()),
+            });
+        }
+
+        None
+    }
+}
+
+/// Validates credentials of a single kind, i.e. API keys or JWTs.
+pub trait Authenticator: Send + Sync {
+    /// Returns the authenticated principal, or `None` if the credentials are not accepted by this authenticator.
+    fn authenticate(&self, credentials: &Credentials) -> Option<Principal>;
+}
+
+struct Session {
+    principal: Principal,
+    expires_at: Instant,
+}
+
+/// Authenticates requests against all configured authenticators and tracks the session tokens issued by handshakes.
+pub struct AuthProvider {
+    authenticators: Vec<Box<dyn Authenticator>>,
+    sessions: DashMap<String, Session>,
+}
+
+impl AuthProvider {
+    pub async fn try_new(config: &AuthConfig, secrets: &Secrets) -> Result<Self> {
+        let mut authenticators: Vec<Box<dyn Authenticator>> = vec![];
+
+        if let Some(api_key) = &config.api_key {
+            authenticators.push(Box::new(
+                api_key::ApiKeyAuthenticator::new(api_key, secrets).await,
+            ));
+        }
+
+        if let Some(basic) = &config.basic {
+            authenticators.push(Box::new(
+                basic::BasicAuthenticator::new(basic, secrets).await,
+            ));
+        }
+
+        if let Some(jwt) = &config.jwt {
+            authenticators.push(Box::new(jwt::JwtAuthenticator::try_new(jwt)?));
+        }
+
+        if authenticators.is_empty() {
+            tracing::warn!("Authentication is enabled without any authenticators configured. All requests will be rejected.");
+        }
+
+        Ok(Self {
+            authenticators,
+            sessions: DashMap::new(),
+        })
+    }
+
+    /// An auth provider that rejects all credentials, for when it isn't known whether authentication is required.
+    #[must_use]
+    pub fn deny_all() -> Self {
+        Self {
+            authenticators: vec![],
+            sessions: DashMap::new(),
+        }
+    }
+
+    /// Returns the principal for the given credentials, checking session tokens first and then each authenticator in turn.
+    #[must_use]
+    pub fn authenticate(&self, credentials: &Credentials) -> Option<Principal> {
+        if let Credentials::Bearer(token) = credentials {
+            if let Some(principal) = self.session_principal(token.expose_secret()) {
+                return Some(principal);
+            }
+        }
+
+        self.authenticators
+            .iter()
+            .find_map(|authenticator| authenticator.authenticate(credentials))
+    }
+
+    /// Issues a new session token for an authenticated principal.
+    #[must_use]
+    pub fn issue_session_token(&self, principal: Principal) -> String {
+        let now = Instant::now();
+        self.sessions.retain(|_, session| session.expires_at > now);
+
+        let token = Uuid::new_v4().to_string();
+        self.sessions.insert(
+            token.clone(),
+            Session {
+                principal,
+                expires_at: now + SESSION_TTL,
+            },
+        );
+
+        token
+    }
+
+    fn session_principal(&self, token: &str) -> Option<Principal> {
+        let session = self.sessions.get(token)?;
+        if session.expires_at <= Instant::now() {
+            drop(session);
+            self.sessions.remove(token);
+            return None;
+        }
+
+        Some(session.principal.clone())
+    }
+}
+
+/// Compares two byte strings in constant time with respect to their contents.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use spicepod::component::runtime::{ApiKey, ApiKeyAuth, BasicAuth, BasicAuthUser};
+
+    use super::*;
+
+    async fn provider() -> AuthProvider {
+        let mut secrets = Secrets::new();
+        secrets.load_from(&[]).await.expect("to load secrets");
+
+        let config = AuthConfig {
+            api_key: Some(ApiKeyAuth {
+                keys: vec![ApiKey {
+                    key: "my-api-key".to_string(),
+                    principal: Some("analytics".to_string()),
+                    roles: vec!["reader".to_string()],
+                }],
+            }),
+            basic: Some(BasicAuth {
+                users: vec![BasicAuthUser {
+                    username: "admin".to_string(),
+                    password: "hunter2".to_string(),
+                    roles: vec![],
+                }],
+            }),
+            jwt: None,
+            grants: vec![],
+        };
+
+        AuthProvider::try_new(&config, &secrets)
+            .await
+            .expect("to create auth provider")
+    }
+
+    #[test]
+    fn test_parse_authorization_header() {
+        let Some(Credentials::Bearer(token)) = Credentials::from_authorization_header("Bearer abc")
+        else {
+            panic!("expected bearer credentials");
+        };
+        assert_eq!(token.expose_secret(), "abc");
+
+        // "admin:hunter2"
+        let Some(Credentials::Basic { username, password }) =
+            Credentials::from_authorization_header("Basic YWRtaW46aHVudGVyMg==")
+        else {
+            panic!("expected basic credentials");
+        };
+        assert_eq!(username, "admin");
+        assert_eq!(password.expose_secret(), "hunter2");
+
+        assert!(Credentials::from_authorization_header("Digest abc").is_none());
+        assert!(Credentials::from_authorization_header("Basic not-base64!").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let provider = provider().await;
+
+        let principal = provider
+            .authenticate(&Credentials::Bearer(SecretString::new(
+                "my-api-key".to_string(),
+            )))
+            .expect("api key to be accepted");
+        assert_eq!(principal.name.as_ref(), "analytics");
+        assert!(principal.has_role("reader"));
+
+        let principal = provider
+            .authenticate(&Credentials::Basic {
+                username: "admin".to_string(),
+                password: SecretString::new("hunter2".to_string()),
+            })
+            .expect("basic credentials to be accepted");
+        assert_eq!(principal.name.as_ref(), "admin");
+
+        assert!(provider
+            .authenticate(&Credentials::Basic {
+                username: "admin".to_string(),
+                password: SecretString::new("wrong".to_string()),
+            })
+            .is_none());
+        assert!(provider
+            .authenticate(&Credentials::Bearer(SecretString::new(
+                "wrong-key".to_string()
+            )))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_session_token() {
+        let provider = provider().await;
+
+        let token = provider.issue_session_token(Principal::new("admin", vec![]));
+        let principal = provider
+            .authenticate(&Credentials::Bearer(SecretString::new(token)))
+            .expect("session token to be accepted");
+        assert_eq!(principal.name.as_ref(), "admin");
+    }
+}
@@ -0,0 +1,278 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::Arc,
+};
+
+use cache::get_logical_plan_input_tables;
+use datafusion::{
+    common::tree_node::TreeNodeRecursion,
+    logical_expr::LogicalPlan,
+    sql::{ResolvedTableReference, TableReference},
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use snafu::prelude::*;
+use spicepod::component::runtime::Grant;
+
+use crate::datafusion::{SPICE_DEFAULT_CATALOG, SPICE_DEFAULT_SCHEMA};
+
+use super::{InvalidGrantPatternSnafu, Principal, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Read,
+    Write,
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::Read => write!(f, "read"),
+            Permission::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// A table access that isn't allowed by the configured grants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessDenied {
+    pub principal: Arc<str>,
+    pub table: TableReference,
+    pub permission: Permission,
+}
+
+impl Display for AccessDenied {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} does not have {} access to {}",
+            self.principal, self.permission, self.table
+        )
+    }
+}
+
+impl std::error::Error for AccessDenied {}
+
+struct CompiledGrant {
+    principal: Option<String>,
+    role: Option<String>,
+    read: GlobSet,
+    write: GlobSet,
+}
+
+impl CompiledGrant {
+    fn try_new(grant: &Grant) -> Result<Self> {
+        Ok(Self {
+            principal: grant.principal.clone(),
+            role: grant.role.clone(),
+            read: build_glob_set(&grant.read)?,
+            write: build_glob_set(&grant.write)?,
+        })
+    }
+
+    fn applies_to(&self, principal: &Principal) -> bool {
+        let principal_matches = self
+            .principal
+            .as_ref()
+            .map_or(true, |name| name.as_str() == principal.name.as_ref());
+        let role_matches = self
+            .role
+            .as_ref()
+            .map_or(true, |role| principal.has_role(role));
+
+        principal_matches && role_matches
+    }
+
+    fn allows(&self, candidates: &[String], permission: Permission) -> bool {
+        let patterns = match permission {
+            Permission::Read => &self.read,
+            Permission::Write => &self.write,
+        };
+
+        candidates
+            .iter()
+            .any(|candidate| patterns.is_match(candidate))
+    }
+}
+
+/// Authorizes table access for principals based on the `runtime.auth.grants` configured in the spicepod.
+///
+/// Access is denied unless a grant that applies to the principal matches the table.
+pub struct AccessControl {
+    grants: Vec<CompiledGrant>,
+}
+
+impl AccessControl {
+    pub fn try_new(grants: &[Grant]) -> Result<Self> {
+        let grants = grants
+            .iter()
+            .map(CompiledGrant::try_new)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { grants })
+    }
+
+    #[must_use]
+    pub fn is_allowed(
+        &self,
+        principal: &Principal,
+        table: &TableReference,
+        permission: Permission,
+    ) -> bool {
+        let table = table
+            .clone()
+            .resolve(SPICE_DEFAULT_CATALOG, SPICE_DEFAULT_SCHEMA);
+
+        // Table metadata is always readable, so that clients can discover the tables they can query.
+        if permission == Permission::Read && table.schema.as_ref() == "information_schema" {
+            return true;
+        }
+
+        let candidates = table_name_candidates(&table);
+        self.grants
+            .iter()
+            .filter(|grant| grant.applies_to(principal))
+            .any(|grant| grant.allows(&candidates, permission))
+    }
+
+    /// Verifies that the principal can read every table scanned by the plan, and write to every table the plan modifies.
+    pub fn check_plan(
+        &self,
+        principal: &Principal,
+        plan: &LogicalPlan,
+    ) -> Result<(), AccessDenied> {
+        let reads = get_logical_plan_input_tables(plan)
+            .into_iter()
+            .map(|table| (table, Permission::Read));
+        let writes = get_logical_plan_output_tables(plan)
+            .into_iter()
+            .map(|table| (table, Permission::Write));
+
+        for (table, permission) in reads.chain(writes) {
+            if !self.is_allowed(principal, &table, permission) {
+                return Err(AccessDenied {
+                    principal: Arc::clone(&principal.name),
+                    table,
+                    permission,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the tables modified by DML statements in a logical plan.
+fn get_logical_plan_output_tables(plan: &LogicalPlan) -> Vec<TableReference> {
+    let mut table_names = vec![];
+
+    // The visitor never returns an error, so the result can be ignored.
+    let _ = plan.apply_with_subqueries(|current_plan| {
+        if let LogicalPlan::Dml(dml) = current_plan {
+            table_names.push(dml.table_name.clone());
+        }
+
+        Ok(TreeNodeRecursion::Continue)
+    });
+
+    table_names
+}
+
+/// The names a grant pattern can match a table by: the fully qualified name, and the shorter names
+/// that resolve to the same table when the catalog and schema are the defaults.
+fn table_name_candidates(table: &ResolvedTableReference) -> Vec<String> {
+    let mut candidates = vec![format!(
+        "{}.{}.{}",
+        table.catalog, table.schema, table.table
+    )];
+
+    if table.catalog.as_ref() == SPICE_DEFAULT_CATALOG {
+        candidates.push(format!("{}.{}", table.schema, table.table));
+
+        if table.schema.as_ref() == SPICE_DEFAULT_SCHEMA {
+            candidates.push(table.table.to_string());
+        }
+    }
+
+    candidates
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).context(InvalidGrantPatternSnafu { pattern })?);
+    }
+
+    builder.build().context(InvalidGrantPatternSnafu {
+        pattern: patterns.join(", "),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(principal: Option<&str>, role: Option<&str>, read: &[&str], write: &[&str]) -> Grant {
+        Grant {
+            principal: principal.map(ToString::to_string),
+            role: role.map(ToString::to_string),
+            read: read.iter().map(ToString::to_string).collect(),
+            write: write.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let access_control = AccessControl::try_new(&[
+            grant(
+                Some("analytics"),
+                None,
+                &["taxi_trips", "my_catalog.*"],
+                &[],
+            ),
+            grant(None, Some("ingest"), &[], &["events"]),
+        ])
+        .expect("valid grants");
+
+        let analytics = Principal::new("analytics", vec![]);
+        let ingest = Principal::new("loader", vec!["ingest".into()]);
+
+        assert!(access_control.is_allowed(&analytics, &"taxi_trips".into(), Permission::Read));
+        assert!(access_control.is_allowed(
+            &analytics,
+            &"spice.public.taxi_trips".into(),
+            Permission::Read
+        ));
+        assert!(access_control.is_allowed(
+            &analytics,
+            &"my_catalog.sales.orders".into(),
+            Permission::Read
+        ));
+        assert!(!access_control.is_allowed(&analytics, &"taxi_trips".into(), Permission::Write));
+        assert!(!access_control.is_allowed(&analytics, &"events".into(), Permission::Read));
+
+        assert!(access_control.is_allowed(&ingest, &"events".into(), Permission::Write));
+        assert!(!access_control.is_allowed(&ingest, &"events".into(), Permission::Read));
+
+        assert!(access_control.is_allowed(
+            &ingest,
+            &"information_schema.tables".into(),
+            Permission::Read
+        ));
+    }
+}
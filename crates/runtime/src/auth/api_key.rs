@@ -0,0 +1,85 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use secrecy::{ExposeSecret, SecretString};
+use spicepod::component::runtime::ApiKeyAuth;
+
+use crate::secrets::{ParamStr, Secrets};
+
+use super::{constant_time_eq, Authenticator, Credentials, Principal};
+
+const DEFAULT_API_KEY_PRINCIPAL: &str = "api_key";
+
+struct ApiKeyEntry {
+    key: SecretString,
+    principal: Principal,
+}
+
+/// Accepts static API keys sent as bearer tokens.
+pub struct ApiKeyAuthenticator {
+    keys: Vec<ApiKeyEntry>,
+}
+
+impl ApiKeyAuthenticator {
+    pub async fn new(config: &ApiKeyAuth, secrets: &Secrets) -> Self {
+        let mut keys = Vec::with_capacity(config.keys.len());
+        for api_key in &config.keys {
+            let key = secrets.inject_secrets("key", ParamStr(&api_key.key)).await;
+            if key.expose_secret().is_empty() {
+                tracing::warn!("Skipping empty API key configured in runtime.auth.api_key");
+                continue;
+            }
+
+            let name = api_key
+                .principal
+                .as_deref()
+                .unwrap_or(DEFAULT_API_KEY_PRINCIPAL);
+            keys.push(ApiKeyEntry {
+                key,
+                principal: Principal::new(
+                    name,
+                    api_key
+                        .roles
+                        .iter()
+                        .map(|r| Arc::from(r.as_str()))
+                        .collect(),
+                ),
+            });
+        }
+
+        Self { keys }
+    }
+}
+
+impl Authenticator for ApiKeyAuthenticator {
+    fn authenticate(&self, credentials: &Credentials) -> Option<Principal> {
+        let Credentials::Bearer(token) = credentials else {
+            return None;
+        };
+
+        self.keys
+            .iter()
+            .find(|entry| {
+                constant_time_eq(
+                    entry.key.expose_secret().as_bytes(),
+                    token.expose_secret().as_bytes(),
+                )
+            })
+            .map(|entry| entry.principal.clone())
+    }
+}
@@ -0,0 +1,75 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{collections::HashMap, sync::Arc};
+
+use secrecy::{ExposeSecret, SecretString};
+use spicepod::component::runtime::BasicAuth;
+
+use crate::secrets::{ParamStr, Secrets};
+
+use super::{constant_time_eq, Authenticator, Credentials, Principal};
+
+struct BasicAuthUser {
+    password: SecretString,
+    principal: Principal,
+}
+
+/// Accepts username and password pairs sent with the `Basic` scheme.
+pub struct BasicAuthenticator {
+    users: HashMap<String, BasicAuthUser>,
+}
+
+impl BasicAuthenticator {
+    pub async fn new(config: &BasicAuth, secrets: &Secrets) -> Self {
+        let mut users = HashMap::with_capacity(config.users.len());
+        for user in &config.users {
+            let password = secrets
+                .inject_secrets("password", ParamStr(&user.password))
+                .await;
+            users.insert(
+                user.username.clone(),
+                BasicAuthUser {
+                    password,
+                    principal: Principal::new(
+                        user.username.as_str(),
+                        user.roles.iter().map(|r| Arc::from(r.as_str())).collect(),
+                    ),
+                },
+            );
+        }
+
+        Self { users }
+    }
+}
+
+impl Authenticator for BasicAuthenticator {
+    fn authenticate(&self, credentials: &Credentials) -> Option<Principal> {
+        let Credentials::Basic { username, password } = credentials else {
+            return None;
+        };
+
+        let user = self.users.get(username)?;
+        if constant_time_eq(
+            user.password.expose_secret().as_bytes(),
+            password.expose_secret().as_bytes(),
+        ) {
+            Some(user.principal.clone())
+        } else {
+            None
+        }
+    }
+}
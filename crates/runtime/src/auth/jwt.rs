@@ -0,0 +1,134 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{collections::HashMap, sync::Arc};
+
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, DecodingKey, Validation};
+use secrecy::ExposeSecret;
+use snafu::prelude::*;
+use spicepod::component::runtime::JwtAuth;
+
+use super::{
+    Authenticator, Credentials, InvalidJwkSnafu, Principal, Result, UnableToParseJwksSnafu,
+    UnableToReadJwksFileSnafu,
+};
+
+struct JwtKey {
+    key_id: Option<String>,
+    key: DecodingKey,
+}
+
+/// Accepts JSON Web Tokens sent as bearer tokens, verified against the keys of a local JWKS file.
+pub struct JwtAuthenticator {
+    keys: Vec<JwtKey>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    principal_claim: String,
+    roles_claim: String,
+}
+
+impl JwtAuthenticator {
+    pub fn try_new(config: &JwtAuth) -> Result<Self> {
+        let path = config.jwks_file.clone();
+        let contents =
+            std::fs::read_to_string(&path).context(UnableToReadJwksFileSnafu { path: &path })?;
+        let jwks: JwkSet =
+            serde_json::from_str(&contents).context(UnableToParseJwksSnafu { path: &path })?;
+
+        let keys = jwks
+            .keys
+            .iter()
+            .map(|jwk| {
+                Ok(JwtKey {
+                    key_id: jwk.common.key_id.clone(),
+                    key: DecodingKey::from_jwk(jwk).context(InvalidJwkSnafu { path: &path })?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        tracing::debug!("Loaded {} keys from JWKS file {path}", keys.len());
+
+        Ok(Self {
+            keys,
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+            principal_claim: config.principal_claim.clone(),
+            roles_claim: config.roles_claim.clone(),
+        })
+    }
+
+    fn principal_from_claims(
+        &self,
+        claims: HashMap<String, serde_json::Value>,
+    ) -> Option<Principal> {
+        let name = claims.get(&self.principal_claim)?.as_str()?.to_string();
+
+        let roles = match claims.get(&self.roles_claim) {
+            Some(serde_json::Value::Array(roles)) => roles
+                .iter()
+                .filter_map(serde_json::Value::as_str)
+                .map(Arc::from)
+                .collect(),
+            // Space separated roles, as used by the OAuth `scope` claim
+            Some(serde_json::Value::String(roles)) => {
+                roles.split_whitespace().map(Arc::from).collect()
+            }
+            _ => vec![],
+        };
+
+        Some(Principal {
+            name: name.into(),
+            roles,
+            claims,
+        })
+    }
+}
+
+impl Authenticator for JwtAuthenticator {
+    fn authenticate(&self, credentials: &Credentials) -> Option<Principal> {
+        let Credentials::Bearer(token) = credentials else {
+            return None;
+        };
+        let token = token.expose_secret();
+
+        let header = decode_header(token).ok()?;
+
+        let mut validation = Validation::new(header.alg);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let candidates = self.keys.iter().filter(|k| match (&header.kid, &k.key_id) {
+            (Some(kid), Some(key_id)) => kid == key_id,
+            _ => true,
+        });
+
+        for candidate in candidates {
+            match decode::<HashMap<String, serde_json::Value>>(token, &candidate.key, &validation) {
+                Ok(data) => return self.principal_from_claims(data.claims),
+                Err(e) => {
+                    tracing::trace!("JWT rejected by key {:?}: {e}", candidate.key_id);
+                }
+            }
+        }
+
+        None
+    }
+}
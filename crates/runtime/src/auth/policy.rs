@@ -0,0 +1,314 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Applies the row filter and column mask policies of datasets to the logical plans of queries.
+
+use std::{collections::HashMap, sync::Arc};
+
+use arrow::datatypes::DataType;
+use datafusion::{
+    common::{
+        tree_node::{Transformed, TransformedResult, TreeNode},
+        Column, ScalarValue,
+    },
+    datasource::source_as_provider,
+    error::Result,
+    execution::context::SessionState,
+    functions::expr_fn::{encode, sha256},
+    logical_expr::{cast, lit, Expr, LogicalPlan, LogicalPlanBuilder, TableScan},
+    sql::TableReference,
+};
+use spicepod::component::dataset::policy::{ColumnMask, Policy};
+
+use crate::datafusion::{SPICE_DEFAULT_CATALOG, SPICE_DEFAULT_SCHEMA};
+
+use super::Principal;
+
+/// The value string columns are replaced with by [`ColumnMask::Redact`].
+const REDACTED: &str = "***";
+
+/// The key a dataset policy is stored under, so that `taxi_trips` and `spice.public.taxi_trips` resolve to the same policy.
+#[must_use]
+pub fn policy_key(table: &TableReference) -> TableReference {
+    let table = table
+        .clone()
+        .resolve(SPICE_DEFAULT_CATALOG, SPICE_DEFAULT_SCHEMA);
+    TableReference::full(table.catalog, table.schema, table.table)
+}
+
+/// Rewrites the plan so that every scan of a dataset with a policy only returns the rows and column values the principal may see.
+///
+/// Views are inlined when a table they read from has a policy, so a view can't be used to bypass it.
+pub fn apply_dataset_policies(
+    plan: LogicalPlan,
+    policies: &HashMap<TableReference, Arc<Policy>>,
+    principal: &Principal,
+    state: &SessionState,
+) -> Result<LogicalPlan> {
+    if policies.is_empty() {
+        return Ok(plan);
+    }
+
+    rewrite_plan(plan, policies, principal, state).data()
+}
+
+fn rewrite_plan(
+    plan: LogicalPlan,
+    policies: &HashMap<TableReference, Arc<Policy>>,
+    principal: &Principal,
+    state: &SessionState,
+) -> Result<Transformed<LogicalPlan>> {
+    plan.transform_up_with_subqueries(|plan| {
+        let LogicalPlan::TableScan(scan) = plan else {
+            return Ok(Transformed::no(plan));
+        };
+
+        let view_plan = source_as_provider(&scan.source)
+            .ok()
+            .and_then(|provider| provider.get_logical_plan().cloned());
+        if let Some(view_plan) = view_plan {
+            let view_plan = rewrite_plan(view_plan, policies, principal, state)?;
+            if !view_plan.transformed {
+                return Ok(Transformed::no(LogicalPlan::TableScan(scan)));
+            }
+
+            let inlined = LogicalPlanBuilder::from(view_plan.data)
+                .alias(scan.table_name.clone())?
+                .build()?;
+            return Ok(Transformed::yes(inlined));
+        }
+
+        match applicable_policy(policies, &scan.table_name, principal) {
+            Some(policy) => apply_policy(scan, policy, principal, state).map(Transformed::yes),
+            None => Ok(Transformed::no(LogicalPlan::TableScan(scan))),
+        }
+    })
+}
+
+/// Returns the policy of a dataset that applies to the principal, unless one of their roles is exempt from it.
+#[must_use]
+pub fn applicable_policy<'a>(
+    policies: &'a HashMap<TableReference, Arc<Policy>>,
+    table: &TableReference,
+    principal: &Principal,
+) -> Option<&'a Arc<Policy>> {
+    policies
+        .get(&policy_key(table))
+        .filter(|policy| !is_exempt(policy, principal))
+}
+
+fn is_exempt(policy: &Policy, principal: &Principal) -> bool {
+    policy
+        .exempt_roles
+        .iter()
+        .any(|role| principal.has_role(role))
+}
+
+/// Wraps the scan in a filter for the row filter, and a projection that masks columns while keeping their names and types.
+fn apply_policy(
+    scan: TableScan,
+    policy: &Policy,
+    principal: &Principal,
+    state: &SessionState,
+) -> Result<LogicalPlan> {
+    let schema = Arc::clone(&scan.projected_schema);
+    let mut builder = LogicalPlanBuilder::from(LogicalPlan::TableScan(scan));
+
+    if let Some(row_filter) = &policy.row_filter {
+        let predicate = state.create_logical_expr(row_filter, &schema)?;
+        builder = builder.filter(bind_identity(predicate, principal)?)?;
+    }
+
+    if !policy.column_masks.is_empty() {
+        let projection = schema.iter().map(|(qualifier, field)| {
+            let column = Expr::Column(Column::new(qualifier.cloned(), field.name()));
+            match policy.column_masks.get(field.name()) {
+                Some(mask) => mask_column(column, *mask, field.data_type())
+                    .alias_qualified(qualifier.cloned(), field.name()),
+                None => column,
+            }
+        });
+        builder = builder.project(projection)?;
+    }
+
+    builder.build()
+}
+
+fn mask_column(column: Expr, mask: ColumnMask, data_type: &DataType) -> Expr {
+    let is_string = matches!(
+        data_type,
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+    );
+
+    match mask {
+        ColumnMask::Hash if is_string => {
+            cast(encode(sha256(column), lit("hex")), data_type.clone())
+        }
+        ColumnMask::Redact if is_string => cast(lit(REDACTED), data_type.clone()),
+        _ => Expr::Literal(ScalarValue::try_from(data_type).unwrap_or(ScalarValue::Null)),
+    }
+}
+
+/// Replaces the `$principal` and `$<claim>` placeholders in a row filter with the caller's identity.
+fn bind_identity(predicate: Expr, principal: &Principal) -> Result<Expr> {
+    predicate
+        .transform_up(|expr| {
+            let Expr::Placeholder(placeholder) = &expr else {
+                return Ok(Transformed::no(expr));
+            };
+
+            let value = match placeholder.id.strip_prefix('$') {
+                Some("principal") => ScalarValue::Utf8(Some(principal.name.to_string())),
+                Some(claim) => principal
+                    .claims
+                    .get(claim)
+                    .map_or(ScalarValue::Null, claim_to_scalar),
+                None => ScalarValue::Null,
+            };
+
+            Ok(Transformed::yes(Expr::Literal(value)))
+        })
+        .data()
+}
+
+fn claim_to_scalar(value: &serde_json::Value) -> ScalarValue {
+    match value {
+        serde_json::Value::Null => ScalarValue::Null,
+        serde_json::Value::Bool(b) => ScalarValue::Boolean(Some(*b)),
+        serde_json::Value::Number(n) => n
+            .as_i64()
+            .map(|n| ScalarValue::Int64(Some(n)))
+            .or_else(|| n.as_f64().map(|n| ScalarValue::Float64(Some(n))))
+            .unwrap_or(ScalarValue::Null),
+        serde_json::Value::String(s) => ScalarValue::Utf8(Some(s.clone())),
+        other => ScalarValue::Utf8(Some(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::{Int64Array, RecordBatch, StringArray},
+        datatypes::{Field, Schema},
+    };
+    use datafusion::{assert_batches_eq, datasource::MemTable, prelude::SessionContext};
+
+    use super::*;
+
+    fn context() -> SessionContext {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("tenant_id", DataType::Utf8, false),
+            Field::new("email", DataType::Utf8, false),
+            Field::new("amount", DataType::Int64, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(StringArray::from(vec!["acme", "globex", "acme"])),
+                Arc::new(StringArray::from(vec![
+                    "a@acme.com",
+                    "g@globex.com",
+                    "b@acme.com",
+                ])),
+                Arc::new(Int64Array::from(vec![10, 20, 30])),
+            ],
+        )
+        .expect("valid batch");
+
+        let ctx = SessionContext::new();
+        ctx.register_table(
+            "orders",
+            Arc::new(MemTable::try_new(schema, vec![vec![batch]]).expect("valid table")),
+        )
+        .expect("table to register");
+        ctx
+    }
+
+    fn policies() -> HashMap<TableReference, Arc<Policy>> {
+        let policy = Policy {
+            row_filter: Some("tenant_id = $tenant_id".to_string()),
+            column_masks: HashMap::from([
+                ("email".to_string(), ColumnMask::Redact),
+                ("amount".to_string(), ColumnMask::Null),
+            ]),
+            exempt_roles: vec!["admin".to_string()],
+        };
+
+        // The test context uses DataFusion's default catalog and schema.
+        HashMap::from([(
+            TableReference::full("datafusion", "public", "orders"),
+            Arc::new(policy),
+        )])
+    }
+
+    async fn query(ctx: &SessionContext, principal: &Principal) -> Vec<RecordBatch> {
+        let state = ctx.state();
+        let plan = state
+            .create_logical_plan("SELECT tenant_id, email, amount FROM orders ORDER BY amount")
+            .await
+            .expect("valid plan");
+        let plan = apply_dataset_policies(plan, &policies(), principal, &state)
+            .expect("policies to apply");
+
+        ctx.execute_logical_plan(plan)
+            .await
+            .expect("plan to execute")
+            .collect()
+            .await
+            .expect("results to collect")
+    }
+
+    #[tokio::test]
+    async fn test_row_filter_and_column_masks() {
+        let ctx = context();
+
+        let mut principal = Principal::new("alice", vec![]);
+        principal
+            .claims
+            .insert("tenant_id".to_string(), serde_json::json!("acme"));
+
+        assert_batches_eq!(
+            &[
+                "+-----------+-------+--------+",
+                "| tenant_id | email | amount |",
+                "+-----------+-------+--------+",
+                "| acme      | ***   |        |",
+                "| acme      | ***   |        |",
+                "+-----------+-------+--------+",
+            ],
+            &query(&ctx, &principal).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_claim_and_exempt_role() {
+        let ctx = context();
+
+        let rows: usize = query(&ctx, &Principal::new("bob", vec![]))
+            .await
+            .iter()
+            .map(RecordBatch::num_rows)
+            .sum();
+        assert_eq!(rows, 0);
+
+        let rows: usize = query(&ctx, &Principal::new("root", vec!["admin".into()]))
+            .await
+            .iter()
+            .map(RecordBatch::num_rows)
+            .sum();
+        assert_eq!(rows, 3);
+    }
+}
@@ -172,7 +172,7 @@ impl RuntimeBuilder {
         rt
     }
 
-    async fn load_secrets(app: Option<&App>) -> Secrets {
+    pub(crate) async fn load_secrets(app: Option<&App>) -> Secrets {
         measure_scope_ms!("load_secret_stores");
         let mut secrets = secrets::Secrets::new();
 
@@ -20,7 +20,9 @@ use datafusion::sql::TableReference;
 use datafusion_table_providers::util::column_reference;
 use snafu::prelude::*;
 use spicepod::component::{
-    dataset as spicepod_dataset, embeddings::ColumnEmbeddingConfig, params::Params,
+    dataset::{self as spicepod_dataset, full_text_search::FullTextSearch, policy::Policy},
+    embeddings::ColumnEmbeddingConfig,
+    params::Params,
 };
 use std::{collections::HashMap, time::Duration};
 
@@ -118,6 +120,8 @@ pub struct Dataset {
     pub time_format: Option<TimeFormat>,
     pub acceleration: Option<acceleration::Acceleration>,
     pub embeddings: Vec<ColumnEmbeddingConfig>,
+    pub full_text_search: Option<FullTextSearch>,
+    pub policy: Option<Policy>,
     schema: Option<SchemaRef>,
 }
 
@@ -148,6 +152,8 @@ impl TryFrom<spicepod_dataset::Dataset> for Dataset {
             time_column: dataset.time_column,
             time_format: dataset.time_format.map(TimeFormat::from),
             embeddings: dataset.embeddings,
+            full_text_search: dataset.full_text_search,
+            policy: dataset.policy,
             acceleration,
             schema: None,
         })
@@ -167,6 +173,8 @@ impl Dataset {
             time_format: None,
             acceleration: None,
             embeddings: Vec::default(),
+            full_text_search: None,
+            policy: None,
             schema: None,
         })
     }
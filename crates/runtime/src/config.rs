@@ -44,6 +44,24 @@ pub struct Config {
         action
     )]
     pub open_telemetry_bind_address: SocketAddr,
+
+    /// Configure the writable dataset that OpenTelemetry spans are appended to.
+    #[arg(
+        long = "open_telemetry_traces_dataset",
+        value_name = "DATASET",
+        default_value = "traces",
+        action
+    )]
+    pub open_telemetry_traces_dataset: String,
+
+    /// Configure the writable dataset that OpenTelemetry log records are appended to.
+    #[arg(
+        long = "open_telemetry_logs_dataset",
+        value_name = "DATASET",
+        default_value = "logs",
+        action
+    )]
+    pub open_telemetry_logs_dataset: String,
 }
 
 impl Config {
@@ -56,6 +74,8 @@ impl Config {
                 IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
                 50052,
             ),
+            open_telemetry_traces_dataset: "traces".to_string(),
+            open_telemetry_logs_dataset: "logs".to_string(),
         }
     }
 
@@ -76,6 +96,18 @@ impl Config {
         self.open_telemetry_bind_address = bind_addr;
         self
     }
+
+    #[must_use]
+    pub fn with_open_telemetry_traces_dataset(mut self, dataset: impl Into<String>) -> Self {
+        self.open_telemetry_traces_dataset = dataset.into();
+        self
+    }
+
+    #[must_use]
+    pub fn with_open_telemetry_logs_dataset(mut self, dataset: impl Into<String>) -> Self {
+        self.open_telemetry_logs_dataset = dataset.into();
+        self
+    }
 }
 
 impl Default for Config {
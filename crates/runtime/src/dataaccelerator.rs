@@ -101,6 +101,27 @@ pub async fn get_accelerator_engine(engine: Engine) -> Option<Arc<dyn DataAccele
     }
 }
 
+/// Returns the path of the file backing a file-mode accelerated dataset, if any.
+pub async fn accelerator_file_path(dataset: &crate::component::dataset::Dataset) -> Option<String> {
+    let acceleration = dataset.acceleration.as_ref()?;
+
+    match acceleration.engine {
+        #[cfg(feature = "duckdb")]
+        Engine::DuckDB => get_accelerator_engine(Engine::DuckDB)
+            .await?
+            .as_any()
+            .downcast_ref::<DuckDBAccelerator>()?
+            .duckdb_file_path(dataset),
+        #[cfg(feature = "sqlite")]
+        Engine::Sqlite => get_accelerator_engine(Engine::Sqlite)
+            .await?
+            .as_any()
+            .downcast_ref::<SqliteAccelerator>()?
+            .sqlite_file_path(dataset),
+        _ => None,
+    }
+}
+
 /// A `DataAccelerator` knows how to read, write and create new tables.
 #[async_trait]
 pub trait DataAccelerator: Send + Sync {
@@ -324,7 +345,10 @@ pub async fn create_accelerator_table(
     Ok(table_provider)
 }
 
-fn get_primary_keys_from_constraints(constraints: &Constraints, schema: &SchemaRef) -> Vec<String> {
+pub(crate) fn get_primary_keys_from_constraints(
+    constraints: &Constraints,
+    schema: &SchemaRef,
+) -> Vec<String> {
     constraints
         .iter()
         .filter_map(|constraint| {
@@ -61,6 +61,14 @@ impl AcceleratedMetadata {
         })
     }
 
+    /// Read and write the metadata entry `entry` instead of the dataset's own, for metadata that is
+    /// kept alongside it.
+    #[must_use]
+    pub fn for_entry(mut self, entry: impl Into<String>) -> Self {
+        self.dataset_name = entry.into();
+        self
+    }
+
     #[must_use]
     pub async fn get_metadata<T: DeserializeOwned>(&self) -> Option<T> {
         self.metadata_provider
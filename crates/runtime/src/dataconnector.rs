@@ -19,15 +19,18 @@ use crate::component::dataset::acceleration::RefreshMode;
 use crate::component::dataset::Dataset;
 use crate::secrets::Secrets;
 use crate::Runtime;
-use arrow::datatypes::SchemaRef;
+use arrow::datatypes::{DataType, SchemaRef};
 use async_trait::async_trait;
-use data_components::cdc::ChangesStream;
+use data_components::cdc::{AppendStream, ChangesStream};
 use data_components::object::metadata::ObjectStoreMetadataTable;
 use data_components::object::text::ObjectStoreTextTable;
 use datafusion::catalog::CatalogProvider;
 use datafusion::dataframe::DataFrame;
+use datafusion::datasource::file_format::arrow::ArrowFormat;
+use datafusion::datasource::file_format::avro::AvroFormat;
 use datafusion::datasource::file_format::csv::CsvFormat;
 use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
+use datafusion::datasource::file_format::json::JsonFormat;
 use datafusion::datasource::file_format::parquet::ParquetFormat;
 use datafusion::datasource::file_format::FileFormat;
 use datafusion::datasource::listing::{
@@ -75,6 +78,8 @@ pub mod flightsql;
 pub mod ftp;
 pub mod graphql;
 pub mod https;
+#[cfg(feature = "kafka")]
+pub mod kafka;
 pub mod localhost;
 #[cfg(feature = "mysql")]
 pub mod mysql;
@@ -271,6 +276,34 @@ pub async fn create_new_connector(
     Some(result)
 }
 
+/// Resolves the parameters a `DataConnector` would be created with by name, including the secrets
+/// autoloaded from the secret stores.
+///
+/// # Returns
+///
+/// `None` if the connector for `name` is not registered.
+#[allow(clippy::implicit_hasher)]
+pub async fn resolve_parameters(
+    name: &str,
+    params: HashMap<String, SecretString>,
+    secrets: Arc<RwLock<Secrets>>,
+) -> Option<AnyErrorResult<Parameters>> {
+    let guard = DATA_CONNECTOR_FACTORY_REGISTRY.lock().await;
+
+    let factory = guard.get(name)?;
+
+    Some(
+        Parameters::try_new(
+            name,
+            params.into_iter().collect(),
+            factory.prefix(),
+            secrets,
+            factory.parameters(),
+        )
+        .await,
+    )
+}
+
 pub async fn register_all() {
     register_connector_factory("localhost", localhost::LocalhostConnectorFactory::new_arc()).await;
     #[cfg(feature = "databricks")]
@@ -307,6 +340,8 @@ pub async fn register_all() {
     register_connector_factory("snowflake", snowflake::SnowflakeFactory::new_arc()).await;
     #[cfg(feature = "debezium")]
     register_connector_factory("debezium", debezium::DebeziumFactory::new_arc()).await;
+    #[cfg(feature = "kafka")]
+    register_connector_factory("kafka", kafka::KafkaFactory::new_arc()).await;
     #[cfg(feature = "delta_lake")]
     register_connector_factory(
         "unity_catalog",
@@ -695,6 +730,18 @@ pub trait DataConnector: Send + Sync {
         None
     }
 
+    /// A stream of record batches to append to an accelerated dataset in `append` refresh mode,
+    /// instead of scanning the table provider.
+    fn append_stream(&self, _table_provider: Arc<dyn TableProvider>) -> Option<AppendStream> {
+        None
+    }
+
+    /// Releases what the data connector created in the source for a dataset, once the dataset is
+    /// removed from the app, e.g. the replication slot its changes were streamed from.
+    async fn remove_dataset(&self, _dataset: &Dataset) -> DataConnectorResult<()> {
+        Ok(())
+    }
+
     async fn metadata_provider(
         &self,
         _dataset: &Dataset,
@@ -805,6 +852,9 @@ pub trait ListingTableConnector: DataConnector {
     /// unstructured formats. It supports the following tabular formats:
     ///  - parquet
     ///  - csv
+    ///  - json, ndjson or jsonl (newline-delimited JSON)
+    ///  - avro
+    ///  - arrow (Arrow IPC)
     ///
     /// For tabular formats, file options can also be specified in the [`Dataset`]'s `param`s.
     ///
@@ -825,28 +875,20 @@ pub trait ListingTableConnector: DataConnector {
             .map(str::to_string);
 
         match params.get("file_format").expose().ok() {
-            Some("csv") => Ok((
-                Some(self.get_csv_format(params)?),
-                extension.unwrap_or(".csv".to_string()),
-            )),
-            Some("parquet") => Ok((
-                Some(Arc::new(ParquetFormat::default())),
-                extension.unwrap_or(".parquet".to_string()),
-            )),
-            Some(format) => Ok((None, format!(".{format}"))),
+            Some(format) => match self.get_tabular_format(format, params)? {
+                Some(file_format) => {
+                    Ok((Some(file_format), extension.unwrap_or(format!(".{format}"))))
+                }
+                None => Ok((None, format!(".{format}"))),
+            },
             None => {
-                if let Some(ext) = std::path::Path::new(dataset.path().as_str()).extension() {
-                    if ext.eq_ignore_ascii_case("csv") {
-                        return Ok((
-                            Some(self.get_csv_format(params)?),
-                            extension.unwrap_or(".csv".to_string()),
-                        ));
-                    }
-                    if ext.eq_ignore_ascii_case("parquet") {
-                        return Ok((
-                            Some(Arc::new(ParquetFormat::default())),
-                            extension.unwrap_or(".parquet".to_string()),
-                        ));
+                if let Some(ext) = std::path::Path::new(dataset.path().as_str())
+                    .extension()
+                    .and_then(std::ffi::OsStr::to_str)
+                {
+                    let format = ext.to_ascii_lowercase();
+                    if let Some(file_format) = self.get_tabular_format(&format, params)? {
+                        return Ok((Some(file_format), extension.unwrap_or(format!(".{format}"))));
                     }
                 }
 
@@ -859,6 +901,67 @@ pub trait ListingTableConnector: DataConnector {
         }
     }
 
+    /// The partition columns listed in the `partition_columns` param, e.g. `date:Date32,region`.
+    /// A column's type is the Arrow type name after the colon, and defaults to `Utf8`.
+    fn get_partition_columns(&self) -> DataConnectorResult<Option<Vec<(String, DataType)>>>
+    where
+        Self: Display,
+    {
+        let Some(columns) = self.get_params().get("partition_columns").expose().ok() else {
+            return Ok(None);
+        };
+
+        columns
+            .split(',')
+            .map(str::trim)
+            .filter(|column| !column.is_empty())
+            .map(|column| match column.split_once(':') {
+                Some((name, data_type)) => {
+                    let data_type = DataType::from_str(data_type.trim()).boxed().context(
+                        InvalidConfigurationSnafu {
+                            dataconnector: format!("{self}"),
+                            message: format!(
+                                "Invalid type for partition column {column}. Use an Arrow type such as Utf8, Int64 or Date32."
+                            ),
+                        },
+                    )?;
+                    Ok((name.trim().to_string(), data_type))
+                }
+                None => Ok((column.to_string(), DataType::Utf8)),
+            })
+            .collect::<DataConnectorResult<_>>()
+            .map(Some)
+    }
+
+    /// Whether partition columns are inferred from Hive-style `key=value` directories when not
+    /// listed in the `partition_columns` param.
+    fn hive_partitioning_enabled(&self) -> bool {
+        self.get_params()
+            .get("hive_partitioning_enabled")
+            .expose()
+            .ok()
+            .is_some_and(|f| f.eq_ignore_ascii_case("true"))
+    }
+
+    /// The tabular [`FileFormat`] for a `file_format` name, or `None` if the format is not tabular.
+    fn get_tabular_format(
+        &self,
+        format: &str,
+        params: &Parameters,
+    ) -> DataConnectorResult<Option<Arc<dyn FileFormat>>>
+    where
+        Self: Display,
+    {
+        Ok(match format {
+            "csv" => Some(self.get_csv_format(params)?),
+            "parquet" => Some(Arc::new(ParquetFormat::default())),
+            "json" | "ndjson" | "jsonl" => Some(self.get_json_format(params)?),
+            "avro" => Some(Arc::new(AvroFormat)),
+            "arrow" => Some(Arc::new(ArrowFormat)),
+            _ => None,
+        })
+    }
+
     fn get_csv_format(&self, params: &Parameters) -> DataConnectorResult<Arc<CsvFormat>>
     where
         Self: Display,
@@ -888,11 +991,6 @@ pub trait ListingTableConnector: DataConnector {
             .expose()
             .ok()
             .map_or(b',', |f| *f.as_bytes().first().unwrap_or(&b','));
-        let compression_type = params
-            .get("file_compression_type")
-            .expose()
-            .ok()
-            .unwrap_or_default();
 
         Ok(Arc::new(
             CsvFormat::default()
@@ -901,16 +999,48 @@ pub trait ListingTableConnector: DataConnector {
                 .with_escape(escape)
                 .with_schema_infer_max_rec(schema_infer_max_rec)
                 .with_delimiter(delimiter)
-                .with_file_compression_type(
-                    FileCompressionType::from_str(compression_type)
-                        .boxed()
-                        .context(InvalidConfigurationSnafu {
-                            dataconnector: format!("{self}"),
-                            message: format!("Invalid CSV compression_type: {compression_type}, supported types are: GZIP, BZIP2, XZ, ZSTD, UNCOMPRESSED"),
-                        })?,
-                ),
+                .with_file_compression_type(self.get_file_compression_type(params)?),
         ))
     }
+
+    /// Newline-delimited JSON, where each line is a JSON object. Nested objects are read as structs.
+    fn get_json_format(&self, params: &Parameters) -> DataConnectorResult<Arc<JsonFormat>>
+    where
+        Self: Display,
+    {
+        let schema_infer_max_rec = params
+            .get("json_schema_infer_max_records")
+            .expose()
+            .ok()
+            .map_or_else(|| 1000, |f| usize::from_str(f).map_or(1000, |f| f));
+
+        Ok(Arc::new(
+            JsonFormat::default()
+                .with_schema_infer_max_rec(schema_infer_max_rec)
+                .with_file_compression_type(self.get_file_compression_type(params)?),
+        ))
+    }
+
+    fn get_file_compression_type(
+        &self,
+        params: &Parameters,
+    ) -> DataConnectorResult<FileCompressionType>
+    where
+        Self: Display,
+    {
+        let compression_type = params
+            .get("file_compression_type")
+            .expose()
+            .ok()
+            .unwrap_or_default();
+
+        FileCompressionType::from_str(compression_type)
+            .boxed()
+            .context(InvalidConfigurationSnafu {
+                dataconnector: format!("{self}"),
+                message: format!("Invalid file_compression_type: {compression_type}, supported types are: GZIP, BZIP2, XZ, ZSTD, UNCOMPRESSED"),
+            })
+    }
 }
 
 #[async_trait]
@@ -966,7 +1096,25 @@ impl<T: ListingTableConnector + Display> DataConnector for T {
                 })?)
             }
             Some(file_format) => {
-                let options = ListingOptions::new(file_format).with_file_extension(&extension);
+                let mut options = ListingOptions::new(file_format).with_file_extension(&extension);
+
+                // Partition columns are either listed explicitly with their types, or inferred as
+                // `Utf8` columns from the Hive-style `key=value` directories under the table path.
+                let partition_columns = match self.get_partition_columns()? {
+                    Some(columns) => columns,
+                    None if self.hive_partitioning_enabled() => options
+                        .infer_partitions(&ctx.state(), &table_path)
+                        .await
+                        .boxed()
+                        .context(UnableToConnectInternalSnafu {
+                            dataconnector: format!("{self}"),
+                        })?
+                        .into_iter()
+                        .map(|column| (column, DataType::Utf8))
+                        .collect(),
+                    None => Vec::new(),
+                };
+                options = options.with_table_partition_cols(partition_columns);
 
                 let resolved_schema = options
                     .infer_schema(&ctx.state(), &table_path)
@@ -1070,7 +1218,10 @@ mod tests {
         ParameterSpec::runtime("csv_escape"),
         ParameterSpec::runtime("csv_schema_infer_max_records"),
         ParameterSpec::runtime("csv_delimiter"),
+        ParameterSpec::runtime("json_schema_infer_max_records"),
         ParameterSpec::runtime("file_compression_type"),
+        ParameterSpec::runtime("hive_partitioning_enabled"),
+        ParameterSpec::runtime("partition_columns"),
     ];
 
     fn setup_connector(path: String, params: HashMap<String, String>) -> (TestConnector, Dataset) {
@@ -1155,6 +1306,84 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_get_file_format_and_extension_detect_json_extension() {
+        let (connector, dataset) = setup_connector("test:test.JSON".to_string(), HashMap::new());
+
+        if let Ok((Some(_file_format), extension)) =
+            connector.get_file_format_and_extension(&dataset)
+        {
+            assert_eq!(extension, ".json");
+        } else {
+            panic!("Unexpected error");
+        }
+    }
+
+    #[test]
+    fn test_get_file_format_and_extension_tabular_formats_from_params() {
+        for format in ["ndjson", "jsonl", "avro", "arrow"] {
+            let mut params = HashMap::new();
+            params.insert("file_format".to_string(), format.to_string());
+            let (connector, dataset) = setup_connector("test:test/".to_string(), params);
+
+            if let Ok((Some(_file_format), extension)) =
+                connector.get_file_format_and_extension(&dataset)
+            {
+                assert_eq!(extension, format!(".{format}"));
+            } else {
+                panic!("Unexpected error for {format}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_file_format_and_extension_unstructured_from_params() {
+        let mut params = HashMap::new();
+        params.insert("file_format".to_string(), "md".to_string());
+        let (connector, dataset) = setup_connector("test:test/".to_string(), params);
+
+        if let Ok((None, extension)) = connector.get_file_format_and_extension(&dataset) {
+            assert_eq!(extension, ".md");
+        } else {
+            panic!("Unexpected error");
+        }
+    }
+
+    #[test]
+    fn test_get_partition_columns() {
+        let (connector, _) = setup_connector("test:test/".to_string(), HashMap::new());
+        assert_eq!(
+            connector
+                .get_partition_columns()
+                .expect("no partition columns"),
+            None
+        );
+        assert!(!connector.hive_partitioning_enabled());
+
+        let mut params = HashMap::new();
+        params.insert(
+            "partition_columns".to_string(),
+            "date: Date32, region,".to_string(),
+        );
+        params.insert("hive_partitioning_enabled".to_string(), "true".to_string());
+        let (connector, _) = setup_connector("test:test/".to_string(), params);
+        assert_eq!(
+            connector
+                .get_partition_columns()
+                .expect("valid partition columns"),
+            Some(vec![
+                ("date".to_string(), DataType::Date32),
+                ("region".to_string(), DataType::Utf8)
+            ])
+        );
+        assert!(connector.hive_partitioning_enabled());
+
+        let mut params = HashMap::new();
+        params.insert("partition_columns".to_string(), "date:Day".to_string());
+        let (connector, _) = setup_connector("test:test/".to_string(), params);
+        assert!(connector.get_partition_columns().is_err());
+    }
+
     #[test]
     fn test_build_fragments() {
         let mut params = HashMap::new();
@@ -20,7 +20,8 @@ use crate::dataaccelerator::metadata::AcceleratedMetadata;
 use arrow::datatypes::SchemaRef;
 use async_trait::async_trait;
 use data_components::cdc::ChangesStream;
-use data_components::debezium::change_event::{ChangeEvent, ChangeEventKey};
+use data_components::debezium::decoder::{self, ChangeEventDecoder, MessageFormat};
+use data_components::debezium::schema_registry::SchemaRegistry;
 use data_components::debezium::{self, change_event};
 use data_components::debezium_kafka::DebeziumKafka;
 use data_components::kafka::KafkaConsumer;
@@ -30,6 +31,7 @@ use snafu::prelude::*;
 use std::any::Any;
 use std::future::Future;
 use std::pin::Pin;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use super::{DataConnector, DataConnectorFactory, ParameterSpec, Parameters};
@@ -39,9 +41,14 @@ pub enum Error {
     #[snafu(display("Invalid value for debezium_transport. Valid values: 'kafka'"))]
     InvalidTransport,
 
-    #[snafu(display("Invalid value for debezium_message_format: Valid values: 'json'"))]
+    #[snafu(display(
+        "Invalid value for debezium_message_format: Valid values: 'json', 'avro', 'protobuf'"
+    ))]
     InvalidMessageFormat,
 
+    #[snafu(display("{source}"))]
+    UnableToCreateDecoder { source: decoder::Error },
+
     #[snafu(display("Missing required parameter: debezium_kafka_bootstrap_servers"))]
     MissingKafkaBootstrapServers,
 }
@@ -50,6 +57,7 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 pub struct Debezium {
     kafka_brokers: String,
+    decoder: Arc<ChangeEventDecoder>,
 }
 
 impl Debezium {
@@ -62,9 +70,23 @@ impl Debezium {
         if transport != "kafka" {
             return InvalidTransportSnafu.fail();
         }
-        if message_format != "json" {
-            return InvalidMessageFormatSnafu.fail();
-        }
+        let message_format = MessageFormat::from_str(message_format)
+            .ok()
+            .context(InvalidMessageFormatSnafu)?;
+
+        let schema_registry = params.get("schema_registry_url").expose().ok().map(|url| {
+            SchemaRegistry::new(
+                url,
+                params
+                    .get("schema_registry_username")
+                    .expose()
+                    .ok()
+                    .map(str::to_string),
+                params.get("schema_registry_password").ok().cloned(),
+            )
+        });
+        let decoder = ChangeEventDecoder::try_new(message_format, schema_registry)
+            .context(UnableToCreateDecoderSnafu)?;
 
         let kakfa_brokers = params
             .get("kafka_bootstrap_servers")
@@ -74,6 +96,7 @@ impl Debezium {
 
         Ok(Self {
             kafka_brokers: kakfa_brokers.to_string(),
+            decoder: Arc::new(decoder),
         })
     }
 }
@@ -101,7 +124,15 @@ const PARAMETERS: &[ParameterSpec] = &[
     ParameterSpec::connector("message_format")
         .required()
         .default("json")
-        .description("The message format to use. The default is json."),
+        .description("The message format to use: json, avro or protobuf. The default is json."),
+    ParameterSpec::connector("schema_registry_url").description(
+        "The URL of the schema registry that resolves the schemas of avro and protobuf messages.",
+    ),
+    ParameterSpec::connector("schema_registry_username")
+        .description("The username for the schema registry."),
+    ParameterSpec::connector("schema_registry_password")
+        .secret()
+        .description("The password for the schema registry."),
     ParameterSpec::runtime("kafka_bootstrap_servers")
         .required()
         .description(
@@ -215,13 +246,17 @@ impl DataConnector for Debezium {
 
                 (kafka_consumer, metadata, Arc::new(schema))
             }
-            None => get_metadata_from_kafka(dataset, &topic, self.kafka_brokers.clone()).await?,
+            None => {
+                get_metadata_from_kafka(dataset, &topic, self.kafka_brokers.clone(), &self.decoder)
+                    .await?
+            }
         };
 
         let debezium_kafka = Arc::new(DebeziumKafka::new(
             schema,
             metadata.primary_keys,
             kafka_consumer,
+            Arc::clone(&self.decoder),
         ));
 
         Ok(debezium_kafka)
@@ -264,6 +299,7 @@ async fn get_metadata_from_kafka(
     dataset: &Dataset,
     topic: &str,
     kafka_brokers: String,
+    decoder: &ChangeEventDecoder,
 ) -> super::DataConnectorResult<(KafkaConsumer, DebeziumKafkaMetadata, SchemaRef)> {
     let dataset_name = dataset.name.to_string();
     let kafka_consumer =
@@ -280,10 +316,7 @@ async fn get_metadata_from_kafka(
             dataconnector: "debezium",
         })?;
 
-    let msg = match kafka_consumer
-        .next_json::<ChangeEventKey, ChangeEvent>()
-        .await
-    {
+    let msg = match kafka_consumer.next_bytes().await {
         Ok(Some(msg)) => msg,
         Ok(None) => {
             return Err(super::DataConnectorError::UnableToGetReadProvider {
@@ -298,9 +331,20 @@ async fn get_metadata_from_kafka(
         }
     };
 
-    let primary_keys = msg.key().get_primary_key();
+    let key = decoder.decode_key(msg.key()).await.boxed().context(
+        super::UnableToGetReadProviderSnafu {
+            dataconnector: "debezium",
+        },
+    )?;
+    let (value, _) = decoder.decode_value(msg.value()).await.boxed().context(
+        super::UnableToGetReadProviderSnafu {
+            dataconnector: "debezium",
+        },
+    )?;
+
+    let primary_keys = key.get_primary_key();
 
-    let Some(schema_fields) = msg.value().get_schema_fields() else {
+    let Some(schema_fields) = value.get_schema_fields() else {
         return Err(super::DataConnectorError::UnableToGetReadProvider {
             dataconnector: "debezium".to_string(),
             source: "Could not get Arrow schema from Debezium message".into(),
@@ -54,7 +54,9 @@ impl FileFactory {
 
 const PARAMETERS: &[ParameterSpec] = &[
     // Common listing table parameters
-    ParameterSpec::runtime("file_format"),
+    ParameterSpec::runtime("file_format").description(
+        "The format of the files: csv, parquet, json (newline-delimited), avro or arrow (IPC).",
+    ),
     ParameterSpec::runtime("file_extension"),
     ParameterSpec::runtime("csv_has_header")
         .description("Set true to indicate that the first line is a header."),
@@ -64,8 +66,14 @@ const PARAMETERS: &[ParameterSpec] = &[
         .description("Set a limit in terms of records to scan to infer the schema."),
     ParameterSpec::runtime("csv_delimiter")
         .description("The character separating values within a row."),
+    ParameterSpec::runtime("json_schema_infer_max_records")
+        .description("Set a limit in terms of JSON records to scan to infer the schema."),
     ParameterSpec::runtime("file_compression_type")
         .description("The type of compression used on the file. Supported types are: GZIP, BZIP2, XZ, ZSTD, UNCOMPRESSED"),
+    ParameterSpec::runtime("hive_partitioning_enabled")
+        .description("Set true to infer partition columns from Hive-style key=value directories."),
+    ParameterSpec::runtime("partition_columns")
+        .description("A comma separated list of Hive-style partition columns with an optional Arrow type, e.g. date:Date32,region. Columns are Utf8 by default."),
 ];
 
 impl DataConnectorFactory for FileFactory {
@@ -105,3 +113,98 @@ impl ListingTableConnector for File {
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use arrow::array::AsArray;
+    use arrow::datatypes::{DataType, Int64Type};
+    use datafusion::datasource::TableProvider;
+    use datafusion::execution::context::SessionContext;
+    use datafusion::physical_plan::displayable;
+    use datafusion_table_providers::util::secrets::to_secret_map;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_partition_filter_reads_matching_directories() {
+        let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        for (year, region, ids) in [
+            (2023, "eu", "1\n"),
+            (2024, "eu", "2\n3\n"),
+            (2024, "us", "4\n"),
+        ] {
+            let dir = root.join(format!("year={year}/region={region}"));
+            std::fs::create_dir_all(&dir).expect("partition directory is created");
+            std::fs::write(dir.join("data.csv"), format!("id\n{ids}")).expect("data is written");
+        }
+
+        let mut params = HashMap::new();
+        params.insert("file_format".to_string(), "csv".to_string());
+        params.insert("csv_has_header".to_string(), "true".to_string());
+        params.insert(
+            "partition_columns".to_string(),
+            "year:Int32,region".to_string(),
+        );
+        let connector = File {
+            params: Parameters::new(
+                to_secret_map(params).into_iter().collect(),
+                "file",
+                PARAMETERS,
+            ),
+        };
+        let dataset = Dataset::try_new(format!("file:{}/", root.display()), "partitioned")
+            .expect("a valid dataset");
+
+        let table = connector
+            .read_provider(&dataset)
+            .await
+            .expect("table is created");
+        let schema = table.schema();
+        assert_eq!(
+            schema.field_with_name("year").expect("year").data_type(),
+            &DataType::Int32
+        );
+        assert_eq!(
+            schema
+                .field_with_name("region")
+                .expect("region")
+                .data_type(),
+            &DataType::Utf8
+        );
+
+        let ctx = SessionContext::new();
+        ctx.register_table("partitioned", table)
+            .expect("table is registered");
+        let df = ctx
+            .sql("SELECT id FROM partitioned WHERE year = 2024 AND region = 'eu' ORDER BY id")
+            .await
+            .expect("query is planned");
+
+        let plan = df
+            .clone()
+            .create_physical_plan()
+            .await
+            .expect("physical plan is created");
+        let plan = displayable(plan.as_ref()).indent(true).to_string();
+        assert!(plan.contains("year=2024/region=eu/data.csv"), "{plan}");
+        assert!(!plan.contains("year=2023"), "{plan}");
+        assert!(!plan.contains("region=us"), "{plan}");
+
+        let batches = df.collect().await.expect("query is executed");
+        let ids: Vec<i64> = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_primitive::<Int64Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect();
+        assert_eq!(ids, vec![2, 3]);
+
+        std::fs::remove_dir_all(root).expect("partitions are removed");
+    }
+}
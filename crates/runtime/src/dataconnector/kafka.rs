@@ -0,0 +1,371 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::component::dataset::acceleration::RefreshMode;
+use crate::component::dataset::Dataset;
+use crate::dataaccelerator::metadata::AcceleratedMetadata;
+use arrow::datatypes::SchemaRef;
+use async_trait::async_trait;
+use data_components::cdc::AppendStream;
+use data_components::debezium::decoder::{self, ChangeEventDecoder, MessageFormat};
+use data_components::debezium::schema_registry::SchemaRegistry;
+use data_components::kafka::{KafkaConsumer, StartOffset};
+use data_components::kafka_table::{self, KafkaTable};
+use datafusion::datasource::TableProvider;
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{DataConnector, DataConnectorFactory, ParameterSpec, Parameters};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display(
+        "Invalid value for kafka_message_format: Valid values: 'json', 'avro', 'protobuf'"
+    ))]
+    InvalidMessageFormat,
+
+    #[snafu(display("{message}"))]
+    InvalidStartOffset { message: String },
+
+    #[snafu(display("{source}"))]
+    UnableToCreateDecoder { source: decoder::Error },
+
+    #[snafu(display("Missing required parameter: kafka_bootstrap_servers"))]
+    MissingKafkaBootstrapServers,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The number of messages the schema of a topic is inferred from.
+const SCHEMA_SAMPLE_SIZE: usize = 100;
+
+/// How long to wait for the first message of a topic to infer its schema from.
+const SCHEMA_INFERENCE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for further messages to sample once the first one was received.
+const SCHEMA_SAMPLE_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct Kafka {
+    kafka_brokers: String,
+    start_offset: StartOffset,
+    decoder: Arc<ChangeEventDecoder>,
+}
+
+impl Kafka {
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn new(params: Parameters) -> Result<Self> {
+        let message_format = params.get("message_format").expose().ok().unwrap_or("json");
+        let message_format = MessageFormat::from_str(message_format)
+            .ok()
+            .context(InvalidMessageFormatSnafu)?;
+
+        let start_offset = params
+            .get("start_offset")
+            .expose()
+            .ok()
+            .map(StartOffset::from_str)
+            .transpose()
+            .map_err(|message| Error::InvalidStartOffset { message })?
+            .unwrap_or_default();
+
+        let schema_registry = params.get("schema_registry_url").expose().ok().map(|url| {
+            SchemaRegistry::new(
+                url,
+                params
+                    .get("schema_registry_username")
+                    .expose()
+                    .ok()
+                    .map(str::to_string),
+                params.get("schema_registry_password").ok().cloned(),
+            )
+        });
+        let decoder = ChangeEventDecoder::try_new(message_format, schema_registry)
+            .context(UnableToCreateDecoderSnafu)?;
+
+        let kafka_brokers = params
+            .get("bootstrap_servers")
+            .expose()
+            .ok()
+            .context(MissingKafkaBootstrapServersSnafu)?;
+
+        Ok(Self {
+            kafka_brokers: kafka_brokers.to_string(),
+            start_offset,
+            decoder: Arc::new(decoder),
+        })
+    }
+}
+
+#[derive(Default, Copy, Clone)]
+pub struct KafkaFactory {}
+
+impl KafkaFactory {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    #[must_use]
+    pub fn new_arc() -> Arc<dyn DataConnectorFactory> {
+        Arc::new(Self {}) as Arc<dyn DataConnectorFactory>
+    }
+}
+
+const PARAMETERS: &[ParameterSpec] = &[
+    ParameterSpec::connector("bootstrap_servers")
+        .required()
+        .description(
+            "A list of host/port pairs for establishing the initial Kafka cluster connection.",
+        ),
+    ParameterSpec::connector("message_format")
+        .default("json")
+        .description("The format of the messages: json, avro or protobuf. The default is json."),
+    ParameterSpec::connector("start_offset")
+        .default("earliest")
+        .description("Where to start reading the topic: earliest, latest, or a timestamp (RFC 3339 or milliseconds since the Unix epoch). The default is earliest."),
+    ParameterSpec::connector("schema_registry_url")
+        .description("The URL of the schema registry that resolves the schemas of avro and protobuf messages."),
+    ParameterSpec::connector("schema_registry_username")
+        .description("The username for the schema registry."),
+    ParameterSpec::connector("schema_registry_password")
+        .secret()
+        .description("The password for the schema registry."),
+];
+
+impl DataConnectorFactory for KafkaFactory {
+    fn create(
+        &self,
+        params: Parameters,
+    ) -> Pin<Box<dyn Future<Output = super::NewDataConnectorResult> + Send>> {
+        Box::pin(async move {
+            let kafka = Kafka::new(params)?;
+            Ok(Arc::new(kafka) as Arc<dyn DataConnector>)
+        })
+    }
+
+    fn prefix(&self) -> &'static str {
+        "kafka"
+    }
+
+    fn parameters(&self) -> &'static [ParameterSpec] {
+        PARAMETERS
+    }
+}
+
+#[async_trait]
+impl DataConnector for Kafka {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn resolve_refresh_mode(&self, refresh_mode: Option<RefreshMode>) -> RefreshMode {
+        refresh_mode.unwrap_or(RefreshMode::Append)
+    }
+
+    async fn read_provider(
+        &self,
+        dataset: &Dataset,
+    ) -> super::DataConnectorResult<Arc<dyn TableProvider>> {
+        ensure!(
+            dataset.is_accelerated(),
+            super::InvalidConfigurationNoSourceSnafu {
+                dataconnector: "kafka",
+                message: "The Kafka data connector only works with accelerated datasets.",
+            }
+        );
+        let Some(ref acceleration) = dataset.acceleration else {
+            unreachable!("we just checked above that the dataset is accelerated");
+        };
+        ensure!(
+            self.resolve_refresh_mode(acceleration.refresh_mode) == RefreshMode::Append,
+            super::InvalidConfigurationNoSourceSnafu {
+                dataconnector: "kafka",
+                message: "The Kafka data connector only works with 'append' refresh mode.",
+            }
+        );
+
+        let dataset_name = dataset.name.to_string();
+        let topic = dataset.path();
+
+        let schema = self.infer_schema(&dataset_name, &topic).await?;
+
+        let kafka_consumer = match get_metadata_from_accelerator(dataset).await {
+            Some(metadata) => {
+                ensure!(
+                    topic == metadata.topic,
+                    super::InvalidConfigurationNoSourceSnafu {
+                        dataconnector: "kafka",
+                        message: format!("The topic has changed from {} to {topic} for dataset {dataset_name}. The existing accelerator data may be out of date.", metadata.topic),
+                    }
+                );
+
+                // Continue from the offsets committed by the consumer group
+                let kafka_consumer = KafkaConsumer::create_with_existing_group_id(
+                    &metadata.consumer_group_id,
+                    self.kafka_brokers.clone(),
+                )
+                .boxed()
+                .context(super::UnableToGetReadProviderSnafu {
+                    dataconnector: "kafka",
+                })?;
+                kafka_consumer.subscribe(&topic).boxed().context(
+                    super::UnableToGetReadProviderSnafu {
+                        dataconnector: "kafka",
+                    },
+                )?;
+                kafka_consumer
+            }
+            None => self.create_consumer(dataset, &topic).await?,
+        };
+
+        Ok(Arc::new(KafkaTable::new(
+            schema,
+            kafka_consumer,
+            Arc::clone(&self.decoder),
+        )))
+    }
+
+    fn append_stream(&self, table_provider: Arc<dyn TableProvider>) -> Option<AppendStream> {
+        let kafka_table = table_provider.as_any().downcast_ref::<KafkaTable>()?;
+
+        Some(kafka_table.stream_appends())
+    }
+}
+
+impl Kafka {
+    /// Infers the schema of the topic from a sample of its first messages, read by a separate
+    /// consumer that doesn't commit its offsets.
+    async fn infer_schema(
+        &self,
+        dataset_name: &str,
+        topic: &str,
+    ) -> super::DataConnectorResult<SchemaRef> {
+        let kafka_consumer =
+            KafkaConsumer::create_with_generated_group_id(dataset_name, self.kafka_brokers.clone())
+                .boxed()
+                .context(super::UnableToGetReadProviderSnafu {
+                    dataconnector: "kafka",
+                })?;
+        kafka_consumer
+            .subscribe(topic)
+            .boxed()
+            .context(super::UnableToGetReadProviderSnafu {
+                dataconnector: "kafka",
+            })?;
+
+        let payloads = kafka_consumer
+            .sample_payloads(
+                SCHEMA_SAMPLE_SIZE,
+                SCHEMA_INFERENCE_TIMEOUT,
+                SCHEMA_SAMPLE_IDLE_TIMEOUT,
+            )
+            .await
+            .boxed()
+            .context(super::UnableToGetReadProviderSnafu {
+                dataconnector: "kafka",
+            })?;
+
+        if payloads.is_empty() {
+            return Err(super::DataConnectorError::UnableToGetReadProvider {
+                dataconnector: "kafka".to_string(),
+                source: format!(
+                    "No message was received from topic '{topic}' within {}s. The schema of the dataset is inferred from the messages of the topic, so it must have at least one message.",
+                    SCHEMA_INFERENCE_TIMEOUT.as_secs()
+                )
+                .into(),
+            });
+        }
+
+        let schema = kafka_table::infer_value_schema(&self.decoder, &payloads)
+            .await
+            .boxed()
+            .context(super::UnableToGetReadProviderSnafu {
+                dataconnector: "kafka",
+            })?;
+        Ok(Arc::new(schema))
+    }
+
+    /// Creates the consumer of a new consumer group that starts reading the topic at the
+    /// configured start offset, and saves the group for file accelerated datasets.
+    async fn create_consumer(
+        &self,
+        dataset: &Dataset,
+        topic: &str,
+    ) -> super::DataConnectorResult<KafkaConsumer> {
+        let kafka_consumer = KafkaConsumer::create_with_start_offset(
+            &dataset.name.to_string(),
+            self.kafka_brokers.clone(),
+            self.start_offset,
+        )
+        .boxed()
+        .context(super::UnableToGetReadProviderSnafu {
+            dataconnector: "kafka",
+        })?;
+
+        match self.start_offset {
+            StartOffset::Timestamp(timestamp) => kafka_consumer.seek_to_timestamp(topic, timestamp),
+            StartOffset::Earliest | StartOffset::Latest => kafka_consumer.subscribe(topic),
+        }
+        .boxed()
+        .context(super::UnableToGetReadProviderSnafu {
+            dataconnector: "kafka",
+        })?;
+
+        if dataset.is_file_accelerated() {
+            let metadata = KafkaMetadata {
+                consumer_group_id: kafka_consumer.group_id().to_string(),
+                topic: topic.to_string(),
+            };
+            set_metadata_to_accelerator(dataset, &metadata)
+                .await
+                .context(super::UnableToGetReadProviderSnafu {
+                    dataconnector: "kafka",
+                })?;
+        } else {
+            tracing::warn!(
+                "Dataset {} is not file accelerated. The topic will be read from the start offset again on restarts.",
+                dataset.name
+            );
+        }
+
+        Ok(kafka_consumer)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct KafkaMetadata {
+    consumer_group_id: String,
+    topic: String,
+}
+
+async fn get_metadata_from_accelerator(dataset: &Dataset) -> Option<KafkaMetadata> {
+    let accelerated_metadata = AcceleratedMetadata::new(dataset).await?;
+    accelerated_metadata.get_metadata().await
+}
+
+async fn set_metadata_to_accelerator(
+    dataset: &Dataset,
+    metadata: &KafkaMetadata,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let accelerated_metadata = AcceleratedMetadata::new_create_if_not_exists(dataset).await?;
+    accelerated_metadata.set_metadata(metadata).await
+}
@@ -14,23 +14,31 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::component::dataset::acceleration::{Engine, RefreshMode};
 use crate::component::dataset::Dataset;
+use crate::dataaccelerator::metadata::AcceleratedMetadata;
 use async_trait::async_trait;
+use data_components::cdc::ChangesStream;
+use data_components::mysql::binlog::{
+    self, Binlog, BinlogCheckpoint, BinlogPosition, MySQLBinlogTable,
+};
 use data_components::Read;
 use datafusion::datasource::TableProvider;
+use datafusion::sql::TableReference;
 use datafusion_table_providers::mysql::MySQLTableFactory;
 use datafusion_table_providers::sql::db_connection_pool::mysqlpool::MySQLConnectionPool;
 use datafusion_table_providers::sql::db_connection_pool::{
     DbConnectionPool, Error as DbConnectionPoolError,
 };
 use mysql_async::prelude::ToValue;
+use mysql_async::Opts;
 use snafu::prelude::*;
 use std::any::Any;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 
-use super::{DataConnector, DataConnectorFactory, ParameterSpec, Parameters};
+use super::{DataConnector, DataConnectorError, DataConnectorFactory, ParameterSpec, Parameters};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -42,6 +50,8 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 pub struct MySQL {
     mysql_factory: MySQLTableFactory,
+    opts: Opts,
+    server_id: Option<u32>,
 }
 
 #[derive(Default, Copy, Clone)]
@@ -68,6 +78,8 @@ const PARAMETERS: &[ParameterSpec] = &[
     ParameterSpec::connector("db"),
     ParameterSpec::connector("sslmode"),
     ParameterSpec::connector("sslrootcert"),
+    ParameterSpec::connector("server_id")
+        .description("The replica server ID used to read the binary log with `refresh_mode: changes`. Must be unique among the replicas of the server. Defaults to an ID derived from the dataset name."),
 ];
 
 impl DataConnectorFactory for MySQLFactory {
@@ -76,6 +88,25 @@ impl DataConnectorFactory for MySQLFactory {
         params: Parameters,
     ) -> Pin<Box<dyn Future<Output = super::NewDataConnectorResult> + Send>> {
         Box::pin(async move {
+            let server_id = match params.get("server_id").expose().ok() {
+                Some(server_id) => Some(server_id.parse::<u32>().map_err(|_| {
+                    DataConnectorError::InvalidConfigurationNoSource {
+                        dataconnector: "mysql".to_string(),
+                        message: format!(
+                            "The server_id '{server_id}' is not a valid MySQL server ID."
+                        ),
+                    }
+                })?),
+                None => None,
+            };
+            let opts = binlog::connection_opts(&params.to_secret_map()).map_err(|e| {
+                DataConnectorError::InvalidConfiguration {
+                    dataconnector: "mysql".to_string(),
+                    message: "Invalid MySQL connection parameters.".to_string(),
+                    source: Box::new(e),
+                }
+            })?;
+
             let pool: Arc<
                 dyn DbConnectionPool<mysql_async::Conn, &'static (dyn ToValue + Sync)>
                     + Send
@@ -88,7 +119,11 @@ impl DataConnectorFactory for MySQLFactory {
 
             let mysql_factory = MySQLTableFactory::new(pool);
 
-            Ok(Arc::new(MySQL { mysql_factory }) as Arc<dyn DataConnector>)
+            Ok(Arc::new(MySQL {
+                mysql_factory,
+                opts,
+                server_id,
+            }) as Arc<dyn DataConnector>)
         })
     }
 
@@ -101,22 +136,133 @@ impl DataConnectorFactory for MySQLFactory {
     }
 }
 
+impl MySQL {
+    /// Wraps the table to stream its changes from the binary log.
+    async fn binlog_table(
+        &self,
+        dataset: &Dataset,
+        provider: Arc<dyn TableProvider>,
+    ) -> super::DataConnectorResult<Arc<dyn TableProvider>> {
+        let Some(ref acceleration) = dataset.acceleration else {
+            unreachable!("only accelerated datasets have a 'changes' refresh mode");
+        };
+        ensure!(
+            acceleration.engine != Engine::Arrow,
+            super::InvalidConfigurationNoSourceSnafu {
+                dataconnector: "mysql",
+                message:
+                    "Streaming changes from MySQL only works with non-Arrow acceleration engines.",
+            }
+        );
+
+        let dataset_name = dataset.name.to_string();
+        if !dataset.is_file_accelerated() {
+            tracing::warn!(
+                "Dataset {dataset_name} is not file accelerated. This is not recommended as it requires reading the whole table again on restarts.",
+            );
+        }
+
+        let table_reference = TableReference::from(dataset.path());
+        let primary_keys = binlog::primary_keys(&self.opts, &table_reference)
+            .await
+            .boxed()
+            .context(super::UnableToGetReadProviderSnafu {
+                dataconnector: "mysql",
+            })?;
+        ensure!(
+            !primary_keys.is_empty(),
+            super::InvalidConfigurationNoSourceSnafu {
+                dataconnector: "mysql",
+                message: format!("The table {table_reference} has no primary key, which is required to stream its changes."),
+            }
+        );
+
+        let server_id = self
+            .server_id
+            .unwrap_or_else(|| binlog::default_server_id(&dataset_name));
+        let checkpoint = Arc::new(AcceleratorCheckpoint {
+            dataset: dataset.clone(),
+        });
+        let binlog = Binlog::try_new(self.opts.clone(), &table_reference, server_id, checkpoint)
+            .boxed()
+            .context(super::UnableToGetReadProviderSnafu {
+                dataconnector: "mysql",
+            })?;
+
+        Ok(Arc::new(MySQLBinlogTable::new(
+            provider,
+            primary_keys,
+            binlog,
+        )))
+    }
+}
+
 #[async_trait]
 impl DataConnector for MySQL {
     fn as_any(&self) -> &dyn Any {
         self
     }
 
+    fn supports_changes_stream(&self) -> bool {
+        true
+    }
+
+    fn changes_stream(&self, table_provider: Arc<dyn TableProvider>) -> Option<ChangesStream> {
+        let binlog_table = table_provider.as_any().downcast_ref::<MySQLBinlogTable>()?;
+
+        Some(binlog_table.stream_changes())
+    }
+
     async fn read_provider(
         &self,
         dataset: &Dataset,
     ) -> super::DataConnectorResult<Arc<dyn TableProvider>> {
-        Ok(
+        let provider =
             Read::table_provider(&self.mysql_factory, dataset.path().into(), dataset.schema())
                 .await
                 .context(super::UnableToGetReadProviderSnafu {
                     dataconnector: "mysql",
-                })?,
-        )
+                })?;
+
+        let streams_changes = dataset.acceleration.as_ref().is_some_and(|acceleration| {
+            self.resolve_refresh_mode(acceleration.refresh_mode) == RefreshMode::Changes
+        });
+        if streams_changes {
+            return self.binlog_table(dataset, provider).await;
+        }
+
+        Ok(provider)
+    }
+}
+
+/// Saves the binary log position up to which changes were written in the accelerator metadata,
+/// so the changes stream resumes after it on restarts. Accelerators that aren't file-based start
+/// over instead.
+struct AcceleratorCheckpoint {
+    dataset: Dataset,
+}
+
+#[async_trait]
+impl BinlogCheckpoint for AcceleratorCheckpoint {
+    async fn load(&self) -> Option<BinlogPosition> {
+        if !self.dataset.is_file_accelerated() {
+            return None;
+        }
+
+        let accelerated_metadata = AcceleratedMetadata::new(&self.dataset).await?;
+        accelerated_metadata.get_metadata().await
+    }
+
+    async fn save(
+        &self,
+        position: &BinlogPosition,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.dataset.is_file_accelerated() {
+            return Ok(());
+        }
+
+        let accelerated_metadata =
+            AcceleratedMetadata::new_create_if_not_exists(&self.dataset).await?;
+        accelerated_metadata.set_metadata(position).await
     }
 }
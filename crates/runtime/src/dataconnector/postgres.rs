@@ -14,16 +14,24 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::component::dataset::acceleration::{Engine, RefreshMode};
 use crate::component::dataset::Dataset;
+use crate::dataaccelerator::metadata::AcceleratedMetadata;
 use async_trait::async_trait;
+use data_components::cdc::ChangesStream;
+use data_components::postgres::replication::{
+    self, Lsn, PostgresReplicationTable, Replication, ReplicationCheckpoint,
+};
 use data_components::Read;
 use datafusion::datasource::TableProvider;
+use datafusion::sql::TableReference;
 use datafusion_table_providers::postgres::PostgresTableFactory;
 use datafusion_table_providers::sql::db_connection_pool::dbconnection;
 use datafusion_table_providers::sql::db_connection_pool::{
     postgrespool::{self, PostgresConnectionPool},
     Error as DbConnectionPoolError,
 };
+use serde::{Deserialize, Serialize};
 use snafu::prelude::*;
 use std::any::Any;
 use std::future::Future;
@@ -40,6 +48,9 @@ pub enum Error {
 
 pub struct Postgres {
     postgres_factory: PostgresTableFactory,
+    pool: Arc<PostgresConnectionPool>,
+    replication_slot: Option<String>,
+    publication: Option<String>,
 }
 
 #[derive(Default, Copy, Clone)]
@@ -66,6 +77,10 @@ const PARAMETERS: &[ParameterSpec] = &[
     ParameterSpec::connector("db"),
     ParameterSpec::connector("sslmode"),
     ParameterSpec::connector("sslrootcert"),
+    ParameterSpec::connector("replication_slot")
+        .description("The logical replication slot to read changes from with `refresh_mode: changes`. Defaults to `spice_<dataset name>`."),
+    ParameterSpec::connector("publication")
+        .description("The publication of the replicated table, created if it doesn't exist. Defaults to the replication slot name."),
 ];
 
 impl DataConnectorFactory for PostgresFactory {
@@ -74,10 +89,23 @@ impl DataConnectorFactory for PostgresFactory {
         params: Parameters,
     ) -> Pin<Box<dyn Future<Output = super::NewDataConnectorResult> + Send>> {
         Box::pin(async move {
+            let replication_slot = params
+                .get("replication_slot")
+                .expose()
+                .ok()
+                .map(str::to_string);
+            let publication = params.get("publication").expose().ok().map(str::to_string);
+
             match PostgresConnectionPool::new(params.to_secret_map()).await {
                 Ok(pool) => {
-                    let postgres_factory = PostgresTableFactory::new(Arc::new(pool));
-                    Ok(Arc::new(Postgres { postgres_factory }) as Arc<dyn DataConnector>)
+                    let pool = Arc::new(pool);
+                    let postgres_factory = PostgresTableFactory::new(Arc::clone(&pool));
+                    Ok(Arc::new(Postgres {
+                        postgres_factory,
+                        pool,
+                        replication_slot,
+                        publication,
+                    }) as Arc<dyn DataConnector>)
                 }
                 Err(e) => match e {
                     postgrespool::Error::InvalidUsernameOrPassword { .. } => Err(
@@ -117,12 +145,134 @@ impl DataConnectorFactory for PostgresFactory {
     }
 }
 
+impl Postgres {
+    /// Wraps the table to stream its changes from a logical replication slot.
+    async fn replication_table(
+        &self,
+        dataset: &Dataset,
+        provider: Arc<dyn TableProvider>,
+    ) -> super::DataConnectorResult<Arc<dyn TableProvider>> {
+        let Some(ref acceleration) = dataset.acceleration else {
+            unreachable!("only accelerated datasets have a 'changes' refresh mode");
+        };
+        ensure!(
+            acceleration.engine != Engine::Arrow,
+            super::InvalidConfigurationNoSourceSnafu {
+                dataconnector: "postgres",
+                message: "Streaming changes from Postgres only works with non-Arrow acceleration engines.",
+            }
+        );
+
+        let dataset_name = dataset.name.to_string();
+        if !dataset.is_file_accelerated() {
+            tracing::warn!(
+                "Dataset {dataset_name} is not file accelerated. This is not recommended as it requires reading the whole table again on restarts.",
+            );
+        }
+
+        let table_reference = TableReference::from(dataset.path());
+        let primary_keys = replication::primary_keys(&self.pool, &table_reference)
+            .await
+            .boxed()
+            .context(super::UnableToGetReadProviderSnafu {
+                dataconnector: "postgres",
+            })?;
+        ensure!(
+            !primary_keys.is_empty(),
+            super::InvalidConfigurationNoSourceSnafu {
+                dataconnector: "postgres",
+                message: format!("The table {table_reference} has no primary key, which is required to stream its changes."),
+            }
+        );
+
+        let (slot_name, publication) = self.slot_and_publication(dataset);
+        let checkpoint = Arc::new(AcceleratorCheckpoint {
+            dataset: dataset.clone(),
+            slot_name: slot_name.clone(),
+        });
+
+        Ok(Arc::new(PostgresReplicationTable::new(
+            provider,
+            primary_keys,
+            Replication::new(
+                Arc::clone(&self.pool),
+                table_reference,
+                slot_name,
+                publication,
+                checkpoint,
+            ),
+        )))
+    }
+
+    /// The replication slot and publication a dataset streams its changes from.
+    fn slot_and_publication(&self, dataset: &Dataset) -> (String, String) {
+        let slot_name = self
+            .replication_slot
+            .clone()
+            .unwrap_or_else(|| replication::default_slot_name(&dataset.name.to_string()));
+        let publication = self
+            .publication
+            .clone()
+            .unwrap_or_else(|| slot_name.clone());
+        (slot_name, publication)
+    }
+
+    fn streams_changes(&self, dataset: &Dataset) -> bool {
+        dataset.acceleration.as_ref().is_some_and(|acceleration| {
+            self.resolve_refresh_mode(acceleration.refresh_mode) == RefreshMode::Changes
+        })
+    }
+}
+
 #[async_trait]
 impl DataConnector for Postgres {
     fn as_any(&self) -> &dyn Any {
         self
     }
 
+    fn supports_changes_stream(&self) -> bool {
+        true
+    }
+
+    fn changes_stream(&self, table_provider: Arc<dyn TableProvider>) -> Option<ChangesStream> {
+        let replication_table = table_provider
+            .as_any()
+            .downcast_ref::<PostgresReplicationTable>()?;
+
+        Some(replication_table.stream_changes())
+    }
+
+    /// Drops the replication slot of a dataset that streamed its changes, and its publication
+    /// unless one was configured.
+    async fn remove_dataset(&self, dataset: &Dataset) -> super::DataConnectorResult<()> {
+        if !self.streams_changes(dataset) {
+            return Ok(());
+        }
+
+        let (slot_name, publication) = self.slot_and_publication(dataset);
+        tracing::info!(
+            "Dropping replication slot {slot_name} of removed dataset {}",
+            dataset.name
+        );
+        replication::drop_slot(&self.pool, &slot_name)
+            .await
+            .boxed()
+            .context(super::InternalWithSourceSnafu {
+                dataconnector: "postgres",
+            })?;
+
+        if self.publication.is_none() {
+            replication::drop_publication(&self.pool, &publication)
+                .await
+                .boxed()
+                .context(super::InternalWithSourceSnafu {
+                    dataconnector: "postgres",
+                })?;
+        }
+
+        Ok(())
+    }
+
     async fn read_provider(
         &self,
         dataset: &Dataset,
@@ -134,7 +284,13 @@ impl DataConnector for Postgres {
         )
         .await
         {
-            Ok(provider) => Ok(provider),
+            Ok(provider) => {
+                if self.streams_changes(dataset) {
+                    return self.replication_table(dataset, provider).await;
+                }
+
+                Ok(provider)
+            }
             Err(e) => {
                 if let Some(err_source) = e.source() {
                     if let Some(dbconnection::Error::UndefinedTable {
@@ -158,3 +314,54 @@ impl DataConnector for Postgres {
         }
     }
 }
+
+#[derive(Serialize, Deserialize)]
+struct PostgresReplicationMetadata {
+    slot_name: String,
+    confirmed_lsn: String,
+}
+
+/// Saves the confirmed LSN of the replication slot in the accelerator metadata, so the changes
+/// stream resumes after it on restarts. Accelerators that aren't file-based start over instead.
+struct AcceleratorCheckpoint {
+    dataset: Dataset,
+    slot_name: String,
+}
+
+#[async_trait]
+impl ReplicationCheckpoint for AcceleratorCheckpoint {
+    async fn load(&self) -> Option<Lsn> {
+        if !self.dataset.is_file_accelerated() {
+            return None;
+        }
+
+        let accelerated_metadata = AcceleratedMetadata::new(&self.dataset).await?;
+        let metadata: PostgresReplicationMetadata = accelerated_metadata.get_metadata().await?;
+        if metadata.slot_name != self.slot_name {
+            tracing::warn!(
+                "The replication slot has changed from {} to {} for dataset {}. Reading the table from the start.",
+                metadata.slot_name,
+                self.slot_name,
+                self.dataset.name
+            );
+            return None;
+        }
+
+        metadata.confirmed_lsn.parse().ok()
+    }
+
+    async fn save(&self, lsn: Lsn) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.dataset.is_file_accelerated() {
+            return Ok(());
+        }
+
+        let accelerated_metadata =
+            AcceleratedMetadata::new_create_if_not_exists(&self.dataset).await?;
+        accelerated_metadata
+            .set_metadata(&PostgresReplicationMetadata {
+                slot_name: self.slot_name.clone(),
+                confirmed_lsn: lsn.to_string(),
+            })
+            .await
+    }
+}
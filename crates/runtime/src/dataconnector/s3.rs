@@ -72,7 +72,9 @@ const PARAMETERS: &[ParameterSpec] = &[
         .description("The timeout setting for S3 client."),
 
     // Common listing table parameters
-    ParameterSpec::runtime("file_format"),
+    ParameterSpec::runtime("file_format").description(
+        "The format of the files: csv, parquet, json (newline-delimited), avro or arrow (IPC).",
+    ),
     ParameterSpec::runtime("file_extension"),
     ParameterSpec::runtime("csv_has_header")
         .description("Set true to indicate that the first line is a header."),
@@ -82,8 +84,14 @@ const PARAMETERS: &[ParameterSpec] = &[
         .description("Set a limit in terms of records to scan to infer the schema."),
     ParameterSpec::runtime("csv_delimiter")
         .description("The character separating values within a row."),
+    ParameterSpec::runtime("json_schema_infer_max_records")
+        .description("Set a limit in terms of JSON records to scan to infer the schema."),
     ParameterSpec::runtime("file_compression_type")
         .description("The type of compression used on the file. Supported types are: GZIP, BZIP2, XZ, ZSTD, UNCOMPRESSED"),
+    ParameterSpec::runtime("hive_partitioning_enabled")
+        .description("Set true to infer partition columns from Hive-style key=value directories."),
+    ParameterSpec::runtime("partition_columns")
+        .description("A comma separated list of Hive-style partition columns with an optional Arrow type, e.g. date:Date32,region. Columns are Utf8 by default."),
 ];
 
 impl DataConnectorFactory for S3Factory {
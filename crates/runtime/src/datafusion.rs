@@ -15,12 +15,14 @@ limitations under the License.
 */
 
 use std::borrow::Borrow;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
 use crate::accelerated_table::refresh;
 use crate::accelerated_table::{refresh::Refresh, AcceleratedTable, Retention};
+use crate::auth::{access_control::AccessControl, policy};
 use crate::component::dataset::acceleration::RefreshMode;
 use crate::component::dataset::{Dataset, Mode};
 use crate::dataaccelerator::{self, create_accelerator_table};
@@ -28,16 +30,20 @@ use crate::dataconnector::{DataConnector, DataConnectorError};
 use crate::dataupdate::{
     DataUpdate, StreamingDataUpdate, StreamingDataUpdateExecutionPlan, UpdateType,
 };
+use crate::embeddings::full_text::FullTextIndex;
+use crate::embeddings::index::EmbeddingIndex;
+use crate::embeddings::SearchIndex;
 use crate::object_store_registry::default_runtime_env;
 use crate::secrets::Secrets;
 use crate::{embeddings, get_dependent_table_names};
 
-use arrow::datatypes::Schema;
+use arrow::datatypes::{Schema, SchemaRef};
 use arrow::error::ArrowError;
 use arrow_tools::schema::verify_schema;
 use cache::QueryResultsCacheProvider;
 use datafusion::catalog::schema::SchemaProvider;
-use datafusion::catalog::{CatalogProvider, MemoryCatalogProvider};
+use datafusion::catalog::{CatalogProvider, MemoryCatalogProvider, MemoryCatalogProviderList};
+use datafusion::common::Constraints;
 use datafusion::datasource::{TableProvider, ViewTable};
 use datafusion::error::DataFusionError;
 use datafusion::execution::context::{SessionConfig, SessionContext, SessionState};
@@ -46,15 +52,17 @@ use datafusion::sql::parser::DFParser;
 use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
 use datafusion::sql::{sqlparser, TableReference};
 use datafusion_federation::{FederatedQueryPlanner, FederationAnalyzerRule};
-use query::{Protocol, QueryBuilder};
+use query::{limits::QueryLimits, running_queries::RunningQueries, Protocol, QueryBuilder};
 use snafu::prelude::*;
+use spicepod::component::dataset::policy::Policy;
 use tokio::spawn;
 use tokio::sync::oneshot;
-use tokio::sync::RwLock as TokioRwLock;
+use tokio::sync::{Mutex as TokioMutex, OwnedMutexGuard, RwLock as TokioRwLock};
 use tokio::time::{sleep, Instant};
 
 pub mod query;
 
+mod dml;
 pub mod filter_converter;
 pub mod initial_load;
 pub mod refresh_sql;
@@ -84,6 +92,9 @@ pub enum Error {
     #[snafu(display("Unable to delete table: {reason}"))]
     UnableToDeleteTable { reason: String },
 
+    #[snafu(display("Unable to delete catalog: {reason}"))]
+    UnableToDeleteCatalog { reason: String },
+
     #[snafu(display("Unable to parse SQL: {source}"))]
     UnableToParseSql {
         source: sqlparser::parser::ParserError,
@@ -142,6 +153,25 @@ pub enum Error {
         source: DataFusionError,
     },
 
+    #[snafu(display(
+        "Unable to execute {operation} statements, only INSERT, UPDATE and DELETE are supported"
+    ))]
+    UnsupportedDmlOperation { operation: String },
+
+    #[snafu(display("The table {table_name} does not support DELETE"))]
+    DeleteNotSupported { table_name: String },
+
+    #[snafu(display(
+        "Unable to execute the statement for {table_name}: only WHERE predicates on the table's own columns are supported"
+    ))]
+    UnsupportedDmlPredicate { table_name: String },
+
+    #[snafu(display("Unable to execute the statement for {table_name}: {source}"))]
+    UnableToExecuteDml {
+        table_name: String,
+        source: DataFusionError,
+    },
+
     #[snafu(display("Unable to trigger refresh for {table_name}: {source}"))]
     UnableToTriggerRefresh {
         table_name: String,
@@ -177,6 +207,9 @@ pub enum Error {
     #[snafu(display("Unable to get the lock of data writers"))]
     UnableToLockDataWriters {},
 
+    #[snafu(display("Unable to get the lock of dataset policies"))]
+    UnableToLockDatasetPolicies {},
+
     #[snafu(display("The schema returned by the data connector for 'refresh_mode: changes' does not contain a data field"))]
     ChangeSchemaWithoutDataField { source: ArrowError },
 
@@ -207,6 +240,13 @@ pub struct DataFusion {
     pub ctx: Arc<SessionContext>,
     data_writers: RwLock<HashSet<TableReference>>,
     cache_provider: RwLock<Option<Arc<QueryResultsCacheProvider>>>,
+    access_control: RwLock<Option<Arc<AccessControl>>>,
+    dataset_policies: RwLock<HashMap<TableReference, Arc<Policy>>>,
+    query_limits: RwLock<QueryLimits>,
+    running_queries: Arc<RunningQueries>,
+
+    /// Serializes the writes to each table, so that the delete and insert of an `UPDATE` aren't interleaved with other writes.
+    write_locks: Mutex<HashMap<TableReference, Arc<TokioMutex<()>>>>,
 
     /// Has the initial load of the data been completed? It is the responsibility of the caller to call `mark_initial_load_complete` when the initial load is complete.
     initial_load_complete: Mutex<bool>,
@@ -277,6 +317,11 @@ impl DataFusion {
             ctx: Arc::new(ctx),
             data_writers: RwLock::new(HashSet::new()),
             cache_provider: RwLock::new(cache_provider),
+            access_control: RwLock::new(None),
+            dataset_policies: RwLock::new(HashMap::new()),
+            query_limits: RwLock::new(QueryLimits::default()),
+            running_queries: Arc::new(RunningQueries::default()),
+            write_locks: Mutex::new(HashMap::new()),
             initial_load_complete: Mutex::new(false),
         }
     }
@@ -305,6 +350,47 @@ impl DataFusion {
         };
     }
 
+    pub fn remove_cache_provider(&self) {
+        if let Ok(mut a) = self.cache_provider.write() {
+            *a = None;
+        };
+    }
+
+    /// Enforces the configured dataset grants on queries run on behalf of an authenticated principal.
+    pub fn set_access_control(&self, access_control: AccessControl) {
+        if let Ok(mut a) = self.access_control.write() {
+            *a = Some(Arc::new(access_control));
+        };
+    }
+
+    pub fn access_control(&self) -> Option<Arc<AccessControl>> {
+        let Ok(access_control) = self.access_control.read() else {
+            return None;
+        };
+
+        access_control.clone()
+    }
+
+    /// Sets the limits applied to queries that don't override them.
+    pub fn set_query_limits(&self, query_limits: QueryLimits) {
+        if let Ok(mut limits) = self.query_limits.write() {
+            *limits = query_limits;
+        };
+    }
+
+    #[must_use]
+    pub fn query_limits(&self) -> QueryLimits {
+        self.query_limits
+            .read()
+            .map(|limits| *limits)
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn running_queries(&self) -> Arc<RunningQueries> {
+        Arc::clone(&self.running_queries)
+    }
+
     pub async fn has_table(&self, table_reference: &TableReference) -> bool {
         let table_name = table_reference.table();
 
@@ -352,11 +438,31 @@ impl DataFusion {
         Ok(())
     }
 
+    pub fn remove_catalog(&self, name: &str) -> Result<()> {
+        // DataFusion can't deregister catalogs, but the default catalog list is a map that can be edited.
+        let catalog_list = Arc::clone(&self.ctx.state().catalog_list());
+        let Some(catalog_list) = catalog_list
+            .as_any()
+            .downcast_ref::<MemoryCatalogProviderList>()
+        else {
+            return UnableToDeleteCatalogSnafu {
+                reason: "The catalog list doesn't support removing catalogs".to_string(),
+            }
+            .fail();
+        };
+
+        catalog_list.catalogs.remove(name);
+
+        Ok(())
+    }
+
     pub async fn register_table(&self, dataset: impl Borrow<Dataset>, table: Table) -> Result<()> {
         let dataset = dataset.borrow();
 
         schema::ensure_schema_exists(&self.ctx, SPICE_DEFAULT_CATALOG, &dataset.name)?;
 
+        self.set_dataset_policy(&dataset.name, dataset.policy.clone())?;
+
         match table {
             Table::Accelerated {
                 source,
@@ -454,6 +560,8 @@ impl DataFusion {
             .fail()?;
         }
 
+        let _write_lock = self.lock_table_for_write(&table_reference).await;
+
         let table_provider = self.get_table_provider(&table_reference).await?;
 
         verify_schema(
@@ -487,6 +595,22 @@ impl DataFusion {
         Ok(())
     }
 
+    /// Waits for the other writes to the table to finish, and holds off new ones until the guard is dropped.
+    pub(crate) async fn lock_table_for_write(
+        &self,
+        table_reference: &TableReference,
+    ) -> OwnedMutexGuard<()> {
+        let lock = {
+            // The map is always left consistent, so it is still usable if a thread panicked.
+            let mut write_locks = match self.write_locks.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            Arc::clone(write_locks.entry(table_reference.clone()).or_default())
+        };
+        lock.lock_owned().await
+    }
+
     pub async fn get_arrow_schema(&self, dataset: &str) -> Result<Schema> {
         let data_frame = self
             .ctx
@@ -525,9 +649,40 @@ impl DataFusion {
                 .remove(dataset_name);
         }
 
+        self.set_dataset_policy(dataset_name, None)?;
+
         Ok(())
     }
 
+    pub(crate) fn set_dataset_policy(
+        &self,
+        dataset_name: &TableReference,
+        policy: Option<Policy>,
+    ) -> Result<()> {
+        let mut policies = self
+            .dataset_policies
+            .write()
+            .map_err(|_| Error::UnableToLockDatasetPolicies {})?;
+
+        let key = policy::policy_key(dataset_name);
+        match policy {
+            Some(policy) => policies.insert(key, Arc::new(policy)),
+            None => policies.remove(&key),
+        };
+
+        Ok(())
+    }
+
+    /// Returns the row filter and column mask policies of all datasets that have one, keyed by fully qualified table name.
+    #[must_use]
+    pub fn dataset_policies(&self) -> HashMap<TableReference, Arc<Policy>> {
+        let Ok(policies) = self.dataset_policies.read() else {
+            return HashMap::new();
+        };
+
+        policies.clone()
+    }
+
     pub async fn create_accelerated_table(
         &self,
         dataset: &Dataset,
@@ -613,6 +768,17 @@ impl DataFusion {
 
         accelerated_table_builder.cache_provider(self.cache_provider());
 
+        accelerated_table_builder.search_indexes(
+            Self::search_indexes(dataset, source_table_provider.constraints(), &source_schema)
+                .await,
+        );
+
+        if refresh_mode == RefreshMode::Append {
+            if let Some(append_stream) = source.append_stream(Arc::clone(&source_table_provider)) {
+                accelerated_table_builder.append_stream(append_stream);
+            }
+        }
+
         if refresh_mode == RefreshMode::Changes {
             let source = Box::leak(Box::new(source));
             let changes_stream = source.changes_stream(source_table_provider);
@@ -624,6 +790,82 @@ impl DataFusion {
         Ok(accelerated_table_builder.build().await)
     }
 
+    /// Create the search indexes of an accelerated dataset: a vector index for each embedding
+    /// column with primary keys, and a full-text index if full-text search is enabled. Vector
+    /// indexes of file-mode accelerators are persisted alongside the accelerator file.
+    async fn search_indexes(
+        dataset: &Dataset,
+        constraints: Option<&Constraints>,
+        schema: &SchemaRef,
+    ) -> Vec<Arc<dyn SearchIndex>> {
+        let accelerator_file = dataaccelerator::accelerator_file_path(dataset).await;
+        let primary_keys = |explicit: Option<&Vec<String>>| {
+            explicit.cloned().unwrap_or_else(|| {
+                constraints.map_or_else(Vec::new, |c| {
+                    dataaccelerator::get_primary_keys_from_constraints(c, schema)
+                })
+            })
+        };
+
+        let mut indexes: Vec<Arc<dyn SearchIndex>> = dataset
+            .embeddings
+            .iter()
+            .filter_map(|embedding| {
+                // Vector indexes identify rows by their primary keys, and searches of columns
+                // without any scan the accelerator instead.
+                let primary_keys = primary_keys(embedding.primary_keys.as_ref());
+                if primary_keys.is_empty() {
+                    tracing::debug!(
+                        "Not indexing {}.{}, as it has no primary keys",
+                        dataset.name,
+                        embedding.column
+                    );
+                    return None;
+                }
+
+                let path = accelerator_file
+                    .as_ref()
+                    .map(|file| PathBuf::from(format!("{file}.{}.ann", embedding.column)));
+
+                Some(Arc::new(EmbeddingIndex::new(
+                    dataset.name.clone(),
+                    embedding.column.clone(),
+                    primary_keys,
+                    embedding.chunking.is_some(),
+                    path,
+                )) as Arc<dyn SearchIndex>)
+            })
+            .collect();
+
+        if let Some(full_text_search) = &dataset.full_text_search {
+            // Results contain the same columns as vector search results: the primary keys and
+            // the embedded column, or the first text column if there is none.
+            let content_column = dataset
+                .embeddings
+                .iter()
+                .map(|e| &e.column)
+                .min()
+                .or(full_text_search.columns.first());
+            if let Some(content_column) = content_column {
+                let mut payload_columns = primary_keys(
+                    dataset
+                        .embeddings
+                        .iter()
+                        .find_map(|e| e.primary_keys.as_ref()),
+                );
+                payload_columns.push(content_column.clone());
+
+                indexes.push(Arc::new(FullTextIndex::new(
+                    dataset.name.clone(),
+                    full_text_search.columns.clone(),
+                    payload_columns,
+                )));
+            }
+        }
+
+        indexes
+    }
+
     pub fn cache_provider(&self) -> Option<Arc<QueryResultsCacheProvider>> {
         let Ok(provider) = self.cache_provider.read() else {
             return None;
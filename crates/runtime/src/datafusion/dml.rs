@@ -0,0 +1,558 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Execution of SQL `INSERT`, `UPDATE` and `DELETE` statements against `read_write` datasets.
+//!
+//! Inserts are written with [`TableProvider::insert_into`] and deletes with the table's
+//! [`DeletionTableProvider`]. An update deletes the matching rows and inserts their updated values,
+//! restoring the deleted rows if the insert fails. For accelerated datasets, both the accelerator
+//! and the source are written to.
+//!
+//! Statements hold the table's write lock, so they aren't interleaved with other writes. An update
+//! is not atomic for readers though: a query running between the delete and the insert doesn't see
+//! the updated rows, and if restoring the deleted rows after a failed insert also fails, they are
+//! lost. `UPDATE` and `DELETE` predicates can only reference the table's own columns; statements
+//! with subqueries or joins are rejected.
+
+use std::sync::Arc;
+
+use arrow::array::{AsArray, RecordBatch};
+use arrow::datatypes::UInt64Type;
+use data_components::delete::{get_deletion_provider, DeletionTableProvider};
+use datafusion::common::tree_node::TreeNode;
+use datafusion::datasource::TableProvider;
+use datafusion::logical_expr::expr_rewriter::unnormalize_cols;
+use datafusion::logical_expr::utils::{conjunction, split_conjunction};
+use datafusion::logical_expr::{DmlStatement, Expr, LogicalPlan, WriteOp};
+use datafusion::physical_plan::collect;
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::sql::TableReference;
+use snafu::prelude::*;
+
+use crate::accelerated_table::AcceleratedTable;
+
+use super::{
+    DataFusion, DeleteNotSupportedSnafu, Result, TableNotWritableSnafu, UnableToExecuteDmlSnafu,
+    UnableToExecuteTableInsertSnafu, UnableToPlanTableInsertSnafu, UnsupportedDmlOperationSnafu,
+    UnsupportedDmlPredicateSnafu,
+};
+
+impl DataFusion {
+    /// Execute a DML statement against a writable table, returning the number of affected rows.
+    pub async fn execute_dml(&self, dml: &DmlStatement) -> Result<u64> {
+        let table_reference = &dml.table_name;
+        ensure!(
+            self.is_writable(table_reference),
+            TableNotWritableSnafu {
+                table_name: table_reference.to_string(),
+            }
+        );
+
+        let table_provider = self.get_table_provider(table_reference).await?;
+        let _write_lock = self.lock_table_for_write(table_reference).await;
+
+        let count = match dml.op {
+            WriteOp::InsertInto => {
+                let batches = self.collect_dml_input(table_reference, &dml.input).await?;
+                self.insert_batches(table_reference, &table_provider, batches)
+                    .await?
+            }
+            WriteOp::Delete => {
+                let filters = dml_filters(table_reference, &dml.input)?;
+                self.delete_rows(table_reference, &table_provider, &filters)
+                    .await?
+            }
+            WriteOp::Update => {
+                // Read the current and updated values of the rows before they are deleted.
+                let filters = dml_filters(table_reference, &dml.input)?;
+                let updated = self.collect_dml_input(table_reference, &dml.input).await?;
+                let current = self
+                    .collect_matching_rows(table_reference, &table_provider, &filters)
+                    .await?;
+                self.delete_rows(table_reference, &table_provider, &filters)
+                    .await?;
+
+                // Deleting and inserting isn't atomic, so the current rows are put back if the updated rows can't be written.
+                match self
+                    .insert_batches(table_reference, &table_provider, updated)
+                    .await
+                {
+                    Ok(count) => count,
+                    Err(e) => {
+                        if let Err(restore_error) = self
+                            .insert_batches(table_reference, &table_provider, current)
+                            .await
+                        {
+                            tracing::error!("Failed to restore the rows of {table_reference} deleted by a failed UPDATE: {restore_error}");
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+            _ => UnsupportedDmlOperationSnafu {
+                operation: dml.op.name(),
+            }
+            .fail()?,
+        };
+
+        if let Some(accelerated_table) = table_provider.as_any().downcast_ref::<AcceleratedTable>()
+        {
+            accelerated_table.mark_search_indexes_stale();
+        }
+
+        if let Some(cache_provider) = self.cache_provider() {
+            if let Err(e) = cache_provider
+                .invalidate_for_table(table_reference.clone())
+                .await
+            {
+                tracing::error!(
+                    "Failed to invalidate cached results for dataset {table_reference}: {e}"
+                );
+            }
+        }
+
+        Ok(count)
+    }
+
+    async fn collect_dml_input(
+        &self,
+        table_reference: &TableReference,
+        input: &LogicalPlan,
+    ) -> Result<Vec<RecordBatch>> {
+        let session = self.ctx.state();
+        let plan = session
+            .create_physical_plan(input)
+            .await
+            .context(UnableToExecuteDmlSnafu {
+                table_name: table_reference.to_string(),
+            })?;
+        collect(plan, self.ctx.task_ctx())
+            .await
+            .context(UnableToExecuteDmlSnafu {
+                table_name: table_reference.to_string(),
+            })
+    }
+
+    /// Read the rows of the table matching all of `filters`.
+    async fn collect_matching_rows(
+        &self,
+        table_reference: &TableReference,
+        table_provider: &Arc<dyn TableProvider>,
+        filters: &[Expr],
+    ) -> Result<Vec<RecordBatch>> {
+        let mut rows =
+            self.ctx
+                .read_table(Arc::clone(table_provider))
+                .context(UnableToExecuteDmlSnafu {
+                    table_name: table_reference.to_string(),
+                })?;
+        if let Some(predicate) = conjunction(filters.to_vec()) {
+            rows = rows.filter(predicate).context(UnableToExecuteDmlSnafu {
+                table_name: table_reference.to_string(),
+            })?;
+        }
+        rows.collect().await.context(UnableToExecuteDmlSnafu {
+            table_name: table_reference.to_string(),
+        })
+    }
+
+    async fn insert_batches(
+        &self,
+        table_reference: &TableReference,
+        table_provider: &Arc<dyn TableProvider>,
+        batches: Vec<RecordBatch>,
+    ) -> Result<u64> {
+        let count = batches.iter().map(|b| b.num_rows() as u64).sum();
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let schema = batches[0].schema();
+        let input =
+            MemoryExec::try_new(&[batches], schema, None).context(UnableToExecuteDmlSnafu {
+                table_name: table_reference.to_string(),
+            })?;
+        let insert_plan = table_provider
+            .insert_into(&self.ctx.state(), Arc::new(input), false)
+            .await
+            .context(UnableToPlanTableInsertSnafu {
+                table_name: table_reference.to_string(),
+            })?;
+        collect(insert_plan, self.ctx.task_ctx()).await.context(
+            UnableToExecuteTableInsertSnafu {
+                table_name: table_reference.to_string(),
+            },
+        )?;
+
+        Ok(count)
+    }
+
+    /// Delete the rows matching all of `filters`. For an accelerated table, rows are deleted from
+    /// the source first and then from the accelerator, whose count of deleted rows is returned.
+    async fn delete_rows(
+        &self,
+        table_reference: &TableReference,
+        table_provider: &Arc<dyn TableProvider>,
+        filters: &[Expr],
+    ) -> Result<u64> {
+        let targets = match table_provider.as_any().downcast_ref::<AcceleratedTable>() {
+            Some(accelerated_table) => vec![
+                accelerated_table.get_federated_table(),
+                accelerated_table.get_accelerator(),
+            ],
+            None => vec![Arc::clone(table_provider)],
+        };
+        let deletion_providers = targets
+            .into_iter()
+            .map(get_deletion_provider)
+            .collect::<Option<Vec<Arc<dyn DeletionTableProvider>>>>()
+            .context(DeleteNotSupportedSnafu {
+                table_name: table_reference.to_string(),
+            })?;
+
+        let session = self.ctx.state();
+        let mut count = 0;
+        for deletion_provider in deletion_providers {
+            let delete_plan = deletion_provider
+                .delete_from(&session, filters)
+                .await
+                .context(UnableToExecuteDmlSnafu {
+                    table_name: table_reference.to_string(),
+                })?;
+            let batches = collect(delete_plan, self.ctx.task_ctx()).await.context(
+                UnableToExecuteDmlSnafu {
+                    table_name: table_reference.to_string(),
+                },
+            )?;
+            count = deleted_rows(&batches);
+        }
+
+        Ok(count)
+    }
+}
+
+/// The `WHERE` predicates of a `DELETE` or `UPDATE` plan, with unqualified column references.
+///
+/// The predicates are passed to the table's [`DeletionTableProvider`], so plans that read other
+/// tables, i.e. with subqueries or joins, are rejected rather than deleting the wrong rows.
+fn dml_filters(table_reference: &TableReference, input: &LogicalPlan) -> Result<Vec<Expr>> {
+    let mut filters = Vec::new();
+    let mut plan = input;
+    loop {
+        match plan {
+            LogicalPlan::Filter(filter) => {
+                let has_subquery = filter
+                    .predicate
+                    .exists(|expr| {
+                        Ok(matches!(
+                            expr,
+                            Expr::Exists(_) | Expr::InSubquery(_) | Expr::ScalarSubquery(_)
+                        ))
+                    })
+                    .unwrap_or(true);
+                ensure!(
+                    !has_subquery,
+                    UnsupportedDmlPredicateSnafu {
+                        table_name: table_reference.to_string(),
+                    }
+                );
+                filters.extend(split_conjunction(&filter.predicate).into_iter().cloned());
+                plan = filter.input.as_ref();
+            }
+            LogicalPlan::TableScan(_) => break,
+            _ => match plan.inputs().as_slice() {
+                [input] => plan = input,
+                _ => {
+                    return UnsupportedDmlPredicateSnafu {
+                        table_name: table_reference.to_string(),
+                    }
+                    .fail()
+                }
+            },
+        }
+    }
+    Ok(unnormalize_cols(filters))
+}
+
+/// Sum the `count` column returned by a deletion plan.
+fn deleted_rows(batches: &[RecordBatch]) -> u64 {
+    batches
+        .iter()
+        .filter_map(|batch| {
+            batch
+                .column_by_name("count")?
+                .as_primitive_opt::<UInt64Type>()
+        })
+        .flat_map(|counts| counts.iter().flatten())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    use arrow::array::{ArrayRef, Int64Array, StringArray, UInt64Array};
+    use arrow::compute::{filter_record_batch, not, prep_null_mask_filter};
+    use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+    use async_trait::async_trait;
+    use data_components::delete::DeletionTableProviderAdapter;
+    use datafusion::assert_batches_sorted_eq;
+    use datafusion::common::DFSchema;
+    use datafusion::datasource::TableType;
+    use datafusion::error::{DataFusionError, Result as DataFusionResult};
+    use datafusion::execution::context::SessionState;
+    use datafusion::logical_expr::lit;
+    use datafusion::physical_plan::ExecutionPlan;
+
+    use super::*;
+
+    /// An in-memory table that supports deletes, and can be made to fail its next insert.
+    struct TestTable {
+        schema: SchemaRef,
+        batches: Mutex<Vec<RecordBatch>>,
+        fail_next_insert: AtomicBool,
+    }
+
+    impl TestTable {
+        fn rows(&self) -> Vec<RecordBatch> {
+            self.batches.lock().expect("lock").clone()
+        }
+    }
+
+    fn count_plan(count: u64) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let batch = RecordBatch::try_from_iter(vec![(
+            "count",
+            Arc::new(UInt64Array::from(vec![count])) as ArrayRef,
+        )])?;
+        let schema = batch.schema();
+        Ok(Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None)?))
+    }
+
+    #[async_trait]
+    impl TableProvider for TestTable {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn schema(&self) -> SchemaRef {
+            Arc::clone(&self.schema)
+        }
+
+        fn table_type(&self) -> TableType {
+            TableType::Base
+        }
+
+        async fn scan(
+            &self,
+            _state: &SessionState,
+            projection: Option<&Vec<usize>>,
+            _filters: &[Expr],
+            _limit: Option<usize>,
+        ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+            Ok(Arc::new(MemoryExec::try_new(
+                &[self.rows()],
+                self.schema(),
+                projection.cloned(),
+            )?))
+        }
+
+        async fn insert_into(
+            &self,
+            state: &SessionState,
+            input: Arc<dyn ExecutionPlan>,
+            _overwrite: bool,
+        ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+            if self.fail_next_insert.swap(false, Ordering::SeqCst) {
+                return Err(DataFusionError::Execution("insert failed".to_string()));
+            }
+
+            let batches = collect(input, state.task_ctx()).await?;
+            let count = batches.iter().map(|b| b.num_rows() as u64).sum();
+            self.batches.lock().expect("lock").extend(batches);
+            count_plan(count)
+        }
+    }
+
+    #[async_trait]
+    impl DeletionTableProvider for TestTable {
+        async fn delete_from(
+            &self,
+            state: &SessionState,
+            filters: &[Expr],
+        ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+            let df_schema = DFSchema::try_from(self.schema())?;
+            let predicate = state.create_physical_expr(
+                conjunction(filters.to_vec()).unwrap_or(lit(true)),
+                &df_schema,
+            )?;
+
+            let mut batches = self.batches.lock().expect("lock");
+            let mut count = 0;
+            let remaining = batches
+                .iter()
+                .map(|batch| {
+                    let matches = predicate.evaluate(batch)?.into_array(batch.num_rows())?;
+                    let matches = prep_null_mask_filter(matches.as_boolean());
+                    count += matches.true_count() as u64;
+                    Ok(filter_record_batch(batch, &not(&matches)?)?)
+                })
+                .collect::<DataFusionResult<Vec<_>>>()?;
+            *batches = remaining;
+            count_plan(count)
+        }
+    }
+
+    fn setup(fail_next_insert: bool) -> (DataFusion, Arc<TestTable>) {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2, 3])),
+                Arc::new(StringArray::from(vec!["a", "b", "c"])),
+            ],
+        )
+        .expect("valid batch");
+        let table = Arc::new(TestTable {
+            schema,
+            batches: Mutex::new(vec![batch]),
+            fail_next_insert: AtomicBool::new(fail_next_insert),
+        });
+
+        let df = DataFusion::new();
+        df.ctx
+            .register_table(
+                "items",
+                Arc::new(DeletionTableProviderAdapter::new(
+                    Arc::clone(&table) as Arc<dyn DeletionTableProvider>
+                )),
+            )
+            .expect("table to register");
+        df.data_writers
+            .write()
+            .expect("lock")
+            .insert(TableReference::bare("items"));
+
+        (df, table)
+    }
+
+    async fn execute(df: &DataFusion, sql: &str) -> Result<u64> {
+        let plan = df
+            .ctx
+            .state()
+            .create_logical_plan(sql)
+            .await
+            .expect("valid plan");
+        let LogicalPlan::Dml(dml) = plan else {
+            panic!("expected a DML statement: {sql}");
+        };
+        df.execute_dml(&dml).await
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete() {
+        let (df, table) = setup(false);
+
+        let updated = execute(&df, "UPDATE items SET name = 'z' WHERE id = 2")
+            .await
+            .expect("update to succeed");
+        assert_eq!(updated, 1);
+        assert_batches_sorted_eq!(
+            [
+                "+----+------+",
+                "| id | name |",
+                "+----+------+",
+                "| 1  | a    |",
+                "| 2  | z    |",
+                "| 3  | c    |",
+                "+----+------+",
+            ],
+            &table.rows()
+        );
+
+        let deleted = execute(&df, "DELETE FROM items WHERE id >= 2")
+            .await
+            .expect("delete to succeed");
+        assert_eq!(deleted, 2);
+        assert_batches_sorted_eq!(
+            [
+                "+----+------+",
+                "| id | name |",
+                "+----+------+",
+                "| 1  | a    |",
+                "+----+------+",
+            ],
+            &table.rows()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_update_restores_rows() {
+        let (df, table) = setup(true);
+
+        assert!(execute(&df, "UPDATE items SET name = 'z' WHERE id = 2")
+            .await
+            .is_err());
+        assert_batches_sorted_eq!(
+            [
+                "+----+------+",
+                "| id | name |",
+                "+----+------+",
+                "| 1  | a    |",
+                "| 2  | b    |",
+                "| 3  | c    |",
+                "+----+------+",
+            ],
+            &table.rows()
+        );
+
+        // Deleting rows that don't exist affects nothing.
+        let deleted = execute(&df, "DELETE FROM items WHERE id > 10")
+            .await
+            .expect("delete to succeed");
+        assert_eq!(deleted, 0);
+    }
+
+    #[tokio::test]
+    async fn test_subquery_predicates_are_rejected() {
+        let (df, table) = setup(false);
+
+        for sql in [
+            "DELETE FROM items WHERE id IN (SELECT id FROM items WHERE name = 'a')",
+            "UPDATE items SET name = 'z' WHERE id = (SELECT MAX(id) FROM items)",
+        ] {
+            assert!(
+                matches!(
+                    execute(&df, sql).await,
+                    Err(crate::datafusion::Error::UnsupportedDmlPredicate { .. })
+                ),
+                "{sql} should be rejected"
+            );
+        }
+        assert_eq!(
+            table
+                .rows()
+                .iter()
+                .map(RecordBatch::num_rows)
+                .sum::<usize>(),
+            3
+        );
+    }
+}
@@ -14,24 +14,40 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::{cell::LazyCell, sync::Arc};
+use std::{cell::LazyCell, collections::HashSet, sync::Arc, time::Duration};
 
+use arrow::array::{ArrayRef, RecordBatch, UInt64Array};
 use arrow::datatypes::{Schema, SchemaRef};
 use arrow_tools::schema::verify_schema;
 use cache::{get_logical_plan_input_tables, to_cached_record_batch_stream, QueryResult};
 use datafusion::{
     error::DataFusionError,
-    execution::{context::SQLOptions, SendableRecordBatchStream},
-    physical_plan::{memory::MemoryStream, stream::RecordBatchStreamAdapter},
+    execution::{
+        context::{SQLOptions, SessionState},
+        SendableRecordBatchStream,
+    },
+    logical_expr::{DmlStatement, LogicalPlan},
+    physical_plan::{execute_stream, memory::MemoryStream, stream::RecordBatchStreamAdapter},
 };
 use error_code::ErrorCode;
-use snafu::Snafu;
+use limits::QueryLimits;
+use running_queries::{KillReason, RunningQueryGuard};
+use snafu::prelude::*;
+use tokio::time::Instant;
 use tracker::QueryTracker;
+use uuid::Uuid;
+
+use crate::auth::{
+    access_control::{AccessDenied, Permission},
+    policy, Principal,
+};
 
 pub mod builder;
 pub mod query_history;
 pub use builder::QueryBuilder;
 pub mod error_code;
+pub mod limits;
+pub mod running_queries;
 mod tracker;
 
 use async_stream::stream;
@@ -44,6 +60,9 @@ pub enum Error {
     #[snafu(display("Failed to execute query: {source}"))]
     UnableToExecuteQuery { source: DataFusionError },
 
+    #[snafu(display("Failed to execute statement: {source}"))]
+    UnableToExecuteDml { source: crate::datafusion::Error },
+
     #[snafu(display("Failed to access query results cache: {source}"))]
     FailedToAccessCache { source: cache::Error },
 
@@ -55,6 +74,33 @@ pub enum Error {
 
     #[snafu(display("Schema mismatch: {source}"))]
     SchemaMismatch { source: arrow_tools::schema::Error },
+
+    #[snafu(display("Access denied: {source}"))]
+    AccessDenied { source: AccessDenied },
+
+    #[snafu(display("Only INSERT, UPDATE and DELETE statements can be executed as an update"))]
+    ExpectedDmlStatement,
+
+    #[snafu(display("{source}"))]
+    QueryKilled { source: KillReason },
+
+    #[snafu(display("Query id {query_id} is already in use by a running query"))]
+    QueryIdInUse { query_id: Uuid },
+
+    #[snafu(display("Invalid query id {value}: {source}"))]
+    InvalidQueryId { value: String, source: uuid::Error },
+
+    #[snafu(display("Invalid query timeout {value}: {source}"))]
+    InvalidQueryTimeout {
+        value: String,
+        source: fundu::ParseError,
+    },
+
+    #[snafu(display("Invalid query memory limit {value}: {source}"))]
+    InvalidMemoryLimit {
+        value: String,
+        source: byte_unit::ParseError,
+    },
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -86,6 +132,12 @@ pub struct Query {
     df: Arc<crate::datafusion::DataFusion>,
     sql: Arc<str>,
     restricted_sql_options: bool,
+    allow_dml: bool,
+    dml_only: bool,
+
+    /// The principal the query runs on behalf of. Queries without a principal are issued by the runtime itself and are not subject to access control.
+    principal: Option<Principal>,
+    limits: QueryLimits,
     tracker: QueryTracker,
 }
 
@@ -99,6 +151,19 @@ macro_rules! handle_error {
     }};
 }
 
+/// Awaits a step of the query that runs before its results are streamed, such as planning, and stops the query if it is
+/// cancelled or exceeds its deadline in the meantime.
+macro_rules! until_killed {
+    ($tracker:expr, $running_query:expr, $deadline:expr, $future:expr) => {{
+        tokio::select! {
+            result = $future => result,
+            reason = $running_query.killed($deadline) => {
+                handle_error!($tracker, reason.error_code(), reason, QueryKilled)
+            }
+        }
+    }};
+}
+
 impl Query {
     pub async fn run(self) -> Result<QueryResult> {
         let session = self.df.ctx.state();
@@ -106,7 +171,27 @@ impl Query {
         let ctx = self;
         let mut tracker = ctx.tracker;
 
-        let plan = match session.create_logical_plan(&ctx.sql).await {
+        // The query isn't tracked, as its id belongs to the query that is already running.
+        let Some(running_query) = ctx
+            .df
+            .running_queries()
+            .register(tracker.query_id, ctx.principal.as_ref())
+        else {
+            return Err(Error::QueryIdInUse {
+                query_id: tracker.query_id,
+            });
+        };
+        let deadline = ctx
+            .limits
+            .timeout
+            .map(|timeout| (Instant::now() + timeout, timeout));
+
+        let plan = match until_killed!(
+            tracker,
+            running_query,
+            deadline,
+            session.create_logical_plan(&ctx.sql)
+        ) {
             Ok(plan) => plan,
             Err(e) => {
                 let error_code = ErrorCode::from(&e);
@@ -114,37 +199,111 @@ impl Query {
             }
         };
 
+        if ctx.dml_only && !matches!(plan, LogicalPlan::Dml(_)) {
+            let error = Error::ExpectedDmlStatement;
+            tracker
+                .finish_with_error(error.to_string(), ErrorCode::QueryPlanningError)
+                .await;
+            return Err(error);
+        }
+
+        // Verified before anything is executed, as `INSERT`, `UPDATE` and `DELETE` statements are executed directly.
+        if ctx.restricted_sql_options {
+            if let Err(e) = RESTRICTED_SQL_OPTIONS.with(|sql_options| {
+                SQLOptions::clone(sql_options)
+                    .with_allow_dml(ctx.allow_dml)
+                    .verify_plan(&plan)
+            }) {
+                handle_error!(
+                    tracker,
+                    ErrorCode::QueryPlanningError,
+                    e,
+                    UnableToExecuteQuery
+                )
+            }
+        }
+
+        if let Err(e) = check_access(&ctx.df, ctx.principal.as_ref(), &plan) {
+            handle_error!(tracker, ErrorCode::AccessDenied, e, AccessDenied)
+        }
+
+        if let LogicalPlan::Dml(dml) = &plan {
+            if let Err(e) = check_dml_policies(&ctx.df, ctx.principal.as_ref(), dml) {
+                handle_error!(tracker, ErrorCode::AccessDenied, e, AccessDenied)
+            }
+        }
+
+        // Policies are applied before the cache lookup, so that results are only shared between callers that see the same data.
+        let plan = match apply_dataset_policies(&ctx.df, ctx.principal.as_ref(), &session, plan) {
+            Ok(plan) => plan,
+            Err(e) => {
+                let error_code = ErrorCode::from(&e);
+                handle_error!(tracker, error_code, e, UnableToExecuteQuery)
+            }
+        };
+
+        // Writes to `read_write` datasets are executed directly, and are never cached.
+        if let LogicalPlan::Dml(dml) = &plan {
+            tracker = tracker.datasets(Arc::new(HashSet::from([dml.table_name.clone()])));
+
+            let count =
+                match until_killed!(tracker, running_query, deadline, ctx.df.execute_dml(dml)) {
+                    Ok(count) => count,
+                    Err(e) => handle_error!(
+                        tracker,
+                        ErrorCode::QueryExecutionError,
+                        e,
+                        UnableToExecuteDml
+                    ),
+                };
+
+            let record_batch_stream = match dml_count_stream(count) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    handle_error!(
+                        tracker,
+                        ErrorCode::InternalError,
+                        e,
+                        UnableToCreateMemoryStream
+                    )
+                }
+            };
+
+            return Ok(QueryResult::new(
+                attach_query_tracker_to_stream(
+                    tracker,
+                    record_batch_stream,
+                    running_query,
+                    deadline,
+                ),
+                None,
+            ));
+        }
+
         let mut plan_is_cache_enabled = false;
         let plan_cache_key = cache::key_for_logical_plan(&plan);
 
         if let Some(cache_provider) = &ctx.df.cache_provider() {
-            if let Some(cached_result) = match cache_provider.get(&plan).await {
-                Ok(Some(v)) => Some(v),
-                Ok(None) => None,
-                Err(e) => handle_error!(tracker, ErrorCode::InternalError, e, FailedToAccessCache),
-            } {
+            if let Some(cached_result) =
+                match until_killed!(tracker, running_query, deadline, cache_provider.get(&plan)) {
+                    Ok(Some(v)) => Some(v),
+                    Ok(None) => None,
+                    Err(e) => {
+                        handle_error!(tracker, ErrorCode::InternalError, e, FailedToAccessCache)
+                    }
+                }
+            {
                 tracker = tracker
                     .datasets(cached_result.input_tables)
                     .results_cache_hit(true);
 
-                let record_batch_stream = match MemoryStream::try_new(
-                    cached_result.records.to_vec(),
-                    cached_result.schema,
-                    None,
-                ) {
-                    Ok(stream) => stream,
-                    Err(e) => {
-                        handle_error!(
-                            tracker,
-                            ErrorCode::InternalError,
-                            e,
-                            UnableToCreateMemoryStream
-                        )
-                    }
-                };
-
                 return Ok(QueryResult::new(
-                    attach_query_tracker_to_stream(tracker, Box::pin(record_batch_stream)),
+                    attach_query_tracker_to_stream(
+                        tracker,
+                        cached_result.data,
+                        running_query,
+                        deadline,
+                    ),
                     Some(true),
                 ));
             }
@@ -153,22 +312,14 @@ impl Query {
             tracker = tracker.results_cache_hit(false);
         }
 
-        if ctx.restricted_sql_options {
-            if let Err(e) =
-                RESTRICTED_SQL_OPTIONS.with(|sql_options| sql_options.verify_plan(&plan))
-            {
-                handle_error!(
-                    tracker,
-                    ErrorCode::QueryPlanningError,
-                    e,
-                    UnableToExecuteQuery
-                )
-            }
-        }
-
         tracker = tracker.datasets(Arc::new(get_logical_plan_input_tables(&plan)));
 
-        let df = match ctx.df.ctx.execute_logical_plan(plan).await {
+        let df = match until_killed!(
+            tracker,
+            running_query,
+            deadline,
+            ctx.df.ctx.execute_logical_plan(plan)
+        ) {
             Ok(df) => df,
             Err(e) => {
                 let error_code = ErrorCode::from(&e);
@@ -178,14 +329,29 @@ impl Query {
 
         let df_schema: SchemaRef = Arc::clone(df.schema().inner());
 
-        let res_stream: SendableRecordBatchStream = match df.execute_stream().await {
-            Ok(stream) => stream,
-            Err(e) => {
-                let error_code = ErrorCode::from(&e);
-                handle_error!(tracker, error_code, e, UnableToExecuteQuery)
-            }
+        let task_ctx = match ctx.limits.memory_limit {
+            Some(memory_limit) => limits::with_memory_limit(df.task_ctx(), memory_limit),
+            None => df.task_ctx(),
         };
 
+        let physical_plan =
+            match until_killed!(tracker, running_query, deadline, df.create_physical_plan()) {
+                Ok(physical_plan) => physical_plan,
+                Err(e) => {
+                    let error_code = ErrorCode::from(&e);
+                    handle_error!(tracker, error_code, e, UnableToExecuteQuery)
+                }
+            };
+
+        let res_stream: SendableRecordBatchStream =
+            match execute_stream(physical_plan, Arc::new(task_ctx)) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let error_code = ErrorCode::from(&e);
+                    handle_error!(tracker, error_code, e, UnableToExecuteQuery)
+                }
+            };
+
         let res_schema = res_stream.schema();
 
         if let Err(e) = verify_schema(df_schema.fields(), res_schema.fields()) {
@@ -202,14 +368,19 @@ impl Query {
                 );
 
                 return Ok(QueryResult::new(
-                    attach_query_tracker_to_stream(tracker, record_batch_stream),
+                    attach_query_tracker_to_stream(
+                        tracker,
+                        record_batch_stream,
+                        running_query,
+                        deadline,
+                    ),
                     Some(false),
                 ));
             }
         }
 
         Ok(QueryResult::new(
-            attach_query_tracker_to_stream(tracker, res_stream),
+            attach_query_tracker_to_stream(tracker, res_stream, running_query, deadline),
             None,
         ))
     }
@@ -220,9 +391,78 @@ impl Query {
             .await;
     }
 
-    pub async fn get_schema(&self) -> Result<Schema, DataFusionError> {
-        let df = self.df.ctx.sql(&self.sql).await?;
-        Ok(df.schema().into())
+    pub async fn get_schema(&self) -> Result<Schema> {
+        let plan = self
+            .df
+            .ctx
+            .state()
+            .create_logical_plan(&self.sql)
+            .await
+            .context(UnableToExecuteQuerySnafu)?;
+
+        check_access(&self.df, self.principal.as_ref(), &plan).context(AccessDeniedSnafu)?;
+
+        Ok(Schema::clone(plan.schema().inner()))
+    }
+}
+
+/// The result of a DML statement: a single `count` row with the number of affected rows.
+fn dml_count_stream(count: u64) -> Result<SendableRecordBatchStream, DataFusionError> {
+    let batch = RecordBatch::try_from_iter_with_nullable(vec![(
+        "count",
+        Arc::new(UInt64Array::from(vec![count])) as ArrayRef,
+        false,
+    )])?;
+    let schema = batch.schema();
+    Ok(Box::pin(MemoryStream::try_new(vec![batch], schema, None)?))
+}
+
+/// Applies the row filter and column mask policies of the datasets scanned by the plan for the principal.
+fn apply_dataset_policies(
+    df: &crate::datafusion::DataFusion,
+    principal: Option<&Principal>,
+    session: &SessionState,
+    plan: LogicalPlan,
+) -> Result<LogicalPlan, DataFusionError> {
+    match principal {
+        Some(principal) => {
+            policy::apply_dataset_policies(plan, &df.dataset_policies(), principal, session)
+        }
+        None => Ok(plan),
+    }
+}
+
+/// Principals that a row filter or column mask policy applies to can't modify the dataset: an
+/// `UPDATE` would write the masked values it reads back over the real ones.
+fn check_dml_policies(
+    df: &crate::datafusion::DataFusion,
+    principal: Option<&Principal>,
+    dml: &DmlStatement,
+) -> Result<(), AccessDenied> {
+    match principal {
+        Some(principal)
+            if policy::applicable_policy(&df.dataset_policies(), &dml.table_name, principal)
+                .is_some() =>
+        {
+            Err(AccessDenied {
+                principal: Arc::clone(&principal.name),
+                table: dml.table_name.clone(),
+                permission: Permission::Write,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Verifies the principal is allowed to access the tables referenced by the plan, if access control is configured.
+fn check_access(
+    df: &crate::datafusion::DataFusion,
+    principal: Option<&Principal>,
+    plan: &LogicalPlan,
+) -> Result<(), AccessDenied> {
+    match (principal, df.access_control()) {
+        (Some(principal), Some(access_control)) => access_control.check_plan(principal, plan),
+        _ => Ok(()),
     }
 }
 
@@ -232,11 +472,14 @@ impl Query {
 /// Processes a stream of record batches, updating the query tracker
 /// with the number of records returned and saving query details at the end.
 ///
-/// Note: If an error occurs during stream processing, the query tracker
-/// is finalized with error details, and further streaming is terminated.
+/// Note: If an error occurs during stream processing, or the query is cancelled or
+/// exceeds its deadline, the query tracker is finalized with error details, and
+/// further streaming is terminated.
 fn attach_query_tracker_to_stream(
     ctx: QueryTracker,
     mut stream: SendableRecordBatchStream,
+    running_query: RunningQueryGuard,
+    deadline: Option<(Instant, Duration)>,
 ) -> SendableRecordBatchStream {
     let schema = stream.schema();
     let schema_copy = Arc::clone(&schema);
@@ -244,7 +487,28 @@ fn attach_query_tracker_to_stream(
     let mut num_records = 0u64;
 
     let updated_stream = stream! {
-        while let Some(batch_result) = stream.next().await {
+        let killed = running_query.killed(deadline);
+        tokio::pin!(killed);
+
+        loop {
+            let next = tokio::select! {
+                batch_result = stream.next() => Ok(batch_result),
+                reason = &mut killed => Err(reason),
+            };
+
+            let batch_result = match next {
+                Ok(Some(batch_result)) => batch_result,
+                Ok(None) => break,
+                Err(reason) => {
+                    let error_code = reason.error_code();
+                    ctx
+                    .schema(schema_copy)
+                    .rows_produced(num_records)
+                    .finish_with_error(reason.to_string(), error_code).await;
+                    yield Err(DataFusionError::Execution(reason.to_string()));
+                    return;
+                }
+            };
 
             match &batch_result {
                 Ok(batch) => {
@@ -252,10 +516,14 @@ fn attach_query_tracker_to_stream(
                     yield batch_result
                 }
                 Err(e) => {
+                    let error_code = match e {
+                        DataFusionError::ResourcesExhausted(_) => ErrorCode::ResourcesExhausted,
+                        _ => ErrorCode::QueryExecutionError,
+                    };
                     ctx
                     .schema(schema_copy)
                     .rows_produced(num_records)
-                    .finish_with_error(e.to_string(), ErrorCode::QueryExecutionError).await;
+                    .finish_with_error(e.to_string(), error_code).await;
                     yield batch_result;
                     return;
                 }
@@ -274,3 +542,115 @@ fn attach_query_tracker_to_stream(
         Box::pin(updated_stream),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::Int64Array;
+    use arrow::datatypes::{DataType, Field};
+    use datafusion::datasource::MemTable;
+    use datafusion::sql::TableReference;
+    use futures::TryStreamExt;
+
+    use crate::datafusion::DataFusion;
+
+    use super::*;
+
+    fn setup() -> Arc<DataFusion> {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![Arc::new(Int64Array::from(vec![1, 2]))],
+        )
+        .expect("valid batch");
+        let table = MemTable::try_new(schema, vec![vec![batch]]).expect("valid table");
+
+        let df = DataFusion::new();
+        df.ctx
+            .register_table("items", Arc::new(table))
+            .expect("table to register");
+        df.data_writers
+            .write()
+            .expect("lock")
+            .insert(TableReference::bare("items"));
+        Arc::new(df)
+    }
+
+    async fn row_count(df: &DataFusion) -> usize {
+        df.ctx
+            .sql("SELECT * FROM items")
+            .await
+            .expect("valid query")
+            .collect()
+            .await
+            .expect("query to run")
+            .iter()
+            .map(RecordBatch::num_rows)
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_restricted_query_rejects_dml() {
+        let df = setup();
+
+        for sql in [
+            "INSERT INTO items VALUES (3)",
+            "UPDATE items SET id = 3 WHERE id = 1",
+            "DELETE FROM items WHERE id = 1",
+        ] {
+            let result = QueryBuilder::new(sql, Arc::clone(&df), Protocol::Http)
+                .use_restricted_sql_options()
+                .build()
+                .run()
+                .await;
+            assert!(
+                matches!(result, Err(Error::UnableToExecuteQuery { .. })),
+                "{sql} should be rejected"
+            );
+        }
+        assert_eq!(row_count(&df).await, 2);
+
+        let result = QueryBuilder::new(
+            "INSERT INTO items VALUES (3)",
+            Arc::clone(&df),
+            Protocol::Http,
+        )
+        .use_restricted_sql_options()
+        .allow_dml()
+        .build()
+        .run()
+        .await
+        .expect("insert to be allowed");
+        result
+            .data
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("insert to succeed");
+        assert_eq!(row_count(&df).await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_query_id_in_use() {
+        let df = setup();
+        let query_id = Uuid::new_v4();
+        let query = || {
+            QueryBuilder::new("SELECT * FROM items", Arc::clone(&df), Protocol::Http)
+                .query_id(query_id)
+                .build()
+                .run()
+        };
+
+        // The query runs until its results are consumed or dropped.
+        let running = query().await.expect("query to run");
+        assert!(matches!(
+            query().await,
+            Err(Error::QueryIdInUse { query_id: id }) if id == query_id
+        ));
+
+        running
+            .data
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("query to succeed");
+        query().await.expect("query id to be reusable");
+    }
+}
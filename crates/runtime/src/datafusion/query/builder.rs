@@ -14,14 +14,18 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::{collections::HashSet, sync::Arc, time::SystemTime};
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use tokio::time::Instant;
 use uuid::Uuid;
 
-use crate::datafusion::DataFusion;
+use crate::{auth::Principal, datafusion::DataFusion};
 
-use super::{tracker::QueryTracker, Protocol, Query};
+use super::{limits::QueryOptions, tracker::QueryTracker, Protocol, Query};
 
 pub struct QueryBuilder<'a> {
     df: Arc<DataFusion>,
@@ -29,7 +33,12 @@ pub struct QueryBuilder<'a> {
     query_id: Uuid,
     nsql: Option<&'a str>,
     restricted_sql_options: bool,
+    allow_dml: bool,
+    dml_only: bool,
     protocol: Protocol,
+    principal: Option<Principal>,
+    timeout: Option<Duration>,
+    memory_limit: Option<usize>,
 }
 
 impl<'a> QueryBuilder<'a> {
@@ -40,7 +49,12 @@ impl<'a> QueryBuilder<'a> {
             query_id: Uuid::new_v4(),
             nsql: None,
             restricted_sql_options: false,
+            allow_dml: false,
+            dml_only: false,
             protocol,
+            principal: None,
+            timeout: None,
+            memory_limit: None,
         }
     }
 
@@ -56,25 +70,78 @@ impl<'a> QueryBuilder<'a> {
         self
     }
 
+    /// Rejects DDL, DML and other statements that modify the runtime, so that only queries are run.
     #[must_use]
     pub fn use_restricted_sql_options(mut self) -> Self {
         self.restricted_sql_options = true;
         self
     }
 
+    /// Allows `INSERT`, `UPDATE` and `DELETE` statements against `read_write` datasets when the
+    /// restricted SQL options are used. Only set for endpoints that accept writes from clients.
+    #[must_use]
+    pub fn allow_dml(mut self) -> Self {
+        self.allow_dml = true;
+        self
+    }
+
+    /// Only allows `INSERT`, `UPDATE` and `DELETE` statements, which are rejected before
+    /// execution otherwise.
+    #[must_use]
+    pub fn dml_only(mut self) -> Self {
+        self.dml_only = true;
+        self
+    }
+
     #[must_use]
     pub fn protocol(mut self, protocol: Protocol) -> Self {
         self.protocol = protocol;
         self
     }
 
+    /// Runs the query on behalf of an authenticated principal, enforcing the configured dataset grants.
+    #[must_use]
+    pub fn principal(mut self, principal: Principal) -> Self {
+        self.principal = Some(principal);
+        self
+    }
+
+    /// Lowers the runtime's query timeout for this query. A longer timeout than the configured one is ignored.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Lowers the runtime's query memory limit for this query, in bytes. A higher limit than the configured one is ignored.
+    #[must_use]
+    pub fn memory_limit(mut self, memory_limit: Option<usize>) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    /// Applies the query id and limits requested by a client.
+    #[must_use]
+    pub fn options(mut self, options: QueryOptions) -> Self {
+        if let Some(query_id) = options.query_id {
+            self.query_id = query_id;
+        }
+        self.timeout(options.timeout)
+            .memory_limit(options.memory_limit)
+    }
+
     #[must_use]
     pub fn build(self) -> Query {
         let sql: Arc<str> = self.sql.into();
+        let default_limits = self.df.query_limits();
         Query {
             df: Arc::clone(&self.df),
             sql: Arc::clone(&sql),
             restricted_sql_options: self.restricted_sql_options,
+            allow_dml: self.allow_dml,
+            dml_only: self.dml_only,
+            principal: self.principal,
+            limits: default_limits.restrict(self.timeout, self.memory_limit),
             tracker: QueryTracker {
                 df: self.df,
                 schema: None,
@@ -21,6 +21,10 @@ pub enum ErrorCode {
     SyntaxError,
     QueryPlanningError,
     QueryExecutionError,
+    AccessDenied,
+    QueryTimedOut,
+    QueryCancelled,
+    ResourcesExhausted,
     InternalError,
 }
 
@@ -30,6 +34,10 @@ impl std::fmt::Display for ErrorCode {
             ErrorCode::SyntaxError => write!(f, "SyntaxError"),
             ErrorCode::QueryPlanningError => write!(f, "QueryPlanningError"),
             ErrorCode::QueryExecutionError => write!(f, "QueryExecutionError"),
+            ErrorCode::AccessDenied => write!(f, "AccessDenied"),
+            ErrorCode::QueryTimedOut => write!(f, "QueryTimedOut"),
+            ErrorCode::QueryCancelled => write!(f, "QueryCancelled"),
+            ErrorCode::ResourcesExhausted => write!(f, "ResourcesExhausted"),
             ErrorCode::InternalError => write!(f, "InternalError"),
         }
     }
@@ -41,6 +49,10 @@ impl From<&ErrorCode> for i8 {
             ErrorCode::SyntaxError => -10,
             ErrorCode::QueryPlanningError => -20,
             ErrorCode::QueryExecutionError => -30,
+            ErrorCode::AccessDenied => -40,
+            ErrorCode::QueryTimedOut => -50,
+            ErrorCode::QueryCancelled => -60,
+            ErrorCode::ResourcesExhausted => -70,
             ErrorCode::InternalError => -120,
         }
     }
@@ -56,8 +68,20 @@ impl From<&DataFusionError> for ErrorCode {
             DataFusionError::ObjectStore(..)
             | DataFusionError::External(..)
             | DataFusionError::Execution(..) => ErrorCode::QueryExecutionError,
+            DataFusionError::ResourcesExhausted(..) => ErrorCode::ResourcesExhausted,
             DataFusionError::Context(_, err) => ErrorCode::from(err.as_ref()),
             _ => ErrorCode::InternalError,
         }
     }
 }
+
+impl From<&super::Error> for ErrorCode {
+    fn from(error: &super::Error) -> Self {
+        match error {
+            super::Error::UnableToExecuteQuery { source } => ErrorCode::from(source),
+            super::Error::AccessDenied { .. } => ErrorCode::AccessDenied,
+            super::Error::QueryKilled { source } => source.error_code(),
+            _ => ErrorCode::InternalError,
+        }
+    }
+}
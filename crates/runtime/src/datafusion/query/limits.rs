@@ -0,0 +1,181 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use byte_unit::Byte;
+use datafusion::execution::{memory_pool::GreedyMemoryPool, runtime_env::RuntimeEnv, TaskContext};
+use snafu::prelude::*;
+use spicepod::component::runtime::QueryConfig;
+use uuid::Uuid;
+
+use super::{InvalidMemoryLimitSnafu, InvalidQueryIdSnafu, InvalidQueryTimeoutSnafu, Result};
+
+/// The request header (or Flight metadata key) a client can use to choose the id of a query, to be able to cancel it.
+pub const QUERY_ID_HEADER: &str = "x-spice-query-id";
+
+/// The request header (or Flight metadata key) that lowers the query timeout, i.e. `30s`.
+pub const QUERY_TIMEOUT_HEADER: &str = "x-spice-query-timeout";
+
+/// The request header (or Flight metadata key) that lowers the query memory limit, i.e. `512MiB`.
+pub const QUERY_MEMORY_LIMIT_HEADER: &str = "x-spice-query-memory-limit";
+
+/// Limits on the resources a single query can use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryLimits {
+    pub timeout: Option<Duration>,
+    pub memory_limit: Option<usize>,
+}
+
+impl TryFrom<&QueryConfig> for QueryLimits {
+    type Error = super::Error;
+
+    fn try_from(config: &QueryConfig) -> Result<Self> {
+        Ok(Self {
+            timeout: config.timeout.as_deref().map(parse_timeout).transpose()?,
+            memory_limit: config
+                .memory_limit
+                .as_deref()
+                .map(parse_memory_limit)
+                .transpose()?,
+        })
+    }
+}
+
+impl QueryLimits {
+    /// Applies the limits requested by a client. A client can lower the configured limits, but never lift them.
+    #[must_use]
+    pub fn restrict(self, timeout: Option<Duration>, memory_limit: Option<usize>) -> Self {
+        Self {
+            timeout: lowest(self.timeout, timeout),
+            memory_limit: lowest(self.memory_limit, memory_limit),
+        }
+    }
+}
+
+fn lowest<T: Ord>(configured: Option<T>, requested: Option<T>) -> Option<T> {
+    match (configured, requested) {
+        (Some(configured), Some(requested)) => Some(configured.min(requested)),
+        (configured, requested) => configured.or(requested),
+    }
+}
+
+/// Query settings a client can set per request, through HTTP headers or Flight metadata.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryOptions {
+    pub query_id: Option<Uuid>,
+    pub timeout: Option<Duration>,
+    pub memory_limit: Option<usize>,
+}
+
+impl QueryOptions {
+    /// Reads the query options from request headers, given a function that returns the value of a header.
+    pub fn from_headers<'a>(header: impl Fn(&str) -> Option<&'a str>) -> Result<Self> {
+        Ok(Self {
+            query_id: header(QUERY_ID_HEADER)
+                .map(|value| Uuid::parse_str(value.trim()).context(InvalidQueryIdSnafu { value }))
+                .transpose()?,
+            timeout: header(QUERY_TIMEOUT_HEADER)
+                .map(parse_timeout)
+                .transpose()?,
+            memory_limit: header(QUERY_MEMORY_LIMIT_HEADER)
+                .map(parse_memory_limit)
+                .transpose()?,
+        })
+    }
+}
+
+fn parse_timeout(value: &str) -> Result<Duration> {
+    fundu::parse_duration(value.trim()).context(InvalidQueryTimeoutSnafu { value })
+}
+
+fn parse_memory_limit(value: &str) -> Result<usize> {
+    let bytes = Byte::parse_str(value.trim(), true).context(InvalidMemoryLimitSnafu { value })?;
+    Ok(usize::try_from(bytes.as_u64()).unwrap_or(usize::MAX))
+}
+
+/// Returns a task context that executes the query with its own memory pool, limited to `memory_limit` bytes.
+///
+/// Operators that can spill to disk do so once the limit is reached, others fail with a resources exhausted error.
+pub(crate) fn with_memory_limit(task_ctx: TaskContext, memory_limit: usize) -> TaskContext {
+    let runtime_env = task_ctx.runtime_env();
+    let query_runtime_env = RuntimeEnv {
+        memory_pool: Arc::new(GreedyMemoryPool::new(memory_limit)),
+        disk_manager: Arc::clone(&runtime_env.disk_manager),
+        cache_manager: Arc::clone(&runtime_env.cache_manager),
+        object_store_registry: Arc::clone(&runtime_env.object_store_registry),
+    };
+
+    task_ctx.with_runtime(Arc::new(query_runtime_env))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_query_options_from_headers() {
+        let headers = HashMap::from([
+            (QUERY_ID_HEADER, "3f4a7a5e-9b5e-4a43-8f0a-51b1f1c3a6d2"),
+            (QUERY_TIMEOUT_HEADER, "30s"),
+            (QUERY_MEMORY_LIMIT_HEADER, "512MiB"),
+        ]);
+
+        let options = QueryOptions::from_headers(|name| headers.get(name).copied())
+            .expect("valid query options");
+        assert_eq!(
+            options.query_id,
+            Some(Uuid::parse_str("3f4a7a5e-9b5e-4a43-8f0a-51b1f1c3a6d2").expect("valid uuid"))
+        );
+        assert_eq!(options.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(options.memory_limit, Some(512 * 1024 * 1024));
+
+        assert!(QueryOptions::from_headers(|name| {
+            (name == QUERY_TIMEOUT_HEADER).then_some("soon")
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_query_limits_restrict() {
+        let configured = QueryLimits {
+            timeout: Some(Duration::from_secs(30)),
+            memory_limit: Some(1024),
+        };
+
+        assert_eq!(
+            configured.restrict(Some(Duration::from_secs(10)), Some(512)),
+            QueryLimits {
+                timeout: Some(Duration::from_secs(10)),
+                memory_limit: Some(512),
+            }
+        );
+        assert_eq!(
+            configured.restrict(Some(Duration::from_secs(3600)), Some(4096)),
+            configured
+        );
+        assert_eq!(configured.restrict(None, None), configured);
+        assert_eq!(
+            QueryLimits::default().restrict(Some(Duration::from_secs(10)), None),
+            QueryLimits {
+                timeout: Some(Duration::from_secs(10)),
+                memory_limit: None,
+            }
+        );
+    }
+}
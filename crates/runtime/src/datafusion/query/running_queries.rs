@@ -0,0 +1,200 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::Arc,
+    time::Duration,
+};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use tokio::{sync::Notify, time::Instant};
+use uuid::Uuid;
+
+use crate::auth::Principal;
+
+use super::error_code::ErrorCode;
+
+struct RunningQuery {
+    principal: Option<Arc<str>>,
+    cancelled: Arc<Notify>,
+}
+
+/// The queries currently executing, so that they can be cancelled by id.
+#[derive(Default)]
+pub struct RunningQueries {
+    queries: DashMap<Uuid, RunningQuery>,
+}
+
+impl RunningQueries {
+    /// Tracks a query until the returned guard is dropped. Returns `None` if a query with the same id is already
+    /// running, as query ids can be chosen by clients.
+    pub(crate) fn register(
+        self: &Arc<Self>,
+        query_id: Uuid,
+        principal: Option<&Principal>,
+    ) -> Option<RunningQueryGuard> {
+        let cancelled = Arc::new(Notify::new());
+        match self.queries.entry(query_id) {
+            Entry::Occupied(_) => return None,
+            Entry::Vacant(entry) => {
+                entry.insert(RunningQuery {
+                    principal: principal.map(|principal| Arc::clone(&principal.name)),
+                    cancelled: Arc::clone(&cancelled),
+                });
+            }
+        }
+
+        Some(RunningQueryGuard {
+            queries: Arc::clone(self),
+            query_id,
+            cancelled,
+        })
+    }
+
+    /// Cancels a running query that was started by the principal. Returns `false` if there is no such query.
+    pub fn cancel(&self, query_id: &Uuid, principal: &Principal) -> bool {
+        let Some(query) = self.queries.get(query_id) else {
+            return false;
+        };
+
+        if query.principal.as_deref() != Some(principal.name.as_ref()) {
+            return false;
+        }
+
+        query.cancelled.notify_one();
+        true
+    }
+}
+
+pub(crate) struct RunningQueryGuard {
+    queries: Arc<RunningQueries>,
+    query_id: Uuid,
+    cancelled: Arc<Notify>,
+}
+
+impl RunningQueryGuard {
+    /// Resolves when the query is cancelled, or when its deadline passes.
+    pub(crate) async fn killed(&self, deadline: Option<(Instant, Duration)>) -> KillReason {
+        let timed_out = async {
+            match deadline {
+                Some((deadline, timeout)) => {
+                    tokio::time::sleep_until(deadline).await;
+                    timeout
+                }
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            () = self.cancelled.notified() => KillReason::Cancelled,
+            timeout = timed_out => KillReason::TimedOut(timeout),
+        }
+    }
+}
+
+impl Drop for RunningQueryGuard {
+    fn drop(&mut self) {
+        self.queries.queries.remove_if(&self.query_id, |_, query| {
+            Arc::ptr_eq(&query.cancelled, &self.cancelled)
+        });
+    }
+}
+
+/// Why a query was stopped before it finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KillReason {
+    Cancelled,
+    TimedOut(Duration),
+}
+
+impl std::error::Error for KillReason {}
+
+impl KillReason {
+    pub(crate) fn error_code(self) -> ErrorCode {
+        match self {
+            KillReason::Cancelled => ErrorCode::QueryCancelled,
+            KillReason::TimedOut(_) => ErrorCode::QueryTimedOut,
+        }
+    }
+}
+
+impl Display for KillReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KillReason::Cancelled => write!(f, "Query was cancelled"),
+            KillReason::TimedOut(timeout) => {
+                write!(f, "Query exceeded the timeout of {timeout:?}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let queries = Arc::new(RunningQueries::default());
+        let query_id = Uuid::new_v4();
+        let guard = queries
+            .register(query_id, Some(&Principal::new("alice", vec![])))
+            .expect("query to register");
+
+        assert!(!queries.cancel(&query_id, &Principal::new("bob", vec![])));
+        assert!(queries.cancel(&query_id, &Principal::new("alice", vec![])));
+        assert_eq!(guard.killed(None).await, KillReason::Cancelled);
+
+        drop(guard);
+        assert!(!queries.cancel(&query_id, &Principal::new("alice", vec![])));
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let queries = Arc::new(RunningQueries::default());
+        let guard = queries
+            .register(Uuid::new_v4(), None)
+            .expect("query to register");
+
+        let timeout = Duration::from_millis(10);
+        assert_eq!(
+            guard
+                .killed(Some((Instant::now() + timeout, timeout)))
+                .await,
+            KillReason::TimedOut(timeout)
+        );
+    }
+
+    #[test]
+    fn test_register_duplicate_id() {
+        let queries = Arc::new(RunningQueries::default());
+        let query_id = Uuid::new_v4();
+        let guard = queries
+            .register(query_id, Some(&Principal::new("alice", vec![])))
+            .expect("query to register");
+
+        // The running query is not replaced, and can still be cancelled by its principal.
+        assert!(queries
+            .register(query_id, Some(&Principal::new("bob", vec![])))
+            .is_none());
+        assert!(!queries.cancel(&query_id, &Principal::new("bob", vec![])));
+        assert!(queries.cancel(&query_id, &Principal::new("alice", vec![])));
+
+        drop(guard);
+        assert!(queries.register(query_id, None).is_some());
+    }
+}
@@ -0,0 +1,278 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Splitting of column values into chunks that are embedded separately.
+
+use std::ops::Range;
+
+use snafu::prelude::*;
+use spicepod::component::embeddings::{ChunkStrategy, EmbeddingChunkConfig};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Chunking 'target_chunk_size' must be greater than zero"))]
+    InvalidChunkSize,
+
+    #[snafu(display(
+        "Chunking 'overlap_size' ({overlap_size}) must be less than 'target_chunk_size' ({target_chunk_size})"
+    ))]
+    InvalidOverlapSize {
+        overlap_size: usize,
+        target_chunk_size: usize,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Splits text into chunks according to an [`EmbeddingChunkConfig`].
+#[derive(Debug, Clone)]
+pub struct Chunker {
+    strategy: ChunkStrategy,
+    size: usize,
+    overlap: usize,
+}
+
+impl Chunker {
+    pub fn try_new(config: &EmbeddingChunkConfig) -> Result<Self> {
+        ensure!(config.target_chunk_size > 0, InvalidChunkSizeSnafu);
+
+        let overlap = match config.strategy {
+            ChunkStrategy::Characters | ChunkStrategy::Tokens => {
+                ensure!(
+                    config.overlap_size < config.target_chunk_size,
+                    InvalidOverlapSizeSnafu {
+                        overlap_size: config.overlap_size,
+                        target_chunk_size: config.target_chunk_size,
+                    }
+                );
+                config.overlap_size
+            }
+            ChunkStrategy::Sentences | ChunkStrategy::Paragraphs => 0,
+        };
+
+        Ok(Self {
+            strategy: config.strategy,
+            size: config.target_chunk_size,
+            overlap,
+        })
+    }
+
+    /// How the chunks are formed, e.g. `tokens(size=512, overlap=64)`. Chunks of a value only
+    /// depend on the value and this description.
+    #[must_use]
+    pub fn description(&self) -> String {
+        let strategy = match self.strategy {
+            ChunkStrategy::Characters => "characters",
+            ChunkStrategy::Tokens => "tokens",
+            ChunkStrategy::Sentences => "sentences",
+            ChunkStrategy::Paragraphs => "paragraphs",
+        };
+        format!("{strategy}(size={}, overlap={})", self.size, self.overlap)
+    }
+
+    /// Split `text` into chunks, returned as byte ranges of `text`. Empty text has no chunks.
+    #[must_use]
+    pub fn chunks(&self, text: &str) -> Vec<Range<usize>> {
+        match self.strategy {
+            ChunkStrategy::Characters => {
+                let chars = text.char_indices().map(|(i, c)| i..i + c.len_utf8());
+                self.windows(&chars.collect::<Vec<_>>())
+            }
+            ChunkStrategy::Tokens => self.windows(&tokens(text)),
+            ChunkStrategy::Sentences => self.pack(text, &sentences(text)),
+            ChunkStrategy::Paragraphs => self.pack(text, &paragraphs(text)),
+        }
+    }
+
+    /// Fixed windows of `size` units, each starting `size - overlap` units after the previous one.
+    fn windows(&self, units: &[Range<usize>]) -> Vec<Range<usize>> {
+        let step = self.size - self.overlap;
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < units.len() {
+            let end = (start + self.size).min(units.len());
+            chunks.push(units[start].start..units[end - 1].end);
+            if end == units.len() {
+                break;
+            }
+            start += step;
+        }
+        chunks
+    }
+
+    /// Greedily pack consecutive segments into chunks of at most `size` characters. A segment
+    /// longer than `size` is a chunk of its own.
+    fn pack(&self, text: &str, segments: &[Range<usize>]) -> Vec<Range<usize>> {
+        let mut chunks: Vec<Range<usize>> = Vec::new();
+        let mut current: Option<Range<usize>> = None;
+        for segment in segments {
+            current = match current {
+                Some(chunk) if text[chunk.start..segment.end].chars().count() <= self.size => {
+                    Some(chunk.start..segment.end)
+                }
+                Some(chunk) => {
+                    chunks.push(chunk);
+                    Some(segment.clone())
+                }
+                None => Some(segment.clone()),
+            };
+        }
+        chunks.extend(current);
+        chunks
+    }
+}
+
+/// Whitespace-separated tokens.
+fn tokens(text: &str) -> Vec<Range<usize>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push(s..i);
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(s..text.len());
+    }
+    tokens
+}
+
+/// Sentences end after a `.`, `!` or `?` that is followed by whitespace, or at the end of the text.
+fn sentences(text: &str) -> Vec<Range<usize>> {
+    let mut boundaries = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if matches!(c, '.' | '!' | '?') && chars.peek().is_some_and(|(_, n)| n.is_whitespace()) {
+            boundaries.push(i + c.len_utf8());
+        }
+    }
+    segments(text, boundaries)
+}
+
+/// Paragraphs are separated by blank lines.
+fn paragraphs(text: &str) -> Vec<Range<usize>> {
+    let mut boundaries = Vec::new();
+    let mut offset = 0;
+    let mut blank = false;
+    for line in text.split_inclusive('\n') {
+        let is_blank = line.trim().is_empty();
+        if is_blank && !blank {
+            boundaries.push(offset);
+        }
+        blank = is_blank;
+        offset += line.len();
+    }
+    segments(text, boundaries)
+}
+
+/// Split `text` at `boundaries`, trimming whitespace from each segment and skipping empty ones.
+fn segments(text: &str, boundaries: Vec<usize>) -> Vec<Range<usize>> {
+    let mut start = 0;
+    boundaries
+        .into_iter()
+        .chain(std::iter::once(text.len()))
+        .filter_map(|end| {
+            let segment = &text[start..end];
+            let trimmed_start = start + (segment.len() - segment.trim_start().len());
+            let trimmed_end = start + segment.trim_end().len();
+            start = end;
+            (trimmed_start < trimmed_end).then_some(trimmed_start..trimmed_end)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_chunker(
+        strategy: ChunkStrategy,
+        target_chunk_size: usize,
+        overlap_size: usize,
+    ) -> Chunker {
+        Chunker::try_new(&EmbeddingChunkConfig {
+            strategy,
+            target_chunk_size,
+            overlap_size,
+        })
+        .expect("valid chunking config")
+    }
+
+    fn chunk_text<'a>(chunker: &Chunker, text: &'a str) -> Vec<&'a str> {
+        chunker.chunks(text).into_iter().map(|r| &text[r]).collect()
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = |strategy, target_chunk_size, overlap_size| EmbeddingChunkConfig {
+            strategy,
+            target_chunk_size,
+            overlap_size,
+        };
+        assert!(Chunker::try_new(&config(ChunkStrategy::Characters, 0, 0)).is_err());
+        assert!(Chunker::try_new(&config(ChunkStrategy::Tokens, 4, 4)).is_err());
+        assert!(Chunker::try_new(&config(ChunkStrategy::Sentences, 4, 4)).is_ok());
+    }
+
+    #[test]
+    fn test_character_chunks() {
+        let chunker = new_chunker(ChunkStrategy::Characters, 4, 1);
+        assert_eq!(
+            chunk_text(&chunker, "abcdefghij"),
+            vec!["abcd", "defg", "ghij"]
+        );
+        assert_eq!(chunk_text(&chunker, "héllo"), vec!["héll", "lo"]);
+        assert!(chunker.chunks("").is_empty());
+    }
+
+    #[test]
+    fn test_token_chunks() {
+        let chunker = new_chunker(ChunkStrategy::Tokens, 3, 1);
+        assert_eq!(
+            chunk_text(&chunker, " one two  three four five "),
+            vec!["one two  three", "three four five"]
+        );
+    }
+
+    #[test]
+    fn test_sentence_chunks() {
+        let chunker = new_chunker(ChunkStrategy::Sentences, 20, 0);
+        assert_eq!(
+            chunk_text(&chunker, "Hi there. How are you? Version 1.5 is out! Bye"),
+            vec!["Hi there.", "How are you?", "Version 1.5 is out!", "Bye"]
+        );
+
+        let chunker = new_chunker(ChunkStrategy::Sentences, 30, 0);
+        assert_eq!(
+            chunk_text(&chunker, "Hi there. How are you? Version 1.5 is out! Bye"),
+            vec!["Hi there. How are you?", "Version 1.5 is out! Bye"]
+        );
+    }
+
+    #[test]
+    fn test_paragraph_chunks() {
+        let chunker = new_chunker(ChunkStrategy::Paragraphs, 10, 0);
+        assert_eq!(
+            chunk_text(&chunker, "first\nline\n\n\nsecond\n  \nthird paragraph\n"),
+            vec!["first\nline", "second", "third paragraph"]
+        );
+    }
+}
@@ -13,9 +13,11 @@ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 See the License for the specific language governing permissions and
 limitations under the License.
 */
+use crate::component::dataset::acceleration::RefreshMode;
 use crate::component::dataset::Dataset;
 use crate::EmbeddingModelStore;
 use async_trait::async_trait;
+use data_components::cdc::{AppendStream, ChangesStream};
 use datafusion::datasource::TableProvider;
 use std::any::Any;
 use std::collections::HashMap;
@@ -23,8 +25,11 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 
 use crate::dataconnector::DataConnector;
+use crate::dataconnector::DataConnectorError;
 use crate::dataconnector::DataConnectorResult;
 
+use super::chunking::Chunker;
+use super::materialized::EmbeddingFingerprints;
 use super::table::EmbeddingTable;
 
 pub struct EmbeddingConnector {
@@ -61,13 +66,29 @@ impl EmbeddingConnector {
             .map(|e| (e.column.clone(), e.model.clone()))
             .collect::<HashMap<_, _>>();
 
+        let mut chunkers = HashMap::new();
+        for embedding in &dataset.embeddings {
+            let Some(config) = &embedding.chunking else {
+                continue;
+            };
+            let chunker = Chunker::try_new(config).map_err(|e| {
+                DataConnectorError::InvalidConfigurationNoSource {
+                    dataconnector: "embeddings".to_string(),
+                    message: format!("Invalid chunking for column '{}': {e}", embedding.column),
+                }
+            })?;
+            chunkers.insert(embedding.column.clone(), chunker);
+        }
+
         Ok(Arc::new(
             EmbeddingTable::new(
                 inner_table_provider,
                 embed_columns,
                 Arc::clone(&self.embedding_models),
+                chunkers,
             )
-            .await,
+            .await
+            .with_fingerprints(EmbeddingFingerprints::new(dataset.clone())),
         ) as Arc<dyn TableProvider>)
     }
 }
@@ -78,6 +99,34 @@ impl DataConnector for EmbeddingConnector {
         self
     }
 
+    fn resolve_refresh_mode(&self, refresh_mode: Option<RefreshMode>) -> RefreshMode {
+        self.inner_connector.resolve_refresh_mode(refresh_mode)
+    }
+
+    fn supports_changes_stream(&self) -> bool {
+        self.inner_connector.supports_changes_stream()
+    }
+
+    /// Changes are read from the inner connector. Their embeddings are computed as they are
+    /// written to the accelerator.
+    fn changes_stream(&self, table_provider: Arc<dyn TableProvider>) -> Option<ChangesStream> {
+        match table_provider.as_any().downcast_ref::<EmbeddingTable>() {
+            Some(embedding_table) => self
+                .inner_connector
+                .changes_stream(embedding_table.base_table()),
+            None => self.inner_connector.changes_stream(table_provider),
+        }
+    }
+
+    fn append_stream(&self, table_provider: Arc<dyn TableProvider>) -> Option<AppendStream> {
+        match table_provider.as_any().downcast_ref::<EmbeddingTable>() {
+            Some(embedding_table) => self
+                .inner_connector
+                .append_stream(embedding_table.base_table()),
+            None => self.inner_connector.append_stream(table_provider),
+        }
+    }
+
     async fn read_provider(
         &self,
         dataset: &Dataset,
@@ -14,7 +14,11 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use arrow::array::{ArrayRef, FixedSizeListArray, Float32Array, RecordBatch, StringArray};
+use arrow::array::{
+    Array, ArrayRef, FixedSizeListArray, Float32Array, Int32Array, ListArray, RecordBatch,
+    StringArray,
+};
+use arrow::buffer::OffsetBuffer;
 use arrow::datatypes::{DataType, Field, SchemaRef};
 
 use arrow::error::ArrowError;
@@ -34,7 +38,10 @@ use std::{any::Any, sync::Arc};
 use std::fmt;
 use tokio::sync::RwLock;
 
+use crate::embeddings::chunking::Chunker;
+use crate::embeddings::materialized::{Embedded, MaterializedEmbeddings};
 use crate::EmbeddingModelStore;
+use llms::embeddings::Embed;
 
 pub struct EmbeddingTableExec {
     projected_schema: SchemaRef,
@@ -46,6 +53,8 @@ pub struct EmbeddingTableExec {
 
     embedded_columns: HashMap<String, String>,
     embedding_models: Arc<RwLock<EmbeddingModelStore>>,
+    chunkers: HashMap<String, Chunker>,
+    materialized: Arc<MaterializedEmbeddings>,
 }
 
 impl std::fmt::Debug for EmbeddingTableExec {
@@ -97,6 +106,8 @@ impl ExecutionPlan for EmbeddingTableExec {
             Arc::clone(&self.base_plan).with_new_children(children)?,
             self.embedded_columns.clone(),
             Arc::clone(&self.embedding_models),
+            self.chunkers.clone(),
+            Arc::clone(&self.materialized),
         )) as Arc<dyn ExecutionPlan>)
     }
 
@@ -113,6 +124,8 @@ impl ExecutionPlan for EmbeddingTableExec {
                 Arc::clone(&self.projected_schema),
                 self.embedded_columns.clone(),
                 Arc::clone(&self.embedding_models),
+                self.chunkers.clone(),
+                Arc::clone(&self.materialized),
             ),
         )))
     }
@@ -120,6 +133,7 @@ impl ExecutionPlan for EmbeddingTableExec {
 
 /// All [`Self::embedded_columns`] must be in [`Self::projected_schema`].
 impl EmbeddingTableExec {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         projected_schema: &SchemaRef,
         filters: &[Expr],
@@ -127,6 +141,8 @@ impl EmbeddingTableExec {
         base_plan: Arc<dyn ExecutionPlan>,
         embedded_columns: HashMap<String, String>,
         embedding_models: Arc<RwLock<EmbeddingModelStore>>,
+        chunkers: HashMap<String, Chunker>,
+        materialized: Arc<MaterializedEmbeddings>,
     ) -> Self {
         Self {
             projected_schema: Arc::clone(projected_schema),
@@ -136,6 +152,8 @@ impl EmbeddingTableExec {
             base_plan,
             embedded_columns,
             embedding_models,
+            chunkers,
+            materialized,
         }
     }
 
@@ -155,12 +173,14 @@ fn to_sendable_stream(
     projected_schema: SchemaRef,
     embedded_columns: HashMap<String, String>,
     embedding_models: Arc<RwLock<EmbeddingModelStore>>,
+    chunkers: HashMap<String, Chunker>,
+    materialized: Arc<MaterializedEmbeddings>,
 ) -> impl Stream<Item = DataFusionResult<RecordBatch>> + 'static {
     stream! {
         while let Some(batch_result) = base_stream.next().await {
             match batch_result {
                 Ok(batch) => {
-                    match get_embeddings(&batch, &embedded_columns, Arc::clone(&embedding_models)).await {
+                    match get_embeddings(&batch, &embedded_columns, Arc::clone(&embedding_models), &chunkers, &materialized).await {
                         Ok(embeddings) => {
 
                             match construct_record_batch(
@@ -202,13 +222,34 @@ fn construct_record_batch(
     RecordBatch::try_new(Arc::clone(projected_schema), cols)
 }
 
+/// Add the embedding columns of `schema` to `batch`, for rows that are not read through an
+/// [`EmbeddingTableExec`].
+pub(crate) async fn embed_record_batch(
+    batch: &RecordBatch,
+    schema: &SchemaRef,
+    embedded_columns: &HashMap<String, String>,
+    embedding_models: Arc<RwLock<EmbeddingModelStore>>,
+    chunkers: &HashMap<String, Chunker>,
+    materialized: &MaterializedEmbeddings,
+) -> Result<RecordBatch, Box<dyn std::error::Error + Send + Sync>> {
+    let embeddings = get_embeddings(
+        batch,
+        embedded_columns,
+        embedding_models,
+        chunkers,
+        materialized,
+    )
+    .await?;
+    Ok(construct_record_batch(batch, schema, &embeddings)?)
+}
+
 async fn get_embeddings(
     rb: &RecordBatch,
     embedded_columns: &HashMap<String, String>,
     embedding_models: Arc<RwLock<EmbeddingModelStore>>,
+    chunkers: &HashMap<String, Chunker>,
+    materialized: &MaterializedEmbeddings,
 ) -> Result<HashMap<String, ArrayRef>, Box<dyn std::error::Error + Send + Sync>> {
-    let field = Arc::new(Field::new("item", DataType::Float32, false));
-
     let mut embed_arrays: HashMap<String, ArrayRef> =
         HashMap::with_capacity(embedded_columns.len());
     for (col, model_name) in embedded_columns {
@@ -234,23 +275,170 @@ async fn get_embeddings(
             continue;
         };
 
-        let column: Vec<String> = arr
-            .iter()
-            .filter_map(|s| s.map(ToString::to_string))
-            .collect();
-
-        let embedded_data = model.embed(EmbeddingInput::StringArray(column)).await?;
-        let vector_length = embedded_data.first().map(Vec::len).unwrap_or_default();
-        let processed = embedded_data.iter().flatten().copied().collect_vec();
-
-        let values = Float32Array::try_new(processed.into(), None)?;
-        let list_array = FixedSizeListArray::try_new(
-            Arc::clone(&field),
-            i32::try_from(vector_length)?,
-            Arc::new(values),
-            None,
-        )?;
-        embed_arrays.insert(format!("{col}_embedding"), Arc::new(list_array));
+        let chunker = chunkers.get(col);
+        let embedded = embed_column(col, arr, chunker, model.as_mut(), materialized).await?;
+        if chunker.is_some() {
+            let (embeddings, offsets) = chunked_arrays(arr, &embedded, model.size())?;
+            embed_arrays.insert(format!("{col}_embedding"), Arc::new(embeddings));
+            embed_arrays.insert(format!("{col}_offset"), Arc::new(offsets));
+        } else {
+            embed_arrays.insert(
+                format!("{col}_embedding"),
+                Arc::new(embedding_array(arr, &embedded, model.size())?),
+            );
+        }
     }
     Ok(embed_arrays)
 }
+
+/// Get the embedding of each value of `arr`. Values with an embedding in `materialized` are not
+/// embedded again; the others are split into chunks (if `chunker` is set), embedded, and kept in
+/// `materialized`.
+async fn embed_column(
+    column: &str,
+    arr: &StringArray,
+    chunker: Option<&Chunker>,
+    model: &mut dyn Embed,
+    materialized: &MaterializedEmbeddings,
+) -> Result<Vec<Option<Arc<Embedded>>>, Box<dyn std::error::Error + Send + Sync>> {
+    let values = arr.iter().flatten().unique().collect_vec();
+    let mut known = match materialized.lookup(column, &values).await {
+        Ok(known) => known,
+        Err(e) => {
+            tracing::warn!("{e}. The values will be embedded again.");
+            HashMap::new()
+        }
+    };
+    let missing = values
+        .into_iter()
+        .filter(|text| !known.contains_key(*text))
+        .collect_vec();
+
+    if !missing.is_empty() {
+        let ranges = missing
+            .iter()
+            .map(|text| {
+                chunker.map_or_else(
+                    || std::iter::once(0..text.len()).collect(),
+                    |c| c.chunks(text),
+                )
+            })
+            .collect_vec();
+        let chunks = missing
+            .iter()
+            .zip(&ranges)
+            .flat_map(|(text, ranges)| ranges.iter().map(|r| text[r.clone()].to_string()))
+            .collect_vec();
+
+        let num_chunks = chunks.len();
+        let vectors = if chunks.is_empty() {
+            Vec::new()
+        } else {
+            model.embed(EmbeddingInput::StringArray(chunks)).await?
+        };
+        if vectors.len() != num_chunks {
+            return Err(format!(
+                "Expected {num_chunks} embeddings for column '{column}', but the model returned {}",
+                vectors.len()
+            )
+            .into());
+        }
+
+        let mut vectors = vectors.into_iter();
+        for (text, ranges) in missing.into_iter().zip(ranges) {
+            let offsets = if chunker.is_some() {
+                ranges
+                    .iter()
+                    .map(|r| Ok([i32::try_from(r.start)?, i32::try_from(r.end)?]))
+                    .collect::<Result<_, std::num::TryFromIntError>>()?
+            } else {
+                Vec::new()
+            };
+            let embedded = Embedded {
+                vectors: vectors.by_ref().take(ranges.len()).collect(),
+                offsets,
+            };
+            let embedded = Arc::new(embedded);
+            materialized.insert(column, text.to_string(), Arc::clone(&embedded));
+            known.insert(text.to_string(), embedded);
+        }
+    }
+
+    Ok(arr
+        .iter()
+        .map(|text| text.and_then(|text| known.get(text).cloned()))
+        .collect())
+}
+
+/// A `{column}_embedding` array of one vector per value. Null values have a null embedding.
+fn embedding_array(
+    arr: &StringArray,
+    embedded: &[Option<Arc<Embedded>>],
+    size: i32,
+) -> Result<FixedSizeListArray, Box<dyn std::error::Error + Send + Sync>> {
+    let length = usize::try_from(size)?;
+    let mut values = Vec::with_capacity(embedded.len() * length);
+    for embedding in embedded {
+        match embedding.as_ref().and_then(|e| e.vectors.first()) {
+            Some(vector) => values.extend_from_slice(vector),
+            None => values.resize(values.len() + length, 0.0),
+        }
+    }
+
+    Ok(FixedSizeListArray::try_new(
+        Arc::new(Field::new("item", DataType::Float32, false)),
+        size,
+        Arc::new(Float32Array::try_new(values.into(), None)?),
+        arr.nulls().cloned(),
+    )?)
+}
+
+/// The `{column}_embedding` and `{column}_offset` arrays of a chunked column: a list of the
+/// chunks' embeddings and a list of the chunks' `[start, end)` byte offsets for each value.
+fn chunked_arrays(
+    arr: &StringArray,
+    embedded: &[Option<Arc<Embedded>>],
+    size: i32,
+) -> Result<(ListArray, ListArray), Box<dyn std::error::Error + Send + Sync>> {
+    let mut vectors = Vec::new();
+    let mut offsets = Vec::new();
+    let mut row_offsets = vec![0_i32];
+    let mut num_chunks = 0_i32;
+    for embedding in embedded {
+        if let Some(embedding) = embedding {
+            vectors.extend(embedding.vectors.iter().flatten().copied());
+            offsets.extend(embedding.offsets.iter().flatten().copied());
+            num_chunks += i32::try_from(embedding.vectors.len())?;
+        }
+        row_offsets.push(num_chunks);
+    }
+
+    let vectors = FixedSizeListArray::try_new(
+        Arc::new(Field::new("item", DataType::Float32, false)),
+        size,
+        Arc::new(Float32Array::try_new(vectors.into(), None)?),
+        None,
+    )?;
+    let offsets = FixedSizeListArray::try_new(
+        Arc::new(Field::new("item", DataType::Int32, false)),
+        2,
+        Arc::new(Int32Array::try_new(offsets.into(), None)?),
+        None,
+    )?;
+
+    let row_offsets = OffsetBuffer::new(row_offsets.into());
+    Ok((
+        ListArray::try_new(
+            Arc::new(Field::new("item", vectors.data_type().clone(), false)),
+            row_offsets.clone(),
+            Arc::new(vectors),
+            arr.nulls().cloned(),
+        )?,
+        ListArray::try_new(
+            Arc::new(Field::new("item", offsets.data_type().clone(), false)),
+            row_offsets,
+            Arc::new(offsets),
+            arr.nulls().cloned(),
+        )?,
+    ))
+}
@@ -0,0 +1,382 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! BM25 full-text index over the text columns of accelerated datasets.
+//!
+//! The text columns of each row are indexed as a single document. Like the vector index, each
+//! document keeps the row's payload columns, so search results are returned without querying the
+//! accelerator.
+
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use arrow::{
+    array::{Array, AsArray, RecordBatch, UInt32Array},
+    compute::{cast, concat_batches, take},
+    datatypes::{DataType, SchemaRef},
+    error::ArrowError,
+};
+use async_trait::async_trait;
+use datafusion::{
+    datasource::TableProvider, error::DataFusionError, execution::context::SessionContext,
+    prelude::ident, sql::TableReference,
+};
+use snafu::prelude::*;
+use tokio::sync::Notify;
+
+use super::{next_rebuild, SearchIndex};
+
+/// Term frequency saturation.
+const K1: f64 = 1.2;
+/// Document length normalization.
+const B: f64 = 0.75;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to read documents from the accelerated table: {source}"))]
+    UnableToReadDocuments { source: DataFusionError },
+
+    #[snafu(display("Unable to build full-text index: {source}"))]
+    UnableToBuildIndex { source: ArrowError },
+
+    #[snafu(display("Unable to search full-text index: {source}"))]
+    UnableToSearchIndex { source: ArrowError },
+
+    #[snafu(display("Full-text index task failed: {source}"))]
+    IndexTaskFailed { source: tokio::task::JoinError },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The name given to the `i`th text column when reading documents, so text columns can also be
+/// payload columns.
+#[must_use]
+pub fn text_column_alias(i: usize) -> String {
+    format!("__fts_text_{i}")
+}
+
+/// A BM25 index over the text columns of an accelerated table.
+///
+/// Like [`super::index::EmbeddingIndex`], the index is rebuilt from the accelerator in the
+/// background each time it is marked stale. Until the first build completes,
+/// [`FullTextIndex::search`] returns `None` and callers fall back to a full scan.
+pub struct FullTextIndex {
+    dataset_name: TableReference,
+    columns: Vec<String>,
+    payload_columns: Vec<String>,
+    bm25: RwLock<Option<Arc<Bm25>>>,
+    stale: Notify,
+}
+
+impl FullTextIndex {
+    /// Create an index over the text of `columns`. Search results contain the `payload_columns`.
+    #[must_use]
+    pub fn new(
+        dataset_name: TableReference,
+        columns: Vec<String>,
+        payload_columns: Vec<String>,
+    ) -> Self {
+        Self {
+            dataset_name,
+            columns,
+            payload_columns,
+            bm25: RwLock::new(None),
+            stale: Notify::new(),
+        }
+    }
+
+    /// The indexed text columns.
+    #[must_use]
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// The columns returned for each search result, in order.
+    #[must_use]
+    pub fn payload_columns(&self) -> &[String] {
+        &self.payload_columns
+    }
+
+    /// Rebuild the index from the current contents of `accelerator`, returning the number of documents indexed.
+    pub async fn rebuild(self: &Arc<Self>, accelerator: &Arc<dyn TableProvider>) -> Result<usize> {
+        let ctx = SessionContext::new();
+        let mut columns = self.payload_columns.iter().map(ident).collect::<Vec<_>>();
+        columns.extend(
+            self.columns
+                .iter()
+                .enumerate()
+                .map(|(i, c)| ident(c).alias(text_column_alias(i))),
+        );
+
+        let df = ctx
+            .read_table(Arc::clone(accelerator))
+            .and_then(|df| df.select(columns))
+            .context(UnableToReadDocumentsSnafu)?;
+        let schema = Arc::clone(df.schema().inner());
+        let batches = df.collect().await.context(UnableToReadDocumentsSnafu)?;
+
+        let index = Arc::clone(self);
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let bm25 = Bm25::build(&schema, &batches, index.columns.len())
+                .context(UnableToBuildIndexSnafu)?;
+            let documents = bm25.num_documents();
+            match index.bm25.write() {
+                Ok(mut guard) => *guard = Some(Arc::new(bm25)),
+                Err(e) => tracing::error!("Unable to update full-text index: {e}"),
+            }
+            Ok(documents)
+        })
+        .await
+        .context(IndexTaskFailedSnafu)?
+    }
+
+    /// Return the payload columns of the rows that best match `query`, ordered by descending BM25
+    /// score, along with the score of each row. Rows that match none of the query terms are not
+    /// returned. Returns `None` if the index hasn't been built yet.
+    #[must_use]
+    pub fn search(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+    ) -> Option<Result<(RecordBatch, Vec<f64>)>> {
+        let bm25 = self.bm25.read().ok()?.clone()?;
+        Some(bm25.search(query, limit).context(UnableToSearchIndexSnafu))
+    }
+}
+
+#[async_trait]
+impl SearchIndex for FullTextIndex {
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn mark_stale(&self) {
+        self.stale.notify_one();
+    }
+
+    async fn start(self: Arc<Self>, accelerator: Arc<dyn TableProvider>) {
+        let mut previous = None;
+        loop {
+            next_rebuild(&self.stale, &mut previous).await;
+            match self.rebuild(&accelerator).await {
+                Ok(documents) => tracing::debug!(
+                    "Rebuilt full-text index for {} with {documents} documents",
+                    self.dataset_name
+                ),
+                Err(e) => tracing::warn!(
+                    "Failed to rebuild full-text index for {}: {e}",
+                    self.dataset_name
+                ),
+            }
+        }
+    }
+}
+
+/// Score every row of `batches` against `query`. The batches are laid out as for
+/// [`FullTextIndex::rebuild`]: the payload columns followed by `num_text_columns` text columns.
+pub fn exhaustive_search(
+    schema: &SchemaRef,
+    batches: &[RecordBatch],
+    num_text_columns: usize,
+    query: &str,
+    limit: Option<usize>,
+) -> Result<(RecordBatch, Vec<f64>)> {
+    Bm25::build(schema, batches, num_text_columns)
+        .context(UnableToBuildIndexSnafu)?
+        .search(query, limit)
+        .context(UnableToSearchIndexSnafu)
+}
+
+/// An inverted index of term frequencies per document.
+struct Bm25 {
+    postings: HashMap<String, Vec<(u32, u32)>>,
+    document_lengths: Vec<u32>,
+    average_length: f64,
+    payload: RecordBatch,
+}
+
+impl Bm25 {
+    #[allow(clippy::cast_precision_loss)]
+    fn build(
+        schema: &SchemaRef,
+        batches: &[RecordBatch],
+        num_text_columns: usize,
+    ) -> Result<Self, ArrowError> {
+        let batch = concat_batches(schema, batches)?;
+        let payload_len = batch
+            .num_columns()
+            .checked_sub(num_text_columns)
+            .ok_or_else(|| ArrowError::SchemaError("Missing text columns".to_string()))?;
+
+        let text_columns = batch.columns()[payload_len..]
+            .iter()
+            .map(|c| cast(c, &DataType::Utf8))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut postings: HashMap<String, Vec<(u32, u32)>> = HashMap::new();
+        let mut document_lengths = Vec::with_capacity(batch.num_rows());
+        for row in 0..batch.num_rows() {
+            let document = u32::try_from(row).map_err(|_| {
+                ArrowError::InvalidArgumentError("Too many rows for a full-text index".to_string())
+            })?;
+
+            let mut frequencies: HashMap<String, u32> = HashMap::new();
+            for column in &text_columns {
+                let column = column.as_string::<i32>();
+                if column.is_valid(row) {
+                    for term in tokenize(column.value(row)) {
+                        *frequencies.entry(term).or_default() += 1;
+                    }
+                }
+            }
+
+            document_lengths.push(frequencies.values().sum());
+            for (term, frequency) in frequencies {
+                postings
+                    .entry(term)
+                    .or_default()
+                    .push((document, frequency));
+            }
+        }
+
+        let total_length: u64 = document_lengths.iter().map(|&l| u64::from(l)).sum();
+        let average_length = if document_lengths.is_empty() {
+            0.0
+        } else {
+            total_length as f64 / document_lengths.len() as f64
+        };
+
+        Ok(Self {
+            postings,
+            document_lengths,
+            average_length,
+            payload: batch.project(&(0..payload_len).collect::<Vec<_>>())?,
+        })
+    }
+
+    fn num_documents(&self) -> usize {
+        self.document_lengths.len()
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn search(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+    ) -> Result<(RecordBatch, Vec<f64>), ArrowError> {
+        let num_documents = self.num_documents() as f64;
+        let mut scores: HashMap<u32, f64> = HashMap::new();
+        for term in tokenize(query).collect::<HashSet<_>>() {
+            let Some(postings) = self.postings.get(&term) else {
+                continue;
+            };
+
+            let document_frequency = postings.len() as f64;
+            let idf = (1.0
+                + (num_documents - document_frequency + 0.5) / (document_frequency + 0.5))
+                .ln();
+            for &(document, frequency) in postings {
+                let frequency = f64::from(frequency);
+                let length = f64::from(self.document_lengths[document as usize]);
+                let normalization = 1.0 - B + B * length / self.average_length;
+                *scores.entry(document).or_default() +=
+                    idf * frequency * (K1 + 1.0) / (frequency + K1 * normalization);
+            }
+        }
+
+        let mut ranked = scores.into_iter().collect::<Vec<_>>();
+        ranked.sort_by(|(a_doc, a), (b_doc, b)| b.total_cmp(a).then(a_doc.cmp(b_doc)));
+        ranked.truncate(limit.unwrap_or(usize::MAX));
+
+        let indices = UInt32Array::from_iter_values(ranked.iter().map(|(document, _)| *document));
+        let columns = self
+            .payload
+            .columns()
+            .iter()
+            .map(|c| take(c, &indices, None))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((
+            RecordBatch::try_new(self.payload.schema(), columns)?,
+            ranked.into_iter().map(|(_, score)| score).collect(),
+        ))
+    }
+}
+
+/// Lowercase alphanumeric terms.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::{Int64Array, StringArray},
+        datatypes::{Field, Int64Type, Schema},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_search_ranks_by_bm25() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("title", DataType::Utf8, true),
+            Field::new("body", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2, 3, 4])),
+                Arc::new(StringArray::from(vec![
+                    Some("Rust"),
+                    Some("Cooking"),
+                    None,
+                    Some("Gardening"),
+                ])),
+                Arc::new(StringArray::from(vec![
+                    Some("Ownership and borrowing in Rust, the Rust way."),
+                    Some("A recipe for bread."),
+                    Some("Borrowing a cup of sugar from the neighbours."),
+                    Some("Growing tomatoes."),
+                ])),
+            ],
+        )
+        .expect("valid batch");
+
+        let (result, scores) = exhaustive_search(&schema, &[batch], 2, "rust BORROWING", None)
+            .expect("search succeeds");
+        assert_eq!(result.num_columns(), 1);
+        assert_eq!(
+            result.column(0).as_primitive::<Int64Type>().values(),
+            &[1, 3]
+        );
+        assert!(scores[0] > scores[1] && scores[1] > 0.0);
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Hello, wörld! It's 2024.").collect::<Vec<_>>(),
+            vec!["hello", "wörld", "it", "s", "2024"]
+        );
+    }
+}
@@ -0,0 +1,1255 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Approximate nearest neighbour index for the embedding columns of accelerated datasets.
+//!
+//! The index is an inverted file (IVF-flat): rows are partitioned into lists around k-means
+//! centroids, and a search only scans the lists whose centroids are closest to the query vector.
+//! Each entry only keeps the row's primary keys, and the columns of the nearest rows are read from
+//! the accelerator by key with [`read_rows`]. For chunked columns there is one entry per chunk,
+//! along with the chunk's offsets within the column value.
+//!
+//! Rebuilds reuse the centroids of the previous build while the number of lists needed stays about
+//! the same, so that only the list assignments are recomputed as the table changes.
+
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use arrow::{
+    array::{
+        Array, ArrayRef, AsArray, FixedSizeListArray, Float32Array, Int32Array, RecordBatch,
+        UInt32Array, UInt64Array,
+    },
+    compute::{cast, concat_batches, take},
+    datatypes::{DataType, Field, Float32Type, Int32Type, Schema, SchemaRef, UInt32Type},
+    error::ArrowError,
+    row::{RowConverter, SortField},
+};
+use arrow_ipc::{reader::FileReader, writer::FileWriter};
+use async_trait::async_trait;
+use datafusion::{
+    common::ScalarValue,
+    datasource::TableProvider,
+    error::DataFusionError,
+    execution::context::SessionContext,
+    logical_expr::utils::{conjunction, disjunction},
+    prelude::{ident, lit, Expr},
+    sql::TableReference,
+};
+use snafu::prelude::*;
+use tokio::sync::Notify;
+
+use super::{next_rebuild, SearchIndex};
+
+/// Tables with fewer entries than this are kept in a single list, i.e. searched exhaustively.
+const MIN_ROWS_TO_PARTITION: usize = 4096;
+const MAX_LISTS: usize = 1024;
+const TRAINING_SAMPLES_PER_LIST: usize = 64;
+const TRAINING_ITERATIONS: usize = 10;
+const MIN_PROBES: usize = 8;
+
+const EMBEDDING_FIELD: &str = "__ann_embedding";
+const LIST_FIELD: &str = "__ann_list";
+const ROW_FIELD: &str = "__ann_row";
+const OFFSET_FIELD: &str = "__ann_offset";
+const NUM_LISTS_METADATA_KEY: &str = "ann_num_lists";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to read embeddings from the accelerated table: {source}"))]
+    UnableToReadEmbeddings { source: DataFusionError },
+
+    #[snafu(display("Unable to build vector index: {source}"))]
+    UnableToBuildIndex { source: ArrowError },
+
+    #[snafu(display("Unable to search vector index: {source}"))]
+    UnableToSearchIndex { source: ArrowError },
+
+    #[snafu(display("Unable to read the rows of vector index results: {source}"))]
+    UnableToReadRows { source: DataFusionError },
+
+    #[snafu(display("Unable to persist vector index to {}: {source}", path.display()))]
+    UnableToPersistIndex { path: PathBuf, source: ArrowError },
+
+    #[snafu(display("Unable to load vector index from {}: {source}", path.display()))]
+    UnableToLoadIndex { path: PathBuf, source: ArrowError },
+
+    #[snafu(display("Vector index task failed: {source}"))]
+    IndexTaskFailed { source: tokio::task::JoinError },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An approximate nearest neighbour index over one embedding column of an accelerated table.
+///
+/// The index is rebuilt from the accelerator in the background each time it is marked stale, i.e.
+/// after refreshes, appends and changes are written to the accelerator, but at most once every 30
+/// seconds. Until the first build completes, [`EmbeddingIndex::search`] returns `None` and callers
+/// fall back to a full scan.
+pub struct EmbeddingIndex {
+    dataset_name: TableReference,
+    column: String,
+    payload_columns: Vec<String>,
+    chunked: bool,
+    path: Option<PathBuf>,
+    ivf: RwLock<Option<Arc<IvfFlat>>>,
+    stale: Notify,
+}
+
+impl EmbeddingIndex {
+    /// Create an index over `{column}_embedding`. Search results contain the `primary_keys` of the
+    /// matching rows, which must not be empty. If `chunked`, the column is indexed per chunk from
+    /// `{column}_embedding` and `{column}_offset`. If `path` is set, the index is persisted to and
+    /// loaded from that file.
+    #[must_use]
+    pub fn new(
+        dataset_name: TableReference,
+        column: String,
+        primary_keys: Vec<String>,
+        chunked: bool,
+        path: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            dataset_name,
+            column,
+            payload_columns: primary_keys,
+            chunked,
+            path,
+            ivf: RwLock::new(None),
+            stale: Notify::new(),
+        }
+    }
+
+    /// The name of the embedded column, without the `_embedding` suffix.
+    #[must_use]
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// The primary keys returned for each search result, in order.
+    #[must_use]
+    pub fn payload_columns(&self) -> &[String] {
+        &self.payload_columns
+    }
+
+    /// Whether the index has one entry per chunk of the column, rather than per row.
+    #[must_use]
+    pub fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+
+    /// Rebuild the index from the current contents of `accelerator`, returning the number of entries indexed.
+    pub async fn rebuild(self: &Arc<Self>, accelerator: &Arc<dyn TableProvider>) -> Result<usize> {
+        let ctx = SessionContext::new();
+        let mut columns = self.payload_columns.iter().map(ident).collect::<Vec<_>>();
+        columns.push(ident(format!("{}_embedding", self.column)));
+        if self.chunked {
+            columns.push(ident(format!("{}_offset", self.column)));
+        }
+
+        let df = ctx
+            .read_table(Arc::clone(accelerator))
+            .and_then(|df| df.select(columns))
+            .context(UnableToReadEmbeddingsSnafu)?;
+        let schema = Arc::clone(df.schema().inner());
+        let batches = df.collect().await.context(UnableToReadEmbeddingsSnafu)?;
+
+        let previous = self.ivf.read().ok().and_then(|ivf| ivf.clone());
+        let index = Arc::clone(self);
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let ivf = IvfFlat::build(&schema, &batches, index.chunked, true, previous.as_deref())
+                .context(UnableToBuildIndexSnafu)?;
+            let entries = ivf.num_entries();
+            let ivf = Arc::new(ivf);
+            index.set(Some(Arc::clone(&ivf)));
+
+            if let Some(path) = &index.path {
+                ivf.persist(path)
+                    .context(UnableToPersistIndexSnafu { path: path.clone() })?;
+            }
+            Ok(entries)
+        })
+        .await
+        .context(IndexTaskFailedSnafu)?
+    }
+
+    /// Return the entries nearest to `query`, ordered by ascending distance. At most `limit`
+    /// entries are returned, and only entries closer than `max_distance`. Returns `None` if the
+    /// index hasn't been built yet.
+    ///
+    /// As only the lists nearest to `query` are searched, entries within `max_distance` may be missed.
+    #[must_use]
+    pub fn search(
+        &self,
+        query: &[f32],
+        limit: Option<usize>,
+        max_distance: Option<f64>,
+    ) -> Option<Result<SearchResult>> {
+        let ivf = self.ivf.read().ok()?.clone()?;
+        Some(
+            ivf.search(query, limit, max_distance)
+                .context(UnableToSearchIndexSnafu),
+        )
+    }
+
+    fn set(&self, ivf: Option<Arc<IvfFlat>>) {
+        match self.ivf.write() {
+            Ok(mut guard) => *guard = ivf,
+            Err(e) => tracing::error!("Unable to update vector index: {e}"),
+        }
+    }
+
+    fn load(&self, path: &Path) -> Result<usize> {
+        let ivf = IvfFlat::load(path, &self.payload_columns, self.chunked)
+            .context(UnableToLoadIndexSnafu { path })?;
+        let entries = ivf.num_entries();
+        self.set(Some(Arc::new(ivf)));
+        Ok(entries)
+    }
+}
+
+#[async_trait]
+impl SearchIndex for EmbeddingIndex {
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn mark_stale(&self) {
+        self.stale.notify_one();
+    }
+
+    /// Load the persisted index, if any, then rebuild the index from `accelerator` each time it is
+    /// marked stale.
+    async fn start(self: Arc<Self>, accelerator: Arc<dyn TableProvider>) {
+        if let Some(path) = self.path.clone().filter(|p| p.exists()) {
+            let index = Arc::clone(&self);
+            match tokio::task::spawn_blocking(move || index.load(&path))
+                .await
+                .context(IndexTaskFailedSnafu)
+                .and_then(|r| r)
+            {
+                Ok(entries) => tracing::debug!(
+                    "Loaded vector index for {}.{} with {entries} entries",
+                    self.dataset_name,
+                    self.column
+                ),
+                Err(e) => tracing::warn!(
+                    "Unable to load vector index for {}.{}, it will be rebuilt: {e}",
+                    self.dataset_name,
+                    self.column
+                ),
+            }
+        }
+
+        let mut previous = None;
+        loop {
+            next_rebuild(&self.stale, &mut previous).await;
+            match self.rebuild(&accelerator).await {
+                Ok(entries) => tracing::debug!(
+                    "Rebuilt vector index for {}.{} with {entries} entries",
+                    self.dataset_name,
+                    self.column
+                ),
+                Err(e) => tracing::warn!(
+                    "Failed to rebuild vector index for {}.{}: {e}",
+                    self.dataset_name,
+                    self.column
+                ),
+            }
+        }
+    }
+}
+
+/// Replace the payload of `result`, the primary keys of the matching rows, with the `columns` of
+/// those rows read from `table`. Results whose row is no longer in `table`, e.g. because it was
+/// deleted since the index was built, are dropped.
+pub async fn read_rows(
+    table: Arc<dyn TableProvider>,
+    result: SearchResult,
+    columns: &[String],
+) -> Result<SearchResult> {
+    let keys = &result.payload;
+    let key_names = keys
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| f.name().clone())
+        .collect::<Vec<_>>();
+
+    let mut matches = Vec::with_capacity(keys.num_rows());
+    for row in 0..keys.num_rows() {
+        let key = key_names
+            .iter()
+            .zip(keys.columns())
+            .map(
+                |(name, column)| Ok(ident(name).eq(lit(ScalarValue::try_from_array(column, row)?))),
+            )
+            .collect::<Result<Vec<_>, DataFusionError>>()
+            .context(UnableToReadRowsSnafu)?;
+        matches.extend(conjunction(key));
+    }
+
+    let mut selected = columns.to_vec();
+    selected.extend(key_names.iter().filter(|k| !columns.contains(k)).cloned());
+    let df = SessionContext::new()
+        .read_table(table)
+        .and_then(|df| df.filter(disjunction(matches).unwrap_or_else(|| lit(false))))
+        .and_then(|df| df.select(selected.iter().map(ident).collect::<Vec<Expr>>()))
+        .context(UnableToReadRowsSnafu)?;
+    let schema = Arc::clone(df.schema().inner());
+    let batches = df.collect().await.context(UnableToReadRowsSnafu)?;
+
+    let rows = concat_batches(&schema, &batches).context(UnableToSearchIndexSnafu)?;
+    resolve_rows(result, &rows, columns.len()).context(UnableToSearchIndexSnafu)
+}
+
+/// Match the primary keys in the payload of `result` to the rows of `rows`, which are laid out as
+/// the `num_columns` requested columns followed by any primary keys not among them.
+fn resolve_rows(
+    result: SearchResult,
+    rows: &RecordBatch,
+    num_columns: usize,
+) -> Result<SearchResult, ArrowError> {
+    let keys = &result.payload;
+    let row_keys = keys
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| rows.column_by_name(f.name()).map(Arc::clone))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| ArrowError::SchemaError("Missing primary key column".to_string()))?;
+
+    let converter = RowConverter::new(
+        keys.columns()
+            .iter()
+            .map(|c| SortField::new(c.data_type().clone()))
+            .collect(),
+    )?;
+    let result_keys = converter.convert_columns(keys.columns())?;
+    let row_keys = converter.convert_columns(&row_keys)?;
+    let positions = row_keys
+        .iter()
+        .enumerate()
+        .map(|(position, key)| (key, position))
+        .collect::<HashMap<_, _>>();
+
+    let mut indices = Vec::with_capacity(keys.num_rows());
+    let mut distances = Vec::with_capacity(keys.num_rows());
+    let mut offsets = result.offsets.as_ref().map(|_| Vec::new());
+    for (i, key) in result_keys.iter().enumerate() {
+        let Some(&position) = positions.get(&key) else {
+            continue;
+        };
+        indices.push(u32::try_from(position).map_err(|_| {
+            ArrowError::InvalidArgumentError("Too many rows in vector index results".to_string())
+        })?);
+        distances.push(result.distances[i]);
+        if let (Some(offsets), Some(result_offsets)) = (&mut offsets, &result.offsets) {
+            offsets.push(result_offsets[i]);
+        }
+    }
+
+    let indices = UInt32Array::from(indices);
+    let projection = (0..num_columns).collect::<Vec<_>>();
+    let rows = rows.project(&projection)?;
+    let columns = rows
+        .columns()
+        .iter()
+        .map(|c| take(c, &indices, None))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(SearchResult {
+        payload: RecordBatch::try_new(rows.schema(), columns)?,
+        distances,
+        offsets,
+    })
+}
+
+/// The result of a vector index search, ordered by ascending distance.
+pub struct SearchResult {
+    /// The payload columns of the row each result belongs to.
+    pub payload: RecordBatch,
+
+    /// The squared euclidean distance of each result from the query vector.
+    pub distances: Vec<f64>,
+
+    /// For chunked columns, the `[start, end)` byte offsets of each matching chunk within the column value.
+    pub offsets: Option<Vec<[i32; 2]>>,
+}
+
+/// Exhaustively search `batches` for the entries nearest to `query`. The batches are laid out as
+/// for [`EmbeddingIndex::rebuild`]: the payload columns, the embedding column and, for chunked
+/// columns, the offset column.
+pub fn exhaustive_search(
+    schema: &SchemaRef,
+    batches: &[RecordBatch],
+    chunked: bool,
+    query: &[f32],
+    limit: Option<usize>,
+    max_distance: Option<f64>,
+) -> Result<SearchResult> {
+    IvfFlat::build(schema, batches, chunked, false, None)
+        .context(UnableToBuildIndexSnafu)?
+        .search(query, limit, max_distance)
+        .context(UnableToSearchIndexSnafu)
+}
+
+/// The embedding vectors of a table, one entry per row, or per chunk for chunked columns.
+struct Entries {
+    dim: usize,
+    vectors: Vec<f32>,
+    rows: Vec<u32>,
+    offsets: Option<Vec<[i32; 2]>>,
+}
+
+/// An IVF-flat index: full vectors partitioned into lists by their nearest centroid.
+struct IvfFlat {
+    centroids: Vec<f32>,
+    lists: Vec<Vec<u32>>,
+    entries: Entries,
+    payload: RecordBatch,
+}
+
+impl IvfFlat {
+    /// Build an index from batches laid out as the payload columns, followed by the embedding
+    /// column and, if `chunked`, the offset column. If `partition` is false, a single list is used.
+    /// The centroids of the `previous` index are reused if it has between half and twice as many
+    /// lists as needed, rather than training new ones.
+    fn build(
+        schema: &SchemaRef,
+        batches: &[RecordBatch],
+        chunked: bool,
+        partition: bool,
+        previous: Option<&IvfFlat>,
+    ) -> Result<Self, ArrowError> {
+        let batch = concat_batches(schema, batches)?;
+        let embedding_idx = batch
+            .num_columns()
+            .checked_sub(if chunked { 2 } else { 1 })
+            .ok_or_else(|| ArrowError::SchemaError("Missing embedding column".to_string()))?;
+
+        let entries = if chunked {
+            chunk_entries(batch.column(embedding_idx), batch.column(embedding_idx + 1))?
+        } else {
+            row_entries(batch.column(embedding_idx))?
+        };
+        let payload = batch.project(&(0..embedding_idx).collect::<Vec<_>>())?;
+
+        let dim = entries.dim;
+        let num_entries = entries.rows.len();
+        let num_lists = if !partition || num_entries < MIN_ROWS_TO_PARTITION {
+            1
+        } else {
+            num_entries.isqrt().min(MAX_LISTS)
+        };
+
+        let trained = previous
+            .filter(|p| p.entries.dim == dim && p.lists.len() > 1)
+            .filter(|p| p.lists.len() >= num_lists / 2 && p.lists.len() <= num_lists * 2);
+        let centroids = match trained {
+            _ if num_lists == 1 => vec![0.0; dim],
+            Some(previous) => previous.centroids.clone(),
+            None => kmeans(&entries.vectors, dim, num_lists),
+        };
+        let assignments = entries
+            .vectors
+            .chunks_exact(dim)
+            .map(|v| nearest_centroid(&centroids, dim, v))
+            .collect::<Vec<_>>();
+
+        Self::try_new(centroids, &assignments, entries, payload)
+    }
+
+    fn try_new(
+        centroids: Vec<f32>,
+        assignments: &[usize],
+        entries: Entries,
+        payload: RecordBatch,
+    ) -> Result<Self, ArrowError> {
+        let mut lists = vec![Vec::new(); centroids.len() / entries.dim];
+        for (entry, &list) in assignments.iter().enumerate() {
+            let entry = u32::try_from(entry).map_err(|_| {
+                ArrowError::InvalidArgumentError("Too many entries for a vector index".to_string())
+            })?;
+            lists
+                .get_mut(list)
+                .ok_or_else(|| {
+                    ArrowError::InvalidArgumentError(format!("Invalid vector index list {list}"))
+                })?
+                .push(entry);
+        }
+
+        Ok(Self {
+            centroids,
+            lists,
+            entries,
+            payload,
+        })
+    }
+
+    fn num_entries(&self) -> usize {
+        self.entries.rows.len()
+    }
+
+    fn search(
+        &self,
+        query: &[f32],
+        limit: Option<usize>,
+        max_distance: Option<f64>,
+    ) -> Result<SearchResult, ArrowError> {
+        let dim = self.entries.dim;
+        if query.len() != dim {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "Expected a query vector of length {dim}, got {}",
+                query.len()
+            )));
+        }
+
+        let mut ranked_lists = self
+            .centroids
+            .chunks_exact(dim)
+            .map(|c| squared_l2(c, query))
+            .enumerate()
+            .collect::<Vec<_>>();
+        ranked_lists.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        let probes = (self.lists.len() / 16).max(MIN_PROBES);
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut nearest = BinaryHeap::new();
+        for (list, _) in ranked_lists.into_iter().take(probes) {
+            for &entry in &self.lists[list] {
+                let start = entry as usize * dim;
+                let distance = squared_l2(&self.entries.vectors[start..start + dim], query);
+                if max_distance.is_some_and(|max| f64::from(distance) >= max) {
+                    continue;
+                }
+
+                if nearest.len() < limit {
+                    nearest.push(Candidate { distance, entry });
+                } else if nearest
+                    .peek()
+                    .is_some_and(|furthest: &Candidate| distance < furthest.distance)
+                {
+                    nearest.pop();
+                    nearest.push(Candidate { distance, entry });
+                }
+            }
+        }
+
+        let nearest = nearest.into_sorted_vec();
+        let distances = nearest.iter().map(|c| f64::from(c.distance)).collect();
+        let offsets = self
+            .entries
+            .offsets
+            .as_ref()
+            .map(|offsets| nearest.iter().map(|c| offsets[c.entry as usize]).collect());
+        let indices = UInt32Array::from_iter_values(
+            nearest.iter().map(|c| self.entries.rows[c.entry as usize]),
+        );
+        let columns = self
+            .payload
+            .columns()
+            .iter()
+            .map(|c| take(c, &indices, None))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SearchResult {
+            payload: RecordBatch::try_new(self.payload.schema(), columns)?,
+            distances,
+            offsets,
+        })
+    }
+
+    /// Write one row per entry, with its payload, vector, list assignment and offsets, as an Arrow
+    /// IPC file. Centroids are recomputed from the assignments on load.
+    fn persist(&self, path: &Path) -> Result<(), ArrowError> {
+        let dim = i32::try_from(self.entries.dim).map_err(|_| {
+            ArrowError::InvalidArgumentError(format!("Invalid embedding size {}", self.entries.dim))
+        })?;
+
+        let mut assignments = vec![0; self.num_entries()];
+        for (list, entries) in self.lists.iter().enumerate() {
+            let list = u32::try_from(list).map_err(|_| {
+                ArrowError::InvalidArgumentError("Too many vector index lists".to_string())
+            })?;
+            for &entry in entries {
+                assignments[entry as usize] = list;
+            }
+        }
+
+        let rows = UInt32Array::from(self.entries.rows.clone());
+        let mut fields = self.payload.schema().fields().to_vec();
+        let mut columns = self
+            .payload
+            .columns()
+            .iter()
+            .map(|c| take(c, &rows, None))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        fields.push(Arc::new(Field::new(
+            EMBEDDING_FIELD,
+            DataType::new_fixed_size_list(DataType::Float32, dim, false),
+            false,
+        )));
+        columns.push(Arc::new(FixedSizeListArray::try_new(
+            Arc::new(Field::new("item", DataType::Float32, false)),
+            dim,
+            Arc::new(Float32Array::from(self.entries.vectors.clone())),
+            None,
+        )?));
+
+        fields.push(Arc::new(Field::new(LIST_FIELD, DataType::UInt32, false)));
+        columns.push(Arc::new(UInt32Array::from(assignments)));
+
+        fields.push(Arc::new(Field::new(ROW_FIELD, DataType::UInt32, false)));
+        columns.push(Arc::new(rows));
+
+        if let Some(offsets) = &self.entries.offsets {
+            fields.push(Arc::new(Field::new(
+                OFFSET_FIELD,
+                offset_data_type(),
+                false,
+            )));
+            columns.push(Arc::new(offset_array(offsets)?));
+        }
+
+        let schema = Arc::new(Schema::new(fields).with_metadata(HashMap::from([(
+            NUM_LISTS_METADATA_KEY.to_string(),
+            self.lists.len().to_string(),
+        )])));
+        let batch = RecordBatch::try_new(Arc::clone(&schema), columns)?;
+
+        // Write to a temporary file first so a crash never leaves a truncated index behind.
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = FileWriter::try_new(File::create(&tmp_path)?, &schema)?;
+        writer.write(&batch)?;
+        writer.finish()?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    fn load(path: &Path, payload_columns: &[String], chunked: bool) -> Result<Self, ArrowError> {
+        let reader = FileReader::try_new(File::open(path)?, None)?;
+        let schema = reader.schema();
+        let num_lists: usize = schema
+            .metadata()
+            .get(NUM_LISTS_METADATA_KEY)
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| {
+                ArrowError::ParseError(format!("Missing '{NUM_LISTS_METADATA_KEY}' metadata"))
+            })?;
+
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        let batch = concat_batches(&schema, &batches)?;
+
+        let embedding_idx = schema.index_of(EMBEDDING_FIELD)?;
+        let persisted_columns = schema.fields()[..embedding_idx]
+            .iter()
+            .map(|f| f.name().clone())
+            .collect::<Vec<_>>();
+        if persisted_columns != payload_columns || schema.index_of(OFFSET_FIELD).is_ok() != chunked
+        {
+            return Err(ArrowError::SchemaError(format!(
+                "Persisted index has columns [{}], expected [{}]",
+                persisted_columns.join(", "),
+                payload_columns.join(", ")
+            )));
+        }
+
+        let assignments = u32_values(&batch, LIST_FIELD)?
+            .iter()
+            .map(|&l| l as usize)
+            .collect::<Vec<_>>();
+        if assignments.iter().any(|&l| l >= num_lists) {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "Expected list assignments below {num_lists}"
+            )));
+        }
+
+        // Entries of the same row were persisted with a copy of the row's payload, keep the first.
+        let mut first_entries = Vec::new();
+        let mut rows = Vec::with_capacity(assignments.len());
+        let mut row_ids = HashMap::new();
+        for (entry, &row) in u32_values(&batch, ROW_FIELD)?.iter().enumerate() {
+            let next_id = u32::try_from(first_entries.len()).map_err(|_| {
+                ArrowError::InvalidArgumentError("Too many rows for a vector index".to_string())
+            })?;
+            let id = *row_ids.entry(row).or_insert_with(|| {
+                first_entries.push(entry as u64);
+                next_id
+            });
+            rows.push(id);
+        }
+        let first_entries = UInt64Array::from(first_entries);
+        let payload = RecordBatch::try_new(
+            Arc::new(Schema::new(schema.fields()[..embedding_idx].to_vec())),
+            batch.columns()[..embedding_idx]
+                .iter()
+                .map(|c| take(c, &first_entries, None))
+                .collect::<Result<Vec<_>, _>>()?,
+        )?;
+
+        let (dim, vectors) = embedding_values(batch.column(embedding_idx))?;
+        let offsets = if chunked {
+            let offsets = batch.column(schema.index_of(OFFSET_FIELD)?);
+            Some(offset_values(offsets)?)
+        } else {
+            None
+        };
+
+        let (centroids, _) = mean_centroids(&vectors, dim, &assignments, num_lists);
+        Self::try_new(
+            centroids,
+            &assignments,
+            Entries {
+                dim,
+                vectors,
+                rows,
+                offsets,
+            },
+            payload,
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Candidate {
+    distance: f32,
+    entry: u32,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.entry.cmp(&other.entry))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// One entry per non-null row of a `FixedSizeList` (or equal-length `List`) embedding column.
+fn row_entries(embeddings: &ArrayRef) -> Result<Entries, ArrowError> {
+    let (dim, values) = embedding_values(embeddings)?;
+
+    let mut vectors = Vec::with_capacity(values.len());
+    let mut rows = Vec::with_capacity(embeddings.len());
+    for (row, vector) in values.chunks_exact(dim).enumerate() {
+        if embeddings.is_valid(row) {
+            vectors.extend_from_slice(vector);
+            rows.push(u32::try_from(row).map_err(|_| {
+                ArrowError::InvalidArgumentError("Too many rows for a vector index".to_string())
+            })?);
+        }
+    }
+
+    Ok(Entries {
+        dim,
+        vectors,
+        rows,
+        offsets: None,
+    })
+}
+
+/// One entry per chunk of a `List<FixedSizeList>` embedding column, with the chunk's offsets
+/// from the matching `List<FixedSizeList<Int32, 2>>` offset column.
+fn chunk_entries(embeddings: &ArrayRef, offsets: &ArrayRef) -> Result<Entries, ArrowError> {
+    let (Some(embeddings), Some(offsets)) = (
+        embeddings.as_list_opt::<i32>(),
+        offsets.as_list_opt::<i32>(),
+    ) else {
+        return Err(ArrowError::SchemaError(
+            "Expected chunked embeddings and offsets to be lists".to_string(),
+        ));
+    };
+
+    let (dim, values) = embedding_values(embeddings.values())?;
+    let offset_values = offset_values(offsets.values())?;
+
+    let mut entries = Entries {
+        dim,
+        vectors: Vec::new(),
+        rows: Vec::new(),
+        offsets: Some(Vec::new()),
+    };
+    let chunk_offsets = entries.offsets.get_or_insert_with(Vec::new);
+    for row in 0..embeddings.len() {
+        if embeddings.is_null(row) {
+            continue;
+        }
+        let chunks = list_range(embeddings.value_offsets(), row);
+        let offset_range = list_range(offsets.value_offsets(), row);
+        if offset_range.len() != chunks.len() {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "Expected one offset per chunk in row {row}"
+            )));
+        }
+
+        let row = u32::try_from(row).map_err(|_| {
+            ArrowError::InvalidArgumentError("Too many rows for a vector index".to_string())
+        })?;
+        for (i, chunk) in chunks.enumerate() {
+            let vector = values.get(chunk * dim..(chunk + 1) * dim).ok_or_else(|| {
+                ArrowError::InvalidArgumentError(
+                    "Embedding values are shorter than expected".to_string(),
+                )
+            })?;
+            let offset = offset_values.get(offset_range.start + i).ok_or_else(|| {
+                ArrowError::InvalidArgumentError(
+                    "Offset values are shorter than expected".to_string(),
+                )
+            })?;
+
+            entries.vectors.extend_from_slice(vector);
+            entries.rows.push(row);
+            chunk_offsets.push(*offset);
+        }
+    }
+
+    Ok(entries)
+}
+
+fn list_range(value_offsets: &[i32], row: usize) -> std::ops::Range<usize> {
+    let offset = |i: usize| usize::try_from(value_offsets[i]).unwrap_or_default();
+    offset(row)..offset(row + 1)
+}
+
+/// Flatten a `FixedSizeList` (or equal-length `List`) embedding column into its size and values.
+fn embedding_values(array: &ArrayRef) -> Result<(usize, Vec<f32>), ArrowError> {
+    let array = match array.data_type() {
+        DataType::FixedSizeList(_, _) => Arc::clone(array),
+        DataType::List(field) if !array.is_empty() => {
+            let size = array.as_list::<i32>().value_length(0);
+            cast(array, &DataType::FixedSizeList(Arc::clone(field), size))?
+        }
+        data_type => {
+            return Err(ArrowError::SchemaError(format!(
+                "Expected embeddings to be a list of floats, got {data_type}"
+            )))
+        }
+    };
+
+    let list = array.as_fixed_size_list();
+    let dim = usize::try_from(list.value_length())
+        .ok()
+        .filter(|&dim| dim > 0)
+        .ok_or_else(|| ArrowError::InvalidArgumentError("Invalid embedding size".to_string()))?;
+    let values = cast(list.values(), &DataType::Float32)?;
+    let values = values.as_primitive::<Float32Type>().values();
+
+    let start = if list.is_empty() {
+        0
+    } else {
+        usize::try_from(list.value_offset(0)).unwrap_or_default()
+    };
+    let end = start + list.len() * dim;
+    let values = values.get(start..end).ok_or_else(|| {
+        ArrowError::InvalidArgumentError("Embedding values are shorter than expected".to_string())
+    })?;
+
+    Ok((dim, values.to_vec()))
+}
+
+/// The `[start, end)` pairs of a `FixedSizeList<Int32, 2>` offset array.
+fn offset_values(array: &ArrayRef) -> Result<Vec<[i32; 2]>, ArrowError> {
+    let pairs = array
+        .as_fixed_size_list_opt()
+        .filter(|pairs| pairs.value_length() == 2)
+        .ok_or_else(|| {
+            ArrowError::SchemaError("Expected chunk offsets to be pairs of integers".to_string())
+        })?;
+    let values = cast(pairs.values(), &DataType::Int32)?;
+    let values = values.as_primitive::<Int32Type>().values();
+
+    (0..pairs.len())
+        .map(|i| {
+            let start = usize::try_from(pairs.value_offset(i)).unwrap_or_default();
+            match values.get(start..start + 2) {
+                Some(&[start, end]) => Ok([start, end]),
+                _ => Err(ArrowError::InvalidArgumentError(
+                    "Offset values are shorter than expected".to_string(),
+                )),
+            }
+        })
+        .collect()
+}
+
+fn offset_data_type() -> DataType {
+    DataType::new_fixed_size_list(DataType::Int32, 2, false)
+}
+
+fn offset_array(offsets: &[[i32; 2]]) -> Result<FixedSizeListArray, ArrowError> {
+    FixedSizeListArray::try_new(
+        Arc::new(Field::new("item", DataType::Int32, false)),
+        2,
+        Arc::new(Int32Array::from(offsets.concat())),
+        None,
+    )
+}
+
+fn u32_values<'a>(batch: &'a RecordBatch, column: &str) -> Result<&'a [u32], ArrowError> {
+    Ok(batch
+        .column(batch.schema().index_of(column)?)
+        .as_primitive_opt::<UInt32Type>()
+        .ok_or_else(|| ArrowError::SchemaError(format!("Expected '{column}' to be UInt32")))?
+        .values())
+}
+
+/// Train `num_lists` centroids with Lloyd's algorithm on an evenly spaced sample of `vectors`.
+fn kmeans(vectors: &[f32], dim: usize, num_lists: usize) -> Vec<f32> {
+    let rows = vectors.len() / dim;
+    let step = (rows / (num_lists * TRAINING_SAMPLES_PER_LIST)).max(1);
+    let sample = vectors
+        .chunks_exact(dim)
+        .step_by(step)
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>();
+
+    let mut centroids = sample
+        .chunks_exact(dim)
+        .step_by((sample.len() / dim / num_lists).max(1))
+        .take(num_lists)
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>();
+    let num_lists = centroids.len() / dim;
+
+    for _ in 0..TRAINING_ITERATIONS {
+        let assignments = sample
+            .chunks_exact(dim)
+            .map(|v| nearest_centroid(&centroids, dim, v))
+            .collect::<Vec<_>>();
+        let (means, counts) = mean_centroids(&sample, dim, &assignments, num_lists);
+
+        // Keep the previous centroid for lists that ended up empty.
+        for (list, mean) in means.chunks_exact(dim).enumerate() {
+            if counts[list] > 0 {
+                centroids[list * dim..(list + 1) * dim].copy_from_slice(mean);
+            }
+        }
+    }
+
+    centroids
+}
+
+/// Compute the mean of the vectors assigned to each list, along with the number of vectors in each list.
+#[allow(clippy::cast_precision_loss)]
+fn mean_centroids(
+    vectors: &[f32],
+    dim: usize,
+    assignments: &[usize],
+    num_lists: usize,
+) -> (Vec<f32>, Vec<usize>) {
+    let mut sums = vec![0.0_f32; num_lists * dim];
+    let mut counts = vec![0_usize; num_lists];
+    for (vector, &list) in vectors.chunks_exact(dim).zip(assignments) {
+        counts[list] += 1;
+        for (sum, value) in sums[list * dim..(list + 1) * dim].iter_mut().zip(vector) {
+            *sum += value;
+        }
+    }
+
+    for (centroid, &count) in sums.chunks_exact_mut(dim).zip(&counts) {
+        if count > 0 {
+            for v in centroid.iter_mut() {
+                *v /= count as f32;
+            }
+        }
+    }
+    (sums, counts)
+}
+
+fn nearest_centroid(centroids: &[f32], dim: usize, vector: &[f32]) -> usize {
+    centroids
+        .chunks_exact(dim)
+        .map(|c| squared_l2(c, vector))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(idx, _)| idx)
+}
+
+fn squared_l2(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Int64Array, StringArray};
+
+    use super::*;
+
+    fn test_batch(rows: usize) -> (SchemaRef, RecordBatch) {
+        let ids = (0..rows).map(|i| i64::try_from(i).unwrap_or_default());
+        let texts = (0..rows).map(|i| format!("row {i}")).collect::<Vec<_>>();
+        #[allow(clippy::cast_precision_loss)]
+        let values = (0..rows)
+            .flat_map(|i| [(i % 97) as f32, (i / 97) as f32])
+            .collect::<Vec<_>>();
+
+        let item = Arc::new(Field::new("item", DataType::Float32, false));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("text", DataType::Utf8, false),
+            Field::new(
+                "text_embedding",
+                DataType::FixedSizeList(Arc::clone(&item), 2),
+                true,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(Int64Array::from_iter_values(ids)),
+                Arc::new(StringArray::from(texts)),
+                Arc::new(
+                    FixedSizeListArray::try_new(
+                        item,
+                        2,
+                        Arc::new(Float32Array::from(values)),
+                        None,
+                    )
+                    .expect("valid embeddings"),
+                ),
+            ],
+        )
+        .expect("valid batch");
+        (schema, batch)
+    }
+
+    fn ids(batch: &RecordBatch) -> Vec<i64> {
+        batch
+            .column(0)
+            .as_primitive::<arrow::datatypes::Int64Type>()
+            .values()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_search_matches_exhaustive_search() {
+        let (schema, batch) = test_batch(10_000);
+        let ivf = IvfFlat::build(&schema, &[batch], false, true, None).expect("index is built");
+        assert!(ivf.lists.len() > 1);
+
+        // Row 5 * 97 + 40 has the embedding [40, 5].
+        let result = ivf
+            .search(&[40.2, 5.1], Some(3), None)
+            .expect("search succeeds");
+        assert_eq!(result.payload.num_columns(), 2);
+        assert_eq!(
+            ids(&result.payload),
+            vec![5 * 97 + 40, 5 * 97 + 41, 6 * 97 + 40]
+        );
+        assert!(result.distances.windows(2).all(|d| d[0] <= d[1]));
+        assert!((result.distances[0] - 0.05).abs() < 1e-3);
+        assert!(result.offsets.is_none());
+
+        // Only rows within the threshold are returned.
+        let result = ivf
+            .search(&[40.2, 5.1], None, Some(1.0))
+            .expect("search succeeds");
+        assert_eq!(
+            ids(&result.payload),
+            vec![5 * 97 + 40, 5 * 97 + 41, 6 * 97 + 40]
+        );
+        assert!(result.distances.iter().all(|&d| d < 1.0));
+
+        let result = ivf
+            .search(&[40.2, 5.1], Some(1), Some(1.0))
+            .expect("search succeeds");
+        assert_eq!(ids(&result.payload), vec![5 * 97 + 40]);
+    }
+
+    #[test]
+    fn test_rebuild_reuses_centroids() {
+        let (schema, batch) = test_batch(10_000);
+        let ivf = IvfFlat::build(&schema, &[batch], false, true, None).expect("index is built");
+
+        let (schema, batch) = test_batch(12_000);
+        let rebuilt =
+            IvfFlat::build(&schema, &[batch], false, true, Some(&ivf)).expect("index is rebuilt");
+        assert_eq!(rebuilt.centroids, ivf.centroids);
+        assert_eq!(rebuilt.num_entries(), 12_000);
+        assert_eq!(
+            ids(&rebuilt
+                .search(&[40.2, 5.1], Some(1), None)
+                .expect("search succeeds")
+                .payload),
+            vec![5 * 97 + 40]
+        );
+
+        // Centroids are retrained once the table has grown too much for them.
+        let (schema, batch) = test_batch(50_000);
+        let rebuilt =
+            IvfFlat::build(&schema, &[batch], false, true, Some(&ivf)).expect("index is rebuilt");
+        assert!(rebuilt.lists.len() > ivf.lists.len());
+    }
+
+    #[tokio::test]
+    async fn test_read_rows() {
+        let (schema, batch) = test_batch(10);
+        let table = datafusion::datasource::MemTable::try_new(schema, vec![vec![batch]])
+            .expect("valid table");
+
+        let result = SearchResult {
+            payload: RecordBatch::try_from_iter(vec![(
+                "id",
+                Arc::new(Int64Array::from(vec![3, 42, 1])) as ArrayRef,
+            )])
+            .expect("valid batch"),
+            distances: vec![0.1, 0.2, 0.3],
+            offsets: Some(vec![[0, 1], [0, 2], [0, 3]]),
+        };
+        let result = read_rows(
+            Arc::new(table),
+            result,
+            &["text".to_string(), "id".to_string()],
+        )
+        .await
+        .expect("rows are read");
+
+        // Row 42 doesn't exist, the other results keep their order.
+        assert_eq!(
+            result
+                .payload
+                .column(0)
+                .as_string::<i32>()
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![Some("row 3"), Some("row 1")]
+        );
+        assert_eq!(
+            result
+                .payload
+                .column(1)
+                .as_primitive::<arrow::datatypes::Int64Type>()
+                .values()
+                .to_vec(),
+            vec![3, 1]
+        );
+        assert_eq!(result.distances, vec![0.1, 0.3]);
+        assert_eq!(result.offsets, Some(vec![[0, 1], [0, 3]]));
+    }
+
+    #[test]
+    fn test_persist_and_load() {
+        let (schema, batch) = test_batch(5_000);
+        let ivf = IvfFlat::build(&schema, &[batch], false, true, None).expect("index is built");
+
+        let path = std::env::temp_dir().join(format!("{}.ann", uuid::Uuid::new_v4()));
+        ivf.persist(&path).expect("index is persisted");
+
+        let columns = vec!["id".to_string(), "text".to_string()];
+        let loaded = IvfFlat::load(&path, &columns, false).expect("index is loaded");
+        assert_eq!(loaded.num_entries(), ivf.num_entries());
+        assert_eq!(loaded.lists, ivf.lists);
+        assert_eq!(
+            ids(&loaded
+                .search(&[1.0, 1.0], Some(1), None)
+                .expect("search succeeds")
+                .payload),
+            vec![97 + 1]
+        );
+
+        assert!(IvfFlat::load(&path, &["text".to_string()], false).is_err());
+        assert!(IvfFlat::load(&path, &columns, true).is_err());
+
+        std::fs::remove_file(&path).expect("index file is removed");
+    }
+
+    #[test]
+    fn test_chunked_search() {
+        // Row 0 has chunks [0, 0] and [5, 5], row 1 is null and row 2 has the chunk [2, 2].
+        let chunk_item = Arc::new(Field::new("item", DataType::Float32, false));
+        let chunks = FixedSizeListArray::try_new(
+            Arc::clone(&chunk_item),
+            2,
+            Arc::new(Float32Array::from(vec![0.0, 0.0, 5.0, 5.0, 2.0, 2.0])),
+            None,
+        )
+        .expect("valid chunks");
+        let embeddings = arrow::array::ListArray::try_new(
+            Arc::new(Field::new("item", chunks.data_type().clone(), false)),
+            arrow::buffer::OffsetBuffer::new(vec![0, 2, 2, 3].into()),
+            Arc::new(chunks),
+            Some(vec![true, false, true].into()),
+        )
+        .expect("valid embeddings");
+
+        let offset_pairs = offset_array(&[[0, 4], [5, 9], [0, 3]]).expect("valid offsets");
+        let offsets = arrow::array::ListArray::try_new(
+            Arc::new(Field::new("item", offset_data_type(), false)),
+            arrow::buffer::OffsetBuffer::new(vec![0, 2, 2, 3].into()),
+            Arc::new(offset_pairs),
+            Some(vec![true, false, true].into()),
+        )
+        .expect("valid offsets");
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("text_embedding", embeddings.data_type().clone(), true),
+            Field::new("text_offset", offsets.data_type().clone(), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(Int64Array::from(vec![10, 11, 12])),
+                Arc::new(embeddings),
+                Arc::new(offsets),
+            ],
+        )
+        .expect("valid batch");
+
+        let result = exhaustive_search(
+            &schema,
+            std::slice::from_ref(&batch),
+            true,
+            &[4.0, 4.0],
+            None,
+            None,
+        )
+        .expect("search succeeds");
+        assert_eq!(ids(&result.payload), vec![10, 12, 10]);
+        assert_eq!(result.offsets, Some(vec![[5, 9], [0, 3], [0, 4]]));
+
+        let ivf = IvfFlat::build(&schema, &[batch], true, true, None).expect("index is built");
+        let path = std::env::temp_dir().join(format!("{}.ann", uuid::Uuid::new_v4()));
+        ivf.persist(&path).expect("index is persisted");
+
+        let loaded = IvfFlat::load(&path, &["id".to_string()], true).expect("index is loaded");
+        assert_eq!(loaded.payload.num_rows(), 2);
+        let result = loaded
+            .search(&[1.0, 1.0], Some(2), None)
+            .expect("search succeeds");
+        assert_eq!(ids(&result.payload), vec![10, 12]);
+        assert_eq!(result.offsets, Some(vec![[0, 4], [0, 3]]));
+
+        std::fs::remove_file(&path).expect("index file is removed");
+    }
+}
@@ -0,0 +1,630 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Embeddings that have already been computed and written to an accelerator. They are reused for
+//! unchanged column values, so that only new or changed values are sent to the embedding model.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+
+use arrow::array::{Array, AsArray, FixedSizeListArray, RecordBatch};
+use arrow::datatypes::{Float32Type, Int32Type};
+use datafusion::datasource::TableProvider;
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::SessionContext;
+use datafusion::prelude::{ident, lit};
+use snafu::prelude::*;
+use tokio::sync::Mutex;
+
+use crate::component::dataset::Dataset;
+use crate::dataaccelerator::metadata::AcceleratedMetadata;
+
+/// Values are looked up in the accelerator this many at a time.
+const LOOKUP_BATCH_SIZE: usize = 1024;
+
+/// The approximate size in bytes of the embeddings kept in memory per column, including the values
+/// they are keyed by. The oldest are evicted first.
+const MAX_CACHED_EMBEDDING_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to read materialized embeddings for column '{column}': {source}"))]
+    UnableToReadEmbeddings {
+        column: String,
+        source: DataFusionError,
+    },
+
+    #[snafu(display(
+        "Unable to record how the materialized embeddings were computed for dataset '{dataset}': {source}"
+    ))]
+    UnableToRecordFingerprints {
+        dataset: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The embedding of a single column value: one vector, or one vector per chunk along with the
+/// chunks' `[start, end)` byte offsets if the column is chunked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Embedded {
+    pub vectors: Vec<Vec<f32>>,
+    pub offsets: Vec<[i32; 2]>,
+}
+
+/// An embedded column of an [`super::table::EmbeddingTable`], as needed to read its materialized
+/// embeddings.
+#[derive(Debug, Clone)]
+pub struct EmbeddedColumn {
+    pub name: String,
+    pub size: i32,
+    pub chunked: bool,
+
+    /// The embedding model and chunking the column's embeddings are computed with. Embeddings
+    /// computed with a different fingerprint are not reused.
+    pub fingerprint: String,
+}
+
+/// Embeddings of column values, looked up in the accelerator they were written to. Only the values
+/// being embedded are read from the accelerator, and a bounded number of recently used embeddings
+/// is kept in memory per column.
+#[derive(Default)]
+pub struct MaterializedEmbeddings {
+    accelerator: RwLock<Option<(Arc<dyn TableProvider>, HashMap<String, EmbeddedColumn>)>>,
+    cached: RwLock<HashMap<String, EmbeddingCache>>,
+}
+
+impl MaterializedEmbeddings {
+    /// Look up embeddings in `table` from now on. Columns are not looked up, and will be
+    /// re-embedded, if `table` has no embedding column for them or if their embeddings were
+    /// computed with a different model or chunking: `recorded` maps columns to the fingerprints of
+    /// their embeddings in `table`, or is `None` if `table` only holds embeddings computed by this
+    /// [`super::table::EmbeddingTable`].
+    pub fn set_accelerator(
+        &self,
+        table: Arc<dyn TableProvider>,
+        columns: &[EmbeddedColumn],
+        recorded: Option<&HashMap<String, String>>,
+    ) {
+        let schema = table.schema();
+        let columns = columns
+            .iter()
+            .filter(|column| {
+                recorded.map_or(true, |recorded| {
+                    recorded.get(&column.name) == Some(&column.fingerprint)
+                })
+            })
+            .filter(|column| {
+                embedding_columns(column)
+                    .iter()
+                    .all(|c| schema.column_with_name(c).is_some())
+            })
+            .map(|column| (column.name.clone(), column.clone()))
+            .collect();
+
+        if let Ok(mut accelerator) = self.accelerator.write() {
+            *accelerator = Some((table, columns));
+        }
+    }
+
+    /// Return the known embeddings of `values` of `column`, from memory or from the accelerator.
+    /// Values without a known embedding are not returned.
+    pub async fn lookup(
+        &self,
+        column: &str,
+        values: &[&str],
+    ) -> Result<HashMap<String, Arc<Embedded>>> {
+        let mut found = HashMap::with_capacity(values.len());
+        let mut missing = Vec::new();
+        if let Ok(cached) = self.cached.read() {
+            let cached = cached.get(column);
+            for &value in values {
+                match cached.and_then(|c| c.values.get(value)) {
+                    Some(embedded) => {
+                        found.insert(value.to_string(), Arc::clone(embedded));
+                    }
+                    None => missing.push(value),
+                }
+            }
+        }
+
+        let Some((table, embedded_column)) = self.accelerator.read().ok().and_then(|a| {
+            let (table, columns) = a.as_ref()?;
+            Some((Arc::clone(table), columns.get(column)?.clone()))
+        }) else {
+            return Ok(found);
+        };
+
+        let ctx = SessionContext::new();
+        for values in missing.chunks(LOOKUP_BATCH_SIZE) {
+            let batches = ctx
+                .read_table(Arc::clone(&table))
+                .and_then(|df| {
+                    df.filter(
+                        ident(column).in_list(values.iter().map(|v| lit(*v)).collect(), false),
+                    )
+                })
+                .and_then(|df| {
+                    df.select_columns(
+                        &embedding_columns(&embedded_column)
+                            .iter()
+                            .map(String::as_str)
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .context(UnableToReadEmbeddingsSnafu { column })?
+                .collect()
+                .await
+                .context(UnableToReadEmbeddingsSnafu { column })?;
+
+            let mut loaded = HashMap::new();
+            for batch in &batches {
+                read_embeddings(batch, &embedded_column, &mut loaded);
+            }
+            for (value, embedded) in loaded {
+                self.insert(column, value.clone(), Arc::clone(&embedded));
+                found.insert(value, embedded);
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Keep the embedding of a value of `column` in memory.
+    pub fn insert(&self, column: &str, value: String, embedded: Arc<Embedded>) {
+        let Ok(mut cached) = self.cached.write() else {
+            return;
+        };
+        cached
+            .entry(column.to_string())
+            .or_default()
+            .insert(value, embedded);
+    }
+}
+
+/// The fingerprints of the materialized embeddings of a dataset's columns, recorded in its
+/// accelerator's metadata so that embeddings computed with a previous model or chunking are not
+/// reused after the dataset's configuration changes.
+pub struct EmbeddingFingerprints {
+    dataset: Dataset,
+    recorded: Mutex<Option<HashMap<String, String>>>,
+}
+
+impl EmbeddingFingerprints {
+    #[must_use]
+    pub fn new(dataset: Dataset) -> Self {
+        Self {
+            dataset,
+            recorded: Mutex::new(None),
+        }
+    }
+
+    /// The fingerprints recorded for the accelerator, or `None` if its engine doesn't store
+    /// metadata, i.e. it is in memory and only holds embeddings computed by this runtime.
+    pub async fn recorded(&self) -> Option<HashMap<String, String>> {
+        let mut recorded = self.recorded.lock().await;
+        if recorded.is_none() {
+            let metadata = AcceleratedMetadata::new(&self.dataset).await?;
+            *recorded = Some(
+                metadata
+                    .for_entry(self.entry())
+                    .get_metadata()
+                    .await
+                    .unwrap_or_default(),
+            );
+        }
+        recorded.clone()
+    }
+
+    /// Record the fingerprints of `columns` after their embeddings were written to the
+    /// accelerator. Unless the accelerator was `overwritten`, it may still hold embeddings of
+    /// earlier writes, so the fingerprints already recorded are kept.
+    pub async fn record(&self, columns: &[EmbeddedColumn], overwritten: bool) -> Result<()> {
+        let mut recorded = self.recorded.lock().await;
+        if let Some(current) = recorded.as_ref() {
+            if updated_fingerprints(current, columns, overwritten) == *current {
+                return Ok(());
+            }
+        }
+
+        let Ok(metadata) = AcceleratedMetadata::new_create_if_not_exists(&self.dataset).await
+        else {
+            // The accelerator's engine doesn't store metadata.
+            return Ok(());
+        };
+        let metadata = metadata.for_entry(self.entry());
+        let current = match recorded.take() {
+            Some(current) => current,
+            None => metadata.get_metadata().await.unwrap_or_default(),
+        };
+        let fingerprints = updated_fingerprints(&current, columns, overwritten);
+        if fingerprints != current {
+            metadata.set_metadata(&fingerprints).await.context(
+                UnableToRecordFingerprintsSnafu {
+                    dataset: self.dataset.name.to_string(),
+                },
+            )?;
+        }
+        *recorded = Some(fingerprints);
+
+        Ok(())
+    }
+
+    fn entry(&self) -> String {
+        format!("{}#embeddings", self.dataset.name)
+    }
+}
+
+fn updated_fingerprints(
+    recorded: &HashMap<String, String>,
+    columns: &[EmbeddedColumn],
+    overwritten: bool,
+) -> HashMap<String, String> {
+    let mut fingerprints = recorded.clone();
+    for column in columns {
+        if overwritten || !fingerprints.contains_key(&column.name) {
+            fingerprints.insert(column.name.clone(), column.fingerprint.clone());
+        }
+    }
+    fingerprints
+}
+
+/// The embeddings of one column's values, evicting the oldest once they take up more than
+/// [`MAX_CACHED_EMBEDDING_BYTES`].
+#[derive(Debug, Default)]
+struct EmbeddingCache {
+    values: HashMap<String, Arc<Embedded>>,
+    order: VecDeque<String>,
+    bytes: usize,
+}
+
+impl EmbeddingCache {
+    fn insert(&mut self, value: String, embedded: Arc<Embedded>) {
+        self.bytes += cached_size(&value, &embedded);
+        match self.values.insert(value.clone(), embedded) {
+            Some(previous) => self.bytes -= cached_size(&value, &previous),
+            None => self.order.push_back(value),
+        }
+        while self.bytes > MAX_CACHED_EMBEDDING_BYTES {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(embedded) = self.values.remove(&oldest) {
+                self.bytes -= cached_size(&oldest, &embedded);
+            }
+        }
+    }
+}
+
+/// The approximate size in bytes of a cached embedding and the value it is keyed by.
+fn cached_size(value: &str, embedded: &Embedded) -> usize {
+    value.len()
+        + embedded
+            .vectors
+            .iter()
+            .map(|v| v.len() * std::mem::size_of::<f32>())
+            .sum::<usize>()
+        + embedded.offsets.len() * std::mem::size_of::<[i32; 2]>()
+}
+
+/// The columns an embedding is read from: `[column, column_embedding, column_offset?]`.
+fn embedding_columns(column: &EmbeddedColumn) -> Vec<String> {
+    let mut columns = vec![column.name.clone(), format!("{}_embedding", column.name)];
+    if column.chunked {
+        columns.push(format!("{}_offset", column.name));
+    }
+    columns
+}
+
+/// Read the embeddings of a batch of `[column, column_embedding, column_offset?]` into `values`.
+/// Rows whose embedding does not have the expected shape are skipped.
+fn read_embeddings(
+    batch: &RecordBatch,
+    column: &EmbeddedColumn,
+    values: &mut HashMap<String, Arc<Embedded>>,
+) {
+    let Some(text) = batch.column(0).as_string_opt::<i32>() else {
+        return;
+    };
+
+    for row in 0..batch.num_rows() {
+        if text.is_null(row) || batch.column(1).is_null(row) {
+            continue;
+        }
+        let embedded = if column.chunked {
+            let (Some(vectors), Some(offsets)) = (
+                batch.column(1).as_list_opt::<i32>(),
+                batch.column(2).as_list_opt::<i32>(),
+            ) else {
+                return;
+            };
+            chunked_embedding(
+                vectors.value(row).as_fixed_size_list_opt(),
+                offsets.value(row).as_fixed_size_list_opt(),
+                column.size,
+            )
+        } else {
+            let Some(vectors) = batch.column(1).as_fixed_size_list_opt() else {
+                return;
+            };
+            vector(vectors, row, column.size).map(|v| Embedded {
+                vectors: vec![v],
+                offsets: Vec::new(),
+            })
+        };
+
+        if let Some(embedded) = embedded {
+            values.insert(text.value(row).to_string(), Arc::new(embedded));
+        }
+    }
+}
+
+fn chunked_embedding(
+    vectors: Option<&FixedSizeListArray>,
+    offsets: Option<&FixedSizeListArray>,
+    size: i32,
+) -> Option<Embedded> {
+    let (vectors, offsets) = (vectors?, offsets?);
+    if vectors.len() != offsets.len() || offsets.value_length() != 2 {
+        return None;
+    }
+    Some(Embedded {
+        vectors: (0..vectors.len())
+            .map(|i| vector(vectors, i, size))
+            .collect::<Option<_>>()?,
+        offsets: (0..offsets.len())
+            .map(|i| {
+                let offset = offsets.value(i);
+                let offset = offset.as_primitive_opt::<Int32Type>()?.values();
+                Some([offset[0], offset[1]])
+            })
+            .collect::<Option<_>>()?,
+    })
+}
+
+fn vector(vectors: &FixedSizeListArray, i: usize, size: i32) -> Option<Vec<f32>> {
+    if vectors.value_length() != size || vectors.is_null(i) {
+        return None;
+    }
+    Some(
+        vectors
+            .value(i)
+            .as_primitive_opt::<Float32Type>()?
+            .values()
+            .to_vec(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Float32Array, Int32Array, ListArray, StringArray};
+    use arrow::buffer::OffsetBuffer;
+    use arrow::datatypes::Field;
+    use datafusion::datasource::MemTable;
+
+    fn fixed_size_list(values: Arc<dyn Array>, size: i32) -> FixedSizeListArray {
+        FixedSizeListArray::try_new(
+            Arc::new(Field::new("item", values.data_type().clone(), false)),
+            size,
+            values,
+            None,
+        )
+        .expect("valid fixed size list")
+    }
+
+    #[test]
+    fn test_read_embeddings() {
+        let column = EmbeddedColumn {
+            name: "body".to_string(),
+            size: 2,
+            chunked: false,
+            fingerprint: "model".to_string(),
+        };
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "body",
+                Arc::new(StringArray::from(vec![Some("a"), None, Some("b")])) as Arc<dyn Array>,
+            ),
+            (
+                "body_embedding",
+                Arc::new(fixed_size_list(
+                    Arc::new(Float32Array::from(vec![1.0, 2.0, 0.0, 0.0, 3.0, 4.0])),
+                    2,
+                )),
+            ),
+        ])
+        .expect("valid batch");
+
+        let mut values = HashMap::new();
+        read_embeddings(&batch, &column, &mut values);
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["b"].vectors, vec![vec![3.0, 4.0]]);
+
+        // A different embedding size is not reused.
+        let mut values = HashMap::new();
+        read_embeddings(&batch, &EmbeddedColumn { size: 3, ..column }, &mut values);
+        assert!(values.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_lookup() {
+        let column = EmbeddedColumn {
+            name: "body".to_string(),
+            size: 2,
+            chunked: false,
+            fingerprint: "model".to_string(),
+        };
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "body",
+                Arc::new(StringArray::from(vec!["a", "b"])) as Arc<dyn Array>,
+            ),
+            (
+                "body_embedding",
+                Arc::new(fixed_size_list(
+                    Arc::new(Float32Array::from(vec![1.0, 2.0, 3.0, 4.0])),
+                    2,
+                )),
+            ),
+        ])
+        .expect("valid batch");
+        let table = MemTable::try_new(batch.schema(), vec![vec![batch]]).expect("valid table");
+
+        let table = Arc::new(table);
+        let materialized = MaterializedEmbeddings::default();
+        materialized.insert(
+            "body",
+            "c".to_string(),
+            Arc::new(Embedded {
+                vectors: vec![vec![5.0, 6.0]],
+                offsets: Vec::new(),
+            }),
+        );
+        materialized.set_accelerator(
+            Arc::clone(&table) as Arc<dyn TableProvider>,
+            &[column.clone()],
+            None,
+        );
+
+        // Only the requested values are read from the accelerator.
+        let found = materialized
+            .lookup("body", &["b", "c", "d"])
+            .await
+            .expect("embeddings looked up");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found["b"].vectors, vec![vec![3.0, 4.0]]);
+        assert_eq!(found["c"].vectors, vec![vec![5.0, 6.0]]);
+
+        // Columns without embeddings in the accelerator are only looked up in memory.
+        let found = materialized
+            .lookup("title", &["b"])
+            .await
+            .expect("embeddings looked up");
+        assert!(found.is_empty());
+
+        // Embeddings computed with another model are not reused.
+        let materialized = MaterializedEmbeddings::default();
+        materialized.set_accelerator(
+            table,
+            &[column],
+            Some(&HashMap::from([(
+                "body".to_string(),
+                "previous_model".to_string(),
+            )])),
+        );
+        let found = materialized
+            .lookup("body", &["b"])
+            .await
+            .expect("embeddings looked up");
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn test_updated_fingerprints() {
+        let column = |name: &str, fingerprint: &str| EmbeddedColumn {
+            name: name.to_string(),
+            size: 2,
+            chunked: false,
+            fingerprint: fingerprint.to_string(),
+        };
+        let recorded = HashMap::from([("body".to_string(), "previous_model".to_string())]);
+        let columns = [column("body", "model"), column("title", "model")];
+
+        // Appended data leaves the previous embeddings in place, so their fingerprint is kept.
+        let appended = updated_fingerprints(&recorded, &columns, false);
+        assert_eq!(appended["body"], "previous_model");
+        assert_eq!(appended["title"], "model");
+
+        let overwritten = updated_fingerprints(&recorded, &columns, true);
+        assert_eq!(overwritten["body"], "model");
+        assert_eq!(overwritten["title"], "model");
+    }
+
+    #[test]
+    fn test_embedding_cache_evicts_oldest() {
+        let embedded = Arc::new(Embedded {
+            vectors: vec![vec![1.0; 1024]],
+            offsets: Vec::new(),
+        });
+        let mut cache = EmbeddingCache::default();
+        let count = MAX_CACHED_EMBEDDING_BYTES / cached_size("0", &embedded) + 1;
+        for i in 0..count {
+            cache.insert(i.to_string(), Arc::clone(&embedded));
+        }
+
+        assert!(cache.bytes <= MAX_CACHED_EMBEDDING_BYTES);
+        assert!(!cache.values.contains_key("0"));
+        assert!(cache.values.contains_key(&(count - 1).to_string()));
+
+        // Replacing a value's embedding doesn't count it twice.
+        let bytes = cache.bytes;
+        cache.insert((count - 1).to_string(), Arc::clone(&embedded));
+        assert_eq!(cache.bytes, bytes);
+    }
+
+    #[test]
+    fn test_read_chunked_embeddings() {
+        let column = EmbeddedColumn {
+            name: "body".to_string(),
+            size: 1,
+            chunked: true,
+            fingerprint: "model; chunking: characters(size=2, overlap=0)".to_string(),
+        };
+        let list = |values: FixedSizeListArray| {
+            ListArray::try_new(
+                Arc::new(Field::new("item", values.data_type().clone(), false)),
+                OffsetBuffer::new(vec![0, 2, 3].into()),
+                Arc::new(values),
+                None,
+            )
+            .expect("valid list")
+        };
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "body",
+                Arc::new(StringArray::from(vec!["ab cd", "ef"])) as Arc<dyn Array>,
+            ),
+            (
+                "body_embedding",
+                Arc::new(list(fixed_size_list(
+                    Arc::new(Float32Array::from(vec![1.0, 2.0, 3.0])),
+                    1,
+                ))),
+            ),
+            (
+                "body_offset",
+                Arc::new(list(fixed_size_list(
+                    Arc::new(Int32Array::from(vec![0, 2, 3, 5, 0, 2])),
+                    2,
+                ))),
+            ),
+        ])
+        .expect("valid batch");
+
+        let mut values = HashMap::new();
+        read_embeddings(&batch, &column, &mut values);
+        assert_eq!(
+            values["ab cd"].as_ref(),
+            &Embedded {
+                vectors: vec![vec![1.0], vec![2.0]],
+                offsets: vec![[0, 2], [3, 5]],
+            }
+        );
+        assert_eq!(values["ef"].offsets, vec![[0, 2]]);
+    }
+}
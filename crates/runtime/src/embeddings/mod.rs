@@ -16,7 +16,45 @@ limitations under the License.
 #![allow(unused_attributes)] // This is for the `f16_and_f128` feature.
 #![feature(f16_and_f128)]
 pub mod array_distance;
+pub mod chunking;
 pub mod connector;
 pub mod execution_plan;
+pub mod full_text;
+pub mod index;
+pub mod materialized;
 pub mod table;
 pub mod vector_search;
+
+use std::{any::Any, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use datafusion::datasource::TableProvider;
+use tokio::{sync::Notify, time::Instant};
+
+/// Appends and changes mark an index stale for every batch written to the accelerator. Rebuilds
+/// start at most once per interval, so that a busy stream doesn't rebuild the index continuously.
+const MIN_REBUILD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// An index over the contents of an accelerated table that serves searches, such as a vector or
+/// full-text index. It is rebuilt in the background each time data is written to the accelerator.
+#[async_trait]
+pub trait SearchIndex: Send + Sync {
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+
+    /// Schedule a rebuild of the index. Multiple calls before the rebuild starts are coalesced.
+    fn mark_stale(&self);
+
+    /// Rebuild the index from `accelerator` each time it is marked stale, but at most once every 30
+    /// seconds. Runs until the task is aborted.
+    async fn start(self: Arc<Self>, accelerator: Arc<dyn TableProvider>);
+}
+
+/// Wait until the index is marked stale, and until [`MIN_REBUILD_INTERVAL`] has passed since the
+/// `previous` rebuild started.
+async fn next_rebuild(stale: &Notify, previous: &mut Option<Instant>) {
+    stale.notified().await;
+    if let Some(previous) = *previous {
+        tokio::time::sleep_until(previous + MIN_REBUILD_INTERVAL).await;
+    }
+    *previous = Some(Instant::now());
+}
@@ -17,6 +17,7 @@ limitations under the License.
 use std::collections::HashMap;
 use std::{any::Any, sync::Arc};
 
+use arrow::array::RecordBatch;
 use arrow::datatypes::{DataType, Schema, SchemaRef};
 use async_trait::async_trait;
 use datafusion::common::{project_schema, Constraints, Statistics};
@@ -33,7 +34,11 @@ use snafu::prelude::*;
 
 use tokio::sync::RwLock;
 
-use crate::embeddings::execution_plan::EmbeddingTableExec;
+use crate::embeddings::chunking::Chunker;
+use crate::embeddings::execution_plan::{embed_record_batch, EmbeddingTableExec};
+use crate::embeddings::materialized::{
+    EmbeddedColumn, EmbeddingFingerprints, MaterializedEmbeddings,
+};
 use crate::EmbeddingModelStore;
 
 #[derive(Debug, Snafu)]
@@ -52,6 +57,32 @@ pub struct EmbeddingTable {
     // Precompute to avoid async lock waits from `embedding_models` data structure.
     // Mapping of column name to the expected size of its embedding.
     embedding_sizes: HashMap<String, i32>,
+
+    // Columns that are split into chunks, each embedded separately.
+    chunkers: HashMap<String, Chunker>,
+
+    // Embeddings already written to the accelerator, reused for unchanged column values.
+    materialized: Arc<MaterializedEmbeddings>,
+
+    // The model and chunking of the embeddings written to the accelerator, if they are recorded.
+    fingerprints: Option<Arc<EmbeddingFingerprints>>,
+}
+
+/// The type of `{column}_embedding` for a chunked column: a list of each chunk's embedding.
+fn chunked_embedding_type(size: i32) -> DataType {
+    DataType::new_list(
+        DataType::new_fixed_size_list(DataType::Float32, size, false),
+        false,
+    )
+}
+
+/// The type of `{column}_offset` for a chunked column: a list of each chunk's `[start, end)` byte
+/// offsets within the column value.
+fn chunk_offset_type() -> DataType {
+    DataType::new_list(
+        DataType::new_fixed_size_list(DataType::Int32, 2, false),
+        false,
+    )
 }
 
 impl EmbeddingTable {
@@ -59,6 +90,7 @@ impl EmbeddingTable {
         base_table: Arc<dyn TableProvider>,
         embedded_columns: HashMap<String, String>,
         embedding_models: Arc<RwLock<EmbeddingModelStore>>,
+        chunkers: HashMap<String, Chunker>,
     ) -> Self {
         let sizes = Self::precompute_embedding_sizes(&embedded_columns, &embedding_models).await;
         Self {
@@ -66,19 +98,105 @@ impl EmbeddingTable {
             embedded_columns,
             embedding_models,
             embedding_sizes: sizes,
+            chunkers,
+            materialized: Arc::default(),
+            fingerprints: None,
         }
     }
 
+    /// Record the model and chunking of the embeddings written to the accelerator, so that they
+    /// are not reused once either changes.
+    #[must_use]
+    pub fn with_fingerprints(mut self, fingerprints: EmbeddingFingerprints) -> Self {
+        self.fingerprints = Some(Arc::new(fingerprints));
+        self
+    }
+
+    /// The table whose columns are augmented with embeddings.
+    #[must_use]
+    pub fn base_table(&self) -> Arc<dyn TableProvider> {
+        Arc::clone(&self.base_table)
+    }
+
     /// Get the names of the embedding models used by this table across its columns.
     #[must_use]
     pub fn get_embedding_models_used(&self) -> Vec<String> {
         self.embedded_columns.values().cloned().collect()
     }
 
-    /// Get the names of the columns that are augmented with embeddings.
+    /// Get the names of the columns that are augmented with embeddings, in alphabetical order.
     #[must_use]
     pub fn get_embedding_columns(&self) -> Vec<String> {
-        self.embedded_columns.keys().cloned().collect()
+        self.embedded_columns.keys().sorted().cloned().collect()
+    }
+
+    /// Whether the values of `column` are split into chunks. If so, `{column}_embedding` is a list
+    /// of the chunks' embeddings and `{column}_offset` a list of the chunks' offsets.
+    #[must_use]
+    pub fn is_chunked(&self, column: &str) -> bool {
+        self.chunkers.contains_key(column)
+    }
+
+    /// Look up the embeddings already written to `accelerator`, so that subsequent scans and
+    /// [`Self::embed_batch`] only embed column values that are new or have changed. Embeddings
+    /// recorded as computed with another model or chunking are not reused.
+    pub async fn reuse_materialized_embeddings(&self, accelerator: Arc<dyn TableProvider>) {
+        let recorded = match &self.fingerprints {
+            Some(fingerprints) => fingerprints.recorded().await,
+            None => None,
+        };
+        self.materialized.set_accelerator(
+            accelerator,
+            &self.materialized_columns(),
+            recorded.as_ref(),
+        );
+    }
+
+    /// Record the model and chunking of the embeddings just written to the accelerator. If the
+    /// accelerator was `overwritten`, it no longer holds embeddings computed differently.
+    pub async fn record_materialized_embeddings(&self, overwritten: bool) {
+        let Some(fingerprints) = &self.fingerprints else {
+            return;
+        };
+        if let Err(e) = fingerprints
+            .record(&self.materialized_columns(), overwritten)
+            .await
+        {
+            tracing::warn!("{e}");
+        }
+    }
+
+    fn materialized_columns(&self) -> Vec<EmbeddedColumn> {
+        self.embedded_columns
+            .iter()
+            .filter_map(|(name, model)| {
+                Some(EmbeddedColumn {
+                    name: name.clone(),
+                    size: *self.embedding_sizes.get(name)?,
+                    chunked: self.is_chunked(name),
+                    fingerprint: match self.chunkers.get(name) {
+                        Some(chunker) => format!("{model}; chunking: {}", chunker.description()),
+                        None => model.clone(),
+                    },
+                })
+            })
+            .collect_vec()
+    }
+
+    /// Add the embedding columns to `batch`, which has the schema of the base table.
+    pub async fn embed_batch(
+        &self,
+        batch: &RecordBatch,
+    ) -> Result<RecordBatch, Box<dyn std::error::Error + Send + Sync>> {
+        embed_record_batch(
+            batch,
+            &self.schema(),
+            &self.embedded_columns,
+            Arc::clone(&self.embedding_models),
+            &self.chunkers,
+            &self.materialized,
+        )
+        .await
     }
 
     async fn precompute_embedding_sizes(
@@ -104,11 +222,11 @@ impl EmbeddingTable {
     /// Any project index (in `projection`) that is greater than the number of columns in the base
     /// table is an embedding column. The relation of underlying column to embedding column is, for example, as follows:
     ///
-    /// | projection idx | 0 | 1 | 2 | 3 | 4 | 5 |      6      |      7      |
-    /// |  column name   | A | B | C | D | E | F | `B_embedding` | `E_embedding` |
+    /// | projection idx | 0 | 1 | 2 | 3 | 4 | 5 |      6      |      7      |     8      |
+    /// |  column name   | A | B | C | D | E | F | `B_embedding` | `E_embedding` | `E_offset` |
     ///
     ///     - 6 Base columns A, B, C, D, E, F
-    ///     - 2 Embedding columns B_embedding, E_embedding
+    ///     - 2 Embedding columns B_embedding, E_embedding, where E is chunked and so also has E_offset.
     ///     - Any projection index >=6 is an embedding column.
     ///
     /// The order of embedding columns in [`Self::Schema`] is alphabetical.
@@ -117,18 +235,19 @@ impl EmbeddingTable {
             None => self.embedded_columns.keys().cloned().collect_vec(),
             Some(column_idx) => {
                 let base_cols = self.base_table.schema().fields.len();
-                let x: Vec<_> = self.embedded_columns.keys().sorted().collect();
+                let schema = self.schema();
 
-                // Order of embedding columns in [`Self::Schema`] is alphabetical.
                 column_idx
                     .iter()
-                    .filter_map(|&c| {
-                        if c >= base_cols {
-                            x.get(c - base_cols).copied()
-                        } else {
-                            None
-                        }
+                    .filter(|&&c| c >= base_cols)
+                    .filter_map(|&c| schema.fields().get(c))
+                    .filter_map(|f| {
+                        self.embedded_columns.keys().find(|col| {
+                            *f.name() == format!("{col}_embedding")
+                                || *f.name() == format!("{col}_offset")
+                        })
                     })
+                    .unique()
                     .cloned()
                     .collect()
             }
@@ -186,7 +305,24 @@ impl TableProvider for EmbeddingTable {
                         .copied()
                         .unwrap_or_default();
 
-                    Some(Arc::new(
+                    if self.is_chunked(k) {
+                        return Some(vec![
+                            Arc::new(
+                                field
+                                    .clone()
+                                    .with_data_type(chunked_embedding_type(embedding_size))
+                                    .with_name(format!("{}_embedding", field.name())),
+                            ),
+                            Arc::new(
+                                field
+                                    .clone()
+                                    .with_data_type(chunk_offset_type())
+                                    .with_name(format!("{}_offset", field.name())),
+                            ),
+                        ]);
+                    }
+
+                    Some(vec![Arc::new(
                         field
                             .clone()
                             .with_data_type(DataType::new_fixed_size_list(
@@ -195,10 +331,11 @@ impl TableProvider for EmbeddingTable {
                                 false,
                             ))
                             .with_name(format!("{}_embedding", field.name())),
-                    ))
+                    )])
                 }
                 None => None,
             })
+            .flatten()
             .collect();
 
         base_fields.append(&mut embedding_fields);
@@ -277,6 +414,8 @@ impl TableProvider for EmbeddingTable {
             base_plan,
             scan_embed_columns,
             Arc::clone(&self.embedding_models),
+            self.chunkers.clone(),
+            Arc::clone(&self.materialized),
         )) as Arc<dyn ExecutionPlan>)
     }
 
@@ -14,17 +14,32 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use app::App;
-use arrow::array::{RecordBatch, StringArray};
+use arrow::array::{AsArray, RecordBatch, StringArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Float64Type, SchemaRef};
+use arrow::util::display::array_value_to_string;
 use async_openai::types::EmbeddingInput;
+use datafusion::sql::unparser::expr_to_sql;
 use datafusion::{common::Constraint, datasource::TableProvider, sql::TableReference};
-
+use futures::TryStreamExt;
+use spicepod::component::dataset::policy::Policy;
 use tokio::sync::RwLock;
 
-use crate::{accelerated_table::AcceleratedTable, datafusion::DataFusion, EmbeddingModelStore};
+use crate::{
+    accelerated_table::AcceleratedTable,
+    auth::{access_control::Permission, policy, Principal},
+    datafusion::{query::Protocol, DataFusion},
+    EmbeddingModelStore,
+};
 
+use super::full_text::{self, text_column_alias};
+use super::index::{self, read_rows, SearchResult};
 use super::table::EmbeddingTable;
 use snafu::prelude::*;
 
@@ -33,6 +48,14 @@ pub enum Error {
     #[snafu(display("Data source {} does not exist", data_source))]
     DataSourceNotFound { data_source: String },
 
+    #[snafu(display("Access denied to data source {}", data_source))]
+    AccessDenied { data_source: String },
+
+    #[snafu(display(
+        "Column {column} of data source {data_source} is masked, so it can't be searched by embedding"
+    ))]
+    MaskedColumn { data_source: String, column: String },
+
     #[snafu(display("Error occurred interacting with datafusion: {}", source))]
     DataFusionError {
         source: Box<dyn std::error::Error + Send + Sync>,
@@ -41,6 +64,9 @@ pub enum Error {
     #[snafu(display("Data source {} does not contain any embedding columns", data_source))]
     NoEmbeddingColumns { data_source: String },
 
+    #[snafu(display("Data source {} does not have full-text search enabled", data_source))]
+    NoFullTextColumns { data_source: String },
+
     #[snafu(display("Only one embedding column per table currently supported. Table: {data_source} has {num_embeddings} embeddings"))]
     IncorrectNumberOfEmbeddingColumns {
         data_source: String,
@@ -54,6 +80,12 @@ pub enum Error {
     EmbeddingError {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
+
+    #[snafu(display("Invalid search filter for data source {data_source}: {source}"))]
+    InvalidFilter {
+        data_source: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -63,17 +95,119 @@ pub struct VectorSearch {
     df: Arc<DataFusion>,
     embeddings: Arc<RwLock<EmbeddingModelStore>>,
     explicit_primary_keys: HashMap<TableReference, Vec<String>>,
+    full_text_columns: HashMap<TableReference, Vec<String>>,
 }
 
 pub enum RetrievalLimit {
+    /// Retrieve the `n` nearest rows.
     TopN(usize),
-    Threshold(f64),
+
+    /// Retrieve the rows whose distance is below `max_distance`, nearest first, up to an optional `limit`.
+    /// In hybrid search, the threshold applies to the vector ranking.
+    Threshold {
+        max_distance: f64,
+        limit: Option<usize>,
+    },
 }
+
+/// How results are ranked.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SearchMode {
+    /// Rank by the distance between the embeddings of the query and of the embedded column.
+    #[default]
+    Vector,
+
+    /// Rank by the BM25 score of the query against the full-text search columns.
+    FullText,
+
+    /// Combine the vector and full-text rankings.
+    Hybrid(Fusion),
+}
+
+/// How the vector and full-text rankings are combined in [`SearchMode::Hybrid`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fusion {
+    /// Reciprocal rank fusion: a row scores `1 / (60 + rank)` for each ranking it appears in.
+    ReciprocalRank,
+
+    /// A weighted sum of the vector similarity, min-max normalized from the distances, and the
+    /// BM25 score, normalized by the highest score. The full-text weight is `1 - vector_weight`.
+    Weighted { vector_weight: f64 },
+}
+
+/// Name of the computed distance column in the vector search SQL query.
+const DISTANCE_COLUMN: &str = "__distance";
+
+/// The rank constant of reciprocal rank fusion, which dampens the advantage of the top ranks.
+const RRF_K: f64 = 60.0;
+
+/// In hybrid search, each ranking retrieves this many times the requested results before fusion.
+const HYBRID_CANDIDATE_MULTIPLIER: usize = 4;
+
 pub type ModelKey = String;
 
 pub struct VectorSearchResult {
     pub retrieved_entries: HashMap<TableReference, Vec<String>>,
     pub retrieved_public_keys: HashMap<TableReference, Vec<RecordBatch>>,
+
+    /// For vector search, the distance of each retrieved entry from the query, in the same order as `retrieved_entries`.
+    pub retrieved_distances: HashMap<TableReference, Vec<f64>>,
+
+    /// For full-text and hybrid search, the score of each retrieved entry, in the same order as
+    /// `retrieved_entries`. Higher is more relevant.
+    pub retrieved_scores: HashMap<TableReference, Vec<f64>>,
+
+    /// For tables with a chunked embedding column, the `[start, end)` byte offsets of each
+    /// retrieved chunk within its column value, in the same order as `retrieved_entries`.
+    pub retrieved_offsets: HashMap<TableReference, Vec<[i32; 2]>>,
+}
+
+/// The results of ranking a table by one method, in rank order.
+struct Ranking {
+    /// The primary keys and the content column of each result.
+    batches: Vec<RecordBatch>,
+
+    /// The content of each result, or of its matching chunk.
+    entries: Vec<String>,
+
+    /// Distances for a vector ranking, scores for a full-text or fused ranking.
+    scores: Vec<f64>,
+
+    offsets: Option<Vec<[i32; 2]>>,
+}
+
+impl Ranking {
+    fn try_new(
+        batches: Vec<RecordBatch>,
+        scores: Vec<f64>,
+        offsets: Option<Vec<[i32; 2]>>,
+        content_column: &str,
+    ) -> Result<Self> {
+        let mut entries = content_values(&batches, content_column)?;
+
+        // For chunked columns, only return the matching chunk of each value.
+        if let Some(offsets) = &offsets {
+            entries = entries
+                .iter()
+                .zip(offsets)
+                .map(|(value, [start, end])| {
+                    usize::try_from(*start)
+                        .ok()
+                        .zip(usize::try_from(*end).ok())
+                        .and_then(|(start, end)| value.get(start..end))
+                        .unwrap_or_default()
+                        .to_string()
+                })
+                .collect();
+        }
+
+        Ok(Self {
+            batches,
+            entries,
+            scores,
+            offsets,
+        })
+    }
 }
 
 impl VectorSearch {
@@ -81,28 +215,56 @@ impl VectorSearch {
         df: Arc<DataFusion>,
         embeddings: Arc<RwLock<EmbeddingModelStore>>,
         explicit_primary_keys: HashMap<TableReference, Vec<String>>,
+        full_text_columns: HashMap<TableReference, Vec<String>>,
     ) -> Self {
         VectorSearch {
             df,
             embeddings,
             explicit_primary_keys,
+            full_text_columns,
         }
     }
 
+    /// Search `tables` for the rows most relevant to `query`, ranked by `mode`. If set, only rows
+    /// matching the SQL predicate `filter` are searched.
+    ///
+    /// Searches on behalf of a `principal` are subject to their dataset grants, row filters and
+    /// column masks.
     pub async fn search(
         &self,
         query: String,
         tables: Vec<TableReference>,
         limit: RetrievalLimit,
+        mode: SearchMode,
+        filter: Option<String>,
+        principal: Option<&Principal>,
     ) -> Result<VectorSearchResult> {
-        let n = match limit {
-            RetrievalLimit::TopN(n) => n,
-            RetrievalLimit::Threshold(_) => unimplemented!(),
+        let (n, max_distance) = match limit {
+            RetrievalLimit::TopN(n) => (Some(n), None),
+            RetrievalLimit::Threshold {
+                max_distance,
+                limit,
+            } => (limit, Some(max_distance)),
         };
 
-        let per_table_embeddings = self
-            .calculate_embeddings_per_table(query.clone(), tables.clone())
-            .await?;
+        // Tables with a policy that applies to the principal.
+        let mut restricted_tables = HashSet::new();
+        for tbl in &tables {
+            if self
+                .applicable_policy(tbl, mode, principal)
+                .await?
+                .is_some()
+            {
+                restricted_tables.insert(tbl.clone());
+            }
+        }
+
+        let per_table_embeddings = if mode == SearchMode::FullText {
+            HashMap::new()
+        } else {
+            self.calculate_embeddings_per_table(query.clone(), tables.clone())
+                .await?
+        };
 
         let table_primary_keys = self
             .get_primary_keys_with_overrides(&self.explicit_primary_keys, tables.clone())
@@ -111,12 +273,14 @@ impl VectorSearch {
         let mut response = VectorSearchResult {
             retrieved_entries: HashMap::new(),
             retrieved_public_keys: HashMap::new(),
+            retrieved_distances: HashMap::new(),
+            retrieved_scores: HashMap::new(),
+            retrieved_offsets: HashMap::new(),
         };
 
-        for (tbl, search_vectors) in per_table_embeddings {
-            tracing::debug!("Running vector search for table {:#?}", tbl.clone());
+        for tbl in tables {
+            tracing::debug!("Running {mode:?} search for table {:#?}", tbl.clone());
 
-            // Only support one embedding column per table.
             let table_provider =
                 self.df
                     .get_table(tbl.clone())
@@ -125,60 +289,137 @@ impl VectorSearch {
                         data_source: tbl.to_string(),
                     })?;
 
-            let embedding_column = get_embedding_table(&table_provider)
-                .and_then(|e| e.get_embedding_columns().first().cloned())
-                .ok_or(Error::NoEmbeddingColumns {
-                    data_source: tbl.to_string(),
-                })?;
+            // Only support one embedding column per table.
+            let embedding_column = get_embedding_table(&table_provider).and_then(|e| {
+                e.get_embedding_columns()
+                    .first()
+                    .map(|c| (c.clone(), e.is_chunked(c)))
+            });
+            let text_columns = self.full_text_columns.get(&tbl);
 
-            if search_vectors.len() != 1 {
-                return Err(Error::IncorrectNumberOfEmbeddingColumns {
+            // Results contain the embedded column, or the first full-text column if there is none.
+            let Some(content_column) = embedding_column
+                .as_ref()
+                .map(|(c, _)| c)
+                .or(text_columns.and_then(|c| c.first()))
+                .cloned()
+            else {
+                return Err(Error::NoEmbeddingColumns {
                     data_source: tbl.to_string(),
-                    num_embeddings: search_vectors.len(),
                 });
-            }
-            match search_vectors.first() {
-                None => unreachable!(),
-                Some(embedding) => {
-                    let mut select_keys = table_primary_keys.get(&tbl).cloned().unwrap_or(vec![]);
-                    select_keys.push(embedding_column.clone());
-
-                    let result = self
-                        .df
-                        .ctx
-                        .sql(&format!(
-                            "SELECT {} FROM {tbl} ORDER BY array_distance({embedding_column}_embedding, {embedding:?}) LIMIT {}", select_keys.join(", "), n
-                        ))
-                        .await
-                        .boxed()
-                        .context(DataFusionSnafu)?;
-                    let batch = result.collect().await.boxed().context(DataFusionSnafu)?;
-
-                    let outt: Vec<_> = batch
-                        .iter()
-                        .map(|b| {
-                            let z =
-                                b.column(b.num_columns() -1).as_any().downcast_ref::<StringArray>().ok_or(
-                                    string_to_boxed_err(
-                                        format!("Expected '{embedding_column}' to be last column of SQL query and return a String type"),
-                                    ),
-                                ).context(DataFusionSnafu);
-                            let zz = z.map(|s| {
-                                s.iter()
-                                    .map(|ss| ss.unwrap_or_default().to_string())
-                                    .collect::<Vec<String>>()
-                            });
-                            zz
-                        })
-                        .collect::<Result<Vec<_>>>()?;
-
-                    let outtt: Vec<String> =
-                        outt.iter().flat_map(std::clone::Clone::clone).collect();
-
-                    response.retrieved_entries.insert(tbl.clone(), outtt);
-                    response.retrieved_public_keys.insert(tbl, batch);
+            };
+
+            let mut select_keys = table_primary_keys.get(&tbl).cloned().unwrap_or(vec![]);
+            let num_primary_keys = select_keys.len();
+            select_keys.push(content_column.clone());
+
+            let filter = match &filter {
+                Some(filter) => Some(self.parse_filter(&tbl, filter).await?),
+                None => None,
+            };
+
+            // The indexes can't apply arbitrary filters, and hold every row with its unmasked values,
+            // so they are only used for unfiltered searches that no dataset policy applies to.
+            let use_index = filter.is_none() && !restricted_tables.contains(&tbl);
+
+            let vector = if mode == SearchMode::FullText {
+                None
+            } else {
+                let (embedding_column, chunked) =
+                    embedding_column.ok_or(Error::NoEmbeddingColumns {
+                        data_source: tbl.to_string(),
+                    })?;
+                let search_vectors = per_table_embeddings.get(&tbl).cloned().unwrap_or_default();
+                let [embedding] = search_vectors.as_slice() else {
+                    return Err(Error::IncorrectNumberOfEmbeddingColumns {
+                        data_source: tbl.to_string(),
+                        num_embeddings: search_vectors.len(),
+                    });
+                };
+
+                let candidates = match mode {
+                    SearchMode::Hybrid(_) => n.map(|n| n * HYBRID_CANDIDATE_MULTIPLIER),
+                    _ => n,
+                };
+                let (batches, distances, offsets) = self
+                    .vector_ranking(
+                        &tbl,
+                        &table_provider,
+                        &embedding_column,
+                        chunked,
+                        &select_keys,
+                        embedding,
+                        candidates,
+                        max_distance,
+                        filter.as_deref(),
+                        use_index,
+                        principal,
+                    )
+                    .await?;
+                Some(Ranking::try_new(
+                    batches,
+                    distances,
+                    offsets,
+                    &content_column,
+                )?)
+            };
+
+            let full_text = if mode == SearchMode::Vector {
+                None
+            } else {
+                let text_columns = text_columns.ok_or(Error::NoFullTextColumns {
+                    data_source: tbl.to_string(),
+                })?;
+                let candidates = match mode {
+                    SearchMode::Hybrid(_) => n.map(|n| n * HYBRID_CANDIDATE_MULTIPLIER),
+                    _ => n,
+                };
+                let (batches, scores) = self
+                    .full_text_ranking(
+                        &tbl,
+                        &table_provider,
+                        text_columns,
+                        &select_keys,
+                        &query,
+                        candidates,
+                        filter.as_deref(),
+                        use_index,
+                        principal,
+                    )
+                    .await?;
+                Some(Ranking::try_new(batches, scores, None, &content_column)?)
+            };
+
+            let ranking = match (mode, vector, full_text) {
+                (SearchMode::Hybrid(fusion), Some(vector), Some(full_text)) => {
+                    let ranking = fuse(&vector, &full_text, num_primary_keys, fusion, n)?;
+                    response
+                        .retrieved_scores
+                        .insert(tbl.clone(), ranking.scores.clone());
+                    ranking
+                }
+                (_, Some(vector), _) => {
+                    response
+                        .retrieved_distances
+                        .insert(tbl.clone(), vector.scores.clone());
+                    vector
+                }
+                (_, None, Some(full_text)) => {
+                    response
+                        .retrieved_scores
+                        .insert(tbl.clone(), full_text.scores.clone());
+                    full_text
                 }
+                (_, None, None) => unreachable!("every search mode produces a ranking"),
             };
+
+            if let Some(offsets) = ranking.offsets {
+                response.retrieved_offsets.insert(tbl.clone(), offsets);
+            }
+            response
+                .retrieved_entries
+                .insert(tbl.clone(), ranking.entries);
+            response.retrieved_public_keys.insert(tbl, ranking.batches);
         }
         tracing::debug!(
             "Relevant data from vector search: {:#?}",
@@ -187,6 +428,234 @@ impl VectorSearch {
         Ok(response)
     }
 
+    /// Rank the rows of `tbl` by the distance of their embeddings from `embedding`, returning the
+    /// `select_keys` of each result, its distance and, for chunked columns, its chunk's offsets.
+    #[allow(clippy::too_many_arguments)]
+    async fn vector_ranking(
+        &self,
+        tbl: &TableReference,
+        table_provider: &Arc<dyn TableProvider>,
+        embedding_column: &str,
+        chunked: bool,
+        select_keys: &[String],
+        embedding: &[f32],
+        n: Option<usize>,
+        max_distance: Option<f64>,
+        filter: Option<&str>,
+        use_index: bool,
+        principal: Option<&Principal>,
+    ) -> Result<(Vec<RecordBatch>, Vec<f64>, Option<Vec<[i32; 2]>>)> {
+        if use_index {
+            if let Some(result) = search_embedding_index(
+                table_provider,
+                embedding_column,
+                select_keys,
+                embedding,
+                n,
+                max_distance,
+            )
+            .await
+            {
+                return Ok((vec![result.payload], result.distances, result.offsets));
+            }
+        }
+
+        if chunked {
+            // Chunk embeddings are nested in a list per row, so are ranked outside of SQL.
+            let filter = filter.map(|f| format!(" WHERE {f}")).unwrap_or_default();
+            let (schema, batches) = self
+                .query(
+                    &format!(
+                        "SELECT {}, {embedding_column}_embedding, {embedding_column}_offset FROM {tbl}{filter}",
+                        select_keys.join(", ")
+                    ),
+                    principal,
+                )
+                .await?;
+            let result =
+                index::exhaustive_search(&schema, &batches, true, embedding, n, max_distance)
+                    .boxed()
+                    .context(DataFusionSnafu)?;
+            return Ok((vec![result.payload], result.distances, result.offsets));
+        }
+
+        let distance = format!("array_distance({embedding_column}_embedding, {embedding:?})");
+        let predicates = filter
+            .map(ToString::to_string)
+            .into_iter()
+            .chain(max_distance.map(|max| format!("{distance} < {max}")))
+            .collect::<Vec<_>>();
+        let filter = if predicates.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", predicates.join(" AND "))
+        };
+        let limit = n.map(|n| format!(" LIMIT {n}")).unwrap_or_default();
+
+        let (_, batches) = self
+            .query(
+                &format!(
+                    "SELECT {}, {distance} AS {DISTANCE_COLUMN} FROM {tbl}{filter} ORDER BY {DISTANCE_COLUMN}{limit}", select_keys.join(", ")
+                ),
+                principal,
+            )
+            .await?;
+        let (batches, distances) = split_distance_column(batches)?;
+        Ok((batches, distances, None))
+    }
+
+    /// Rank the rows of `tbl` by the BM25 score of `text_columns` against `query`, returning the
+    /// `select_keys` of each result and its score.
+    #[allow(clippy::too_many_arguments)]
+    async fn full_text_ranking(
+        &self,
+        tbl: &TableReference,
+        table_provider: &Arc<dyn TableProvider>,
+        text_columns: &[String],
+        select_keys: &[String],
+        query: &str,
+        n: Option<usize>,
+        filter: Option<&str>,
+        use_index: bool,
+        principal: Option<&Principal>,
+    ) -> Result<(Vec<RecordBatch>, Vec<f64>)> {
+        if use_index {
+            if let Some((batch, scores)) =
+                search_full_text_index(table_provider, select_keys, query, n)
+            {
+                return Ok((vec![batch], scores));
+            }
+        }
+
+        let text_columns = text_columns
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("{c} AS {}", text_column_alias(i)))
+            .collect::<Vec<_>>();
+        let filter = filter.map(|f| format!(" WHERE {f}")).unwrap_or_default();
+        let (schema, batches) = self
+            .query(
+                &format!(
+                    "SELECT {}, {} FROM {tbl}{filter}",
+                    select_keys.join(", "),
+                    text_columns.join(", ")
+                ),
+                principal,
+            )
+            .await?;
+        let (batch, scores) =
+            full_text::exhaustive_search(&schema, &batches, text_columns.len(), query, n)
+                .boxed()
+                .context(DataFusionSnafu)?;
+        Ok((vec![batch], scores))
+    }
+
+    /// Verify the principal can read `tbl`, and return the dataset policy that applies to them, if
+    /// any. Vector searches of a masked column are refused, as the distances of its embeddings
+    /// would reveal the values the mask hides.
+    async fn applicable_policy(
+        &self,
+        tbl: &TableReference,
+        mode: SearchMode,
+        principal: Option<&Principal>,
+    ) -> Result<Option<Arc<Policy>>> {
+        let Some(principal) = principal else {
+            return Ok(None);
+        };
+
+        if let Some(access_control) = self.df.access_control() {
+            ensure!(
+                access_control.is_allowed(principal, tbl, Permission::Read),
+                AccessDeniedSnafu {
+                    data_source: tbl.to_string(),
+                }
+            );
+        }
+
+        let policies = self.df.dataset_policies();
+        let Some(policy) = policy::applicable_policy(&policies, tbl, principal).cloned() else {
+            return Ok(None);
+        };
+
+        if mode != SearchMode::FullText {
+            let table_provider =
+                self.df
+                    .get_table(tbl.clone())
+                    .await
+                    .context(DataSourceNotFoundSnafu {
+                        data_source: tbl.to_string(),
+                    })?;
+            let embedding_columns = get_embedding_table(&table_provider)
+                .map(|e| e.get_embedding_columns())
+                .unwrap_or_default();
+            if let Some(column) = embedding_columns
+                .into_iter()
+                .find(|c| policy.column_masks.contains_key(c))
+            {
+                return MaskedColumnSnafu {
+                    data_source: tbl.to_string(),
+                    column,
+                }
+                .fail();
+            }
+        }
+
+        Ok(Some(policy))
+    }
+
+    /// Run a search query, on behalf of the principal if there is one, so that their dataset
+    /// grants and policies apply.
+    async fn query(
+        &self,
+        sql: &str,
+        principal: Option<&Principal>,
+    ) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+        let mut query = self.df.query_builder(sql, Protocol::Http);
+        if let Some(principal) = principal {
+            query = query.principal(principal.clone());
+        }
+
+        let data = query
+            .build()
+            .run()
+            .await
+            .boxed()
+            .context(DataFusionSnafu)?
+            .data;
+        let schema = data.schema();
+        let batches: Vec<RecordBatch> =
+            data.try_collect().await.boxed().context(DataFusionSnafu)?;
+        Ok((schema, batches))
+    }
+
+    /// Parse the SQL predicate `filter` against the schema of `tbl`, and return it as SQL. Parsing
+    /// ensures the filter is a single expression over the table before it's added to a query.
+    async fn parse_filter(&self, tbl: &TableReference, filter: &str) -> Result<String> {
+        let df = self
+            .df
+            .ctx
+            .table(tbl.clone())
+            .await
+            .boxed()
+            .context(DataFusionSnafu)?;
+        let predicate = self
+            .df
+            .ctx
+            .state()
+            .create_logical_expr(filter, df.schema())
+            .boxed()
+            .context(InvalidFilterSnafu {
+                data_source: tbl.to_string(),
+            })?;
+
+        expr_to_sql(&predicate)
+            .map(|sql| format!("({sql})"))
+            .boxed()
+            .context(InvalidFilterSnafu {
+                data_source: tbl.to_string(),
+            })
+    }
+
     /// For the data sources that assumedly exist in the [`DataFusion`] instance, find the embedding models used in each data source.
     async fn find_relevant_embedding_models(
         &self,
@@ -351,6 +820,222 @@ fn get_embedding_table(tbl: &Arc<dyn TableProvider>) -> Option<Arc<EmbeddingTabl
     None
 }
 
+/// Search the vector index of an accelerated table's embedding column, returning the `select_keys`
+/// of the nearest entries, their distances and, for chunked columns, their offsets. The index only
+/// holds primary keys, so the `select_keys` are read from the table. Returns `None` if the table
+/// has no usable index, and a full scan is needed.
+async fn search_embedding_index(
+    tbl: &Arc<dyn TableProvider>,
+    embedding_column: &str,
+    select_keys: &[String],
+    embedding: &[f32],
+    limit: Option<usize>,
+    max_distance: Option<f64>,
+) -> Option<SearchResult> {
+    let index = tbl
+        .as_any()
+        .downcast_ref::<AcceleratedTable>()?
+        .embedding_index(embedding_column)?;
+
+    let result = match index.search(embedding, limit, max_distance)? {
+        Ok(result) => read_rows(Arc::clone(tbl), result, select_keys).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(result) => Some(result),
+        Err(e) => {
+            tracing::warn!("Vector index search failed for column {embedding_column}, falling back to a full scan: {e}");
+            None
+        }
+    }
+}
+
+/// Search the full-text index of an accelerated table, returning the `select_keys` of the best
+/// matching rows and their scores. Returns `None` if the table has no usable index, and a full
+/// scan is needed.
+fn search_full_text_index(
+    tbl: &Arc<dyn TableProvider>,
+    select_keys: &[String],
+    query: &str,
+    limit: Option<usize>,
+) -> Option<(RecordBatch, Vec<f64>)> {
+    let index = tbl
+        .as_any()
+        .downcast_ref::<AcceleratedTable>()?
+        .full_text_index()?;
+
+    // The index must return the same columns as the full scan would.
+    let projection = select_keys
+        .iter()
+        .map(|key| index.payload_columns().iter().position(|c| c == key))
+        .collect::<Option<Vec<_>>>()?;
+
+    match index.search(query, limit)? {
+        Ok((batch, scores)) => batch.project(&projection).ok().map(|batch| (batch, scores)),
+        Err(e) => {
+            tracing::warn!("Full-text index search failed, falling back to a full scan: {e}");
+            None
+        }
+    }
+}
+
+/// Combine a vector and a full-text ranking of the same table into one, keeping at most `limit`
+/// results. Rows are identified by their first `num_primary_keys` columns, or by their content if
+/// there are no primary keys. Only the best ranked chunk of a row is kept.
+fn fuse(
+    vector: &Ranking,
+    full_text: &Ranking,
+    num_primary_keys: usize,
+    fusion: Fusion,
+    limit: Option<usize>,
+) -> Result<Ranking> {
+    struct Hit {
+        row: RecordBatch,
+        entry: String,
+        offset: Option<[i32; 2]>,
+        score: f64,
+    }
+
+    let (min_distance, max_distance) = vector
+        .scores
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &d| {
+            (min.min(d), max.max(d))
+        });
+    let max_score = full_text.scores.iter().copied().fold(0.0, f64::max);
+
+    let mut hits: Vec<Hit> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (ranking, is_vector) in [(vector, true), (full_text, false)] {
+        let mut rank = 0_u32;
+        for (i, row) in ranking
+            .batches
+            .iter()
+            .flat_map(|b| (0..b.num_rows()).map(|i| b.slice(i, 1)))
+            .enumerate()
+        {
+            let key_columns = if num_primary_keys > 0 {
+                0..num_primary_keys
+            } else {
+                row.num_columns().saturating_sub(1)..row.num_columns()
+            };
+            let key = key_columns
+                .map(|c| array_value_to_string(row.column(c), 0))
+                .collect::<Result<Vec<_>, _>>()
+                .boxed()
+                .context(DataFusionSnafu)?
+                .join("\u{1f}");
+
+            let position = match positions.get(&key) {
+                // A row that already has a better ranked chunk in this ranking.
+                Some(_) if is_vector => continue,
+                Some(&position) => position,
+                None => {
+                    let entry = ranking.entries.get(i).cloned().unwrap_or_default();
+                    let offset = match &vector.offsets {
+                        Some(offsets) if is_vector => offsets.get(i).copied(),
+                        Some(_) => Some([0, i32::try_from(entry.len()).unwrap_or(i32::MAX)]),
+                        None => None,
+                    };
+                    positions.insert(key, hits.len());
+                    hits.push(Hit {
+                        row,
+                        entry,
+                        offset,
+                        score: 0.0,
+                    });
+                    hits.len() - 1
+                }
+            };
+
+            let score = ranking.scores.get(i).copied().unwrap_or_default();
+            hits[position].score += match fusion {
+                Fusion::ReciprocalRank => 1.0 / (RRF_K + f64::from(rank) + 1.0),
+                Fusion::Weighted { vector_weight } if is_vector => {
+                    let similarity = if max_distance > min_distance {
+                        (max_distance - score) / (max_distance - min_distance)
+                    } else {
+                        1.0
+                    };
+                    vector_weight * similarity
+                }
+                Fusion::Weighted { vector_weight } => {
+                    (1.0 - vector_weight)
+                        * if max_score > 0.0 {
+                            score / max_score
+                        } else {
+                            0.0
+                        }
+                }
+            };
+            rank += 1;
+        }
+    }
+
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(limit.unwrap_or(usize::MAX));
+
+    let offsets = vector
+        .offsets
+        .as_ref()
+        .map(|_| hits.iter().map(|h| h.offset.unwrap_or_default()).collect());
+    Ok(Ranking {
+        scores: hits.iter().map(|h| h.score).collect(),
+        entries: hits.iter().map(|h| h.entry.clone()).collect(),
+        batches: hits.into_iter().map(|h| h.row).collect(),
+        offsets,
+    })
+}
+
+/// The values of the last column of each batch, which is expected to be `content_column`.
+fn content_values(batches: &[RecordBatch], content_column: &str) -> Result<Vec<String>> {
+    let values = batches
+        .iter()
+        .map(|b| {
+            b.column(b.num_columns() - 1)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .ok_or(string_to_boxed_err(format!(
+                    "Expected '{content_column}' to be last column of SQL query and return a String type"
+                )))
+                .context(DataFusionSnafu)
+                .map(|s| {
+                    s.iter()
+                        .map(|ss| ss.unwrap_or_default().to_string())
+                        .collect::<Vec<String>>()
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(values.into_iter().flatten().collect())
+}
+
+/// Remove the trailing [`DISTANCE_COLUMN`] from each batch, returning the batches and the distances.
+fn split_distance_column(batches: Vec<RecordBatch>) -> Result<(Vec<RecordBatch>, Vec<f64>)> {
+    let mut distances = Vec::new();
+    let batches = batches
+        .into_iter()
+        .map(|b| {
+            let distance_idx = b.num_columns() - 1;
+            let column = cast(b.column(distance_idx), &DataType::Float64)
+                .boxed()
+                .context(DataFusionSnafu)?;
+            distances.extend(
+                column
+                    .as_primitive::<Float64Type>()
+                    .iter()
+                    .map(|d| d.unwrap_or(f64::NAN)),
+            );
+
+            b.project(&(0..distance_idx).collect::<Vec<_>>())
+                .boxed()
+                .context(DataFusionSnafu)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((batches, distances))
+}
+
 fn string_to_boxed_err(s: String) -> Box<dyn std::error::Error + Send + Sync> {
     Box::<dyn std::error::Error + Send + Sync>::from(s)
 }
@@ -371,3 +1056,77 @@ pub async fn compute_primary_keys(
             .collect::<HashMap<TableReference, Vec<_>>>()
     })
 }
+
+/// Compute the full-text search columns of each dataset in the app.
+pub async fn compute_full_text_columns(
+    app: Arc<RwLock<Option<App>>>,
+) -> HashMap<TableReference, Vec<String>> {
+    app.read().await.as_ref().map_or(HashMap::new(), |app| {
+        app.datasets
+            .iter()
+            .filter_map(|d| {
+                d.full_text_search
+                    .as_ref()
+                    .map(|f| (TableReference::parse_str(&d.name), f.columns.clone()))
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::Int64Array,
+        datatypes::{Field, Int64Type, Schema},
+    };
+
+    use super::*;
+
+    fn ranking(ids: &[i64], scores: &[f64]) -> Ranking {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("text", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(ids.to_vec())),
+                Arc::new(StringArray::from_iter_values(
+                    ids.iter().map(|id| format!("row {id}")),
+                )),
+            ],
+        )
+        .expect("valid batch");
+        Ranking::try_new(vec![batch], scores.to_vec(), None, "text").expect("valid ranking")
+    }
+
+    fn ids(ranking: &Ranking) -> Vec<i64> {
+        ranking
+            .batches
+            .iter()
+            .flat_map(|b| b.column(0).as_primitive::<Int64Type>().values().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_fuse() {
+        let vector = ranking(&[1, 2, 3], &[0.1, 0.2, 0.9]);
+        let full_text = ranking(&[3, 4, 2], &[9.0, 3.0, 1.0]);
+
+        // Rows 2 and 3 are in both rankings.
+        let fused = fuse(&vector, &full_text, 1, Fusion::ReciprocalRank, None).expect("fused");
+        assert_eq!(ids(&fused), vec![3, 2, 1, 4]);
+        assert_eq!(fused.entries[0], "row 3");
+
+        let fused = fuse(
+            &vector,
+            &full_text,
+            1,
+            Fusion::Weighted { vector_weight: 1.0 },
+            Some(2),
+        )
+        .expect("fused");
+        assert_eq!(ids(&fused), vec![1, 2]);
+        assert!((fused.scores[0] - 1.0).abs() < f64::EPSILON);
+    }
+}
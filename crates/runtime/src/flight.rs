@@ -14,8 +14,9 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::auth::{access_control::Permission, AuthProvider, Credentials, Principal};
 use crate::datafusion::query::error_code::ErrorCode;
-use crate::datafusion::query::{Protocol, QueryBuilder};
+use crate::datafusion::query::{self, limits::QueryOptions, Protocol, QueryBuilder};
 use crate::datafusion::DataFusion;
 use crate::dataupdate::DataUpdate;
 use crate::measure_scope_ms;
@@ -38,6 +39,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::broadcast::Sender;
 use tokio::sync::RwLock;
+use tonic::metadata::MetadataMap;
 use tonic::transport::{Identity, Server, ServerTlsConfig};
 use tonic::{Request, Response, Status, Streaming};
 
@@ -59,6 +61,7 @@ use arrow_flight::{
 pub struct Service {
     datafusion: Arc<DataFusion>,
     channel_map: Arc<RwLock<HashMap<TableReference, Arc<Sender<DataUpdate>>>>>,
+    auth: Option<Arc<AuthProvider>>,
 }
 
 #[tonic::async_trait]
@@ -73,17 +76,18 @@ impl FlightService for Service {
 
     async fn handshake(
         &self,
-        _request: Request<Streaming<HandshakeRequest>>,
+        request: Request<Streaming<HandshakeRequest>>,
     ) -> Result<Response<Self::HandshakeStream>, Status> {
         metrics::counter!("flight_handshake_requests").increment(1);
-        handshake::handle()
+        handshake::handle(self.auth.as_deref(), request).await
     }
 
     async fn list_flights(
         &self,
-        _request: Request<Criteria>,
+        request: Request<Criteria>,
     ) -> Result<Response<Self::ListFlightsStream>, Status> {
         metrics::counter!("flight_list_flights_requests").increment(1);
+        self.authenticate(&request)?;
         tracing::trace!("list_flights - unimplemented");
         Err(Status::unimplemented("Not yet implemented"))
     }
@@ -94,21 +98,24 @@ impl FlightService for Service {
     ) -> Result<Response<FlightInfo>, Status> {
         measure_scope_ms!("flight_get_flight_info_request_duration_ms");
         metrics::counter!("flight_get_flight_info_requests").increment(1);
-        Box::pin(get_flight_info::handle(self, request)).await
+        let principal = self.authenticate(&request)?;
+        Box::pin(get_flight_info::handle(self, request, principal)).await
     }
 
     async fn poll_flight_info(
         &self,
-        _request: Request<FlightDescriptor>,
+        request: Request<FlightDescriptor>,
     ) -> Result<Response<PollInfo>, Status> {
+        self.authenticate(&request)?;
         Err(Status::unimplemented("Not yet implemented"))
     }
 
     async fn get_schema(
         &self,
-        _request: Request<FlightDescriptor>,
+        request: Request<FlightDescriptor>,
     ) -> Result<Response<SchemaResult>, Status> {
         metrics::counter!("flight_get_schema_requests").increment(1);
+        self.authenticate(&request)?;
         tracing::trace!("get_schema - unimplemented");
         Err(Status::unimplemented("Not yet implemented"))
     }
@@ -118,7 +125,8 @@ impl FlightService for Service {
         request: Request<Ticket>,
     ) -> Result<Response<Self::DoGetStream>, Status> {
         metrics::counter!("flight_do_get_requests").increment(1);
-        Box::pin(do_get::handle(self, request)).await
+        let principal = self.authenticate(&request)?;
+        Box::pin(do_get::handle(self, request, principal)).await
     }
 
     async fn do_put(
@@ -126,7 +134,8 @@ impl FlightService for Service {
         request: Request<Streaming<FlightData>>,
     ) -> Result<Response<Self::DoPutStream>, Status> {
         metrics::counter!("flight_do_put_requests").increment(1);
-        do_put::handle(self, request).await
+        let principal = self.authenticate(&request)?;
+        do_put::handle(self, request, &principal).await
     }
 
     async fn do_exchange(
@@ -134,7 +143,8 @@ impl FlightService for Service {
         request: Request<Streaming<FlightData>>,
     ) -> Result<Response<Self::DoExchangeStream>, Status> {
         metrics::counter!("flight_do_exchange_requests").increment(1);
-        do_exchange::handle(self, request).await
+        let principal = self.authenticate(&request)?;
+        do_exchange::handle(self, request, &principal).await
     }
 
     async fn do_action(
@@ -142,21 +152,62 @@ impl FlightService for Service {
         request: Request<Action>,
     ) -> Result<Response<Self::DoActionStream>, Status> {
         metrics::counter!("flight_do_action_requests").increment(1);
-        Box::pin(actions::do_action(self, request)).await
+        let principal = self.authenticate(&request)?;
+        Box::pin(actions::do_action(self, request, principal)).await
     }
 
     async fn list_actions(
         &self,
-        _request: Request<arrow_flight::Empty>,
+        request: Request<arrow_flight::Empty>,
     ) -> Result<Response<Self::ListActionsStream>, Status> {
         metrics::counter!("flight_list_actions_requests").increment(1);
+        self.authenticate(&request)?;
         Ok(actions::list())
     }
 }
 
 impl Service {
-    async fn get_arrow_schema(datafusion: Arc<DataFusion>, sql: &str) -> Result<Schema, Status> {
-        let query = QueryBuilder::new(sql, datafusion, Protocol::Flight).build();
+    /// Authenticates the credentials attached to a request, returning the anonymous principal if authentication is not configured.
+    fn authenticate<T>(&self, request: &Request<T>) -> Result<Principal, Status> {
+        let Some(auth) = &self.auth else {
+            return Ok(Principal::anonymous());
+        };
+
+        let principal = request_credentials(request.metadata())
+            .and_then(|credentials| auth.authenticate(&credentials));
+
+        principal.ok_or_else(|| {
+            metrics::counter!("flight_auth_failures").increment(1);
+            Status::unauthenticated("Missing or invalid credentials")
+        })
+    }
+
+    /// Checks the principal's dataset grants for direct table access that doesn't go through a query.
+    fn authorize(
+        &self,
+        principal: &Principal,
+        table: &TableReference,
+        permission: Permission,
+    ) -> Result<(), Status> {
+        match self.datafusion.access_control() {
+            Some(access_control) if !access_control.is_allowed(principal, table, permission) => {
+                Err(Status::permission_denied(format!(
+                    "{} does not have {permission} access to {table}",
+                    principal.name
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_arrow_schema(
+        datafusion: Arc<DataFusion>,
+        sql: &str,
+        principal: Principal,
+    ) -> Result<Schema, Status> {
+        let query = QueryBuilder::new(sql, datafusion, Protocol::Flight)
+            .principal(principal)
+            .build();
 
         let schema = match query.get_schema().await {
             Ok(schema) => schema,
@@ -164,7 +215,7 @@ impl Service {
                 let error_code = ErrorCode::from(&err);
                 query.finish_with_error(err.to_string(), error_code).await;
 
-                return Err(handle_datafusion_error(err));
+                return Err(handle_query_error(err));
             }
         };
         Ok(schema)
@@ -182,13 +233,21 @@ impl Service {
     async fn sql_to_flight_stream(
         datafusion: Arc<DataFusion>,
         sql: &str,
+        principal: Principal,
+        options: QueryOptions,
     ) -> Result<(BoxStream<'static, Result<FlightData, Status>>, Option<bool>), Status> {
         let query = QueryBuilder::new(sql, Arc::clone(&datafusion), Protocol::Flight)
             .use_restricted_sql_options()
             .protocol(Protocol::Flight)
+            .principal(principal)
+            .options(options)
             .build();
 
-        let query_result = query.run().await.map_err(to_tonic_err)?;
+        let query_result = query.run().await.map_err(|e| match e {
+            query::Error::AccessDenied { .. } => Status::permission_denied(e.to_string()),
+            query::Error::QueryIdInUse { .. } => Status::already_exists(e.to_string()),
+            _ => to_tonic_err(e),
+        })?;
 
         let schema = query_result.data.schema();
         let options = datafusion::arrow::ipc::writer::IpcWriteOptions::default();
@@ -245,6 +304,12 @@ impl Service {
     }
 }
 
+/// Reads the credentials from the `authorization` header of a request.
+fn request_credentials(metadata: &MetadataMap) -> Option<Credentials> {
+    let value = metadata.get("authorization")?.to_str().ok()?;
+    Credentials::from_authorization_header(value)
+}
+
 fn record_batches_to_flight_stream(
     record_batches: Vec<RecordBatch>,
 ) -> impl Stream<Item = Result<FlightData, Status>> {
@@ -286,6 +351,21 @@ fn handle_datafusion_error(e: DataFusionError) -> Status {
     }
 }
 
+/// Reads the query id, timeout and memory limit a client set in the `x-spice-query-*` metadata keys.
+fn request_query_options(metadata: &MetadataMap) -> Result<QueryOptions, Status> {
+    QueryOptions::from_headers(|name| metadata.get(name).and_then(|value| value.to_str().ok()))
+        .map_err(|e| Status::invalid_argument(e.to_string()))
+}
+
+fn handle_query_error(e: query::Error) -> Status {
+    match e {
+        query::Error::UnableToExecuteQuery { source } => handle_datafusion_error(source),
+        query::Error::AccessDenied { .. } => Status::permission_denied(e.to_string()),
+        query::Error::QueryIdInUse { .. } => Status::already_exists(e.to_string()),
+        _ => to_tonic_err(e),
+    }
+}
+
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Unable to register parquet file: {source}"))]
@@ -309,10 +389,12 @@ pub async fn start(
     bind_address: std::net::SocketAddr,
     df: Arc<DataFusion>,
     tls_config: Option<Arc<TlsConfig>>,
+    auth: Option<Arc<AuthProvider>>,
 ) -> Result<()> {
     let service = Service {
         datafusion: Arc::clone(&df),
         channel_map: Arc::new(RwLock::new(HashMap::new())),
+        auth,
     };
     let svc = FlightServiceServer::new(service);
 
@@ -18,8 +18,10 @@ use std::fmt::{self, Display, Formatter};
 
 use prost::Message;
 use tonic::{Request, Response, Status};
+use uuid::Uuid;
 
 use crate::{
+    auth::Principal,
     flight::{flightsql::prepared_statement_query, to_tonic_err, Service},
     timing::{TimeMeasurement, TimedStream},
 };
@@ -33,6 +35,7 @@ use arrow_flight::{
 enum ActionType {
     CreatePreparedStatement,
     ClosePreparedStatement,
+    CancelQuery,
     Unknown,
 }
 
@@ -41,6 +44,7 @@ impl ActionType {
         match s {
             "CreatePreparedStatement" => ActionType::CreatePreparedStatement,
             "ClosePreparedStatement" => ActionType::ClosePreparedStatement,
+            "CancelQuery" => ActionType::CancelQuery,
             _ => ActionType::Unknown,
         }
     }
@@ -49,6 +53,7 @@ impl ActionType {
         match self {
             ActionType::CreatePreparedStatement => "CreatePreparedStatement",
             ActionType::ClosePreparedStatement => "ClosePreparedStatement",
+            ActionType::CancelQuery => "CancelQuery",
             ActionType::Unknown => "Unknown",
         }
     }
@@ -76,9 +81,17 @@ pub(crate) fn list() -> Response<<Service as FlightService>::ListActionsStream>
             Response Message: N/A"
             .into(),
     };
+    let cancel_query_action_type = FlightActionType {
+        r#type: ActionType::CancelQuery.to_string(),
+        description: "Cancels a running query started by the caller.\n
+            Request Message: The query id, as set with the x-spice-query-id metadata key\n
+            Response Message: N/A"
+            .into(),
+    };
     let actions: Vec<Result<FlightActionType, Status>> = vec![
         Ok(create_prepared_statement_action_type),
         Ok(close_prepared_statement_action_type),
+        Ok(cancel_query_action_type),
     ];
 
     let output = TimedStream::new(futures::stream::iter(actions), || {
@@ -91,6 +104,7 @@ pub(crate) fn list() -> Response<<Service as FlightService>::ListActionsStream>
 pub(crate) async fn do_action(
     flight_svc: &Service,
     request: Request<Action>,
+    principal: Principal,
 ) -> Result<Response<<Service as FlightService>::DoActionStream>, Status> {
     let action_type = ActionType::from_str(request.get_ref().r#type.as_str());
 
@@ -111,9 +125,10 @@ pub(crate) async fn do_action(
                         "Unable to unpack ActionCreatePreparedStatementRequest.",
                     )
                 })?;
-            let stmt =
-                prepared_statement_query::do_action_create_prepared_statement(flight_svc, cmd)
-                    .await?;
+            let stmt = prepared_statement_query::do_action_create_prepared_statement(
+                flight_svc, cmd, principal,
+            )
+            .await?;
             futures::stream::iter(vec![Ok(arrow_flight::Result {
                 body: stmt.as_any().encode_to_vec().into(),
             })])
@@ -122,6 +137,24 @@ pub(crate) async fn do_action(
             tracing::trace!("do_action: ClosePreparedStatement");
             futures::stream::iter(vec![Ok(arrow_flight::Result::default())])
         }
+        ActionType::CancelQuery => {
+            tracing::trace!("do_action: CancelQuery");
+            let query_id = std::str::from_utf8(&request.get_ref().body)
+                .ok()
+                .and_then(|query_id| Uuid::parse_str(query_id.trim()).ok())
+                .ok_or_else(|| Status::invalid_argument("Invalid query id"))?;
+
+            if !flight_svc
+                .datafusion
+                .running_queries()
+                .cancel(&query_id, &principal)
+            {
+                return Err(Status::not_found(format!("Query {query_id} not found")));
+            }
+
+            metrics::counter!("queries_cancelled", "protocol" => "flight").increment(1);
+            futures::stream::iter(vec![Ok(arrow_flight::Result::default())])
+        }
         ActionType::Unknown => return Err(Status::invalid_argument("Unknown action type")),
     };
 
@@ -23,7 +23,10 @@ use futures::{stream, StreamExt};
 use tokio::sync::broadcast;
 use tonic::{Request, Response, Status, Streaming};
 
-use crate::dataupdate::{DataUpdate, UpdateType};
+use crate::{
+    auth::{access_control::Permission, policy, Principal},
+    dataupdate::{DataUpdate, UpdateType},
+};
 
 use super::Service;
 
@@ -31,6 +34,7 @@ use super::Service;
 pub(crate) async fn handle(
     flight_svc: &Service,
     request: Request<Streaming<FlightData>>,
+    principal: &Principal,
 ) -> Result<Response<<Service as FlightService>::DoExchangeStream>, Status> {
     let mut streaming_request = request.into_inner();
     let req = streaming_request.next().await;
@@ -64,6 +68,22 @@ pub(crate) async fn handle(
 
     let data_path = TableReference::parse_str(&flight_descriptor.path.join("."));
 
+    flight_svc.authorize(principal, &data_path, Permission::Read)?;
+
+    // Updates are broadcast to every subscriber as they are, so row filters and column masks can't be applied to them.
+    if policy::applicable_policy(
+        &flight_svc.datafusion.dataset_policies(),
+        &data_path,
+        principal,
+    )
+    .is_some()
+    {
+        return Err(Status::permission_denied(format!(
+            "{} can't subscribe to {data_path}, as a row filter or column mask policy applies to them",
+            principal.name
+        )));
+    }
+
     if flight_svc
         .datafusion
         .get_table(data_path.clone())
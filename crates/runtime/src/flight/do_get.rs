@@ -25,28 +25,36 @@ use prost::Message;
 use tonic::{Request, Response, Status};
 
 use crate::{
+    auth::Principal,
+    datafusion::query::limits::QueryOptions,
     flight::util::attach_cache_metadata,
     timing::{TimeMeasurement, TimedStream},
 };
 
-use super::{flightsql, to_tonic_err, Service};
+use super::{flightsql, request_query_options, to_tonic_err, Service};
 
 pub(crate) async fn handle(
     flight_svc: &Service,
     request: Request<Ticket>,
+    principal: Principal,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
+    let options = request_query_options(request.metadata())?;
+
     let msg: Any = match Message::decode(&*request.get_ref().ticket) {
         Ok(msg) => msg,
-        Err(_) => return Box::pin(do_get_simple(flight_svc, request)).await,
+        Err(_) => return Box::pin(do_get_simple(flight_svc, request, principal, options)).await,
     };
 
     match Command::try_from(msg).map_err(to_tonic_err)? {
         Command::CommandStatementQuery(command) => {
-            Box::pin(flightsql::statement_query::do_get(flight_svc, command)).await
+            Box::pin(flightsql::statement_query::do_get(
+                flight_svc, command, principal, options,
+            ))
+            .await
         }
         Command::CommandPreparedStatementQuery(command) => {
             Box::pin(flightsql::prepared_statement_query::do_get(
-                flight_svc, command,
+                flight_svc, command, principal, options,
             ))
             .await
         }
@@ -71,6 +79,8 @@ pub(crate) async fn handle(
 async fn do_get_simple(
     flight_svc: &Service,
     request: Request<Ticket>,
+    principal: Principal,
+    options: QueryOptions,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
     let datafusion = Arc::clone(&flight_svc.datafusion);
     let ticket = request.into_inner();
@@ -78,8 +88,10 @@ async fn do_get_simple(
     match std::str::from_utf8(&ticket.ticket) {
         Ok(sql) => {
             let start = TimeMeasurement::new("flight_do_get_simple_duration_ms", vec![]);
-            let (output, from_cache) =
-                Box::pin(Service::sql_to_flight_stream(datafusion, sql)).await?;
+            let (output, from_cache) = Box::pin(Service::sql_to_flight_stream(
+                datafusion, sql, principal, options,
+            ))
+            .await?;
 
             let timed_output = TimedStream::new(output, move || start);
 
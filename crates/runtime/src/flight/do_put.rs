@@ -16,19 +16,25 @@ limitations under the License.
 
 use std::{collections::HashMap, sync::Arc};
 
-use arrow_flight::{flight_service_server::FlightService, FlightData, PutResult};
+use arrow_flight::{
+    flight_service_server::FlightService,
+    sql::{Any, Command},
+    FlightData, PutResult,
+};
 use arrow_ipc::convert::try_schema_from_flatbuffer_bytes;
 use datafusion::sql::TableReference;
 use futures::stream;
+use prost::Message;
 use tokio::sync::{broadcast::Sender, RwLock};
 use tonic::{Request, Response, Status, Streaming};
 
 use crate::{
+    auth::{access_control::Permission, Principal},
     dataupdate::{DataUpdate, UpdateType},
     timing::{TimeMeasurement, TimedStream},
 };
 
-use super::Service;
+use super::{flightsql, request_query_options, to_tonic_err, Service};
 
 async fn get_sender_channel(
     channel_map: Arc<RwLock<HashMap<TableReference, Arc<Sender<DataUpdate>>>>>,
@@ -46,8 +52,10 @@ async fn get_sender_channel(
 pub(crate) async fn handle(
     flight_svc: &Service,
     request: Request<Streaming<FlightData>>,
+    principal: &Principal,
 ) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
     let mut duration_metric = TimeMeasurement::new("flight_do_put_duration_ms", vec![]);
+    let options = request_query_options(request.metadata())?;
     let mut streaming_flight = request.into_inner();
 
     let Ok(Some(message)) = streaming_flight.message().await else {
@@ -56,6 +64,23 @@ pub(crate) async fn handle(
     let Some(fd) = &message.flight_descriptor else {
         return Err(Status::invalid_argument("No flight descriptor provided"));
     };
+
+    // FlightSQL commands are sent in the descriptor, plain uploads are addressed by path.
+    if !fd.cmd.is_empty() {
+        let msg: Any = Message::decode(&*fd.cmd).map_err(to_tonic_err)?;
+        return match Command::try_from(msg).map_err(to_tonic_err)? {
+            Command::CommandStatementUpdate(command) => {
+                Box::pin(flightsql::statement_update::do_put(
+                    flight_svc,
+                    command,
+                    principal.clone(),
+                    options,
+                ))
+                .await
+            }
+            _ => Err(Status::unimplemented("Not yet implemented")),
+        };
+    }
     if fd.path.is_empty() {
         return Err(Status::invalid_argument("No path provided"));
     };
@@ -64,6 +89,8 @@ pub(crate) async fn handle(
 
     duration_metric.with_labels(vec![("path", path.to_string())]);
 
+    flight_svc.authorize(principal, &path, Permission::Write)?;
+
     if !flight_svc.datafusion.is_writable(&path) {
         return Err(Status::invalid_argument(format!(
             "Path doesn't exist or is not writable: {path}",
@@ -22,3 +22,4 @@ pub(crate) mod get_table_types;
 pub(crate) mod get_tables;
 pub(crate) mod prepared_statement_query;
 pub(crate) mod statement_query;
+pub(crate) mod statement_update;
@@ -480,7 +480,8 @@ static INSTANCE: Lazy<SqlInfoData> = Lazy::new(|| {
     builder.append(SqlInfo::FlightSqlServerVersion, env!("CARGO_PKG_VERSION"));
     // 1.3 comes from https://github.com/apache/arrow/blob/f9324b79bf4fc1ec7e97b32e3cce16e75ef0f5e3/format/Schema.fbs#L24
     builder.append(SqlInfo::FlightSqlServerArrowVersion, "1.3");
-    builder.append(SqlInfo::FlightSqlServerReadOnly, true);
+    // `read_write` datasets accept INSERT, UPDATE and DELETE statements
+    builder.append(SqlInfo::FlightSqlServerReadOnly, false);
     builder.append(SqlInfoFlightSqlServerSql, true);
     builder.append(SqlInfoFlightSqlServerSubstrait, false);
     builder.append(
@@ -25,6 +25,8 @@ use prost::Message;
 use tonic::{Request, Response, Status};
 
 use crate::{
+    auth::Principal,
+    datafusion::query::limits::QueryOptions,
     flight::{to_tonic_err, util::attach_cache_metadata, Service},
     timing::{TimeMeasurement, TimedStream},
 };
@@ -33,12 +35,16 @@ use crate::{
 pub(crate) async fn do_action_create_prepared_statement(
     flight_svc: &Service,
     statement: sql::ActionCreatePreparedStatementRequest,
+    principal: Principal,
 ) -> Result<sql::ActionCreatePreparedStatementResult, Status> {
     tracing::trace!("do_action_create_prepared_statement: {statement:?}");
-    let arrow_schema =
-        Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), &statement.query)
-            .await
-            .map_err(to_tonic_err)?;
+    let arrow_schema = Service::get_arrow_schema(
+        Arc::clone(&flight_svc.datafusion),
+        &statement.query,
+        principal,
+    )
+    .await
+    .map_err(to_tonic_err)?;
 
     let schema_bytes = Service::serialize_schema(&arrow_schema)?;
 
@@ -53,6 +59,7 @@ pub(crate) async fn get_flight_info(
     flight_svc: &Service,
     handle: sql::CommandPreparedStatementQuery,
     request: Request<FlightDescriptor>,
+    principal: Principal,
 ) -> Result<Response<FlightInfo>, Status> {
     tracing::trace!("get_flight_info: {handle:?}");
 
@@ -65,9 +72,10 @@ pub(crate) async fn get_flight_info(
         }
     };
 
-    let arrow_schema = Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), sql)
-        .await
-        .map_err(to_tonic_err)?;
+    let arrow_schema =
+        Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), sql, principal)
+            .await
+            .map_err(to_tonic_err)?;
 
     tracing::trace!("get_flight_info_prepared_statement: arrow_schema={arrow_schema:?}");
 
@@ -89,6 +97,8 @@ pub(crate) async fn get_flight_info(
 pub(crate) async fn do_get(
     flight_svc: &Service,
     query: sql::CommandPreparedStatementQuery,
+    principal: Principal,
+    options: QueryOptions,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
     let datafusion = Arc::clone(&flight_svc.datafusion);
     tracing::trace!("do_get: {query:?}");
@@ -96,8 +106,10 @@ pub(crate) async fn do_get(
         Ok(sql) => {
             let start =
                 TimeMeasurement::new("flight_do_get_prepared_statement_query_duration_ms", vec![]);
-            let (output, from_cache) =
-                Box::pin(Service::sql_to_flight_stream(datafusion, sql)).await?;
+            let (output, from_cache) = Box::pin(Service::sql_to_flight_stream(
+                datafusion, sql, principal, options,
+            ))
+            .await?;
             let timed_output = TimedStream::new(output, move || start);
 
             let mut response =
@@ -25,6 +25,8 @@ use prost::Message;
 use tonic::{Request, Response, Status};
 
 use crate::{
+    auth::Principal,
+    datafusion::query::limits::QueryOptions,
     flight::{to_tonic_err, util::attach_cache_metadata, Service},
     timing::{TimeMeasurement, TimedStream},
 };
@@ -34,14 +36,16 @@ pub(crate) async fn get_flight_info(
     flight_svc: &Service,
     query: sql::CommandStatementQuery,
     request: Request<FlightDescriptor>,
+    principal: Principal,
 ) -> Result<Response<FlightInfo>, Status> {
     tracing::trace!("get_flight_info: {query:?}");
 
     let sql = query.query.as_str();
 
-    let arrow_schema = Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), sql)
-        .await
-        .map_err(to_tonic_err)?;
+    let arrow_schema =
+        Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), sql, principal)
+            .await
+            .map_err(to_tonic_err)?;
 
     let fd = request.into_inner();
 
@@ -61,12 +65,16 @@ pub(crate) async fn get_flight_info(
 pub(crate) async fn do_get(
     flight_svc: &Service,
     cmd: sql::CommandStatementQuery,
+    principal: Principal,
+    options: QueryOptions,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
     let datafusion = Arc::clone(&flight_svc.datafusion);
     tracing::trace!("do_get_statement: {cmd:?}");
     let start = TimeMeasurement::new("flight_do_get_statement_query_duration_ms", vec![]);
-    let (output, from_cache) =
-        Box::pin(Service::sql_to_flight_stream(datafusion, &cmd.query)).await?;
+    let (output, from_cache) = Box::pin(Service::sql_to_flight_stream(
+        datafusion, &cmd.query, principal, options,
+    ))
+    .await?;
     let timed_output = TimedStream::new(output, move || start);
 
     let mut response =
@@ -0,0 +1,86 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use arrow::{array::AsArray, datatypes::UInt64Type};
+use arrow_flight::{flight_service_server::FlightService, sql, PutResult};
+use futures::{stream, TryStreamExt};
+use prost::Message;
+use tonic::{Response, Status};
+
+use crate::{
+    auth::Principal,
+    datafusion::query::{limits::QueryOptions, Error as QueryError, Protocol, QueryBuilder},
+    flight::{handle_datafusion_error, handle_query_error, Service},
+    timing::{TimeMeasurement, TimedStream},
+};
+
+/// Execute an `INSERT`, `UPDATE` or `DELETE` statement against a `read_write` dataset. The number
+/// of affected rows is returned as a `DoPutUpdateResult` in the `PutResult` metadata.
+pub(crate) async fn do_put(
+    flight_svc: &Service,
+    cmd: sql::CommandStatementUpdate,
+    principal: Principal,
+    options: QueryOptions,
+) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
+    let start = TimeMeasurement::new("flight_do_put_statement_update_duration_ms", vec![]);
+    tracing::trace!("do_put_statement_update: {cmd:?}");
+
+    let query = QueryBuilder::new(
+        &cmd.query,
+        Arc::clone(&flight_svc.datafusion),
+        Protocol::Flight,
+    )
+    .use_restricted_sql_options()
+    .allow_dml()
+    .dml_only()
+    .principal(principal)
+    .options(options)
+    .build();
+
+    // The statement is planned and rejected if it isn't DML before anything is executed.
+    let query_result = query.run().await.map_err(|e| match e {
+        QueryError::ExpectedDmlStatement => Status::invalid_argument(e.to_string()),
+        e => handle_query_error(e),
+    })?;
+    let batches: Vec<_> = query_result
+        .data
+        .try_collect()
+        .await
+        .map_err(handle_datafusion_error)?;
+
+    // DML statements return the number of affected rows as a single `count` column.
+    let record_count: u64 = batches
+        .iter()
+        .filter_map(|batch| batch.column(0).as_primitive_opt::<UInt64Type>())
+        .flat_map(|counts| counts.iter().flatten())
+        .sum();
+    let result = sql::DoPutUpdateResult {
+        record_count: i64::try_from(record_count).unwrap_or(i64::MAX),
+    };
+
+    let output = stream::once(async move {
+        Ok(PutResult {
+            app_metadata: result.encode_to_vec().into(),
+        })
+    });
+
+    Ok(Response::new(
+        Box::pin(TimedStream::new(output, move || start))
+            as <Service as FlightService>::DoPutStream,
+    ))
+}
@@ -23,22 +23,28 @@ use arrow_flight::{
 use prost::Message;
 use tonic::{Request, Response, Status};
 
+use crate::auth::Principal;
+
 use super::{flightsql, to_tonic_err, Service};
 
 pub(crate) async fn handle(
     flight_svc: &Service,
     request: Request<FlightDescriptor>,
+    principal: Principal,
 ) -> Result<Response<FlightInfo>, Status> {
     let Ok(message) = Any::decode(&*request.get_ref().cmd) else {
-        return get_flight_info_simple(flight_svc, request).await;
+        return get_flight_info_simple(flight_svc, request, principal).await;
     };
 
     match Command::try_from(message).map_err(to_tonic_err)? {
         Command::CommandStatementQuery(token) => {
-            flightsql::statement_query::get_flight_info(flight_svc, token, request).await
+            flightsql::statement_query::get_flight_info(flight_svc, token, request, principal).await
         }
         Command::CommandPreparedStatementQuery(handle) => {
-            flightsql::prepared_statement_query::get_flight_info(flight_svc, handle, request).await
+            flightsql::prepared_statement_query::get_flight_info(
+                flight_svc, handle, request, principal,
+            )
+            .await
         }
         Command::CommandGetCatalogs(token) => {
             Ok(flightsql::get_catalogs::get_flight_info(&token, request))
@@ -65,15 +71,17 @@ pub(crate) async fn handle(
 async fn get_flight_info_simple(
     flight_svc: &Service,
     request: Request<FlightDescriptor>,
+    principal: Principal,
 ) -> Result<Response<FlightInfo>, Status> {
     tracing::trace!("get_flight_info_simple: {request:?}");
 
     let fd = request.into_inner();
 
     let sql: &str = std::str::from_utf8(&fd.cmd).map_err(to_tonic_err)?;
-    let arrow_schema = Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), sql)
-        .await
-        .map_err(to_tonic_err)?;
+    let arrow_schema =
+        Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), sql, principal)
+            .await
+            .map_err(to_tonic_err)?;
 
     let info = FlightInfo {
         flight_descriptor: Some(fd.clone()),
@@ -16,20 +16,52 @@ limitations under the License.
 
 use std::pin::Pin;
 
-use arrow_flight::HandshakeResponse;
+use arrow_flight::{HandshakeRequest, HandshakeResponse};
 use futures::Stream;
-use tonic::{metadata::MetadataValue, Response, Status};
+use secrecy::SecretString;
+use tonic::{metadata::MetadataValue, Request, Response, Status, Streaming};
 use uuid::Uuid;
 
-use crate::timing::{TimeMeasurement, TimedStream};
+use crate::{
+    auth::{AuthProvider, Credentials},
+    timing::{TimeMeasurement, TimedStream},
+};
+
+use super::request_credentials;
 
 type HandshakeResponseStream =
     Pin<Box<dyn Stream<Item = Result<HandshakeResponse, Status>> + Send>>;
 
-pub(crate) fn handle() -> Result<Response<HandshakeResponseStream>, Status> {
-    // THIS IS PLACEHOLDER NO-OP AUTH THAT DOES NOT CHECK THE PROVIDED TOKEN AND SIMPLY RETURNS A UUID.
-    // TODO: Implement proper auth.
-    let token = Uuid::new_v4().to_string();
+/// Validates the credentials presented on the handshake and returns a bearer token for subsequent calls.
+///
+/// Credentials are read from the `authorization` header (`Basic` or `Bearer`), falling back to a token sent as the
+/// payload of the first handshake message.
+pub(crate) async fn handle(
+    auth: Option<&AuthProvider>,
+    request: Request<Streaming<HandshakeRequest>>,
+) -> Result<Response<HandshakeResponseStream>, Status> {
+    let token = match auth {
+        Some(auth) => {
+            let credentials = match request_credentials(request.metadata()) {
+                Some(credentials) => credentials,
+                None => payload_credentials(request).await?,
+            };
+
+            let Some(principal) = auth.authenticate(&credentials) else {
+                metrics::counter!("flight_auth_failures").increment(1);
+                return Err(Status::unauthenticated("Invalid credentials"));
+            };
+
+            tracing::debug!(
+                "Flight handshake authenticated principal {}",
+                principal.name
+            );
+            auth.issue_session_token(principal)
+        }
+        // Authentication is not configured, any client is accepted.
+        None => Uuid::new_v4().to_string(),
+    };
+
     let result = HandshakeResponse {
         protocol_version: 0,
         payload: token.as_bytes().to_vec().into(),
@@ -45,3 +77,20 @@ pub(crate) fn handle() -> Result<Response<HandshakeResponseStream>, Status> {
     resp.metadata_mut().insert("authorization", md);
     Ok(resp)
 }
+
+async fn payload_credentials(
+    request: Request<Streaming<HandshakeRequest>>,
+) -> Result<Credentials, Status> {
+    let mut stream = request.into_inner();
+    let Some(message) = stream.message().await? else {
+        return Err(Status::unauthenticated("Missing credentials"));
+    };
+
+    let token = String::from_utf8(message.payload.to_vec())
+        .map_err(|_| Status::unauthenticated("Invalid credentials"))?;
+    if token.is_empty() {
+        return Err(Status::unauthenticated("Missing credentials"));
+    }
+
+    Ok(Credentials::Bearer(SecretString::new(token)))
+}
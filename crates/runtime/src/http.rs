@@ -32,14 +32,16 @@ use tokio::{
 use tokio_rustls::TlsAcceptor;
 
 use crate::{
+    auth::AuthProvider,
     config,
     datafusion::DataFusion,
-    embeddings::vector_search::{self, compute_primary_keys},
+    embeddings::vector_search::{self, compute_full_text_columns, compute_primary_keys},
     model::LLMModelStore,
     tls::TlsConfig,
     EmbeddingModelStore,
 };
 
+mod auth;
 mod routes;
 mod v1;
 
@@ -65,6 +67,7 @@ pub(crate) async fn start<A>(
     config: Arc<config::Config>,
     with_metrics: Option<SocketAddr>,
     tls_config: Option<Arc<TlsConfig>>,
+    auth: Option<Arc<AuthProvider>>,
 ) -> Result<()>
 where
     A: ToSocketAddrs + Debug,
@@ -73,6 +76,7 @@ where
         Arc::clone(&df),
         Arc::clone(&embeddings),
         compute_primary_keys(Arc::clone(&app)).await,
+        compute_full_text_columns(Arc::clone(&app)).await,
     ));
     let routes = routes::routes(
         app,
@@ -83,6 +87,7 @@ where
         config,
         with_metrics,
         vsearch,
+        auth,
     );
 
     let listener = TcpListener::bind(&bind_address)
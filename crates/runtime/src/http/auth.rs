@@ -0,0 +1,255 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use secrecy::SecretString;
+
+use crate::auth::{AuthProvider, Credentials, Principal};
+
+/// Routes that are reachable without credentials, used by orchestrators for health checks.
+const UNAUTHENTICATED_PATHS: [&str; 2] = ["/health", "/v1/ready"];
+
+/// Authenticates the request and attaches the resulting [`Principal`] as a request extension.
+///
+/// Responds with `401 Unauthorized` when no credentials are provided or the credentials are rejected. Requests that are
+/// authenticated but not permitted are rejected with `403 Forbidden` by the handlers.
+pub(crate) async fn authenticate(
+    Extension(auth): Extension<Option<Arc<AuthProvider>>>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let principal = match auth {
+        Some(auth) if !UNAUTHENTICATED_PATHS.contains(&req.uri().path()) => {
+            let Some(credentials) = request_credentials(req.headers()) else {
+                metrics::counter!("http_auth_failures", "reason" => "missing_credentials")
+                    .increment(1);
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, "Bearer")],
+                    "Missing credentials",
+                )
+                    .into_response();
+            };
+
+            let Some(principal) = auth.authenticate(&credentials) else {
+                metrics::counter!("http_auth_failures", "reason" => "invalid_credentials")
+                    .increment(1);
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, r#"Bearer error="invalid_token""#)],
+                    "Invalid credentials",
+                )
+                    .into_response();
+            };
+
+            principal
+        }
+        _ => Principal::anonymous(),
+    };
+
+    req.extensions_mut().insert(principal);
+    next.run(req).await
+}
+
+/// Reads the credentials from the `Authorization` header, or an API key from the `X-API-Key` header.
+fn request_credentials(headers: &HeaderMap) -> Option<Credentials> {
+    if let Some(value) = headers.get(header::AUTHORIZATION) {
+        return Credentials::from_authorization_header(value.to_str().ok()?);
+    }
+
+    let api_key = headers.get("x-api-key")?.to_str().ok()?;
+    Some(Credentials::Bearer(SecretString::new(api_key.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{middleware, routing::get, Router};
+    use spicepod::component::runtime::{ApiKey, ApiKeyAuth, AuthConfig};
+    use tower::ServiceExt;
+
+    use crate::secrets::Secrets;
+
+    use super::*;
+
+    async fn auth_provider() -> Arc<AuthProvider> {
+        let mut secrets = Secrets::new();
+        secrets.load_from(&[]).await.expect("to load secrets");
+
+        let config = AuthConfig {
+            api_key: Some(ApiKeyAuth {
+                keys: vec![ApiKey {
+                    key: "my-api-key".to_string(),
+                    principal: Some("analytics".to_string()),
+                    roles: vec![],
+                }],
+            }),
+            basic: None,
+            jwt: None,
+            grants: vec![],
+        };
+
+        Arc::new(
+            AuthProvider::try_new(&config, &secrets)
+                .await
+                .expect("to create auth provider"),
+        )
+    }
+
+    /// A router that responds with the name of the request's principal.
+    fn router(auth: Option<Arc<AuthProvider>>) -> Router {
+        Router::new()
+            .route(
+                "/v1/whoami",
+                get(|Extension(principal): Extension<Principal>| async move {
+                    principal.name.to_string()
+                }),
+            )
+            .route("/health", get(|| async { "ok" }))
+            .layer(middleware::from_fn(authenticate))
+            .layer(Extension(auth))
+    }
+
+    async fn send(router: Router, path: &str, headers: &[(&str, &str)]) -> (StatusCode, String) {
+        let (status, _, body) = send_with_headers(router, path, headers).await;
+        (status, body)
+    }
+
+    async fn send_with_headers(
+        router: Router,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> (StatusCode, HeaderMap, String) {
+        let mut request = Request::builder().uri(path);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let response = router
+            .oneshot(request.body(Body::empty()).expect("a valid request"))
+            .await
+            .expect("a response");
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("a response body");
+        (
+            status,
+            headers,
+            String::from_utf8(body.to_vec()).expect("a UTF-8 body"),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_missing_key_is_unauthorized() {
+        let router = router(Some(auth_provider().await));
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/whoami")
+                    .body(Body::empty())
+                    .expect("a valid request"),
+            )
+            .await
+            .expect("a response");
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers().get(header::WWW_AUTHENTICATE),
+            Some(&header::HeaderValue::from_static("Bearer"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrong_key_is_unauthorized() {
+        let router = router(Some(auth_provider().await));
+
+        for headers in [
+            [("x-api-key", "wrong")],
+            [("authorization", "Bearer wrong")],
+        ] {
+            let (status, headers, _) =
+                send_with_headers(router.clone(), "/v1/whoami", &headers).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(
+                headers.get(header::WWW_AUTHENTICATE),
+                Some(&header::HeaderValue::from_static(
+                    r#"Bearer error="invalid_token""#
+                ))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_valid_key_attaches_principal() {
+        let router = router(Some(auth_provider().await));
+
+        let (status, body) =
+            send(router.clone(), "/v1/whoami", &[("x-api-key", "my-api-key")]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "analytics");
+
+        let (status, body) = send(
+            router,
+            "/v1/whoami",
+            &[("authorization", "Bearer my-api-key")],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "analytics");
+    }
+
+    #[tokio::test]
+    async fn test_health_is_unauthenticated() {
+        let router = router(Some(auth_provider().await));
+
+        let (status, body) = send(router, "/health", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_deny_all_rejects_every_key() {
+        let router = router(Some(Arc::new(AuthProvider::deny_all())));
+
+        let (status, _) = send(router.clone(), "/v1/whoami", &[("x-api-key", "my-api-key")]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = send(router, "/health", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_auth_disabled_attaches_anonymous_principal() {
+        let router = router(None);
+
+        let (status, body) = send(router.clone(), "/v1/whoami", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "anonymous");
+
+        let (status, body) = send(router, "/v1/whoami", &[("x-api-key", "wrong")]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "anonymous");
+    }
+}
@@ -14,6 +14,7 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::auth::AuthProvider;
 use crate::embeddings::vector_search;
 use crate::model::LLMModelStore;
 use crate::EmbeddingModelStore;
@@ -35,7 +36,7 @@ use axum::{
 };
 use tokio::{sync::RwLock, time::Instant};
 
-use super::v1;
+use super::{auth, v1};
 
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn routes(
@@ -47,10 +48,12 @@ pub(crate) fn routes(
     config: Arc<config::Config>,
     with_metrics: Option<SocketAddr>,
     vector_search: Arc<vector_search::VectorSearch>,
+    auth: Option<Arc<AuthProvider>>,
 ) -> Router {
     let mut router = Router::new()
         .route("/health", get(|| async { "ok\n" }))
         .route("/v1/sql", post(v1::query::post))
+        .route("/v1/queries/:id/cancel", post(v1::queries::cancel))
         .route("/v1/status", get(v1::status::get))
         .route("/v1/catalogs", get(v1::catalogs::get))
         .route("/v1/datasets", get(v1::datasets::get))
@@ -86,7 +89,9 @@ pub(crate) fn routes(
         .layer(Extension(app))
         .layer(Extension(df))
         .layer(Extension(with_metrics))
-        .layer(Extension(config));
+        .layer(Extension(config))
+        .layer(middleware::from_fn(auth::authenticate))
+        .layer(Extension(auth));
     router
 }
 
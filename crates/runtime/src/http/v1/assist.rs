@@ -36,8 +36,16 @@ use tokio::sync::RwLock;
 use futures::StreamExt;
 
 use crate::{
-    embeddings::vector_search::{RetrievalLimit, VectorSearch, VectorSearchResult},
-    model::LLMModelStore,
+    auth::Principal,
+    datafusion::DataFusion,
+    embeddings::vector_search::{
+        self, RetrievalLimit, SearchMode, VectorSearch, VectorSearchResult,
+    },
+    model::{
+        queue::is_too_many_requests_chat_error,
+        usage::{Endpoint, UsageTracker},
+        LLMModelStore,
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -120,17 +128,28 @@ pub(crate) fn create_primary_key_payload(
 }
 
 async fn context_aware_stream(
-    model: &RwLock<Box<dyn Chat>>,
+    model: &dyn Chat,
     vector_search_data: &VectorSearchResult,
     model_input: String,
+    mut usage: UsageTracker,
 ) -> Response {
-    let mut model_stream = match model.write().await.stream(model_input).await {
+    let mut model_stream = match model.stream(model_input).await {
         Ok(model_stream) => model_stream,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(e) => {
+            usage.error(&e);
+            usage.finish().await;
+            if is_too_many_requests_chat_error(&e) {
+                return (StatusCode::TOO_MANY_REQUESTS, e.to_string()).into_response();
+            }
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
     };
     let vector_data = match create_primary_key_payload(&vector_search_data.retrieved_public_keys) {
         Ok(vector_data) => vector_data,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(e) => {
+            usage.finish().await;
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
     };
 
     Sse::new(Box::pin(stream! {
@@ -138,6 +157,7 @@ async fn context_aware_stream(
         while let Some(msg_result) = model_stream.next().await {
             match msg_result {
                 Err(e) => {
+                    usage.error(&e);
                     yield Err(axum::Error::new(e.to_string()));
                     break;
                 }
@@ -150,17 +170,25 @@ async fn context_aware_stream(
                 }
             }
         }
+        usage.finish().await;
     }))
     .keep_alive(KeepAlive::default())
     .into_response()
 }
 
 async fn context_aware_chat(
-    model: &RwLock<Box<dyn Chat>>,
+    model: &dyn Chat,
     vector_search_data: &VectorSearchResult,
     model_input: String,
+    mut usage: UsageTracker,
 ) -> Response {
-    match model.write().await.run(model_input).await {
+    let result = model.run(model_input).await;
+    if let Err(e) = &result {
+        usage.error(e);
+    }
+    usage.finish().await;
+
+    match result {
         Ok(Some(text)) => {
             match create_primary_key_payload(&vector_search_data.retrieved_public_keys) {
                 Ok(from) => (StatusCode::OK, Json(AssistResponse { text, from })).into_response(),
@@ -172,6 +200,9 @@ async fn context_aware_chat(
             "No response from LLM".to_string(),
         )
             .into_response(),
+        Err(e) if is_too_many_requests_chat_error(&e) => {
+            (StatusCode::TOO_MANY_REQUESTS, e.to_string()).into_response()
+        }
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
@@ -217,6 +248,8 @@ async fn context_aware_chat(
 pub(crate) async fn post(
     Extension(vs): Extension<Arc<VectorSearch>>,
     Extension(llms): Extension<Arc<RwLock<LLMModelStore>>>,
+    Extension(df): Extension<Arc<DataFusion>>,
+    Extension(principal): Extension<Principal>,
     Query(params): Query<QueryParams>,
     Json(payload): Json<Request>,
 ) -> Response {
@@ -231,10 +264,20 @@ pub(crate) async fn post(
         .collect();
 
     let relevant_data = match vs
-        .search(payload.text.clone(), input_tables, RetrievalLimit::TopN(3))
+        .search(
+            payload.text.clone(),
+            input_tables,
+            RetrievalLimit::TopN(3),
+            SearchMode::Vector,
+            None,
+            Some(&principal),
+        )
         .await
     {
         Ok(relevant_data) => relevant_data,
+        Err(e @ vector_search::Error::AccessDenied { .. }) => {
+            return (StatusCode::FORBIDDEN, e.to_string()).into_response()
+        }
         Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     };
 
@@ -244,16 +287,20 @@ pub(crate) async fn post(
     );
 
     // Run LLM with input.
-    match llms.read().await.get(&payload.model) {
+    let llm_model = llms.read().await.get(&payload.model).cloned();
+    match llm_model {
         Some(llm_model) => {
             let model_input = combined_relevant_data_and_input(
                 &relevant_data.retrieved_entries,
                 &payload.text.clone(),
             );
+            // Models run through `/v1/assist` don't report the tokens they use.
+            let usage =
+                UsageTracker::new(df, &payload.model, Endpoint::Assist).principal(&principal);
             if params.stream {
-                context_aware_stream(llm_model, &relevant_data, model_input).await
+                context_aware_stream(llm_model.as_ref(), &relevant_data, model_input, usage).await
             } else {
-                context_aware_chat(llm_model, &relevant_data, model_input).await
+                context_aware_chat(llm_model.as_ref(), &relevant_data, model_input, usage).await
             }
         }
         None => (
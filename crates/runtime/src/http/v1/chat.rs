@@ -17,7 +17,13 @@ limitations under the License.
 use core::time;
 use std::{sync::Arc, time::Duration};
 
-use async_openai::types::{ChatCompletionResponseStream, CreateChatCompletionRequest};
+use app::App;
+use async_openai::error::OpenAIError;
+use async_openai::types::{
+    ChatChoiceStream, ChatCompletionMessageToolCallChunk, ChatCompletionResponseStream,
+    ChatCompletionStreamResponseDelta, CreateChatCompletionRequest, CreateChatCompletionResponse,
+    CreateChatCompletionStreamResponse, FunctionCallStream,
+};
 use async_stream::stream;
 use axum::{
     http::StatusCode,
@@ -30,37 +36,179 @@ use axum::{
 use futures::StreamExt;
 use tokio::sync::RwLock;
 
-use crate::model::LLMModelStore;
+use crate::{
+    auth::Principal,
+    datafusion::DataFusion,
+    embeddings::vector_search::VectorSearch,
+    model::{
+        queue::is_too_many_requests,
+        usage::{Endpoint, UsageTracker},
+        LLMModelStore,
+    },
+    tools::{self, BuiltinTool, ToolContext},
+};
 
 pub(crate) async fn post(
+    Extension(app): Extension<Arc<RwLock<Option<App>>>>,
     Extension(llms): Extension<Arc<RwLock<LLMModelStore>>>,
+    Extension(df): Extension<Arc<DataFusion>>,
+    Extension(vector_search): Extension<Arc<VectorSearch>>,
+    Extension(principal): Extension<Principal>,
     Json(req): Json<CreateChatCompletionRequest>,
 ) -> Response {
     let model_id = req.model.clone();
-    match llms.read().await.get(&model_id) {
-        Some(model) => {
-            if req.stream.unwrap_or_default() {
-                match model.write().await.chat_stream(req).await {
-                    Ok(strm) => create_sse_response(strm, time::Duration::from_secs(30)),
-                    Err(e) => {
-                        tracing::debug!("Error from v1/chat: {e}");
-                        StatusCode::INTERNAL_SERVER_ERROR.into_response()
-                    }
-                }
-            } else {
-                match model.write().await.chat_request(req).await {
-                    Ok(response) => Json(response).into_response(),
-                    Err(e) => {
-                        tracing::debug!("Error from v1/chat: {e}");
-                        StatusCode::INTERNAL_SERVER_ERROR.into_response()
-                    }
-                }
+    let builtin_tools = match model_tools(&app, &model_id).await {
+        Ok(tools) => tools,
+        Err(e) => {
+            tracing::debug!("Error from v1/chat: {e}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    };
+
+    // Clone the model out of the store, so requests don't hold the lock while they run.
+    let Some(model) = llms.read().await.get(&model_id).cloned() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let mut usage =
+        UsageTracker::new(Arc::clone(&df), &model_id, Endpoint::Chat).principal(&principal);
+
+    if !builtin_tools.is_empty() {
+        let stream = req.stream.unwrap_or_default();
+        let ctx = ToolContext::new(df, vector_search, principal);
+        let result = tools::run_tool_loop(model.as_ref(), req, &builtin_tools, &ctx).await;
+        match &result {
+            Ok(response) => usage.add_usage(response.usage.as_ref()),
+            Err(e) => usage.error(e),
+        }
+        usage.finish().await;
+
+        return match result {
+            Ok(response) if stream => {
+                create_sse_response(response_to_stream(response), time::Duration::from_secs(30))
+            }
+            Ok(response) => Json(response).into_response(),
+            Err(tools::Error::FailedToRunModel { source }) => error_response(&source),
+            Err(e) => {
+                tracing::debug!("Error from v1/chat: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        };
+    }
+
+    if req.stream.unwrap_or_default() {
+        match model.chat_stream(req).await {
+            Ok(strm) => {
+                create_sse_response(track_usage(strm, usage), time::Duration::from_secs(30))
+            }
+            Err(e) => {
+                usage.error(&e);
+                usage.finish().await;
+                error_response(&e)
+            }
+        }
+    } else {
+        match model.chat_request(req).await {
+            Ok(response) => {
+                usage.add_usage(response.usage.as_ref());
+                usage.finish().await;
+                Json(response).into_response()
+            }
+            Err(e) => {
+                usage.error(&e);
+                usage.finish().await;
+                error_response(&e)
             }
         }
-        None => StatusCode::NOT_FOUND.into_response(),
     }
 }
 
+/// Records the usage of a stream once it ends. Usage is only sent in the last chunk of streams
+/// that request it with `stream_options`.
+fn track_usage(
+    mut strm: ChatCompletionResponseStream,
+    mut usage: UsageTracker,
+) -> ChatCompletionResponseStream {
+    Box::pin(stream! {
+        while let Some(chunk) = strm.next().await {
+            match &chunk {
+                Ok(chunk) => usage.add_usage(chunk.usage.as_ref()),
+                Err(e) => usage.error(e),
+            }
+            yield chunk;
+        }
+        usage.finish().await;
+    })
+}
+
+fn error_response(e: &OpenAIError) -> Response {
+    tracing::debug!("Error from v1/chat: {e}");
+    if is_too_many_requests(e) {
+        return (StatusCode::TOO_MANY_REQUESTS, e.to_string()).into_response();
+    }
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+/// The built-in tools enabled by the `tools` parameter of the model, if any.
+async fn model_tools(app: &RwLock<Option<App>>, model_id: &str) -> tools::Result<Vec<BuiltinTool>> {
+    let app = app.read().await;
+    let tools = app
+        .as_ref()
+        .and_then(|app| app.models.iter().find(|m| m.name == model_id))
+        .and_then(|m| m.params.get("tools"));
+
+    match tools {
+        Some(tools) => BuiltinTool::parse_list(tools),
+        None => Ok(vec![]),
+    }
+}
+
+/// Sends a response from the tool-call loop, which doesn't stream, as a single chunk.
+#[allow(deprecated)]
+fn response_to_stream(response: CreateChatCompletionResponse) -> ChatCompletionResponseStream {
+    let choices = response
+        .choices
+        .into_iter()
+        .map(|choice| ChatChoiceStream {
+            delta: ChatCompletionStreamResponseDelta {
+                content: choice.message.content,
+                tool_calls: choice.message.tool_calls.map(|calls| {
+                    calls
+                        .into_iter()
+                        .zip(0..)
+                        .map(|(call, index)| ChatCompletionMessageToolCallChunk {
+                            index,
+                            id: Some(call.id),
+                            r#type: Some(call.r#type),
+                            function: Some(FunctionCallStream {
+                                name: Some(call.function.name),
+                                arguments: Some(call.function.arguments),
+                            }),
+                        })
+                        .collect()
+                }),
+                role: Some(choice.message.role),
+                function_call: None,
+            },
+            index: choice.index,
+            finish_reason: choice.finish_reason,
+            logprobs: choice.logprobs,
+        })
+        .collect();
+
+    let chunk = CreateChatCompletionStreamResponse {
+        id: response.id,
+        choices,
+        model: response.model,
+        created: response.created,
+        system_fingerprint: response.system_fingerprint,
+        object: "chat.completion.chunk".to_string(),
+        usage: response.usage,
+    };
+
+    Box::pin(futures::stream::once(async { Ok(chunk) }))
+}
+
 /// Create a SSE [`axum::response::Response`] from a [`ChatCompletionResponseStream`].
 fn create_sse_response(
     mut strm: ChatCompletionResponseStream,
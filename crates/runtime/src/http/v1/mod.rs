@@ -21,6 +21,7 @@ pub mod embeddings;
 pub mod inference;
 pub mod models;
 pub mod nsql;
+pub mod queries;
 pub mod query;
 pub mod ready;
 pub mod search;
@@ -30,8 +31,13 @@ pub mod status;
 use std::sync::Arc;
 
 use crate::{
+    auth::Principal,
     component::dataset::Dataset,
-    datafusion::query::{Protocol, QueryBuilder},
+    datafusion::query::{
+        self,
+        limits::{QueryOptions, QUERY_ID_HEADER},
+        Protocol, QueryBuilder,
+    },
 };
 use arrow::array::RecordBatch;
 use axum::{
@@ -40,6 +46,7 @@ use axum::{
 };
 use csv::Writer;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::{datafusion::DataFusion, status::ComponentStatus};
 
@@ -71,11 +78,33 @@ fn dataset_status(df: &DataFusion, ds: &Dataset) -> ComponentStatus {
 }
 
 // Runs query and converts query results to HTTP response (as JSON).
-pub async fn sql_to_http_response(df: Arc<DataFusion>, sql: &str, nsql: Option<&str>) -> Response {
+//
+// The query id, timeout and memory limit can be set with the `X-Spice-Query-*` request headers.
+pub async fn sql_to_http_response(
+    df: Arc<DataFusion>,
+    sql: &str,
+    nsql: Option<&str>,
+    principal: Principal,
+    request_headers: &HeaderMap,
+) -> Response {
+    let options = match QueryOptions::from_headers(|name| {
+        request_headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    }) {
+        Ok(options) => options,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let query_id = options.query_id.unwrap_or_else(Uuid::new_v4);
+
     let query = QueryBuilder::new(sql, Arc::clone(&df), Protocol::Http)
         .use_restricted_sql_options()
+        .allow_dml()
         .nsql(nsql)
         .protocol(Protocol::Http)
+        .principal(principal)
+        .options(options)
+        .query_id(query_id)
         .build();
 
     let (data, is_data_from_cache) = match query.run().await {
@@ -90,6 +119,14 @@ pub async fn sql_to_http_response(df: Arc<DataFusion>, sql: &str, nsql: Option<&
                     .into_response();
             }
         },
+        Err(e @ query::Error::AccessDenied { .. }) => {
+            tracing::debug!("Error executing query: {e}");
+            return (StatusCode::FORBIDDEN, e.to_string()).into_response();
+        }
+        Err(e @ query::Error::QueryIdInUse { .. }) => {
+            tracing::debug!("Error executing query: {e}");
+            return (StatusCode::CONFLICT, e.to_string()).into_response();
+        }
         Err(e) => {
             tracing::debug!("Error executing query: {e}");
             return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
@@ -118,6 +155,10 @@ pub async fn sql_to_http_response(df: Arc<DataFusion>, sql: &str, nsql: Option<&
 
     let mut headers = HeaderMap::new();
 
+    if let Ok(value) = query_id.to_string().parse() {
+        headers.insert(QUERY_ID_HEADER, value);
+    }
+
     match is_data_from_cache {
         Some(true) => {
             if let Ok(value) = "Hit from spiceai".parse() {
@@ -13,30 +13,80 @@ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 See the License for the specific language governing permissions and
 limitations under the License.
 */
+use app::App;
+use arrow::{
+    array::RecordBatch,
+    datatypes::{DataType, Schema},
+};
 use async_openai::{
     error::OpenAIError,
     types::{
-        ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+        ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+        ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
         ChatCompletionResponseFormat, ChatCompletionResponseFormatType,
         CreateChatCompletionRequest, CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
     },
 };
 use axum::{
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Extension, Json,
 };
+use datafusion::{error::DataFusionError, scalar::ScalarValue, sql::TableReference};
 use datafusion_table_providers::sql::arrow_sql_gen::statement::CreateTableBuilder;
+use futures::TryStreamExt;
 use llms::{
     chat::{Error as ChatError, Result as ChatResult},
     openai::MAX_COMPLETION_TOKENS,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::sync::Arc;
+use serde_json::{json, Value};
+use snafu::prelude::*;
+use std::{cmp::Reverse, collections::HashSet, sync::Arc};
 use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    auth::{access_control::Permission, Principal},
+    datafusion::{
+        query::{
+            self,
+            limits::{QueryOptions, QUERY_ID_HEADER},
+            Protocol,
+        },
+        DataFusion,
+    },
+    model::{
+        queue::is_too_many_requests,
+        usage::{Endpoint, UsageTracker},
+        LLMModelStore,
+    },
+};
+
+/// The maximum number of times the model is asked to repair SQL that failed to plan or run.
+const MAX_REPAIR_ATTEMPTS: usize = 3;
+
+/// Above this number of tables, only the tables most relevant to the question are described in the prompt.
+const MAX_PROMPT_TABLES: usize = 10;
+
+/// The number of sample rows of each table included in the prompt.
+const SAMPLE_ROWS: usize = 3;
 
-use crate::{datafusion::DataFusion, http::v1::sql_to_http_response, model::LLMModelStore};
+/// Column statistics in the prompt are computed over at most this many rows of each table, to
+/// bound their cost on large tables.
+const STATISTICS_ROWS: usize = 10_000;
+
+/// Longer sample rows and statistics values are truncated in the prompt.
+const MAX_PROMPT_VALUE_LENGTH: usize = 256;
+
+#[derive(Debug, Snafu)]
+enum Error {
+    #[snafu(display("Failed to plan the query: {source}"))]
+    FailedToPlanQuery { source: query::Error },
+
+    #[snafu(display("Failed to run the query: {source}"))]
+    FailedToRunQuery { source: DataFusionError },
+}
 
 fn clean_model_based_sql(input: &str) -> String {
     let no_dashes = match input.strip_prefix("--") {
@@ -62,11 +112,31 @@ fn default_model() -> String {
     "nql".to_string()
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NsqlResponse {
+    /// The SQL generated by the model that produced the results.
+    pub sql: String,
+
+    /// The rows returned by the SQL.
+    pub results: Value,
+}
+
+#[allow(clippy::too_many_lines)]
 pub(crate) async fn post(
+    Extension(app): Extension<Arc<RwLock<Option<App>>>>,
     Extension(df): Extension<Arc<DataFusion>>,
     Extension(llms): Extension<Arc<RwLock<LLMModelStore>>>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
     Json(payload): Json<Request>,
 ) -> Response {
+    let options = match QueryOptions::from_headers(|name| {
+        headers.get(name).and_then(|value| value.to_str().ok())
+    }) {
+        Ok(options) => options,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
     // Get all public table CREATE TABLE statements to add to prompt.
     let tables = match df.get_public_table_names() {
         Ok(t) => t,
@@ -76,13 +146,25 @@ pub(crate) async fn post(
         }
     };
 
-    let mut table_create_stms: Vec<String> = Vec::with_capacity(tables.len());
-    for t in &tables {
-        match df.get_arrow_schema(t).await {
-            Ok(schm) => {
-                let c = CreateTableBuilder::new(Arc::new(schm), format!("public.{t}").as_str());
-                table_create_stms.push(c.build_sqlite());
-            }
+    // Only describe the tables the caller is allowed to read.
+    let access_control = df.access_control();
+    let tables = tables
+        .into_iter()
+        .filter(|t| {
+            access_control.as_ref().map_or(true, |access_control| {
+                access_control.is_allowed(
+                    &principal,
+                    &TableReference::bare(t.as_str()),
+                    Permission::Read,
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut table_schemas: Vec<(String, Schema)> = Vec::with_capacity(tables.len());
+    for t in tables {
+        match df.get_arrow_schema(&t).await {
+            Ok(schm) => table_schemas.push((t, schm)),
             Err(e) => {
                 tracing::error!("Error getting table={t} schema: {e}");
                 return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
@@ -90,62 +172,425 @@ pub(crate) async fn post(
         }
     }
 
+    let mut table_descriptions: Vec<String> = Vec::new();
+    for (t, schm) in relevant_tables(table_schemas, &payload.query) {
+        table_descriptions.push(describe_table(&df, &t, schm, &principal).await);
+    }
+
     // Construct prompt
     let nsql_query = format!(
             "```SQL\n{table_create_schemas}\n-- Using valid postgres SQL, without comments, answer the following questions for the tables provided above.\n-- {user_query}",
             user_query=payload.query,
-            table_create_schemas=table_create_stms.join("\n")
+            table_create_schemas=table_descriptions.join("\n")
         );
 
     tracing::trace!("Running prompt: {nsql_query}");
 
     let model_id = payload.model.clone();
-    let response = match llms.read().await.get(&model_id) {
-        Some(nql_model) => {
-            let Ok(req) = create_chat_request(model_id, &nsql_query) else {
-                return (
+    let Some(nql_model) = llms.read().await.get(&model_id).cloned() else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Model {} not found", payload.model),
+        )
+            .into_response();
+    };
+    let max_completion_tokens = model_max_completion_tokens(&app, &model_id).await;
+    let mut usage =
+        UsageTracker::new(Arc::clone(&df), &model_id, Endpoint::Nsql).principal(&principal);
+    let Ok(mut req) = create_chat_request(model_id, &nsql_query, max_completion_tokens) else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error preparing data for NQL model".to_string(),
+        )
+            .into_response();
+    };
+
+    // Run the SQL from the NSQL model through datafusion, asking the model to repair it if it fails.
+    let mut repair_attempts = 0;
+    let response = loop {
+        let response = match nql_model.chat_request(req.clone()).await {
+            Ok(r) => r,
+            Err(e) if is_too_many_requests(&e) => {
+                usage.error(&e);
+                break (StatusCode::TOO_MANY_REQUESTS, e.to_string()).into_response();
+            }
+            Err(e) => {
+                tracing::error!("Error running NQL model: {e}");
+                usage.error(&e);
+                break (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+            }
+        };
+        usage.add_usage(response.usage.as_ref());
+
+        let cleaned_query = match process_response(response, max_completion_tokens) {
+            Ok(Some(model_sql_query)) => clean_model_based_sql(&model_sql_query),
+            Ok(None) => {
+                tracing::trace!("No query produced from NSQL model");
+                break (
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    "Error preparing data for NQL model".to_string(),
+                    "No query produced from NSQL model".to_string(),
                 )
                     .into_response();
-            };
-            match nql_model.write().await.chat_request(req).await {
-                Ok(r) => r,
-                Err(e) => {
-                    tracing::error!("Error running NQL model: {e}");
-                    return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
-                }
             }
+            Err(e) => {
+                tracing::error!("Error running NSQL model: {e}");
+                break (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+            }
+        };
+        tracing::trace!("Running query:\n{cleaned_query}");
+
+        let query_id = options.query_id.unwrap_or_else(Uuid::new_v4);
+        let error = match run_query(
+            &df,
+            &cleaned_query,
+            &nsql_query,
+            principal.clone(),
+            options,
+            query_id,
+        )
+        .await
+        {
+            Ok((batches, from_cache)) => {
+                break nsql_response(cleaned_query, &batches, from_cache, query_id)
+            }
+            Err(
+                e @ Error::FailedToPlanQuery {
+                    source: query::Error::AccessDenied { .. },
+                },
+            ) => {
+                tracing::debug!("Error executing query: {e}");
+                break (StatusCode::FORBIDDEN, e.to_string()).into_response();
+            }
+            Err(e) => e,
+        };
+
+        if repair_attempts == MAX_REPAIR_ATTEMPTS {
+            tracing::debug!("Error executing query: {error}");
+            break (
+                StatusCode::BAD_REQUEST,
+                format!("{error}\nGenerated SQL: {cleaned_query}"),
+            )
+                .into_response();
         }
-        None => {
-            return (
+        repair_attempts += 1;
+        tracing::debug!(
+            "Generated SQL failed, asking the model to repair it ({repair_attempts}/{MAX_REPAIR_ATTEMPTS}): {error}"
+        );
+
+        let Ok(messages) = repair_messages(&cleaned_query, &error) else {
+            break (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Model {} not found", payload.model),
+                "Error preparing data for NQL model".to_string(),
             )
-                .into_response()
-        }
+                .into_response();
+        };
+        req.messages.extend(messages);
     };
 
-    // Run the SQL from the NSQL model through datafusion.
-    match process_response(response) {
-        Ok(Some(model_sql_query)) => {
-            let cleaned_query = clean_model_based_sql(&model_sql_query);
-            tracing::trace!("Running query:\n{cleaned_query}");
+    usage.finish().await;
+    response
+}
+
+/// The `max_completion_tokens` param of the model, defaulting to [`MAX_COMPLETION_TOKENS`]. The
+/// param is validated when the model is loaded.
+async fn model_max_completion_tokens(app: &RwLock<Option<App>>, model_id: &str) -> u32 {
+    let app = app.read().await;
+    app.as_ref()
+        .and_then(|app| app.models.iter().find(|m| m.name == model_id))
+        .and_then(|m| m.params.get("max_completion_tokens"))
+        .and_then(|tokens| tokens.parse().ok())
+        .unwrap_or(u32::from(MAX_COMPLETION_TOKENS))
+}
+
+/// Plans and runs the SQL generated for a question, collecting its results.
+async fn run_query(
+    df: &Arc<DataFusion>,
+    sql: &str,
+    nsql: &str,
+    principal: Principal,
+    options: QueryOptions,
+    query_id: Uuid,
+) -> Result<(Vec<RecordBatch>, Option<bool>), Error> {
+    let query_result = df
+        .query_builder(sql, Protocol::Http)
+        .use_restricted_sql_options()
+        .nsql(Some(nsql))
+        .principal(principal)
+        .options(options)
+        .query_id(query_id)
+        .build()
+        .run()
+        .await
+        .context(FailedToPlanQuerySnafu)?;
+
+    let batches = query_result
+        .data
+        .try_collect::<Vec<RecordBatch>>()
+        .await
+        .context(FailedToRunQuerySnafu)?;
+
+    Ok((batches, query_result.from_cache))
+}
 
-            sql_to_http_response(Arc::clone(&df), &cleaned_query, Some(&nsql_query)).await
+fn nsql_response(
+    sql: String,
+    batches: &[RecordBatch],
+    from_cache: Option<bool>,
+    query_id: Uuid,
+) -> Response {
+    let mut writer = arrow_json::ArrayWriter::new(Vec::new());
+    if let Err(e) = writer.write_batches(&batches.iter().collect::<Vec<_>>()) {
+        tracing::debug!("Error converting results to JSON: {e}");
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    if let Err(e) = writer.finish() {
+        tracing::debug!("Error finishing JSON conversion: {e}");
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    // The writer outputs nothing for empty results.
+    let buf = writer.into_inner();
+    let results = if buf.is_empty() {
+        Ok(json!([]))
+    } else {
+        serde_json::from_slice(&buf)
+    };
+    let results = match results {
+        Ok(results) => results,
+        Err(e) => {
+            tracing::debug!("Error converting JSON buffer: {e}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
         }
-        Ok(None) => {
-            tracing::trace!("No query produced from NSQL model");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "No query produced from NSQL model".to_string(),
-            )
-                .into_response()
+    };
+
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = query_id.to_string().parse() {
+        headers.insert(QUERY_ID_HEADER, value);
+    }
+    let cache_status = match from_cache {
+        Some(true) => Some("Hit from spiceai"),
+        Some(false) => Some("Miss from spiceai"),
+        None => None,
+    };
+    if let Some(Ok(value)) = cache_status.map(str::parse) {
+        headers.insert("X-Cache", value);
+    }
+
+    (StatusCode::OK, headers, Json(NsqlResponse { sql, results })).into_response()
+}
+
+/// The messages asking the model to repair SQL it generated that failed.
+fn repair_messages(
+    sql: &str,
+    error: &Error,
+) -> Result<Vec<ChatCompletionRequestMessage>, OpenAIError> {
+    Ok(vec![
+        ChatCompletionRequestAssistantMessageArgs::default()
+            .content(json!({ "sql": sql }).to_string())
+            .build()?
+            .into(),
+        ChatCompletionRequestUserMessageArgs::default()
+            .content(format!(
+                "The SQL failed with the following error. Return JSON, with the corrected SQL under 'sql'.\n{error}"
+            ))
+            .build()?
+            .into(),
+    ])
+}
+
+/// For large catalogs, keeps the [`MAX_PROMPT_TABLES`] tables whose name and columns share the
+/// most words with the question.
+fn relevant_tables(tables: Vec<(String, Schema)>, question: &str) -> Vec<(String, Schema)> {
+    if tables.len() <= MAX_PROMPT_TABLES {
+        return tables;
+    }
+
+    let terms: HashSet<String> = words(question).collect();
+    let mut scored_tables: Vec<(usize, (String, Schema))> = tables
+        .into_iter()
+        .map(|(name, schema)| {
+            let name_matches = words(&name).filter(|w| terms.contains(w)).count();
+            let column_matches = schema
+                .fields()
+                .iter()
+                .flat_map(|f| words(f.name()))
+                .filter(|w| terms.contains(w))
+                .count();
+            (3 * name_matches + column_matches, (name, schema))
+        })
+        .collect();
+
+    // Stable, so tables that score the same keep their order.
+    scored_tables.sort_by_key(|(score, _)| Reverse(*score));
+    scored_tables
+        .into_iter()
+        .take(MAX_PROMPT_TABLES)
+        .map(|(_, table)| table)
+        .collect()
+}
+
+/// The lowercase words of a question or identifier, without a plural `s`, i.e. `taxi_trips` is
+/// `taxi` and `trip`.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(|w| w.to_lowercase().trim_end_matches('s').to_string())
+}
+
+/// Describes a table for the prompt, with its `CREATE TABLE` statement, sample rows and column
+/// statistics. Sample rows and statistics are left out if they fail to compute.
+///
+/// Sample rows and statistics are queried as the principal, so they only include the rows and
+/// column values the principal can read.
+async fn describe_table(
+    df: &Arc<DataFusion>,
+    table: &str,
+    schema: Schema,
+    principal: &Principal,
+) -> String {
+    let table_reference = TableReference::partial("public", table);
+    let schema = Arc::new(schema);
+    let mut description =
+        vec![
+            CreateTableBuilder::new(Arc::clone(&schema), format!("public.{table}").as_str())
+                .build_sqlite(),
+        ];
+
+    match sample_rows(df, &table_reference, principal).await {
+        Ok(rows) if !rows.is_empty() => {
+            description.push(format!("-- Sample rows from public.{table}:"));
+            description.extend(rows.iter().map(|row| format!("-- {row}")));
         }
-        Err(e) => {
-            tracing::error!("Error running NSQL model: {e}");
-            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        Ok(_) => {}
+        Err(e) => tracing::debug!("Error getting sample rows of table={table}: {e}"),
+    }
+
+    match column_statistics(df, &table_reference, &schema, principal).await {
+        Ok(statistics) if !statistics.is_empty() => {
+            description.push(format!(
+                "-- Column statistics of public.{table}, over up to {STATISTICS_ROWS} rows:"
+            ));
+            description.extend(statistics.iter().map(|s| format!("-- {s}")));
         }
+        Ok(_) => {}
+        Err(e) => tracing::debug!("Error getting column statistics of table={table}: {e}"),
+    }
+
+    description.join("\n")
+}
+
+async fn sample_rows(
+    df: &Arc<DataFusion>,
+    table: &TableReference,
+    principal: &Principal,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let batches = collect_as(
+        df,
+        &format!(
+            "SELECT * FROM {} LIMIT {SAMPLE_ROWS}",
+            table.to_quoted_string()
+        ),
+        principal,
+    )
+    .await?;
+
+    let mut writer = arrow_json::LineDelimitedWriter::new(Vec::new());
+    writer.write_batches(&batches.iter().collect::<Vec<_>>())?;
+    writer.finish()?;
+
+    Ok(String::from_utf8(writer.into_inner())?
+        .lines()
+        .map(|row| truncate(row).to_string())
+        .collect())
+}
+
+/// The minimum, maximum, number of distinct values and number of nulls of each column with
+/// orderable values.
+async fn column_statistics(
+    df: &Arc<DataFusion>,
+    table: &TableReference,
+    schema: &Schema,
+    principal: &Principal,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let columns: Vec<&String> = schema
+        .fields()
+        .iter()
+        .filter(|f| has_statistics(f.data_type()))
+        .map(|f| f.name())
+        .collect();
+    if columns.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let aggregates = columns
+        .iter()
+        .map(|column| {
+            let column = format!("\"{}\"", column.replace('"', "\"\""));
+            format!("MIN({column}), MAX({column}), COUNT(DISTINCT {column}), COUNT(*) - COUNT({column})")
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let batches = collect_as(
+        df,
+        &format!(
+            "SELECT {aggregates} FROM (SELECT * FROM {} LIMIT {STATISTICS_ROWS})",
+            table.to_quoted_string()
+        ),
+        principal,
+    )
+    .await?;
+    let Some(batch) = batches.iter().find(|b| b.num_rows() > 0) else {
+        return Ok(vec![]);
+    };
+
+    columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let value = |j: usize| -> Result<String, DataFusionError> {
+                let value = ScalarValue::try_from_array(batch.column(4 * i + j), 0)?;
+                Ok(truncate(&value.to_string()).to_string())
+            };
+            Ok(format!(
+                "{column}: min {}, max {}, {} distinct values, {} nulls",
+                value(0)?,
+                value(1)?,
+                value(2)?,
+                value(3)?
+            ))
+        })
+        .collect()
+}
+
+/// Runs a query for the prompt as the principal, enforcing the dataset grants, policies and query
+/// limits.
+async fn collect_as(
+    df: &Arc<DataFusion>,
+    sql: &str,
+    principal: &Principal,
+) -> Result<Vec<RecordBatch>, Box<dyn std::error::Error + Send + Sync>> {
+    let query_result = df
+        .query_builder(sql, Protocol::Http)
+        .use_restricted_sql_options()
+        .principal(principal.clone())
+        .build()
+        .run()
+        .await?;
+    Ok(query_result.data.try_collect().await?)
+}
+
+fn has_statistics(data_type: &DataType) -> bool {
+    data_type.is_primitive()
+        || matches!(
+            data_type,
+            DataType::Boolean | DataType::Utf8 | DataType::LargeUtf8
+        )
+}
+
+fn truncate(value: &str) -> &str {
+    match value.char_indices().nth(MAX_PROMPT_VALUE_LENGTH) {
+        Some((end, _)) => value.get(..end).unwrap_or(value),
+        None => value,
     }
 }
 
@@ -162,6 +607,7 @@ pub fn convert_json_object_to_sql(raw_json: &str) -> ChatResult<Option<String>>
 pub fn create_chat_request(
     model_id: String,
     prompt: &str,
+    max_completion_tokens: u32,
 ) -> Result<CreateChatCompletionRequest, OpenAIError> {
     let messages: Vec<ChatCompletionRequestMessage> = vec![
         ChatCompletionRequestSystemMessageArgs::default()
@@ -180,16 +626,18 @@ pub fn create_chat_request(
             r#type: ChatCompletionResponseFormatType::JsonObject,
         })
         .messages(messages)
-        .max_tokens(MAX_COMPLETION_TOKENS)
+        .max_tokens(max_completion_tokens)
         .build()
 }
 
-pub fn process_response(resp: CreateChatCompletionResponse) -> ChatResult<Option<String>> {
+pub fn process_response(
+    resp: CreateChatCompletionResponse,
+    max_completion_tokens: u32,
+) -> ChatResult<Option<String>> {
     if let Some(usage) = resp.usage {
-        if usage.completion_tokens >= u32::from(MAX_COMPLETION_TOKENS) {
+        if usage.completion_tokens >= max_completion_tokens {
             tracing::warn!(
-                "Completion response may have been cut off after {} tokens",
-                MAX_COMPLETION_TOKENS
+                "Completion response may have been cut off after {max_completion_tokens} tokens"
             );
         }
     }
@@ -199,3 +647,88 @@ pub fn process_response(resp: CreateChatCompletionResponse) -> ChatResult<Option
         None => Ok(None),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::StringArray;
+    use arrow::datatypes::Field;
+    use datafusion::datasource::MemTable;
+    use spicepod::component::dataset::policy::{ColumnMask, Policy};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_relevant_tables() {
+        let schema = |columns: &[&str]| {
+            Schema::new(
+                columns
+                    .iter()
+                    .map(|c| Field::new(*c, DataType::Utf8, true))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let few_tables = vec![("orders".to_string(), schema(&["id"]))];
+        assert_eq!(
+            relevant_tables(few_tables.clone(), "How many trips?"),
+            few_tables
+        );
+
+        let mut tables: Vec<(String, Schema)> = (0..12)
+            .map(|i| (format!("table_{i}"), schema(&["id"])))
+            .collect();
+        tables.push(("customers".to_string(), schema(&["id", "fare_amount"])));
+        tables.push(("taxi_trips".to_string(), schema(&["id", "fare_amount"])));
+
+        let relevant = relevant_tables(tables, "What is the average fare of the trips?");
+        assert_eq!(relevant.len(), MAX_PROMPT_TABLES);
+        assert_eq!(relevant[0].0, "taxi_trips");
+        assert_eq!(relevant[1].0, "customers");
+        assert_eq!(relevant[2].0, "table_0");
+    }
+
+    #[tokio::test]
+    async fn test_describe_table_applies_column_masks() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("ssn", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(StringArray::from(vec!["ada", "grace"])),
+                Arc::new(StringArray::from(vec!["123-45-6789", "987-65-4321"])),
+            ],
+        )
+        .expect("valid batch");
+        let table = MemTable::try_new(Arc::clone(&schema), vec![vec![batch]]).expect("valid table");
+
+        let df = Arc::new(DataFusion::new());
+        let customers = TableReference::partial("public", "customers");
+        df.ctx
+            .register_table(customers.clone(), Arc::new(table))
+            .expect("table to register");
+        df.set_dataset_policy(
+            &customers,
+            Some(Policy {
+                row_filter: None,
+                column_masks: HashMap::from([("ssn".to_string(), ColumnMask::Redact)]),
+                exempt_roles: vec![],
+            }),
+        )
+        .expect("policy to set");
+
+        let description = describe_table(
+            &df,
+            "customers",
+            Schema::clone(&schema),
+            &Principal::new("analyst", vec![]),
+        )
+        .await;
+        assert!(description.contains("Sample rows"), "{description}");
+        assert!(description.contains("grace"), "{description}");
+        assert!(description.contains("***"), "{description}");
+        assert!(!description.contains("123-45-6789"), "{description}");
+        assert!(!description.contains("987-65-4321"), "{description}");
+    }
+}
@@ -0,0 +1,65 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use uuid::Uuid;
+
+use crate::{auth::Principal, datafusion::DataFusion};
+
+use super::datasets::MessageResponse;
+
+/// Cancels a running query started by the caller, using the id returned in the `X-Spice-Query-Id` response header.
+pub(crate) async fn cancel(
+    Extension(df): Extension<Arc<DataFusion>>,
+    Extension(principal): Extension<Principal>,
+    Path(query_id): Path<String>,
+) -> Response {
+    let Ok(query_id) = Uuid::parse_str(&query_id) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(MessageResponse {
+                message: format!("Invalid query id {query_id}"),
+            }),
+        )
+            .into_response();
+    };
+
+    if !df.running_queries().cancel(&query_id, &principal) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(MessageResponse {
+                message: format!("Query {query_id} not found"),
+            }),
+        )
+            .into_response();
+    }
+
+    metrics::counter!("queries_cancelled", "protocol" => "http").increment(1);
+    (
+        StatusCode::OK,
+        Json(MessageResponse {
+            message: format!("Query {query_id} cancelled"),
+        }),
+    )
+        .into_response()
+}
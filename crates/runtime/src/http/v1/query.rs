@@ -17,16 +17,21 @@ use std::sync::Arc;
 
 use axum::{
     body::Bytes,
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Extension,
 };
 
-use crate::datafusion::DataFusion;
+use crate::{auth::Principal, datafusion::DataFusion};
 
 use super::sql_to_http_response;
 
-pub(crate) async fn post(Extension(df): Extension<Arc<DataFusion>>, body: Bytes) -> Response {
+pub(crate) async fn post(
+    Extension(df): Extension<Arc<DataFusion>>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
     let query = match String::from_utf8(body.to_vec()) {
         Ok(query) => query,
         Err(e) => {
@@ -35,5 +40,5 @@ pub(crate) async fn post(Extension(df): Extension<Arc<DataFusion>>, body: Bytes)
         }
     };
 
-    sql_to_http_response(df, &query, None).await
+    sql_to_http_response(df, &query, None, principal, &headers).await
 }
@@ -13,7 +13,12 @@ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 See the License for the specific language governing permissions and
 limitations under the License.
 */
-use crate::embeddings::vector_search::{RetrievalLimit, VectorSearch, VectorSearchResult};
+use crate::{
+    auth::Principal,
+    embeddings::vector_search::{
+        self, Fusion, RetrievalLimit, SearchMode, VectorSearch, VectorSearchResult,
+    },
+};
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
@@ -35,18 +40,113 @@ pub struct Request {
     #[serde(rename = "from", default)]
     pub data_source: Vec<String>,
 
-    #[serde(default = "default_limit")]
-    pub limit: usize,
+    /// The maximum number of results to return per data source. Defaults to 3 unless a `threshold` is set.
+    #[serde(default)]
+    pub limit: Option<usize>,
+
+    /// Only return results whose distance from `text` is below this threshold.
+    #[serde(default)]
+    pub threshold: Option<f64>,
+
+    /// How results are ranked.
+    #[serde(default)]
+    pub mode: Mode,
+
+    /// In `hybrid` mode, how the vector and full-text rankings are combined.
+    #[serde(default)]
+    pub fusion: FusionMethod,
+
+    /// For `weighted` fusion, the weight of the vector ranking, between 0 and 1. Defaults to 0.5.
+    #[serde(default)]
+    pub vector_weight: Option<f64>,
+
+    /// A SQL predicate that rows must satisfy to be searched, e.g. `customer_id = 42`.
+    #[serde(rename = "where", default)]
+    pub where_cond: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Vector,
+    FullText,
+    Hybrid,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FusionMethod {
+    /// Reciprocal rank fusion.
+    #[default]
+    Rrf,
+    Weighted,
 }
 
 fn default_limit() -> usize {
     3
 }
 
+fn default_vector_weight() -> f64 {
+    0.5
+}
+
+impl Request {
+    fn retrieval_limit(&self) -> Result<RetrievalLimit, String> {
+        match self.threshold {
+            Some(max_distance) if !max_distance.is_finite() || max_distance < 0.0 => Err(format!(
+                "Invalid threshold {max_distance}, expected a non-negative number"
+            )),
+            Some(max_distance) => Ok(RetrievalLimit::Threshold {
+                max_distance,
+                limit: self.limit,
+            }),
+            None => Ok(RetrievalLimit::TopN(
+                self.limit.unwrap_or_else(default_limit),
+            )),
+        }
+    }
+
+    fn search_mode(&self) -> Result<SearchMode, String> {
+        match (self.mode, self.fusion) {
+            (Mode::FullText, _) if self.threshold.is_some() => {
+                Err("A threshold can't be used in full_text mode".to_string())
+            }
+            (Mode::Vector, _) => Ok(SearchMode::Vector),
+            (Mode::FullText, _) => Ok(SearchMode::FullText),
+            (Mode::Hybrid, FusionMethod::Rrf) => Ok(SearchMode::Hybrid(Fusion::ReciprocalRank)),
+            (Mode::Hybrid, FusionMethod::Weighted) => {
+                match self.vector_weight.unwrap_or_else(default_vector_weight) {
+                    weight if (0.0..=1.0).contains(&weight) => {
+                        Ok(SearchMode::Hybrid(Fusion::Weighted {
+                            vector_weight: weight,
+                        }))
+                    }
+                    weight => Err(format!(
+                        "Invalid vector_weight {weight}, expected a number between 0 and 1"
+                    )),
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResponse {
     pub entries: HashMap<String, Vec<String>>,
     pub retrieved_public_keys: HashMap<String, Value>,
+
+    /// In `vector` mode, the distance of each entry from the search text, in the same order as `entries`. Lower is more similar.
+    pub distances: HashMap<String, Vec<f64>>,
+
+    /// In `full_text` and `hybrid` modes, the score of each entry, in the same order as `entries`. Higher is more relevant.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub scores: HashMap<String, Vec<f64>>,
+
+    /// For data sources with a chunked embedding column, the `[start, end)` byte offsets of each
+    /// entry's chunk within the column value, in the same order as `entries`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub offsets: HashMap<String, Vec<[i32; 2]>>,
 }
 
 impl SearchResponse {
@@ -61,12 +161,28 @@ impl SearchResponse {
                 .map(|(k, v)| (k.to_string(), v))
                 .collect(),
             retrieved_public_keys: keys,
+            distances: result
+                .retrieved_distances
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+            scores: result
+                .retrieved_scores
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+            offsets: result
+                .retrieved_offsets
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
         })
     }
 }
 
 pub(crate) async fn post(
     Extension(vs): Extension<Arc<VectorSearch>>,
+    Extension(principal): Extension<Principal>,
     Json(payload): Json<Request>,
 ) -> Response {
     // For now, force the user to specify which data.
@@ -79,11 +195,19 @@ pub(crate) async fn post(
         .map(TableReference::from)
         .collect();
 
+    let (limit, mode) = match (payload.retrieval_limit(), payload.search_mode()) {
+        (Ok(limit), Ok(mode)) => (limit, mode),
+        (Err(e), _) | (_, Err(e)) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
     match vs
         .search(
             payload.text.clone(),
             input_tables,
-            RetrievalLimit::TopN(payload.limit),
+            limit,
+            mode,
+            payload.where_cond.clone(),
+            Some(&principal),
         )
         .await
     {
@@ -91,6 +215,12 @@ pub(crate) async fn post(
             Ok(r) => (StatusCode::OK, Json(r)).into_response(),
             Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
         },
+        Err(e @ vector_search::Error::InvalidFilter { .. }) => {
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+        Err(e @ vector_search::Error::AccessDenied { .. }) => {
+            (StatusCode::FORBIDDEN, e.to_string()).into_response()
+        }
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
@@ -32,6 +32,7 @@ use ::datafusion::sql::sqlparser::{self, ast};
 use ::datafusion::sql::TableReference;
 use accelerated_table::AcceleratedTable;
 use app::App;
+use auth::{access_control::AccessControl, AuthProvider};
 use builder::RuntimeBuilder;
 use cache::QueryResultsCacheProvider;
 use component::catalog::Catalog;
@@ -39,7 +40,7 @@ use component::dataset::acceleration::RefreshMode;
 use component::dataset::{self, Dataset};
 use component::view::View;
 use config::Config;
-use datafusion::query::query_history;
+use datafusion::query::{limits::QueryLimits, query_history};
 use datafusion::SPICE_RUNTIME_SCHEMA;
 use datasets_health_monitor::DatasetsHealthMonitor;
 use embeddings::connector::EmbeddingConnector;
@@ -50,15 +51,17 @@ use llms::chat::Chat;
 use llms::embeddings::Embed;
 use metrics::SetRecorderError;
 use metrics_exporter_prometheus::PrometheusHandle;
-use model::{try_to_chat_model, try_to_embedding, LLMModelStore};
+use model::{try_to_chat_model, try_to_embedding, try_to_queued_chat_model, LLMModelStore};
 use model_components::model::Model;
 pub use notify::Error as NotifyError;
+use reload::{ComponentChanges, ReloadReport, SecretComponent, SecretFingerprints};
 use secrecy::SecretString;
 use secrets::ParamStr;
 use snafu::prelude::*;
 use spice_metrics::get_metrics_table_reference;
 use spicepod::component::embeddings::Embeddings;
 use spicepod::component::model::{Model as SpicepodModel, ModelType};
+use spicepod::component::runtime::{QueryConfig, ResultsCache};
 use tls::TlsConfig;
 use tokio::sync::oneshot::error::RecvError;
 use tokio::sync::RwLock;
@@ -69,6 +72,7 @@ use util::{retry, RetryError};
 
 use crate::extension::Extension;
 pub mod accelerated_table;
+pub mod auth;
 mod builder;
 pub mod component;
 pub mod config;
@@ -89,11 +93,13 @@ pub mod object_store_registry;
 pub mod objectstore;
 mod opentelemetry;
 pub mod podswatcher;
+pub mod reload;
 pub mod secrets;
 pub mod spice_metrics;
 pub mod status;
 pub mod timing;
 pub mod tls;
+pub mod tools;
 pub(crate) mod tracers;
 mod tracing_util;
 
@@ -114,6 +120,9 @@ pub enum Error {
     #[snafu(display("Unable to start OpenTelemetry server: {source}"))]
     UnableToStartOpenTelemetryServer { source: opentelemetry::Error },
 
+    #[snafu(display("Unable to initialize authentication: {source}"))]
+    UnableToInitializeAuth { source: auth::Error },
+
     #[snafu(display("Unknown data source: {data_source}"))]
     UnknownDataSource { data_source: String },
 
@@ -238,6 +247,9 @@ pub enum Error {
     #[snafu(display("Unable to track query history: {source}"))]
     UnableToTrackQueryHistory { source: query_history::Error },
 
+    #[snafu(display("Unable to track LLM usage: {source}"))]
+    UnableToTrackLlmUsage { source: model::usage::Error },
+
     #[snafu(display("Unable to create metrics table: {source}"))]
     UnableToCreateMetricsTable { source: DataFusionError },
 
@@ -344,6 +356,8 @@ impl Runtime {
         self.register_metrics_table(self.metrics_handle.clone())
             .await?;
 
+        let auth = self.load_auth_provider().await?;
+
         let http_server_future = tokio::spawn(http::start(
             config.http_bind_address,
             Arc::clone(&self.app),
@@ -354,6 +368,7 @@ impl Runtime {
             config.clone().into(),
             self.metrics_endpoint,
             tls_config.clone(),
+            auth.clone(),
         ));
 
         // Spawn the metrics server in the background
@@ -372,11 +387,16 @@ impl Runtime {
             config.flight_bind_address,
             Arc::clone(&self.df),
             tls_config.clone(),
+            auth.clone(),
         ));
         let open_telemetry_server_future = tokio::spawn(opentelemetry::start(
             config.open_telemetry_bind_address,
             Arc::clone(&self.df),
             tls_config.clone(),
+            opentelemetry::Datasets {
+                traces: config.open_telemetry_traces_dataset.clone(),
+                logs: config.open_telemetry_logs_dataset.clone(),
+            },
         ));
         let pods_watcher_future = self.start_pods_watcher();
 
@@ -424,6 +444,36 @@ impl Runtime {
         }
     }
 
+    /// Creates the authentication provider from the `runtime.auth` section of the spicepod, if configured.
+    ///
+    /// Without a loaded spicepod it isn't known whether authentication is required, so all credentials are rejected. As
+    /// authentication changes require a restart, this holds until the runtime is restarted with a valid spicepod.
+    async fn load_auth_provider(&self) -> Result<Option<Arc<AuthProvider>>> {
+        let app_lock = self.app.read().await;
+        let Some(app) = app_lock.as_ref() else {
+            tracing::warn!("No spicepod is loaded, all requests to the HTTP and Flight endpoints will be rejected until the runtime is restarted with a valid spicepod.");
+            return Ok(Some(Arc::new(AuthProvider::deny_all())));
+        };
+        let Some(auth_config) = app.runtime.auth.as_ref() else {
+            return Ok(None);
+        };
+
+        let secrets = self.secrets.read().await;
+        let auth_provider = AuthProvider::try_new(auth_config, &secrets)
+            .await
+            .context(UnableToInitializeAuthSnafu)?;
+        tracing::info!("Authentication enabled for the HTTP and Flight endpoints");
+
+        if !auth_config.grants.is_empty() {
+            let access_control =
+                AccessControl::try_new(&auth_config.grants).context(UnableToInitializeAuthSnafu)?;
+            self.df.set_access_control(access_control);
+            tracing::info!("Dataset access control enabled");
+        }
+
+        Ok(Some(Arc::new(auth_provider)))
+    }
+
     /// Will load all of the components of the Runtime, including `secret_stores`, `catalogs`, `datasets`, `models`, and `embeddings`.
     ///
     /// The future returned by this function will not resolve until all components have been loaded.
@@ -440,11 +490,17 @@ impl Runtime {
                 };
             }),
             Box::pin(self.init_results_cache()),
+            Box::pin(self.init_query_limits()),
             Box::pin(self.load_datasets()),
             Box::pin(self.load_catalogs()),
         ];
 
         if cfg!(feature = "models") {
+            futures.push(Box::pin(async {
+                if let Err(err) = self.init_llm_usage().await {
+                    tracing::warn!("Creating internal LLM usage table: {err}");
+                };
+            }));
             futures.push(Box::pin(self.load_models()));
         }
 
@@ -679,6 +735,15 @@ impl Runtime {
         .await;
     }
 
+    fn remove_view(&self, view: &View) {
+        if let Err(e) = self.df.remove_table(&view.name) {
+            tracing::warn!("Unable to unload view {}: {e}", &view.name);
+            return;
+        }
+
+        tracing::info!("Unloaded view {}", &view.name);
+    }
+
     fn load_view(&self, view: &View, all_datasets: &[Dataset]) -> Result<()> {
         let existing_tables = all_datasets
             .iter()
@@ -723,6 +788,18 @@ impl Runtime {
         Ok(data_connector)
     }
 
+    fn remove_catalog(&self, catalog_name: &str) {
+        // Only catalogs that loaded successfully are registered in DataFusion.
+        if self.df.catalog_exists(catalog_name) {
+            if let Err(e) = self.df.remove_catalog(catalog_name) {
+                tracing::warn!("Unable to unload catalog {catalog_name}: {e}");
+                return;
+            }
+        }
+
+        tracing::info!("Unloaded catalog {catalog_name}");
+    }
+
     async fn load_dataset_connector(&self, ds: &Dataset) -> Result<Arc<dyn DataConnector>> {
         let spaced_tracer = Arc::clone(&self.spaced_tracer);
         let ds = ds.clone();
@@ -927,6 +1004,25 @@ impl Runtime {
         metrics::gauge!("datasets_count", "engine" => engine).decrement(1.0);
     }
 
+    /// Lets the data connector of a dataset that was removed from the app release what it created
+    /// in the source for it. Datasets that are reloaded keep it.
+    async fn remove_dataset_from_source(&self, ds: &Dataset) {
+        let result = match self
+            .get_dataconnector_from_source(&ds.source(), ds.params.clone())
+            .await
+        {
+            Ok(connector) => connector.remove_dataset(ds).await.boxed(),
+            Err(e) => Err(e.into()),
+        };
+
+        if let Err(e) = result {
+            tracing::warn!(
+                "Unable to clean up the source of removed dataset {}: {e}",
+                ds.name
+            );
+        }
+    }
+
     async fn update_dataset(&self, ds: &Dataset) {
         status::update_dataset(&ds.name, status::ComponentStatus::Refreshing);
         match self.load_dataset_connector(ds).await {
@@ -1134,7 +1230,16 @@ impl Runtime {
         m: SpicepodModel,
         params: HashMap<String, SecretString>,
     ) -> Result<Box<dyn Chat>> {
-        let mut l = try_to_chat_model(&m, &params)
+        if let Some(tools) = m.params.get("tools") {
+            tools::BuiltinTool::parse_list(tools)
+                .boxed()
+                .context(UnableToInitializeLlmSnafu)?;
+        }
+
+        let l = try_to_chat_model(&m, &params)
+            .boxed()
+            .context(UnableToInitializeLlmSnafu)?;
+        let l = try_to_queued_chat_model(&m, &params, l)
             .boxed()
             .context(UnableToInitializeLlmSnafu)?;
 
@@ -1142,7 +1247,7 @@ impl Runtime {
             .await
             .boxed()
             .context(UnableToInitializeLlmSnafu)?;
-        Ok(l)
+        Ok(Box::new(l))
     }
 
     /// Loads a specific Embedding model from the spicepod. If an error occurs, no retry attempt is made.
@@ -1164,28 +1269,43 @@ impl Runtime {
         if let Some(app) = app_lock.as_ref() {
             for in_embed in &app.embeddings {
                 status::update_embedding(&in_embed.name, status::ComponentStatus::Initializing);
-                match self.load_embedding(in_embed).await {
-                    Ok(e) => {
-                        let mut embeds_map = self.embeds.write().await;
-                        embeds_map.insert(in_embed.name.clone(), e.into());
-                        tracing::info!("Embedding [{}] ready to embed", in_embed.name);
-                        metrics::gauge!("embeddings_count", "embeddings" => in_embed.name.clone(), "source" => in_embed.get_prefix().map(|x| x.to_string()).unwrap_or_default()).increment(1.0);
-                        status::update_embedding(&in_embed.name, status::ComponentStatus::Ready);
-                    }
-                    Err(e) => {
-                        metrics::counter!("embeddings_load_error").increment(1);
-                        status::update_embedding(&in_embed.name, status::ComponentStatus::Error);
-                        tracing::warn!(
-                            "Unable to load embedding from spicepod {}, error: {}",
-                            in_embed.name,
-                            e,
-                        );
-                    }
-                }
+                self.register_embedding(in_embed).await;
+            }
+        }
+    }
+
+    // Caller must set `status::update_embedding(...` before calling `register_embedding`. This function will set error/ready statuses appropriately.`
+    async fn register_embedding(&self, in_embed: &Embeddings) {
+        match self.load_embedding(in_embed).await {
+            Ok(e) => {
+                let mut embeds_map = self.embeds.write().await;
+                embeds_map.insert(in_embed.name.clone(), e.into());
+                tracing::info!("Embedding [{}] ready to embed", in_embed.name);
+                metrics::gauge!("embeddings_count", "embeddings" => in_embed.name.clone(), "source" => in_embed.get_prefix().map(|x| x.to_string()).unwrap_or_default()).increment(1.0);
+                status::update_embedding(&in_embed.name, status::ComponentStatus::Ready);
+            }
+            Err(e) => {
+                metrics::counter!("embeddings_load_error").increment(1);
+                status::update_embedding(&in_embed.name, status::ComponentStatus::Error);
+                tracing::warn!(
+                    "Unable to load embedding from spicepod {}, error: {}",
+                    in_embed.name,
+                    e,
+                );
             }
         }
     }
 
+    async fn remove_embedding(&self, in_embed: &Embeddings) {
+        let mut embeds_map = self.embeds.write().await;
+        if embeds_map.remove(&in_embed.name).is_none() {
+            return;
+        }
+
+        tracing::info!("Embedding [{}] has been unloaded", in_embed.name);
+        metrics::gauge!("embeddings_count", "embeddings" => in_embed.name.clone(), "source" => in_embed.get_prefix().map(|x| x.to_string()).unwrap_or_default()).decrement(1.0);
+    }
+
     async fn load_models(&self) {
         let app_lock = self.app.read().await;
         if let Some(app) = app_lock.as_ref() {
@@ -1314,95 +1434,261 @@ impl Runtime {
                 tracing::debug!("Updated pods information: {:?}", new_app);
                 tracing::debug!("Previous pods information: {:?}", current_app);
 
-                // Check for new and updated catalogs
-                let valid_catalogs = Self::get_valid_catalogs(&new_app, LogErrors(true));
-                let existing_catalogs = Self::get_valid_catalogs(current_app, LogErrors(false));
+                let report = self.apply_app_changes(current_app, &new_app).await;
+                tracing::info!("Reloaded spicepod: {report}");
+                metrics::counter!("spicepod_reloads").increment(1);
 
-                for catalog in &valid_catalogs {
-                    if let Some(current_catalog) =
-                        existing_catalogs.iter().find(|c| c.name == catalog.name)
-                    {
-                        if catalog != current_catalog {
-                            // It isn't currently possible to remove catalogs once they have been loaded in DataFusion. `load_catalog` will overwrite the existing catalog.
-                            self.load_catalog(catalog).await;
-                        }
-                    } else {
-                        status::update_catalog(
-                            &catalog.name,
-                            status::ComponentStatus::Initializing,
-                        );
-                        self.load_catalog(catalog).await;
-                    }
-                }
+                *current_app = new_app;
+            } else {
+                *app_lock = Some(new_app);
+            }
+        }
 
-                // Check for new and updated datasets
-                let valid_datasets = Self::get_valid_datasets(&new_app, LogErrors(true));
-                let existing_datasets = Self::get_valid_datasets(current_app, LogErrors(false));
+        Ok(())
+    }
 
-                for ds in &valid_datasets {
-                    if let Some(current_ds) = existing_datasets.iter().find(|d| d.name == ds.name) {
-                        if ds != current_ds {
-                            self.update_dataset(ds).await;
-                        }
-                    } else {
-                        status::update_dataset(&ds.name, status::ComponentStatus::Initializing);
-                        self.load_dataset(ds).await;
-                    }
-                }
+    /// Applies the changes between two versions of the spicepod to the loaded components, and
+    /// reports what changed.
+    #[allow(clippy::too_many_lines)]
+    async fn apply_app_changes(&self, current_app: &App, new_app: &App) -> ReloadReport {
+        let mut report = ReloadReport::default();
+
+        // Reload the secret stores first, so that components load with the new secrets.
+        let mut secrets_changed = None;
+        if current_app.secrets != new_app.secrets {
+            let before = self.secret_fingerprints(current_app).await;
+            *self.secrets.write().await = RuntimeBuilder::load_secrets(Some(new_app)).await;
+            let after = self.secret_fingerprints(new_app).await;
+
+            report.secrets = true;
+            secrets_changed = Some((before, after));
+        }
 
-                // Remove datasets that are no longer in the app
-                for ds in &current_app.datasets {
-                    if !new_app.datasets.iter().any(|d| d.name == ds.name) {
-                        let ds = match Dataset::try_from(ds.clone()) {
-                            Ok(ds) => ds,
-                            Err(e) => {
-                                tracing::error!("Could not remove dataset {}: {e}", ds.name);
-                                continue;
-                            }
-                        };
-                        status::update_dataset(&ds.name, status::ComponentStatus::Disabled);
-                        self.remove_dataset(&ds).await;
-                    }
+        let valid_catalogs = Self::get_valid_catalogs(new_app, LogErrors(true));
+        let existing_catalogs = Self::get_valid_catalogs(current_app, LogErrors(false));
+        report.catalogs =
+            ComponentChanges::diff(&existing_catalogs, &valid_catalogs, |c| c.name.clone());
+
+        let valid_datasets = Self::get_valid_datasets(new_app, LogErrors(true));
+        let existing_datasets = Self::get_valid_datasets(current_app, LogErrors(false));
+        report.datasets =
+            ComponentChanges::diff(&existing_datasets, &valid_datasets, |d| d.name.to_string());
+
+        report.embeddings =
+            ComponentChanges::diff(&current_app.embeddings, &new_app.embeddings, |e| {
+                e.name.clone()
+            });
+        report.models =
+            ComponentChanges::diff(&current_app.models, &new_app.models, |m| m.name.clone());
+
+        // Components are reloaded if the secrets in their params changed, even if their definition didn't.
+        if let Some((before, after)) = &secrets_changed {
+            for (changes, component) in [
+                (&mut report.catalogs, SecretComponent::Catalog),
+                (&mut report.datasets, SecretComponent::Dataset),
+                (&mut report.embeddings, SecretComponent::Embedding),
+                (&mut report.models, SecretComponent::Model),
+            ] {
+                for name in before.changed(after, component) {
+                    changes.update(name);
                 }
+            }
+        }
 
-                // check for new and updated models
-                for model in &new_app.models {
-                    if let Some(current_model) =
-                        current_app.models.iter().find(|m| m.name == model.name)
-                    {
-                        if current_model != model {
-                            self.update_model(model).await;
-                        }
-                    } else {
-                        status::update_model(&model.name, status::ComponentStatus::Initializing);
-                        self.load_model(model).await;
-                    }
+        // Datasets precompute vectors with the embedding models they use, so they are reloaded when those change.
+        for ds in &valid_datasets {
+            let uses_changed_embedding = ds.embeddings.iter().any(|e| {
+                report.embeddings.updated.contains(&e.model)
+                    || report.embeddings.removed.contains(&e.model)
+            });
+            if uses_changed_embedding {
+                report.datasets.update(ds.name.to_string());
+            }
+        }
+
+        // Embeddings must be loaded before the datasets that use them.
+        for name in &report.embeddings.removed {
+            if let Some(embedding) = current_app.embeddings.iter().find(|e| &e.name == name) {
+                status::update_embedding(&embedding.name, status::ComponentStatus::Disabled);
+                self.remove_embedding(embedding).await;
+            }
+        }
+        for embedding in &new_app.embeddings {
+            if report.embeddings.added.contains(&embedding.name) {
+                status::update_embedding(&embedding.name, status::ComponentStatus::Initializing);
+                self.register_embedding(embedding).await;
+            } else if report.embeddings.updated.contains(&embedding.name) {
+                status::update_embedding(&embedding.name, status::ComponentStatus::Refreshing);
+                if let Some(current) = current_app
+                    .embeddings
+                    .iter()
+                    .find(|e| e.name == embedding.name)
+                {
+                    self.remove_embedding(current).await;
                 }
+                self.register_embedding(embedding).await;
+            }
+        }
 
-                // Remove models that are no longer in the app
-                for model in &current_app.models {
-                    if !new_app.models.iter().any(|m| m.name == model.name) {
-                        status::update_model(&model.name, status::ComponentStatus::Disabled);
-                        self.remove_model(model).await;
-                    }
+        for name in &report.catalogs.removed {
+            status::update_catalog(name, status::ComponentStatus::Disabled);
+            self.remove_catalog(name);
+        }
+        for catalog in &valid_catalogs {
+            if report.catalogs.added.contains(&catalog.name) {
+                status::update_catalog(&catalog.name, status::ComponentStatus::Initializing);
+                self.load_catalog(catalog).await;
+            } else if report.catalogs.updated.contains(&catalog.name) {
+                // `load_catalog` overwrites the existing catalog.
+                status::update_catalog(&catalog.name, status::ComponentStatus::Refreshing);
+                self.load_catalog(catalog).await;
+            }
+        }
+
+        for ds in &existing_datasets {
+            if report.datasets.removed.contains(&ds.name.to_string()) {
+                status::update_dataset(&ds.name, status::ComponentStatus::Disabled);
+                self.remove_dataset(ds).await;
+                self.remove_dataset_from_source(ds).await;
+            }
+        }
+        for ds in &valid_datasets {
+            let name = ds.name.to_string();
+            if report.datasets.added.contains(&name) {
+                status::update_dataset(&ds.name, status::ComponentStatus::Initializing);
+                self.load_dataset(ds).await;
+            } else if report.datasets.updated.contains(&name) {
+                self.update_dataset(ds).await;
+            }
+        }
+
+        let valid_views = Self::get_valid_views(new_app, LogErrors(true));
+        let existing_views = Self::get_valid_views(current_app, LogErrors(false));
+        report.views =
+            ComponentChanges::diff(&existing_views, &valid_views, |v| v.name.to_string());
+
+        // Views are planned against the tables they depend on, so they are recreated when those change.
+        for view in &valid_views {
+            let depends_on_changed_dataset = get_view_dependent_tables(view).is_ok_and(|tables| {
+                tables
+                    .iter()
+                    .any(|t| report.datasets.updated.contains(&t.to_string()))
+            });
+            if depends_on_changed_dataset {
+                report.views.update(view.name.to_string());
+            }
+        }
+
+        for view in &existing_views {
+            let name = view.name.to_string();
+            if report.views.removed.contains(&name) || report.views.updated.contains(&name) {
+                self.remove_view(view);
+            }
+        }
+        for view in &valid_views {
+            let name = view.name.to_string();
+            if report.views.added.contains(&name) || report.views.updated.contains(&name) {
+                if let Err(e) = self.load_view(view, &valid_datasets) {
+                    tracing::error!("Unable to load view: {e}");
                 }
+            }
+        }
 
-                *current_app = new_app;
-            } else {
-                *app_lock = Some(new_app);
+        for model in &current_app.models {
+            if report.models.removed.contains(&model.name) {
+                status::update_model(&model.name, status::ComponentStatus::Disabled);
+                self.remove_model(model).await;
+            }
+        }
+        for model in &new_app.models {
+            if report.models.added.contains(&model.name) {
+                status::update_model(&model.name, status::ComponentStatus::Initializing);
+                self.load_model(model).await;
+            } else if report.models.updated.contains(&model.name) {
+                self.update_model(model).await;
             }
         }
 
-        Ok(())
+        if current_app.runtime.results_cache != new_app.runtime.results_cache {
+            self.set_results_cache(&new_app.runtime.results_cache);
+            report.runtime.push("results_cache");
+        }
+        if current_app.runtime.query != new_app.runtime.query {
+            self.set_query_limits(&new_app.runtime.query);
+            report.runtime.push("query");
+        }
+
+        // The endpoints and extensions are only configured when the runtime starts.
+        if current_app.runtime.tls != new_app.runtime.tls {
+            report.requires_restart.push("runtime.tls");
+        }
+        if current_app.runtime.auth != new_app.runtime.auth {
+            report.requires_restart.push("runtime.auth");
+        }
+        if current_app.extensions != new_app.extensions {
+            report.requires_restart.push("extensions");
+        }
+
+        report
+    }
+
+    /// Fingerprints the secrets resolved in the params of each component of the app.
+    async fn secret_fingerprints(&self, app: &App) -> SecretFingerprints {
+        let mut fingerprints = SecretFingerprints::default();
+        for catalog in Self::get_valid_catalogs(app, LogErrors(false)) {
+            let params = self
+                .resolve_connector_params(&catalog.provider, &catalog.params)
+                .await;
+            fingerprints.insert(SecretComponent::Catalog, catalog.name, params);
+        }
+        for ds in Self::get_valid_datasets(app, LogErrors(false)) {
+            let params = self
+                .resolve_connector_params(&ds.source(), &ds.params)
+                .await;
+            fingerprints.insert(SecretComponent::Dataset, ds.name.to_string(), params);
+        }
+        for embedding in &app.embeddings {
+            let params = self.get_params_with_secrets(&embedding.params).await;
+            fingerprints.insert(
+                SecretComponent::Embedding,
+                embedding.name.clone(),
+                Some(params),
+            );
+        }
+        for model in &app.models {
+            let params = self.get_params_with_secrets(&model.params).await;
+            fingerprints.insert(SecretComponent::Model, model.name.clone(), Some(params));
+        }
+
+        fingerprints
+    }
+
+    /// The params a data connector is created with, including the secrets autoloaded from the secret stores.
+    async fn resolve_connector_params(
+        &self,
+        source: &str,
+        params: &HashMap<String, String>,
+    ) -> Option<HashMap<String, SecretString>> {
+        let params = self.get_params_with_secrets(params).await;
+        match dataconnector::resolve_parameters(source, params, self.secrets()).await {
+            Some(Ok(params)) => Some(params.to_secret_map()),
+            _ => None,
+        }
     }
 
     pub async fn init_results_cache(&self) {
         let app = self.app.read().await;
         let Some(app) = app.as_ref() else { return };
 
-        let cache_config = &app.runtime.results_cache;
+        self.set_results_cache(&app.runtime.results_cache);
+    }
 
+    fn set_results_cache(&self, cache_config: &ResultsCache) {
         if !cache_config.enabled {
+            if self.df.cache_provider().is_some() {
+                self.df.remove_cache_provider();
+                tracing::info!("Disabled results cache");
+            }
             return;
         }
 
@@ -1420,6 +1706,27 @@ impl Runtime {
         };
     }
 
+    pub async fn init_query_limits(&self) {
+        let app = self.app.read().await;
+        let Some(app) = app.as_ref() else { return };
+
+        self.set_query_limits(&app.runtime.query);
+    }
+
+    fn set_query_limits(&self, query_config: &QueryConfig) {
+        match QueryLimits::try_from(query_config) {
+            Ok(query_limits) => {
+                if query_limits != QueryLimits::default() {
+                    tracing::info!("Initialized query limits; {query_limits:?}");
+                }
+                self.df.set_query_limits(query_limits);
+            }
+            Err(e) => {
+                tracing::warn!("Failed to initialize query limits: {e}");
+            }
+        }
+    }
+
     pub async fn init_query_history(&self) -> Result<()> {
         let query_history_table_reference = TableReference::partial(
             SPICE_RUNTIME_SCHEMA,
@@ -1433,6 +1740,18 @@ impl Runtime {
             Err(err) => Err(Error::UnableToTrackQueryHistory { source: err }),
         }
     }
+
+    pub async fn init_llm_usage(&self) -> Result<()> {
+        let llm_usage_table_reference =
+            TableReference::partial(SPICE_RUNTIME_SCHEMA, model::usage::DEFAULT_LLM_USAGE_TABLE);
+        match model::usage::instantiate_llm_usage_table().await {
+            Ok(table) => self
+                .df
+                .register_runtime_table(llm_usage_table_reference, table)
+                .context(UnableToCreateBackendSnafu),
+            Err(err) => Err(Error::UnableToTrackLlmUsage { source: err }),
+        }
+    }
 }
 
 fn verify_dependent_tables(view: &View, existing_tables: &[TableReference]) -> bool {
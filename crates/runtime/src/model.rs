@@ -22,13 +22,26 @@ use secrecy::{ExposeSecret, Secret, SecretString};
 use spicepod::component::model::ModelFileType;
 use spicepod::component::{embeddings::EmbeddingPrefix, model::ModelSource};
 use std::collections::HashMap;
+use std::fmt::Display;
 use std::path::Path;
 use std::result::Result;
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 use crate::DataFusion;
-pub type LLMModelStore = HashMap<String, RwLock<Box<dyn Chat>>>;
+
+pub mod queue;
+pub mod rate_limit;
+pub mod usage;
+
+use queue::QueuedChat;
+use rate_limit::RateLimiter;
+
+pub type LLMModelStore = HashMap<String, Arc<dyn Chat>>;
+
+/// The default number of requests a local chat model runs concurrently, matching the batch size
+/// of the `mistral.rs` scheduler.
+const DEFAULT_LOCAL_MAX_CONCURRENT_REQUESTS: usize = 5;
 
 pub async fn run(m: &Model, df: Arc<DataFusion>) -> Result<RecordBatch, ModelError> {
     match df
@@ -130,6 +143,57 @@ pub fn try_to_embedding<S: ::std::hash::BuildHasher>(
     }
 }
 
+/// Limits the concurrent requests to a chat model with the `max_concurrent_requests` and
+/// `max_waiting_requests` parameters. Local models run up to
+/// [`DEFAULT_LOCAL_MAX_CONCURRENT_REQUESTS`] requests at a time by default, while requests to
+/// OpenAI-compatible models aren't limited unless configured.
+///
+/// Requests are also rate limited with the `requests_per_minute` and `tokens_per_minute`
+/// parameters, and the tokens of each completion capped with `max_completion_tokens`.
+pub fn try_to_queued_chat_model<S: ::std::hash::BuildHasher>(
+    component: &spicepod::component::model::Model,
+    params: &HashMap<String, SecretString, S>,
+    chat: Box<dyn Chat>,
+) -> Result<QueuedChat, LlmError> {
+    let max_concurrent = match parse_limit_param(params, "max_concurrent_requests", 1)? {
+        Some(max_concurrent) => Some(max_concurrent),
+        None if component.get_source() == Some(ModelSource::OpenAi) => None,
+        None => Some(DEFAULT_LOCAL_MAX_CONCURRENT_REQUESTS),
+    };
+    let max_waiting = parse_limit_param(params, "max_waiting_requests", 0)?;
+    let rate_limiter = RateLimiter::new(
+        parse_limit_param(params, "requests_per_minute", 1)?,
+        parse_limit_param(params, "tokens_per_minute", 1)?,
+    );
+    let max_completion_tokens = parse_limit_param(params, "max_completion_tokens", 1)?;
+
+    Ok(
+        QueuedChat::new(component.name.clone(), chat, max_concurrent, max_waiting)
+            .with_rate_limiter(rate_limiter)
+            .with_max_completion_tokens(max_completion_tokens),
+    )
+}
+
+fn parse_limit_param<T: FromStr + PartialOrd + Display, S: ::std::hash::BuildHasher>(
+    params: &HashMap<String, SecretString, S>,
+    name: &str,
+    min: T,
+) -> Result<Option<T>, LlmError> {
+    params
+        .get(name)
+        .map(|value| match value.expose_secret().parse::<T>() {
+            Ok(limit) if limit >= min => Ok(limit),
+            _ => Err(LlmError::FailedToLoadModel {
+                source: format!(
+                    "Invalid {name} '{}', expected a number of at least {min}",
+                    value.expose_secret()
+                )
+                .into(),
+            }),
+        })
+        .transpose()
+}
+
 /// Attempt to derive a runnable Chat model from a given component from the Spicepod definition.
 pub fn try_to_chat_model<S: ::std::hash::BuildHasher>(
     component: &spicepod::component::model::Model,
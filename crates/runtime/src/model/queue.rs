@@ -0,0 +1,351 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use async_openai::{
+    error::{ApiError, OpenAIError},
+    types::{
+        ChatCompletionResponseStream, CompletionUsage, CreateChatCompletionRequest,
+        CreateChatCompletionResponse, CreateChatCompletionStreamResponse,
+    },
+};
+use async_stream::stream;
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use llms::chat::{Chat, Error as ChatError, Result as ChatResult};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::rate_limit::{Limit, RateLimiter};
+
+/// The `type` of the [`ApiError`] returned when a request is rejected by the limits of a model.
+const TOO_MANY_REQUESTS: &str = "too_many_requests";
+
+/// Limits the number of requests a chat model runs concurrently. Requests over the limit wait
+/// for a slot in the order they arrived, and are rejected once too many are already waiting.
+///
+/// Requests can also be rate limited, and the completion tokens of chat requests capped.
+pub struct QueuedChat {
+    model_name: String,
+    chat: Box<dyn Chat>,
+    slots: Option<Arc<Semaphore>>,
+    max_waiting: Option<usize>,
+    waiting: Arc<AtomicUsize>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    max_completion_tokens: Option<u32>,
+}
+
+impl QueuedChat {
+    /// Wraps a chat model that runs at most `max_concurrent` requests at a time and rejects
+    /// requests once `max_waiting` are waiting. Limits that are `None` are unbounded.
+    #[must_use]
+    pub fn new(
+        model_name: String,
+        chat: Box<dyn Chat>,
+        max_concurrent: Option<usize>,
+        max_waiting: Option<usize>,
+    ) -> Self {
+        Self {
+            model_name,
+            chat,
+            slots: max_concurrent.map(|n| Arc::new(Semaphore::new(n))),
+            max_waiting,
+            waiting: Arc::new(AtomicUsize::new(0)),
+            rate_limiter: None,
+            max_completion_tokens: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_rate_limiter(mut self, rate_limiter: Option<RateLimiter>) -> Self {
+        self.rate_limiter = rate_limiter.map(Arc::new);
+        self
+    }
+
+    /// Caps the `max_tokens` of chat requests, and sets it on requests that don't.
+    #[must_use]
+    pub fn with_max_completion_tokens(mut self, max_completion_tokens: Option<u32>) -> Self {
+        self.max_completion_tokens = max_completion_tokens;
+        self
+    }
+
+    /// Waits for a slot to run a request, unless the request is over the rate limits or too many
+    /// requests are already waiting. The request runs until the returned permit is dropped.
+    async fn acquire(&self) -> Result<Option<OwnedSemaphorePermit>, TooManyRequests> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            if let Err(limit) = rate_limiter.check() {
+                metrics::counter!("llm_requests_rate_limited", "model" => self.model_name.clone())
+                    .increment(1);
+                return Err(TooManyRequests::RateLimited {
+                    model_name: self.model_name.clone(),
+                    limit,
+                });
+            }
+        }
+
+        let Some(slots) = &self.slots else {
+            return Ok(None);
+        };
+        if let Ok(permit) = Arc::clone(slots).try_acquire_owned() {
+            return Ok(Some(permit));
+        }
+
+        let waiting = WaitingGuard::new(&self.model_name, Arc::clone(&self.waiting));
+        if self.max_waiting.is_some_and(|max| waiting.position > max) {
+            metrics::counter!("llm_requests_rejected", "model" => self.model_name.clone())
+                .increment(1);
+            return Err(TooManyRequests::QueueFull {
+                model_name: self.model_name.clone(),
+            });
+        }
+
+        // The semaphore is never closed.
+        Ok(Arc::clone(slots).acquire_owned().await.ok())
+    }
+
+    fn limit_completion_tokens(
+        &self,
+        mut req: CreateChatCompletionRequest,
+    ) -> CreateChatCompletionRequest {
+        if let Some(max) = self.max_completion_tokens {
+            req.max_tokens = Some(req.max_tokens.map_or(max, |tokens| tokens.min(max)));
+        }
+        req
+    }
+
+    fn record_tokens(&self, usage: Option<&CompletionUsage>) {
+        if let (Some(rate_limiter), Some(usage)) = (&self.rate_limiter, usage) {
+            rate_limiter.record_tokens(u64::from(usage.total_tokens));
+        }
+    }
+}
+
+/// Counts a request as waiting in the `llm_requests_waiting` gauge until it is dropped, including
+/// when the client disconnects while waiting.
+struct WaitingGuard {
+    model_name: String,
+    waiting: Arc<AtomicUsize>,
+
+    /// The number of requests waiting, including this one.
+    position: usize,
+}
+
+impl WaitingGuard {
+    fn new(model_name: &str, waiting: Arc<AtomicUsize>) -> Self {
+        let position = waiting.fetch_add(1, Ordering::SeqCst) + 1;
+        record_waiting(model_name, position);
+        Self {
+            model_name: model_name.to_string(),
+            waiting,
+            position,
+        }
+    }
+}
+
+impl Drop for WaitingGuard {
+    fn drop(&mut self) {
+        let waiting = self.waiting.fetch_sub(1, Ordering::SeqCst) - 1;
+        record_waiting(&self.model_name, waiting);
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn record_waiting(model_name: &str, waiting: usize) {
+    metrics::gauge!("llm_requests_waiting", "model" => model_name.to_string()).set(waiting as f64);
+}
+
+#[derive(Debug)]
+enum TooManyRequests {
+    QueueFull { model_name: String },
+    RateLimited { model_name: String, limit: Limit },
+}
+
+impl std::fmt::Display for TooManyRequests {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TooManyRequests::QueueFull { model_name } => write!(
+                f,
+                "Too many requests are waiting for model {model_name}, try again later"
+            ),
+            TooManyRequests::RateLimited { model_name, limit } => write!(
+                f,
+                "Model {model_name} is limited to {limit}, try again later"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TooManyRequests {}
+
+impl From<TooManyRequests> for ChatError {
+    fn from(e: TooManyRequests) -> Self {
+        ChatError::FailedToRunModel {
+            source: Box::new(e),
+        }
+    }
+}
+
+impl From<TooManyRequests> for OpenAIError {
+    fn from(e: TooManyRequests) -> Self {
+        OpenAIError::ApiError(ApiError {
+            message: e.to_string(),
+            r#type: Some(TOO_MANY_REQUESTS.to_string()),
+            param: None,
+            code: None,
+        })
+    }
+}
+
+/// Whether a chat request was rejected by the rate limits of the model, or because too many
+/// requests were waiting for it.
+#[must_use]
+pub fn is_too_many_requests(e: &OpenAIError) -> bool {
+    matches!(e, OpenAIError::ApiError(ApiError { r#type: Some(t), .. }) if t == TOO_MANY_REQUESTS)
+}
+
+/// Whether a [`Chat::run`] or [`Chat::stream`] call was rejected by the rate limits of the model,
+/// or because too many requests were waiting for it.
+#[must_use]
+pub fn is_too_many_requests_chat_error(e: &ChatError) -> bool {
+    matches!(e, ChatError::FailedToRunModel { source } if source.is::<TooManyRequests>())
+}
+
+/// Holds the permit of a streamed request until the stream ends or is dropped.
+fn with_permit<T: Send + 'static>(
+    mut strm: Pin<Box<dyn Stream<Item = T> + Send>>,
+    permit: Option<OwnedSemaphorePermit>,
+) -> Pin<Box<dyn Stream<Item = T> + Send>> {
+    Box::pin(stream! {
+        let _permit = permit;
+        while let Some(item) = strm.next().await {
+            yield item;
+        }
+    })
+}
+
+#[async_trait]
+impl Chat for QueuedChat {
+    async fn run(&self, prompt: String) -> ChatResult<Option<String>> {
+        let _permit = self.acquire().await?;
+        self.chat.run(prompt).await
+    }
+
+    async fn health(&self) -> ChatResult<()> {
+        self.chat.health().await
+    }
+
+    async fn stream<'a>(
+        &self,
+        prompt: String,
+    ) -> ChatResult<Pin<Box<dyn Stream<Item = ChatResult<Option<String>>> + Send>>> {
+        let permit = self.acquire().await?;
+        let strm = self.chat.stream(prompt).await?;
+        Ok(with_permit(strm, permit))
+    }
+
+    async fn chat_stream(
+        &self,
+        req: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        let permit = self.acquire().await?;
+        let mut strm = self
+            .chat
+            .chat_stream(self.limit_completion_tokens(req))
+            .await?;
+
+        // Usage is only sent in the last chunk of streams that request it.
+        if let Some(rate_limiter) = self.rate_limiter.as_ref().map(Arc::clone) {
+            strm = Box::pin(stream! {
+                while let Some(chunk) = strm.next().await {
+                    if let Ok(CreateChatCompletionStreamResponse { usage: Some(usage), .. }) = &chunk {
+                        rate_limiter.record_tokens(u64::from(usage.total_tokens));
+                    }
+                    yield chunk;
+                }
+            });
+        }
+        Ok(with_permit(strm, permit))
+    }
+
+    async fn chat_request(
+        &self,
+        req: CreateChatCompletionRequest,
+    ) -> Result<CreateChatCompletionResponse, OpenAIError> {
+        let _permit = self.acquire().await?;
+        let response = self
+            .chat
+            .chat_request(self.limit_completion_tokens(req))
+            .await?;
+        self.record_tokens(response.usage.as_ref());
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct SlowChat;
+
+    #[async_trait]
+    impl Chat for SlowChat {
+        async fn run(&self, prompt: String) -> ChatResult<Option<String>> {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(Some(prompt))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_requests_over_max_waiting() {
+        let chat = Arc::new(QueuedChat::new(
+            "slow".to_string(),
+            Box::new(SlowChat),
+            Some(1),
+            Some(1),
+        ));
+
+        let running = tokio::spawn({
+            let chat = Arc::clone(&chat);
+            async move { chat.run("running".to_string()).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let waiting = tokio::spawn({
+            let chat = Arc::clone(&chat);
+            async move { chat.run("waiting".to_string()).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let rejected = chat.run("rejected".to_string()).await;
+        assert!(rejected.is_err_and(|e| is_too_many_requests_chat_error(&e)));
+
+        assert_eq!(
+            running.await.expect("task").expect("response"),
+            Some("running".to_string())
+        );
+        assert_eq!(
+            waiting.await.expect("task").expect("response"),
+            Some("waiting".to_string())
+        );
+        assert_eq!(chat.waiting.load(Ordering::SeqCst), 0);
+    }
+}
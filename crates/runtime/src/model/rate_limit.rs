@@ -0,0 +1,170 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// Rate limits are enforced over a sliding window of this length.
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// The rate limit that rejected a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    RequestsPerMinute(usize),
+    TokensPerMinute(u64),
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::RequestsPerMinute(limit) => write!(f, "{limit} requests per minute"),
+            Limit::TokensPerMinute(limit) => write!(f, "{limit} tokens per minute"),
+        }
+    }
+}
+
+/// Limits the requests and tokens a model handles over the last minute.
+///
+/// The tokens of a request are only known once the model responds, so a request is accepted as
+/// long as the tokens used over the last minute are under the limit.
+pub struct RateLimiter {
+    requests_per_minute: Option<usize>,
+    tokens_per_minute: Option<u64>,
+    window: Mutex<Window>,
+}
+
+#[derive(Default)]
+struct Window {
+    requests: VecDeque<Instant>,
+    tokens: VecDeque<(Instant, u64)>,
+    total_tokens: u64,
+}
+
+impl Window {
+    fn evict(&mut self, now: Instant) {
+        let Some(start) = now.checked_sub(WINDOW) else {
+            return;
+        };
+        while self.requests.front().is_some_and(|t| *t <= start) {
+            self.requests.pop_front();
+        }
+        while let Some((_, tokens)) = self.tokens.front().filter(|(t, _)| *t <= start) {
+            self.total_tokens -= tokens;
+            self.tokens.pop_front();
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Returns `None` if neither limit is set.
+    #[must_use]
+    pub fn new(requests_per_minute: Option<usize>, tokens_per_minute: Option<u64>) -> Option<Self> {
+        if requests_per_minute.is_none() && tokens_per_minute.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            requests_per_minute,
+            tokens_per_minute,
+            window: Mutex::new(Window::default()),
+        })
+    }
+
+    /// Counts a new request, unless it would exceed a limit.
+    pub fn check(&self) -> Result<(), Limit> {
+        self.check_at(Instant::now())
+    }
+
+    /// Counts the tokens used by a request.
+    pub fn record_tokens(&self, tokens: u64) {
+        self.record_tokens_at(tokens, Instant::now());
+    }
+
+    fn check_at(&self, now: Instant) -> Result<(), Limit> {
+        let mut window = self.window();
+        window.evict(now);
+
+        if let Some(limit) = self.requests_per_minute {
+            if window.requests.len() >= limit {
+                return Err(Limit::RequestsPerMinute(limit));
+            }
+        }
+        if let Some(limit) = self.tokens_per_minute {
+            if window.total_tokens >= limit {
+                return Err(Limit::TokensPerMinute(limit));
+            }
+        }
+
+        window.requests.push_back(now);
+        Ok(())
+    }
+
+    fn record_tokens_at(&self, tokens: u64, now: Instant) {
+        if self.tokens_per_minute.is_none() || tokens == 0 {
+            return;
+        }
+
+        let mut window = self.window();
+        window.evict(now);
+        window.tokens.push_back((now, tokens));
+        window.total_tokens += tokens;
+    }
+
+    fn window(&self) -> MutexGuard<'_, Window> {
+        // The window is always left consistent, so it is still usable if a thread panicked.
+        match self.window.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limits() {
+        let start = Instant::now();
+        let later = |secs| start + Duration::from_secs(secs);
+
+        let limiter = RateLimiter::new(Some(2), None).expect("limiter");
+        assert_eq!(limiter.check_at(start), Ok(()));
+        assert_eq!(limiter.check_at(later(10)), Ok(()));
+        assert_eq!(
+            limiter.check_at(later(20)),
+            Err(Limit::RequestsPerMinute(2))
+        );
+        assert_eq!(limiter.check_at(later(60)), Ok(()));
+
+        let limiter = RateLimiter::new(None, Some(100)).expect("limiter");
+        assert_eq!(limiter.check_at(start), Ok(()));
+        limiter.record_tokens_at(60, start);
+        assert_eq!(limiter.check_at(later(10)), Ok(()));
+        limiter.record_tokens_at(60, later(10));
+        assert_eq!(
+            limiter.check_at(later(20)),
+            Err(Limit::TokensPerMinute(100))
+        );
+        assert_eq!(limiter.check_at(later(61)), Ok(()));
+
+        assert!(RateLimiter::new(None, None).is_none());
+    }
+}
@@ -0,0 +1,259 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use arrow::{
+    array::{RecordBatch, StringArray, TimestampNanosecondArray, UInt32Array},
+    datatypes::{DataType, Field, Schema, TimeUnit},
+};
+use async_openai::types::CompletionUsage;
+use datafusion::sql::TableReference;
+use snafu::{ResultExt, Snafu};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    accelerated_table::{refresh::Refresh, AcceleratedTable, Retention},
+    auth::Principal,
+    component::dataset::{acceleration::Acceleration, TimeFormat},
+    datafusion::{DataFusion, SPICE_RUNTIME_SCHEMA},
+    dataupdate::{DataUpdate, UpdateType},
+    internal_table::create_internal_accelerated_table,
+    secrets::Secrets,
+};
+
+pub const DEFAULT_LLM_USAGE_TABLE: &str = "llm_usage";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Error registering table: {source}"))]
+    UnableToRegisterTable {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("Error writing to llm_usage table: {source}"))]
+    UnableToWriteToTable {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("Error creating llm_usage row: {source}"))]
+    UnableToCreateRow {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+pub async fn instantiate_llm_usage_table() -> Result<Arc<AcceleratedTable>, Error> {
+    let time_column = Some("start_time".to_string());
+    let time_format = Some(TimeFormat::UnixSeconds);
+
+    let retention = Retention::new(
+        time_column.clone(),
+        time_format,
+        Some(Duration::from_secs(24 * 60 * 60)), // 1 day
+        Some(Duration::from_secs(300)),
+        true,
+    );
+    create_internal_accelerated_table(
+        TableReference::partial(SPICE_RUNTIME_SCHEMA, DEFAULT_LLM_USAGE_TABLE),
+        Arc::new(table_schema()),
+        Acceleration::default(),
+        Refresh::default(),
+        retention,
+        Arc::new(RwLock::new(Secrets::default())),
+    )
+    .await
+    .boxed()
+    .context(UnableToRegisterTableSnafu)
+}
+
+fn table_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("request_id", DataType::Utf8, false),
+        Field::new("model", DataType::Utf8, false),
+        Field::new("endpoint", DataType::Utf8, false),
+        Field::new("principal", DataType::Utf8, true),
+        Field::new(
+            "start_time",
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            false,
+        ),
+        Field::new(
+            "end_time",
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            false,
+        ),
+        Field::new("prompt_tokens", DataType::UInt32, true),
+        Field::new("completion_tokens", DataType::UInt32, true),
+        Field::new("total_tokens", DataType::UInt32, true),
+        Field::new("error_message", DataType::Utf8, true),
+    ])
+}
+
+/// The endpoint an LLM request was made through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Chat,
+    Assist,
+    Nsql,
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Chat => write!(f, "chat"),
+            Endpoint::Assist => write!(f, "assist"),
+            Endpoint::Nsql => write!(f, "nsql"),
+        }
+    }
+}
+
+/// Tracks the tokens used by a request to a chat model, recording them in the `llm_usage` table
+/// and as metrics when it finishes. Requests to models that don't report usage have no tokens.
+pub struct UsageTracker {
+    df: Arc<DataFusion>,
+    request_id: Uuid,
+    model: String,
+    endpoint: Endpoint,
+    principal: Option<Arc<str>>,
+    start_time: SystemTime,
+    usage: Option<CompletionUsage>,
+    error_message: Option<String>,
+}
+
+impl UsageTracker {
+    #[must_use]
+    pub fn new(df: Arc<DataFusion>, model: &str, endpoint: Endpoint) -> Self {
+        Self {
+            df,
+            request_id: Uuid::new_v4(),
+            model: model.to_string(),
+            endpoint,
+            principal: None,
+            start_time: SystemTime::now(),
+            usage: None,
+            error_message: None,
+        }
+    }
+
+    #[must_use]
+    pub fn principal(mut self, principal: &Principal) -> Self {
+        self.principal = Some(Arc::clone(&principal.name));
+        self
+    }
+
+    /// Adds the usage of a response, for requests that call the model more than once.
+    pub fn add_usage(&mut self, usage: Option<&CompletionUsage>) {
+        let Some(usage) = usage else {
+            return;
+        };
+        self.usage = Some(match self.usage.take() {
+            Some(total) => CompletionUsage {
+                prompt_tokens: total.prompt_tokens + usage.prompt_tokens,
+                completion_tokens: total.completion_tokens + usage.completion_tokens,
+                total_tokens: total.total_tokens + usage.total_tokens,
+            },
+            None => usage.clone(),
+        });
+    }
+
+    /// Records that a call to the model failed.
+    pub fn error(&mut self, error: &impl Display) {
+        self.error_message = Some(error.to_string());
+    }
+
+    pub async fn finish(self) {
+        let labels = [
+            ("model", self.model.clone()),
+            ("endpoint", self.endpoint.to_string()),
+        ];
+        metrics::counter!("llm_requests", &labels).increment(1);
+        if self.error_message.is_some() {
+            metrics::counter!("llm_request_failures", &labels).increment(1);
+        }
+        if let Some(usage) = &self.usage {
+            metrics::counter!("llm_prompt_tokens", &labels)
+                .increment(u64::from(usage.prompt_tokens));
+            metrics::counter!("llm_completion_tokens", &labels)
+                .increment(u64::from(usage.completion_tokens));
+        }
+
+        if let Err(err) = self.write_usage().await {
+            tracing::error!("Error writing LLM usage: {err}");
+        }
+    }
+
+    async fn write_usage(&self) -> Result<(), Error> {
+        let data = self.to_record_batch()?;
+
+        self.df
+            .write_data(
+                TableReference::partial(SPICE_RUNTIME_SCHEMA, DEFAULT_LLM_USAGE_TABLE),
+                DataUpdate {
+                    schema: Arc::new(table_schema()),
+                    data: vec![data],
+                    update_type: UpdateType::Append,
+                },
+            )
+            .await
+            .boxed()
+            .context(UnableToWriteToTableSnafu)
+    }
+
+    fn to_record_batch(&self) -> Result<RecordBatch, Error> {
+        let timestamp = |time: SystemTime| {
+            time.duration_since(SystemTime::UNIX_EPOCH)
+                .boxed()
+                .and_then(|duration| i64::try_from(duration.as_nanos()).boxed())
+                .context(UnableToCreateRowSnafu)
+        };
+
+        RecordBatch::try_new(
+            Arc::new(table_schema()),
+            vec![
+                Arc::new(StringArray::from(vec![self.request_id.to_string()])),
+                Arc::new(StringArray::from(vec![self.model.as_str()])),
+                Arc::new(StringArray::from(vec![self.endpoint.to_string()])),
+                Arc::new(StringArray::from(vec![self.principal.as_deref()])),
+                Arc::new(TimestampNanosecondArray::from(vec![timestamp(
+                    self.start_time,
+                )?])),
+                Arc::new(TimestampNanosecondArray::from(vec![timestamp(
+                    SystemTime::now(),
+                )?])),
+                Arc::new(UInt32Array::from(vec![self
+                    .usage
+                    .as_ref()
+                    .map(|u| u.prompt_tokens)])),
+                Arc::new(UInt32Array::from(vec![self
+                    .usage
+                    .as_ref()
+                    .map(|u| u.completion_tokens)])),
+                Arc::new(UInt32Array::from(vec![self
+                    .usage
+                    .as_ref()
+                    .map(|u| u.total_tokens)])),
+                Arc::new(StringArray::from(vec![self.error_message.clone()])),
+            ],
+        )
+        .boxed()
+        .context(UnableToCreateRowSnafu)
+    }
+}
@@ -14,6 +14,7 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::fmt::Write as _;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
@@ -22,26 +23,37 @@ use arrow::array::ArrayRef;
 use arrow::array::BinaryBuilder;
 use arrow::array::BooleanBuilder;
 use arrow::array::Float64Builder;
+use arrow::array::Int32Builder;
 use arrow::array::Int64Builder;
+use arrow::array::ListArray;
+use arrow::array::ListBuilder;
 use arrow::array::StringBuilder;
+use arrow::array::StructArray;
 use arrow::array::UInt64Builder;
+use arrow::buffer::OffsetBuffer;
 use arrow::datatypes::DataType;
 use arrow::datatypes::Field;
+use arrow::datatypes::Fields;
 use arrow::datatypes::Schema;
 use arrow::record_batch::RecordBatch;
 use datafusion::sql::TableReference;
 use indexmap::IndexMap;
+use opentelemetry_proto::tonic::collector::logs::v1::logs_service_server::LogsServiceServer;
 use opentelemetry_proto::tonic::collector::metrics::v1::metrics_service_server::MetricsService;
 use opentelemetry_proto::tonic::collector::metrics::v1::metrics_service_server::MetricsServiceServer;
 use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsPartialSuccess;
 use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
 use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceResponse;
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::TraceServiceServer;
 use opentelemetry_proto::tonic::common::v1::any_value;
 use opentelemetry_proto::tonic::common::v1::KeyValue;
 use opentelemetry_proto::tonic::metrics::v1::metric::Data;
 use opentelemetry_proto::tonic::metrics::v1::number_data_point::Value;
 use opentelemetry_proto::tonic::metrics::v1::DataPointFlags;
+use opentelemetry_proto::tonic::metrics::v1::ExponentialHistogramDataPoint;
+use opentelemetry_proto::tonic::metrics::v1::HistogramDataPoint;
 use opentelemetry_proto::tonic::metrics::v1::NumberDataPoint;
+use opentelemetry_proto::tonic::metrics::v1::SummaryDataPoint;
 use secrecy::ExposeSecret;
 use snafu::prelude::*;
 use tonic_0_9_0::async_trait;
@@ -59,6 +71,9 @@ use crate::dataupdate::UpdateType;
 use crate::tls::TlsConfig;
 use crate::{tracers::OnceTracer, warn_once};
 
+mod logs;
+mod traces;
+
 type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[derive(Debug, Snafu)]
@@ -71,9 +86,6 @@ pub enum Error {
     #[snafu(display("Failed to build record batch from OpenTelemetry metrics: {source}"))]
     FailedToBuildRecordBatch { source: arrow::error::ArrowError },
 
-    #[snafu(display("Unsupported metric data type"))]
-    UnsupportedMetricDataType {},
-
     #[snafu(display("Unsupported metric attribute type"))]
     UnsupportedMetricAttributeType {},
 
@@ -94,6 +106,12 @@ pub enum Error {
     ))]
     FirstMetricDataPointHasNoValue { metric: String },
 
+    #[snafu(display("Export request has no spans"))]
+    NoSpans {},
+
+    #[snafu(display("Export request has no log records"))]
+    NoLogRecords {},
+
     #[snafu(display("Unable to configure TLS on the Flight server: {source}"))]
     UnableToConfigureTls {
         source: tonic_0_9_0::transport::Error,
@@ -103,9 +121,34 @@ pub enum Error {
 const VALUE_COLUMN_NAME: &str = "value";
 const TIME_UNIX_NANO_COLUMN_NAME: &str = "time_unix_nano";
 const START_TIME_UNIX_NANO_COLUMN_NAME: &str = "start_time_unix_nano";
+const COUNT_COLUMN_NAME: &str = "count";
+const SUM_COLUMN_NAME: &str = "sum";
+const MIN_COLUMN_NAME: &str = "min";
+const MAX_COLUMN_NAME: &str = "max";
+const BUCKET_COUNTS_COLUMN_NAME: &str = "bucket_counts";
+const EXPLICIT_BOUNDS_COLUMN_NAME: &str = "explicit_bounds";
+const SCALE_COLUMN_NAME: &str = "scale";
+const ZERO_COUNT_COLUMN_NAME: &str = "zero_count";
+const POSITIVE_OFFSET_COLUMN_NAME: &str = "positive_offset";
+const POSITIVE_BUCKET_COUNTS_COLUMN_NAME: &str = "positive_bucket_counts";
+const NEGATIVE_OFFSET_COLUMN_NAME: &str = "negative_offset";
+const NEGATIVE_BUCKET_COUNTS_COLUMN_NAME: &str = "negative_bucket_counts";
+const QUANTILE_VALUES_COLUMN_NAME: &str = "quantile_values";
+const TRACE_ID_COLUMN_NAME: &str = "trace_id";
+const SPAN_ID_COLUMN_NAME: &str = "span_id";
+const SCOPE_NAME_COLUMN_NAME: &str = "scope_name";
+
+/// The writable datasets that spans and log records are appended to. Metrics are appended to the
+/// dataset named after each metric.
+#[derive(Debug, Clone)]
+pub struct Datasets {
+    pub traces: String,
+    pub logs: String,
+}
 
 pub struct Service {
     data_fusion: Arc<DataFusion>,
+    datasets: Datasets,
     once_tracer: OnceTracer,
 }
 
@@ -121,14 +164,7 @@ impl MetricsService for Service {
             for scope_metric in resource_metric.scope_metrics {
                 for metric in scope_metric.metrics {
                     if let Some(data) = metric.data {
-                        let existing_schema = match self
-                            .data_fusion
-                            .get_arrow_schema(metric.name.as_str())
-                            .await
-                        {
-                            Ok(schema) => Some(schema),
-                            Err(_) => None,
-                        };
+                        let existing_schema = self.existing_schema(&metric.name).await;
                         let (record_batch_result, data_points_count) = metric_data_to_record_batch(
                             metric.name.as_str(),
                             &data,
@@ -138,40 +174,7 @@ impl MetricsService for Service {
 
                         match record_batch_result {
                             Ok(record_batch) => {
-                                if !self
-                                    .data_fusion
-                                    .is_writable(&TableReference::bare(metric.name.to_string()))
-                                {
-                                    warn_once!(
-                                        self.once_tracer,
-                                        "No writable dataset defined for metric {}, skipping",
-                                        metric.name
-                                    );
-                                    rejected_data_points += data_points_count;
-                                    continue;
-                                };
-
-                                let schema = record_batch.schema();
-                                let data_update = DataUpdate {
-                                    data: vec![record_batch],
-                                    schema,
-                                    update_type: UpdateType::Append,
-                                };
-
-                                let mut write_failed = false;
-                                if let Err(e) = self
-                                    .data_fusion
-                                    .write_data(
-                                        TableReference::bare(metric.name.as_str()),
-                                        data_update,
-                                    )
-                                    .await
-                                {
-                                    write_failed = true;
-                                    tracing::debug!("Failed to add OpenTelemetry data: {e}");
-                                };
-
-                                if write_failed {
+                                if !self.append_to_dataset(&metric.name, record_batch).await {
                                     rejected_data_points += data_points_count;
                                 }
                             }
@@ -205,11 +208,56 @@ impl MetricsService for Service {
     }
 }
 
+impl Service {
+    /// Append a record batch to the writable dataset `dataset`, returning whether it was written.
+    async fn append_to_dataset(&self, dataset: &str, record_batch: RecordBatch) -> bool {
+        if !self
+            .data_fusion
+            .is_writable(&TableReference::bare(dataset.to_string()))
+        {
+            warn_once!(
+                self.once_tracer,
+                "No writable dataset defined for {}, skipping",
+                dataset
+            );
+            return false;
+        };
+
+        let schema = record_batch.schema();
+        let data_update = DataUpdate {
+            data: vec![record_batch],
+            schema,
+            update_type: UpdateType::Append,
+        };
+
+        if let Err(e) = self
+            .data_fusion
+            .write_data(TableReference::bare(dataset), data_update)
+            .await
+        {
+            tracing::debug!("Failed to add OpenTelemetry data: {e}");
+            return false;
+        };
+
+        true
+    }
+
+    async fn existing_schema(&self, dataset: &str) -> Option<Schema> {
+        self.data_fusion.get_arrow_schema(dataset).await.ok()
+    }
+}
+
 async fn create_health_service() -> HealthServer<impl Health> {
     let (mut health_reporter, health_service) = tonic_health_0_9_0::server::health_reporter();
     health_reporter
         .set_serving::<MetricsServiceServer<Service>>()
         .await;
+    health_reporter
+        .set_serving::<TraceServiceServer<Service>>()
+        .await;
+    health_reporter
+        .set_serving::<LogsServiceServer<Service>>()
+        .await;
     health_service
 }
 
@@ -227,8 +275,22 @@ pub fn metric_data_to_record_batch(
             number_data_points_to_record_batch(metric, &sum.data_points, existing_schema),
             sum.data_points.len() as u64,
         ),
-        // TODO: Support other metric data types
-        _ => (UnsupportedMetricDataTypeSnafu.fail(), 0),
+        Data::Histogram(histogram) => (
+            histogram_data_points_to_record_batch(metric, &histogram.data_points, existing_schema),
+            histogram.data_points.len() as u64,
+        ),
+        Data::ExponentialHistogram(histogram) => (
+            exponential_histogram_data_points_to_record_batch(
+                metric,
+                &histogram.data_points,
+                existing_schema,
+            ),
+            histogram.data_points.len() as u64,
+        ),
+        Data::Summary(summary) => (
+            summary_data_points_to_record_batch(metric, &summary.data_points, existing_schema),
+            summary.data_points.len() as u64,
+        ),
     }
 }
 
@@ -337,33 +399,317 @@ fn number_data_points_to_record_batch(
         start_time_unix_nano_builder.append_value(data_point.start_time_unix_nano);
     }
 
-    let mut columns: Vec<ArrayRef>;
-    let mut fields: Vec<Arc<Field>>;
-    if let Some(builder) = &mut values_builder {
-        fields = vec![
-            Arc::new(Field::new(VALUE_COLUMN_NAME, values_type, true)),
-            Arc::new(Field::new(
-                TIME_UNIX_NANO_COLUMN_NAME,
-                DataType::UInt64,
-                true,
-            )),
-            Arc::new(Field::new(
-                START_TIME_UNIX_NANO_COLUMN_NAME,
-                DataType::UInt64,
-                true,
-            )),
-        ];
-        columns = vec![
-            Arc::new(builder.finish()),
-            Arc::new(time_unix_nano_builder.finish()),
-            Arc::new(start_time_unix_nano_builder.finish()),
-        ];
-    } else {
+    let Some(builder) = &mut values_builder else {
         return MetricWithNoDataPointsSnafu.fail();
+    };
+
+    let fields = vec![
+        Arc::new(Field::new(VALUE_COLUMN_NAME, values_type, true)),
+        Arc::new(Field::new(
+            TIME_UNIX_NANO_COLUMN_NAME,
+            DataType::UInt64,
+            true,
+        )),
+        Arc::new(Field::new(
+            START_TIME_UNIX_NANO_COLUMN_NAME,
+            DataType::UInt64,
+            true,
+        )),
+    ];
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(builder.finish()),
+        Arc::new(time_unix_nano_builder.finish()),
+        Arc::new(start_time_unix_nano_builder.finish()),
+    ];
+
+    with_attribute_columns(
+        metric,
+        fields,
+        columns,
+        attributes.as_slice(),
+        existing_schema,
+    )
+}
+
+/// Histogram data points have the columns `count`, `sum`, `min`, `max`, `bucket_counts` and
+/// `explicit_bounds`, followed by the time columns and one column per attribute.
+fn histogram_data_points_to_record_batch(
+    metric: &str,
+    data_points: &[HistogramDataPoint],
+    existing_schema: &Option<Schema>,
+) -> Result<RecordBatch> {
+    ensure!(!data_points.is_empty(), MetricWithNoDataPointsSnafu);
+
+    let mut bucket_counts = ListBuilder::new(UInt64Builder::new());
+    let mut explicit_bounds = ListBuilder::new(Float64Builder::new());
+    for data_point in data_points {
+        bucket_counts.append_value(data_point.bucket_counts.iter().copied().map(Some));
+        explicit_bounds.append_value(data_point.explicit_bounds.iter().copied().map(Some));
+    }
+
+    let (mut fields, mut columns) =
+        aggregate_columns(data_points.iter().map(|p| (p.count, p.sum, p.min, p.max)));
+    fields.extend([
+        list_field(BUCKET_COUNTS_COLUMN_NAME, DataType::UInt64),
+        list_field(EXPLICIT_BOUNDS_COLUMN_NAME, DataType::Float64),
+    ]);
+    columns.extend([
+        Arc::new(bucket_counts.finish()) as ArrayRef,
+        Arc::new(explicit_bounds.finish()),
+    ]);
+    append_time_columns(
+        &mut fields,
+        &mut columns,
+        data_points
+            .iter()
+            .map(|p| (p.time_unix_nano, p.start_time_unix_nano)),
+    );
+
+    let attributes: Vec<_> = data_points
+        .iter()
+        .map(|p| p.attributes.as_slice())
+        .collect();
+    with_attribute_columns(metric, fields, columns, &attributes, existing_schema)
+}
+
+/// Exponential histogram data points have the columns `count`, `sum`, `min`, `max`, `scale`,
+/// `zero_count`, and the offset and bucket counts of the positive and negative buckets, followed by
+/// the time columns and one column per attribute.
+fn exponential_histogram_data_points_to_record_batch(
+    metric: &str,
+    data_points: &[ExponentialHistogramDataPoint],
+    existing_schema: &Option<Schema>,
+) -> Result<RecordBatch> {
+    ensure!(!data_points.is_empty(), MetricWithNoDataPointsSnafu);
+
+    let mut scale = Int32Builder::new();
+    let mut zero_count = UInt64Builder::new();
+    let mut positive_offset = Int32Builder::new();
+    let mut positive_bucket_counts = ListBuilder::new(UInt64Builder::new());
+    let mut negative_offset = Int32Builder::new();
+    let mut negative_bucket_counts = ListBuilder::new(UInt64Builder::new());
+    for data_point in data_points {
+        scale.append_value(data_point.scale);
+        zero_count.append_value(data_point.zero_count);
+        for (buckets, offset, bucket_counts) in [
+            (
+                &data_point.positive,
+                &mut positive_offset,
+                &mut positive_bucket_counts,
+            ),
+            (
+                &data_point.negative,
+                &mut negative_offset,
+                &mut negative_bucket_counts,
+            ),
+        ] {
+            offset.append_option(buckets.as_ref().map(|b| b.offset));
+            bucket_counts.append_option(
+                buckets
+                    .as_ref()
+                    .map(|b| b.bucket_counts.iter().copied().map(Some)),
+            );
+        }
+    }
+
+    let (mut fields, mut columns) =
+        aggregate_columns(data_points.iter().map(|p| (p.count, p.sum, p.min, p.max)));
+    fields.extend([
+        Arc::new(Field::new(SCALE_COLUMN_NAME, DataType::Int32, false)),
+        Arc::new(Field::new(ZERO_COUNT_COLUMN_NAME, DataType::UInt64, false)),
+        Arc::new(Field::new(
+            POSITIVE_OFFSET_COLUMN_NAME,
+            DataType::Int32,
+            true,
+        )),
+        list_field(POSITIVE_BUCKET_COUNTS_COLUMN_NAME, DataType::UInt64),
+        Arc::new(Field::new(
+            NEGATIVE_OFFSET_COLUMN_NAME,
+            DataType::Int32,
+            true,
+        )),
+        list_field(NEGATIVE_BUCKET_COUNTS_COLUMN_NAME, DataType::UInt64),
+    ]);
+    columns.extend([
+        Arc::new(scale.finish()) as ArrayRef,
+        Arc::new(zero_count.finish()),
+        Arc::new(positive_offset.finish()),
+        Arc::new(positive_bucket_counts.finish()),
+        Arc::new(negative_offset.finish()),
+        Arc::new(negative_bucket_counts.finish()),
+    ]);
+    append_time_columns(
+        &mut fields,
+        &mut columns,
+        data_points
+            .iter()
+            .map(|p| (p.time_unix_nano, p.start_time_unix_nano)),
+    );
+
+    let attributes: Vec<_> = data_points
+        .iter()
+        .map(|p| p.attributes.as_slice())
+        .collect();
+    with_attribute_columns(metric, fields, columns, &attributes, existing_schema)
+}
+
+/// Summary data points have the columns `count`, `sum` and `quantile_values`, a list of
+/// `{quantile, value}` structs, followed by the time columns and one column per attribute.
+fn summary_data_points_to_record_batch(
+    metric: &str,
+    data_points: &[SummaryDataPoint],
+    existing_schema: &Option<Schema>,
+) -> Result<RecordBatch> {
+    ensure!(!data_points.is_empty(), MetricWithNoDataPointsSnafu);
+
+    let mut count = UInt64Builder::new();
+    let mut sum = Float64Builder::new();
+    let mut quantiles = Float64Builder::new();
+    let mut values = Float64Builder::new();
+    for data_point in data_points {
+        count.append_value(data_point.count);
+        sum.append_value(data_point.sum);
+        for quantile_value in &data_point.quantile_values {
+            quantiles.append_value(quantile_value.quantile);
+            values.append_value(quantile_value.value);
+        }
     }
 
+    let quantile_fields = quantile_value_fields();
+    let quantile_values = StructArray::try_new(
+        quantile_fields.clone(),
+        vec![Arc::new(quantiles.finish()), Arc::new(values.finish())],
+        None,
+    )
+    .context(FailedToBuildRecordBatchSnafu)?;
+    let quantile_values = ListArray::try_new(
+        Arc::new(Field::new_list_field(
+            DataType::Struct(quantile_fields),
+            false,
+        )),
+        OffsetBuffer::from_lengths(data_points.iter().map(|p| p.quantile_values.len())),
+        Arc::new(quantile_values),
+        None,
+    )
+    .context(FailedToBuildRecordBatchSnafu)?;
+
+    let mut fields = vec![
+        Arc::new(Field::new(COUNT_COLUMN_NAME, DataType::UInt64, false)),
+        Arc::new(Field::new(SUM_COLUMN_NAME, DataType::Float64, true)),
+        Arc::new(Field::new(
+            QUANTILE_VALUES_COLUMN_NAME,
+            quantile_values.data_type().clone(),
+            true,
+        )),
+    ];
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(count.finish()),
+        Arc::new(sum.finish()),
+        Arc::new(quantile_values),
+    ];
+    append_time_columns(
+        &mut fields,
+        &mut columns,
+        data_points
+            .iter()
+            .map(|p| (p.time_unix_nano, p.start_time_unix_nano)),
+    );
+
+    let attributes: Vec<_> = data_points
+        .iter()
+        .map(|p| p.attributes.as_slice())
+        .collect();
+    with_attribute_columns(metric, fields, columns, &attributes, existing_schema)
+}
+
+fn quantile_value_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("quantile", DataType::Float64, false),
+        Field::new("value", DataType::Float64, false),
+    ])
+}
+
+fn list_field(name: &str, item_type: DataType) -> Arc<Field> {
+    Arc::new(Field::new(name, DataType::new_list(item_type, true), true))
+}
+
+/// The `count`, `sum`, `min` and `max` of a histogram data point.
+type Aggregate = (u64, Option<f64>, Option<f64>, Option<f64>);
+
+/// The `count`, `sum`, `min` and `max` columns shared by histogram data points.
+fn aggregate_columns(
+    aggregates: impl Iterator<Item = Aggregate>,
+) -> (Vec<Arc<Field>>, Vec<ArrayRef>) {
+    let mut count = UInt64Builder::new();
+    let mut sum = Float64Builder::new();
+    let mut min = Float64Builder::new();
+    let mut max = Float64Builder::new();
+    for (c, s, mn, mx) in aggregates {
+        count.append_value(c);
+        sum.append_option(s);
+        min.append_option(mn);
+        max.append_option(mx);
+    }
+
+    (
+        vec![
+            Arc::new(Field::new(COUNT_COLUMN_NAME, DataType::UInt64, false)),
+            Arc::new(Field::new(SUM_COLUMN_NAME, DataType::Float64, true)),
+            Arc::new(Field::new(MIN_COLUMN_NAME, DataType::Float64, true)),
+            Arc::new(Field::new(MAX_COLUMN_NAME, DataType::Float64, true)),
+        ],
+        vec![
+            Arc::new(count.finish()),
+            Arc::new(sum.finish()),
+            Arc::new(min.finish()),
+            Arc::new(max.finish()),
+        ],
+    )
+}
+
+/// Append the `time_unix_nano` and `start_time_unix_nano` columns.
+fn append_time_columns(
+    fields: &mut Vec<Arc<Field>>,
+    columns: &mut Vec<ArrayRef>,
+    times: impl Iterator<Item = (u64, u64)>,
+) {
+    let mut time_unix_nano_builder = UInt64Builder::new();
+    let mut start_time_unix_nano_builder = UInt64Builder::new();
+    for (time_unix_nano, start_time_unix_nano) in times {
+        time_unix_nano_builder.append_value(time_unix_nano);
+        start_time_unix_nano_builder.append_value(start_time_unix_nano);
+    }
+
+    fields.extend([
+        Arc::new(Field::new(
+            TIME_UNIX_NANO_COLUMN_NAME,
+            DataType::UInt64,
+            true,
+        )),
+        Arc::new(Field::new(
+            START_TIME_UNIX_NANO_COLUMN_NAME,
+            DataType::UInt64,
+            true,
+        )),
+    ]);
+    columns.extend([
+        Arc::new(time_unix_nano_builder.finish()) as ArrayRef,
+        Arc::new(start_time_unix_nano_builder.finish()),
+    ]);
+}
+
+/// Build the record batch of a metric's data points from their data columns, followed by one
+/// column per attribute.
+fn with_attribute_columns(
+    metric: &str,
+    mut fields: Vec<Arc<Field>>,
+    mut columns: Vec<ArrayRef>,
+    attributes: &[&[KeyValue]],
+    existing_schema: &Option<Schema>,
+) -> Result<RecordBatch> {
+    let data_columns: Vec<&str> = fields.iter().map(|f| f.name().as_str()).collect();
     let (attribute_fields_map, attribute_columns_map) =
-        attributes_to_fields_and_columns(metric, attributes.as_slice(), existing_schema);
+        attributes_to_fields_and_columns(metric, attributes, &data_columns, existing_schema);
     fields.extend(
         attribute_fields_map
             .into_iter()
@@ -415,6 +761,7 @@ macro_rules! append_attribute {
 fn attributes_to_fields_and_columns(
     metric: &str,
     attributes: &[&[KeyValue]],
+    data_columns: &[&str],
     existing_schema: &Option<Schema>,
 ) -> (
     IndexMap<String, Arc<Field>>,
@@ -423,7 +770,7 @@ fn attributes_to_fields_and_columns(
     let mut fields: IndexMap<String, Arc<Field>> = IndexMap::new();
     let mut columns: IndexMap<String, Box<dyn ArrayBuilder>> = IndexMap::new();
 
-    initialize_attribute_schema(&mut fields, &mut columns, existing_schema);
+    initialize_attribute_schema(&mut fields, &mut columns, data_columns, existing_schema);
 
     for (i, inner_attributes) in attributes.iter().enumerate() {
         for attribute in *inner_attributes {
@@ -525,18 +872,40 @@ fn attributes_to_fields_and_columns(
     (fields, columns)
 }
 
+/// Flatten layers of attributes (e.g. resource, scope, then span attributes) into one list, where
+/// an attribute in a later layer replaces an attribute with the same key in an earlier layer.
+fn merge_attributes(layers: &[&[KeyValue]]) -> Vec<KeyValue> {
+    let mut merged: IndexMap<&str, &KeyValue> = IndexMap::new();
+    for attribute in layers.iter().flat_map(|layer| layer.iter()) {
+        merged.insert(attribute.key.as_str(), attribute);
+    }
+    merged.into_values().cloned().collect()
+}
+
+/// Hex encode a trace or span ID, or `None` if the ID is empty.
+fn hex_id(id: &[u8]) -> Option<String> {
+    if id.is_empty() {
+        return None;
+    }
+    Some(
+        id.iter()
+            .fold(String::with_capacity(id.len() * 2), |mut hex, byte| {
+                let _ = write!(hex, "{byte:02x}");
+                hex
+            }),
+    )
+}
+
 fn initialize_attribute_schema(
     fields: &mut IndexMap<String, Arc<Field>>,
     columns: &mut IndexMap<String, Box<dyn ArrayBuilder>>,
+    data_columns: &[&str],
     existing_schema: &Option<Schema>,
 ) {
     if let Some(s) = existing_schema {
         for field in s.fields() {
-            // Skip value and time fields because they are not attributes and are already handled.
-            if field.name() == VALUE_COLUMN_NAME
-                || field.name() == TIME_UNIX_NANO_COLUMN_NAME
-                || field.name() == START_TIME_UNIX_NANO_COLUMN_NAME
-            {
+            // Skip the data point columns because they are not attributes and are already handled.
+            if data_columns.contains(&field.name().as_str()) {
                 continue;
             }
 
@@ -594,12 +963,19 @@ pub async fn start(
     bind_address: SocketAddr,
     data_fusion: Arc<DataFusion>,
     tls_config: Option<Arc<TlsConfig>>,
+    datasets: Datasets,
 ) -> Result<()> {
-    let service = Service {
+    let service = Arc::new(Service {
         data_fusion,
+        datasets,
         once_tracer: OnceTracer::new(),
-    };
-    let svc = MetricsServiceServer::new(service).accept_compressed(CompressionEncoding::Gzip);
+    });
+    let metrics_svc = MetricsServiceServer::from_arc(Arc::clone(&service))
+        .accept_compressed(CompressionEncoding::Gzip);
+    let trace_svc = TraceServiceServer::from_arc(Arc::clone(&service))
+        .accept_compressed(CompressionEncoding::Gzip);
+    let logs_svc =
+        LogsServiceServer::from_arc(service).accept_compressed(CompressionEncoding::Gzip);
 
     tracing::info!("Spice Runtime OpenTelemetry listening on {bind_address}");
 
@@ -617,10 +993,220 @@ pub async fn start(
 
     server
         .add_service(create_health_service().await)
-        .add_service(svc)
+        .add_service(metrics_svc)
+        .add_service(trace_svc)
+        .add_service(logs_svc)
         .serve(bind_address)
         .await
         .context(UnableToServeSnafu)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{AsArray, Float64Array};
+    use arrow::datatypes::{Float64Type, Int32Type, UInt64Type};
+    use opentelemetry_proto::tonic::common::v1::AnyValue;
+    use opentelemetry_proto::tonic::metrics::v1::exponential_histogram_data_point::Buckets;
+    use opentelemetry_proto::tonic::metrics::v1::summary_data_point::ValueAtQuantile;
+    use opentelemetry_proto::tonic::metrics::v1::{ExponentialHistogram, Histogram, Summary};
+
+    use super::*;
+
+    fn route_attribute(route: &str) -> KeyValue {
+        KeyValue {
+            key: "route".to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::StringValue(route.to_string())),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_histogram_to_record_batch() {
+        let data = Data::Histogram(Histogram {
+            data_points: vec![
+                HistogramDataPoint {
+                    attributes: vec![route_attribute("/cart")],
+                    start_time_unix_nano: 1_000,
+                    time_unix_nano: 2_000,
+                    count: 3,
+                    sum: Some(0.6),
+                    bucket_counts: vec![1, 2, 0],
+                    explicit_bounds: vec![0.1, 0.5],
+                    min: Some(0.05),
+                    max: Some(0.3),
+                    ..Default::default()
+                },
+                HistogramDataPoint {
+                    attributes: vec![route_attribute("/checkout")],
+                    time_unix_nano: 3_000,
+                    count: 1,
+                    bucket_counts: vec![0, 0, 1],
+                    explicit_bounds: vec![0.1, 0.5],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        });
+
+        let (batch, data_points) = metric_data_to_record_batch("latency", &data, &None);
+        let batch = batch.expect("histogram is converted");
+        assert_eq!(data_points, 2);
+        assert_eq!(batch.num_rows(), 2);
+
+        let column = |name: &str| batch.column_by_name(name).expect("column exists");
+        assert_eq!(
+            column(COUNT_COLUMN_NAME)
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            vec![3, 1]
+        );
+        let sum = column(SUM_COLUMN_NAME).as_primitive::<Float64Type>();
+        assert!((sum.value(0) - 0.6).abs() < f64::EPSILON);
+        assert!(sum.is_null(1));
+        assert!(column(MIN_COLUMN_NAME).is_valid(0));
+        assert!(column(MAX_COLUMN_NAME).is_null(1));
+
+        let bucket_counts = column(BUCKET_COUNTS_COLUMN_NAME).as_list::<i32>();
+        assert_eq!(
+            bucket_counts
+                .value(1)
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            vec![0, 0, 1]
+        );
+        let explicit_bounds = column(EXPLICIT_BOUNDS_COLUMN_NAME).as_list::<i32>();
+        assert_eq!(
+            explicit_bounds.value(0).as_primitive::<Float64Type>(),
+            &Float64Array::from(vec![0.1, 0.5])
+        );
+        assert_eq!(
+            column(START_TIME_UNIX_NANO_COLUMN_NAME)
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            vec![1_000, 0]
+        );
+        assert_eq!(column("route").as_string::<i32>().value(1), "/checkout");
+    }
+
+    #[test]
+    fn test_exponential_histogram_to_record_batch() {
+        let data = Data::ExponentialHistogram(ExponentialHistogram {
+            data_points: vec![ExponentialHistogramDataPoint {
+                time_unix_nano: 2_000,
+                count: 4,
+                sum: Some(10.0),
+                scale: 2,
+                zero_count: 1,
+                positive: Some(Buckets {
+                    offset: -1,
+                    bucket_counts: vec![2, 1],
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+
+        let (batch, _) = metric_data_to_record_batch("latency", &data, &None);
+        let batch = batch.expect("exponential histogram is converted");
+
+        let column = |name: &str| batch.column_by_name(name).expect("column exists");
+        assert_eq!(
+            column(SCALE_COLUMN_NAME)
+                .as_primitive::<Int32Type>()
+                .value(0),
+            2
+        );
+        assert_eq!(
+            column(ZERO_COUNT_COLUMN_NAME)
+                .as_primitive::<UInt64Type>()
+                .value(0),
+            1
+        );
+        assert_eq!(
+            column(POSITIVE_OFFSET_COLUMN_NAME)
+                .as_primitive::<Int32Type>()
+                .value(0),
+            -1
+        );
+        assert_eq!(
+            column(POSITIVE_BUCKET_COUNTS_COLUMN_NAME)
+                .as_list::<i32>()
+                .value(0)
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            vec![2, 1]
+        );
+        assert!(column(NEGATIVE_OFFSET_COLUMN_NAME).is_null(0));
+        assert!(column(NEGATIVE_BUCKET_COUNTS_COLUMN_NAME).is_null(0));
+    }
+
+    #[test]
+    fn test_summary_to_record_batch() {
+        let data = Data::Summary(Summary {
+            data_points: vec![
+                SummaryDataPoint {
+                    time_unix_nano: 2_000,
+                    count: 10,
+                    sum: 5.0,
+                    quantile_values: vec![
+                        ValueAtQuantile {
+                            quantile: 0.5,
+                            value: 0.4,
+                        },
+                        ValueAtQuantile {
+                            quantile: 0.99,
+                            value: 1.2,
+                        },
+                    ],
+                    ..Default::default()
+                },
+                SummaryDataPoint {
+                    time_unix_nano: 3_000,
+                    count: 0,
+                    ..Default::default()
+                },
+            ],
+        });
+
+        let (batch, _) = metric_data_to_record_batch("latency", &data, &None);
+        let batch = batch.expect("summary is converted");
+        assert_eq!(batch.num_rows(), 2);
+
+        let quantile_values = batch
+            .column_by_name(QUANTILE_VALUES_COLUMN_NAME)
+            .expect("column exists")
+            .as_list::<i32>();
+        let first = quantile_values.value(0);
+        let first = first.as_struct();
+        assert_eq!(
+            first
+                .column_by_name("quantile")
+                .expect("quantile field")
+                .as_primitive::<Float64Type>(),
+            &Float64Array::from(vec![0.5, 0.99])
+        );
+        assert_eq!(
+            first
+                .column_by_name("value")
+                .expect("value field")
+                .as_primitive::<Float64Type>(),
+            &Float64Array::from(vec![0.4, 1.2])
+        );
+        assert_eq!(quantile_values.value(1).len(), 0);
+    }
+
+    #[test]
+    fn test_metric_with_no_data_points() {
+        let data = Data::Histogram(Histogram::default());
+        let (batch, data_points) = metric_data_to_record_batch("latency", &data, &None);
+        assert!(batch.is_err());
+        assert_eq!(data_points, 0);
+    }
+}
@@ -0,0 +1,307 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Receives OTLP log records and appends them to the configured logs dataset, `logs` by default.
+
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Int32Builder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use opentelemetry_proto::tonic::collector::logs::v1::logs_service_server::LogsService;
+use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceRequest;
+use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceResponse;
+use opentelemetry_proto::tonic::common::v1::any_value;
+use opentelemetry_proto::tonic::common::v1::AnyValue;
+use opentelemetry_proto::tonic::common::v1::KeyValue;
+use opentelemetry_proto::tonic::logs::v1::ResourceLogs;
+use snafu::prelude::*;
+use tonic_0_9_0::async_trait;
+use tonic_0_9_0::Request;
+use tonic_0_9_0::Response;
+use tonic_0_9_0::Status;
+
+use super::{
+    hex_id, merge_attributes, with_attribute_columns, NoLogRecordsSnafu, Result, Service,
+    SCOPE_NAME_COLUMN_NAME, SPAN_ID_COLUMN_NAME, TIME_UNIX_NANO_COLUMN_NAME, TRACE_ID_COLUMN_NAME,
+};
+
+const OBSERVED_TIME_UNIX_NANO_COLUMN_NAME: &str = "observed_time_unix_nano";
+const SEVERITY_NUMBER_COLUMN_NAME: &str = "severity_number";
+const SEVERITY_TEXT_COLUMN_NAME: &str = "severity_text";
+const BODY_COLUMN_NAME: &str = "body";
+
+#[async_trait]
+impl LogsService for Service {
+    async fn export(
+        &self,
+        request: Request<ExportLogsServiceRequest>,
+    ) -> std::result::Result<Response<ExportLogsServiceResponse>, Status> {
+        let resource_logs = request.into_inner().resource_logs;
+        let total_log_records: usize = resource_logs
+            .iter()
+            .flat_map(|r| r.scope_logs.iter())
+            .map(|s| s.log_records.len())
+            .sum();
+        if total_log_records == 0 {
+            return Ok(Response::new(ExportLogsServiceResponse {
+                partial_success: None,
+            }));
+        }
+
+        let dataset = self.datasets.logs.as_str();
+        let existing_schema = self.existing_schema(dataset).await;
+        let written = match log_records_to_record_batch(dataset, &resource_logs, &existing_schema) {
+            Ok(record_batch) => self.append_to_dataset(dataset, record_batch).await,
+            Err(e) => {
+                tracing::error!("Failed to build arrow data from OpenTelemetry logs: {e}");
+                false
+            }
+        };
+
+        if !written {
+            return Err(Status::invalid_argument("All log records were rejected"));
+        }
+
+        Ok(Response::new(ExportLogsServiceResponse {
+            partial_success: None,
+        }))
+    }
+}
+
+/// Convert log records to a record batch with one row per log record. Resource, scope and log
+/// record attributes are flattened into one column per attribute, with log record attributes
+/// taking precedence.
+fn log_records_to_record_batch(
+    dataset: &str,
+    resource_logs: &[ResourceLogs],
+    existing_schema: &Option<Schema>,
+) -> Result<RecordBatch> {
+    let mut time_unix_nano = UInt64Builder::new();
+    let mut observed_time_unix_nano = UInt64Builder::new();
+    let mut severity_number = Int32Builder::new();
+    let mut severity_text = StringBuilder::new();
+    let mut body = StringBuilder::new();
+    let mut trace_id = StringBuilder::new();
+    let mut span_id = StringBuilder::new();
+    let mut scope_name = StringBuilder::new();
+    let mut attributes: Vec<Vec<KeyValue>> = Vec::new();
+
+    for resource_log in resource_logs {
+        let resource_attributes = resource_log
+            .resource
+            .as_ref()
+            .map_or(&[][..], |r| r.attributes.as_slice());
+        for scope_log in &resource_log.scope_logs {
+            let scope_attributes = scope_log
+                .scope
+                .as_ref()
+                .map_or(&[][..], |s| s.attributes.as_slice());
+            for log_record in &scope_log.log_records {
+                time_unix_nano.append_value(log_record.time_unix_nano);
+                observed_time_unix_nano.append_value(log_record.observed_time_unix_nano);
+                severity_number.append_value(log_record.severity_number);
+                severity_text.append_option(
+                    Some(log_record.severity_text.as_str()).filter(|text| !text.is_empty()),
+                );
+                body.append_option(log_record.body.as_ref().and_then(any_value_to_string));
+                trace_id.append_option(hex_id(&log_record.trace_id));
+                span_id.append_option(hex_id(&log_record.span_id));
+                scope_name.append_option(scope_log.scope.as_ref().map(|s| s.name.as_str()));
+                attributes.push(merge_attributes(&[
+                    resource_attributes,
+                    scope_attributes,
+                    log_record.attributes.as_slice(),
+                ]));
+            }
+        }
+    }
+    ensure!(!attributes.is_empty(), NoLogRecordsSnafu);
+
+    let fields = vec![
+        Arc::new(Field::new(
+            TIME_UNIX_NANO_COLUMN_NAME,
+            DataType::UInt64,
+            true,
+        )),
+        Arc::new(Field::new(
+            OBSERVED_TIME_UNIX_NANO_COLUMN_NAME,
+            DataType::UInt64,
+            true,
+        )),
+        Arc::new(Field::new(
+            SEVERITY_NUMBER_COLUMN_NAME,
+            DataType::Int32,
+            true,
+        )),
+        Arc::new(Field::new(SEVERITY_TEXT_COLUMN_NAME, DataType::Utf8, true)),
+        Arc::new(Field::new(BODY_COLUMN_NAME, DataType::Utf8, true)),
+        Arc::new(Field::new(TRACE_ID_COLUMN_NAME, DataType::Utf8, true)),
+        Arc::new(Field::new(SPAN_ID_COLUMN_NAME, DataType::Utf8, true)),
+        Arc::new(Field::new(SCOPE_NAME_COLUMN_NAME, DataType::Utf8, true)),
+    ];
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(time_unix_nano.finish()),
+        Arc::new(observed_time_unix_nano.finish()),
+        Arc::new(severity_number.finish()),
+        Arc::new(severity_text.finish()),
+        Arc::new(body.finish()),
+        Arc::new(trace_id.finish()),
+        Arc::new(span_id.finish()),
+        Arc::new(scope_name.finish()),
+    ];
+
+    let attributes: Vec<&[KeyValue]> = attributes.iter().map(Vec::as_slice).collect();
+    with_attribute_columns(dataset, fields, columns, &attributes, existing_schema)
+}
+
+/// The text of a log record body. Bodies that are lists or maps are not supported.
+fn any_value_to_string(value: &AnyValue) -> Option<String> {
+    match value.value.as_ref()? {
+        any_value::Value::StringValue(s) => Some(s.clone()),
+        any_value::Value::BoolValue(b) => Some(b.to_string()),
+        any_value::Value::IntValue(i) => Some(i.to_string()),
+        any_value::Value::DoubleValue(d) => Some(d.to_string()),
+        any_value::Value::BytesValue(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+        any_value::Value::ArrayValue(_) | any_value::Value::KvlistValue(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{AsArray, StringArray};
+    use arrow::datatypes::{Int32Type, UInt64Type};
+    use opentelemetry_proto::tonic::common::v1::InstrumentationScope;
+    use opentelemetry_proto::tonic::logs::v1::{LogRecord, ScopeLogs, SeverityNumber};
+    use opentelemetry_proto::tonic::resource::v1::Resource;
+
+    use super::*;
+
+    fn attribute(key: &str, value: any_value::Value) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue { value: Some(value) }),
+        }
+    }
+
+    fn strings<'a>(batch: &'a RecordBatch, name: &str) -> &'a StringArray {
+        batch
+            .column_by_name(name)
+            .expect("column exists")
+            .as_string::<i32>()
+    }
+
+    #[test]
+    fn test_log_records_to_record_batch() {
+        let request = ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: Some(Resource {
+                    attributes: vec![
+                        attribute(
+                            "service.name",
+                            any_value::Value::StringValue("payments".to_string()),
+                        ),
+                        attribute("retry", any_value::Value::BoolValue(false)),
+                    ],
+                    ..Default::default()
+                }),
+                scope_logs: vec![ScopeLogs {
+                    scope: Some(InstrumentationScope {
+                        name: "worker".to_string(),
+                        ..Default::default()
+                    }),
+                    log_records: vec![
+                        LogRecord {
+                            time_unix_nano: 1_000,
+                            observed_time_unix_nano: 1_100,
+                            severity_number: SeverityNumber::Error.into(),
+                            severity_text: "ERROR".to_string(),
+                            body: Some(AnyValue {
+                                value: Some(any_value::Value::StringValue(
+                                    "payment failed".to_string(),
+                                )),
+                            }),
+                            trace_id: vec![0x0b; 16],
+                            span_id: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                            attributes: vec![attribute("retry", any_value::Value::BoolValue(true))],
+                            ..Default::default()
+                        },
+                        LogRecord {
+                            observed_time_unix_nano: 2_000,
+                            severity_number: SeverityNumber::Info.into(),
+                            body: Some(AnyValue {
+                                value: Some(any_value::Value::IntValue(42)),
+                            }),
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+
+        let batch = log_records_to_record_batch("logs", &request.resource_logs, &None)
+            .expect("log records are converted");
+        assert_eq!(batch.num_rows(), 2);
+
+        let time_column = |name: &str| {
+            batch
+                .column_by_name(name)
+                .expect("column exists")
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec()
+        };
+        assert_eq!(time_column(TIME_UNIX_NANO_COLUMN_NAME), vec![1_000, 0]);
+        assert_eq!(
+            time_column(OBSERVED_TIME_UNIX_NANO_COLUMN_NAME),
+            vec![1_100, 2_000]
+        );
+        assert_eq!(
+            batch
+                .column_by_name(SEVERITY_NUMBER_COLUMN_NAME)
+                .expect("column exists")
+                .as_primitive::<Int32Type>()
+                .values()
+                .to_vec(),
+            vec![17, 9]
+        );
+
+        let severity_text = strings(&batch, SEVERITY_TEXT_COLUMN_NAME);
+        assert_eq!(severity_text.value(0), "ERROR");
+        assert!(severity_text.is_null(1));
+        assert_eq!(strings(&batch, BODY_COLUMN_NAME).value(0), "payment failed");
+        assert_eq!(strings(&batch, BODY_COLUMN_NAME).value(1), "42");
+        assert_eq!(
+            strings(&batch, TRACE_ID_COLUMN_NAME).value(0),
+            "0b".repeat(16)
+        );
+        assert!(strings(&batch, SPAN_ID_COLUMN_NAME).is_null(1));
+        assert_eq!(strings(&batch, SCOPE_NAME_COLUMN_NAME).value(0), "worker");
+
+        // Resource attributes are on every log record, and log record attributes take precedence.
+        assert_eq!(strings(&batch, "service.name").value(1), "payments");
+        let retry = batch
+            .column_by_name("retry")
+            .expect("column exists")
+            .as_boolean();
+        assert!(retry.value(0));
+        assert!(!retry.value(1));
+
+        assert!(log_records_to_record_batch("logs", &[], &None).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Receives OTLP spans and appends them to the configured traces dataset, `traces` by default.
+
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::TraceService;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceResponse;
+use opentelemetry_proto::tonic::common::v1::KeyValue;
+use opentelemetry_proto::tonic::trace::v1::span::SpanKind;
+use opentelemetry_proto::tonic::trace::v1::status::StatusCode;
+use opentelemetry_proto::tonic::trace::v1::ResourceSpans;
+use snafu::prelude::*;
+use tonic_0_9_0::async_trait;
+use tonic_0_9_0::Request;
+use tonic_0_9_0::Response;
+use tonic_0_9_0::Status;
+
+use super::{
+    hex_id, merge_attributes, with_attribute_columns, NoSpansSnafu, Result, Service,
+    SCOPE_NAME_COLUMN_NAME, SPAN_ID_COLUMN_NAME, START_TIME_UNIX_NANO_COLUMN_NAME,
+    TRACE_ID_COLUMN_NAME,
+};
+
+const PARENT_SPAN_ID_COLUMN_NAME: &str = "parent_span_id";
+const TRACE_STATE_COLUMN_NAME: &str = "trace_state";
+const NAME_COLUMN_NAME: &str = "name";
+const KIND_COLUMN_NAME: &str = "kind";
+const END_TIME_UNIX_NANO_COLUMN_NAME: &str = "end_time_unix_nano";
+const STATUS_CODE_COLUMN_NAME: &str = "status_code";
+const STATUS_MESSAGE_COLUMN_NAME: &str = "status_message";
+
+#[async_trait]
+impl TraceService for Service {
+    async fn export(
+        &self,
+        request: Request<ExportTraceServiceRequest>,
+    ) -> std::result::Result<Response<ExportTraceServiceResponse>, Status> {
+        let resource_spans = request.into_inner().resource_spans;
+        let total_spans: usize = resource_spans
+            .iter()
+            .flat_map(|r| r.scope_spans.iter())
+            .map(|s| s.spans.len())
+            .sum();
+        if total_spans == 0 {
+            return Ok(Response::new(ExportTraceServiceResponse {
+                partial_success: None,
+            }));
+        }
+
+        let dataset = self.datasets.traces.as_str();
+        let existing_schema = self.existing_schema(dataset).await;
+        let written = match spans_to_record_batch(dataset, &resource_spans, &existing_schema) {
+            Ok(record_batch) => self.append_to_dataset(dataset, record_batch).await,
+            Err(e) => {
+                tracing::error!("Failed to build arrow data from OpenTelemetry spans: {e}");
+                false
+            }
+        };
+
+        if !written {
+            return Err(Status::invalid_argument("All spans were rejected"));
+        }
+
+        Ok(Response::new(ExportTraceServiceResponse {
+            partial_success: None,
+        }))
+    }
+}
+
+/// Convert spans to a record batch with one row per span. Resource, scope and span attributes are
+/// flattened into one column per attribute, with span attributes taking precedence.
+fn spans_to_record_batch(
+    dataset: &str,
+    resource_spans: &[ResourceSpans],
+    existing_schema: &Option<Schema>,
+) -> Result<RecordBatch> {
+    let mut trace_id = StringBuilder::new();
+    let mut span_id = StringBuilder::new();
+    let mut parent_span_id = StringBuilder::new();
+    let mut trace_state = StringBuilder::new();
+    let mut name = StringBuilder::new();
+    let mut kind = StringBuilder::new();
+    let mut start_time_unix_nano = UInt64Builder::new();
+    let mut end_time_unix_nano = UInt64Builder::new();
+    let mut status_code = StringBuilder::new();
+    let mut status_message = StringBuilder::new();
+    let mut scope_name = StringBuilder::new();
+    let mut attributes: Vec<Vec<KeyValue>> = Vec::new();
+
+    for resource_span in resource_spans {
+        let resource_attributes = resource_span
+            .resource
+            .as_ref()
+            .map_or(&[][..], |r| r.attributes.as_slice());
+        for scope_span in &resource_span.scope_spans {
+            let scope_attributes = scope_span
+                .scope
+                .as_ref()
+                .map_or(&[][..], |s| s.attributes.as_slice());
+            for span in &scope_span.spans {
+                trace_id.append_option(hex_id(&span.trace_id));
+                span_id.append_option(hex_id(&span.span_id));
+                parent_span_id.append_option(hex_id(&span.parent_span_id));
+                trace_state.append_option(
+                    Some(span.trace_state.as_str()).filter(|state| !state.is_empty()),
+                );
+                name.append_value(&span.name);
+                kind.append_option(SpanKind::try_from(span.kind).ok().map(|k| k.as_str_name()));
+                start_time_unix_nano.append_value(span.start_time_unix_nano);
+                end_time_unix_nano.append_value(span.end_time_unix_nano);
+                status_code.append_option(
+                    span.status
+                        .as_ref()
+                        .and_then(|status| StatusCode::try_from(status.code).ok())
+                        .map(|code| code.as_str_name()),
+                );
+                status_message.append_option(
+                    span.status
+                        .as_ref()
+                        .map(|status| status.message.as_str())
+                        .filter(|message| !message.is_empty()),
+                );
+                scope_name.append_option(scope_span.scope.as_ref().map(|s| s.name.as_str()));
+                attributes.push(merge_attributes(&[
+                    resource_attributes,
+                    scope_attributes,
+                    span.attributes.as_slice(),
+                ]));
+            }
+        }
+    }
+    ensure!(!attributes.is_empty(), NoSpansSnafu);
+
+    let string_field = |name: &str| Arc::new(Field::new(name, DataType::Utf8, true));
+    let fields = vec![
+        string_field(TRACE_ID_COLUMN_NAME),
+        string_field(SPAN_ID_COLUMN_NAME),
+        string_field(PARENT_SPAN_ID_COLUMN_NAME),
+        string_field(TRACE_STATE_COLUMN_NAME),
+        string_field(NAME_COLUMN_NAME),
+        string_field(KIND_COLUMN_NAME),
+        Arc::new(Field::new(
+            START_TIME_UNIX_NANO_COLUMN_NAME,
+            DataType::UInt64,
+            true,
+        )),
+        Arc::new(Field::new(
+            END_TIME_UNIX_NANO_COLUMN_NAME,
+            DataType::UInt64,
+            true,
+        )),
+        string_field(STATUS_CODE_COLUMN_NAME),
+        string_field(STATUS_MESSAGE_COLUMN_NAME),
+        string_field(SCOPE_NAME_COLUMN_NAME),
+    ];
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(trace_id.finish()),
+        Arc::new(span_id.finish()),
+        Arc::new(parent_span_id.finish()),
+        Arc::new(trace_state.finish()),
+        Arc::new(name.finish()),
+        Arc::new(kind.finish()),
+        Arc::new(start_time_unix_nano.finish()),
+        Arc::new(end_time_unix_nano.finish()),
+        Arc::new(status_code.finish()),
+        Arc::new(status_message.finish()),
+        Arc::new(scope_name.finish()),
+    ];
+
+    let attributes: Vec<&[KeyValue]> = attributes.iter().map(Vec::as_slice).collect();
+    with_attribute_columns(dataset, fields, columns, &attributes, existing_schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{AsArray, StringArray};
+    use arrow::datatypes::{Int64Type, UInt64Type};
+    use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, InstrumentationScope};
+    use opentelemetry_proto::tonic::resource::v1::Resource;
+    use opentelemetry_proto::tonic::trace::v1::{ScopeSpans, Span, Status as SpanStatus};
+
+    use super::*;
+
+    fn attribute(key: &str, value: any_value::Value) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue { value: Some(value) }),
+        }
+    }
+
+    fn strings<'a>(batch: &'a RecordBatch, name: &str) -> &'a StringArray {
+        batch
+            .column_by_name(name)
+            .expect("column exists")
+            .as_string::<i32>()
+    }
+
+    #[test]
+    fn test_spans_to_record_batch() {
+        let request = ExportTraceServiceRequest {
+            resource_spans: vec![ResourceSpans {
+                resource: Some(Resource {
+                    attributes: vec![
+                        attribute(
+                            "service.name",
+                            any_value::Value::StringValue("checkout".to_string()),
+                        ),
+                        attribute(
+                            "region",
+                            any_value::Value::StringValue("us-east-1".to_string()),
+                        ),
+                    ],
+                    ..Default::default()
+                }),
+                scope_spans: vec![ScopeSpans {
+                    scope: Some(InstrumentationScope {
+                        name: "http".to_string(),
+                        ..Default::default()
+                    }),
+                    spans: vec![
+                        Span {
+                            trace_id: vec![0x0a; 16],
+                            span_id: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                            name: "GET /cart".to_string(),
+                            kind: SpanKind::Server.into(),
+                            start_time_unix_nano: 1_000,
+                            end_time_unix_nano: 2_500,
+                            status: Some(SpanStatus {
+                                code: StatusCode::Error.into(),
+                                message: "timeout".to_string(),
+                            }),
+                            attributes: vec![
+                                attribute(
+                                    "region",
+                                    any_value::Value::StringValue("eu-west-1".to_string()),
+                                ),
+                                attribute("http.status_code", any_value::Value::IntValue(504)),
+                            ],
+                            ..Default::default()
+                        },
+                        Span {
+                            trace_id: vec![0x0a; 16],
+                            span_id: vec![9; 8],
+                            parent_span_id: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                            name: "SELECT cart".to_string(),
+                            kind: SpanKind::Client.into(),
+                            start_time_unix_nano: 1_200,
+                            end_time_unix_nano: 2_000,
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+
+        let batch = spans_to_record_batch("traces", &request.resource_spans, &None)
+            .expect("spans are converted");
+        assert_eq!(batch.num_rows(), 2);
+
+        assert_eq!(
+            strings(&batch, TRACE_ID_COLUMN_NAME).value(0),
+            "0a".repeat(16)
+        );
+        assert_eq!(
+            strings(&batch, SPAN_ID_COLUMN_NAME).value(0),
+            "0102030405060708"
+        );
+        let parent_span_id = strings(&batch, PARENT_SPAN_ID_COLUMN_NAME);
+        assert!(parent_span_id.is_null(0));
+        assert_eq!(parent_span_id.value(1), "0102030405060708");
+        assert_eq!(strings(&batch, NAME_COLUMN_NAME).value(1), "SELECT cart");
+        assert_eq!(
+            strings(&batch, KIND_COLUMN_NAME).value(0),
+            "SPAN_KIND_SERVER"
+        );
+        assert_eq!(
+            strings(&batch, KIND_COLUMN_NAME).value(1),
+            "SPAN_KIND_CLIENT"
+        );
+
+        let time_column = |name: &str| {
+            batch
+                .column_by_name(name)
+                .expect("column exists")
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec()
+        };
+        assert_eq!(
+            time_column(START_TIME_UNIX_NANO_COLUMN_NAME),
+            vec![1_000, 1_200]
+        );
+        assert_eq!(
+            time_column(END_TIME_UNIX_NANO_COLUMN_NAME),
+            vec![2_500, 2_000]
+        );
+
+        let status_code = strings(&batch, STATUS_CODE_COLUMN_NAME);
+        assert_eq!(status_code.value(0), "STATUS_CODE_ERROR");
+        assert!(status_code.is_null(1));
+        assert_eq!(
+            strings(&batch, STATUS_MESSAGE_COLUMN_NAME).value(0),
+            "timeout"
+        );
+        assert_eq!(strings(&batch, SCOPE_NAME_COLUMN_NAME).value(1), "http");
+
+        // Resource attributes are on every span, and span attributes take precedence.
+        let service_name = strings(&batch, "service.name");
+        assert_eq!(service_name.value(0), "checkout");
+        assert_eq!(service_name.value(1), "checkout");
+        let region = strings(&batch, "region");
+        assert_eq!(region.value(0), "eu-west-1");
+        assert_eq!(region.value(1), "us-east-1");
+        let http_status_code = batch
+            .column_by_name("http.status_code")
+            .expect("column exists")
+            .as_primitive::<Int64Type>();
+        assert_eq!(http_status_code.value(0), 504);
+        assert!(http_status_code.is_null(1));
+
+        assert!(spans_to_record_batch("traces", &[], &None).is_err());
+    }
+}
@@ -19,7 +19,7 @@ use notify::{
     EventKind, RecursiveMode, Watcher,
 };
 use spicepod::component::ComponentOrReference;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::sync::mpsc::{channel, Receiver};
 
 use app::{App, AppBuilder};
@@ -105,30 +105,27 @@ fn get_watch_paths(app_path: impl Into<PathBuf>) -> Vec<PathBuf> {
             dirs.push(dep_path);
         }
 
-        for dataset in spicepod.datasets {
-            match dataset {
-                ComponentOrReference::Reference(reference) => {
-                    let ref_path = root_dir.join(&reference.r#ref);
-                    dirs.push(root_dir.join(ref_path));
-                }
-                ComponentOrReference::Component(_) => { /* ignore component */ }
-            }
-        }
-
-        for model in spicepod.models {
-            match model {
-                ComponentOrReference::Reference(reference) => {
-                    let ref_path = root_dir.join(&reference.r#ref);
-                    dirs.push(root_dir.join(ref_path));
-                }
-                ComponentOrReference::Component(_) => { /* ignore component */ }
-            }
-        }
+        dirs.extend(reference_paths(&root_dir, &spicepod.catalogs));
+        dirs.extend(reference_paths(&root_dir, &spicepod.datasets));
+        dirs.extend(reference_paths(&root_dir, &spicepod.views));
+        dirs.extend(reference_paths(&root_dir, &spicepod.models));
+        dirs.extend(reference_paths(&root_dir, &spicepod.embeddings));
     }
 
     dirs
 }
 
+/// The paths of the components defined in separate files, which are watched for changes too.
+fn reference_paths<'a, T>(
+    root_dir: &'a Path,
+    components: &'a [ComponentOrReference<T>],
+) -> impl Iterator<Item = PathBuf> + 'a {
+    components.iter().filter_map(|component| match component {
+        ComponentOrReference::Reference(reference) => Some(root_dir.join(&reference.r#ref)),
+        ComponentOrReference::Component(_) => None,
+    })
+}
+
 fn is_spicepods_modification_event(spicepod_paths: &[PathBuf], event: &notify::Event) -> bool {
     match event.kind {
         EventKind::Create(CreateKind::File)
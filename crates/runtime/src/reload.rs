@@ -0,0 +1,271 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Diffs two versions of a spicepod, so the runtime only reloads the components that changed.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt::Display,
+    hash::{Hash, Hasher},
+};
+
+use secrecy::{ExposeSecret, SecretString};
+
+/// The components added, updated and removed between two versions of a spicepod, by name.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ComponentChanges {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ComponentChanges {
+    /// Diffs components by name. A component whose definition changed is updated.
+    pub fn diff<T: PartialEq>(current: &[T], new: &[T], name: impl Fn(&T) -> String) -> Self {
+        let mut changes = Self::default();
+        for component in new {
+            match current.iter().find(|c| name(c) == name(component)) {
+                Some(current_component) if current_component != component => {
+                    changes.updated.push(name(component));
+                }
+                Some(_) => {}
+                None => changes.added.push(name(component)),
+            }
+        }
+        for component in current {
+            if !new.iter().any(|c| name(c) == name(component)) {
+                changes.removed.push(name(component));
+            }
+        }
+
+        changes
+    }
+
+    /// Marks an unchanged component as updated, i.e. because its secrets changed.
+    pub fn update(&mut self, name: String) {
+        if !self.added.contains(&name) && !self.updated.contains(&name) {
+            self.updated.push(name);
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl Display for ComponentChanges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let changes: Vec<String> = [
+            ("added", &self.added),
+            ("updated", &self.updated),
+            ("removed", &self.removed),
+        ]
+        .iter()
+        .filter(|(_, names)| !names.is_empty())
+        .map(|(change, names)| format!("{change} {}", names.join(", ")))
+        .collect();
+
+        write!(f, "{}", changes.join(", "))
+    }
+}
+
+/// The kinds of components whose parameters can reference secrets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SecretComponent {
+    Catalog,
+    Dataset,
+    Model,
+    Embedding,
+}
+
+/// Fingerprints the parameters of each component once their secrets are resolved, to find the
+/// components affected when the secret stores change. Only a hash of the secrets is kept.
+#[derive(Debug, Default)]
+pub struct SecretFingerprints(HashMap<(SecretComponent, String), Option<u64>>);
+
+impl SecretFingerprints {
+    /// Adds the resolved parameters of a component, or `None` if they failed to resolve.
+    pub fn insert(
+        &mut self,
+        component: SecretComponent,
+        name: String,
+        params: Option<HashMap<String, SecretString>>,
+    ) {
+        let fingerprint = params.map(|params| {
+            let mut params: Vec<_> = params.into_iter().collect();
+            params.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            let mut hasher = DefaultHasher::new();
+            for (key, value) in &params {
+                key.hash(&mut hasher);
+                value.expose_secret().hash(&mut hasher);
+            }
+            hasher.finish()
+        });
+        self.0.insert((component, name), fingerprint);
+    }
+
+    /// The names of the components of a kind whose resolved parameters differ in `other`.
+    #[must_use]
+    pub fn changed(&self, other: &Self, component: SecretComponent) -> Vec<String> {
+        let mut changed: Vec<String> = self
+            .0
+            .iter()
+            .filter(|((c, name), fingerprint)| {
+                *c == component
+                    && other
+                        .0
+                        .get(&(component, name.clone()))
+                        .is_some_and(|other| other != *fingerprint)
+            })
+            .map(|((_, name), _)| name.clone())
+            .collect();
+        changed.sort();
+        changed
+    }
+}
+
+/// What changed when the spicepod was reloaded.
+#[derive(Debug, Default)]
+pub struct ReloadReport {
+    pub secrets: bool,
+    pub catalogs: ComponentChanges,
+    pub datasets: ComponentChanges,
+    pub views: ComponentChanges,
+    pub embeddings: ComponentChanges,
+    pub models: ComponentChanges,
+
+    /// The `runtime` settings that were applied, i.e. `results_cache`.
+    pub runtime: Vec<&'static str>,
+
+    /// The `runtime` settings and other sections that changed, but only apply on restart.
+    pub requires_restart: Vec<&'static str>,
+}
+
+impl ReloadReport {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        !self.secrets
+            && self.catalogs.is_empty()
+            && self.datasets.is_empty()
+            && self.views.is_empty()
+            && self.embeddings.is_empty()
+            && self.models.is_empty()
+            && self.runtime.is_empty()
+            && self.requires_restart.is_empty()
+    }
+}
+
+impl Display for ReloadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+
+        let mut report = vec![];
+        if self.secrets {
+            report.push("secret stores reloaded".to_string());
+        }
+        for (component, changes) in [
+            ("catalogs", &self.catalogs),
+            ("datasets", &self.datasets),
+            ("views", &self.views),
+            ("embeddings", &self.embeddings),
+            ("models", &self.models),
+        ] {
+            if !changes.is_empty() {
+                report.push(format!("{component} {changes}"));
+            }
+        }
+        for setting in &self.runtime {
+            report.push(format!("runtime.{setting} applied"));
+        }
+        for setting in &self.requires_restart {
+            report.push(format!("{setting} changed, restart to apply"));
+        }
+
+        write!(f, "{}", report.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_component_changes() {
+        let current = vec![("a", 1), ("b", 1), ("c", 1)];
+        let new = vec![("a", 1), ("b", 2), ("d", 1)];
+        let mut changes = ComponentChanges::diff(&current, &new, |(name, _)| (*name).to_string());
+        assert_eq!(
+            changes,
+            ComponentChanges {
+                added: vec!["d".to_string()],
+                updated: vec!["b".to_string()],
+                removed: vec!["c".to_string()],
+            }
+        );
+
+        changes.update("a".to_string());
+        changes.update("b".to_string());
+        changes.update("d".to_string());
+        assert_eq!(changes.updated, vec!["b".to_string(), "a".to_string()]);
+
+        let report = ReloadReport {
+            secrets: true,
+            datasets: changes,
+            runtime: vec!["results_cache"],
+            requires_restart: vec!["runtime.tls"],
+            ..Default::default()
+        };
+        assert_eq!(
+            report.to_string(),
+            "secret stores reloaded; datasets added d, updated b, a, removed c; runtime.results_cache applied; runtime.tls changed, restart to apply"
+        );
+        assert_eq!(ReloadReport::default().to_string(), "no changes");
+    }
+
+    #[test]
+    fn test_secret_fingerprints() {
+        let params = |value: &str| {
+            Some(HashMap::from([(
+                "password".to_string(),
+                SecretString::new(value.to_string()),
+            )]))
+        };
+
+        let mut before = SecretFingerprints::default();
+        before.insert(SecretComponent::Dataset, "a".to_string(), params("old"));
+        before.insert(SecretComponent::Dataset, "b".to_string(), params("same"));
+        before.insert(SecretComponent::Model, "a".to_string(), params("same"));
+
+        let mut after = SecretFingerprints::default();
+        after.insert(SecretComponent::Dataset, "a".to_string(), params("new"));
+        after.insert(SecretComponent::Dataset, "b".to_string(), params("same"));
+        after.insert(SecretComponent::Model, "a".to_string(), None);
+
+        assert_eq!(
+            before.changed(&after, SecretComponent::Dataset),
+            vec!["a".to_string()]
+        );
+        assert_eq!(
+            before.changed(&after, SecretComponent::Model),
+            vec!["a".to_string()]
+        );
+        assert!(before.changed(&after, SecretComponent::Catalog).is_empty());
+    }
+}
@@ -0,0 +1,688 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Built-in tools that chat models can call to answer questions from the datasets of the
+//! runtime, and the loop that runs their calls before answering the client.
+//!
+//! Tools are enabled per model with the `tools` parameter, a comma-separated list of tool names
+//! or `builtin` for all of them:
+//! ```yaml
+//! models:
+//!   - from: openai/gpt-4o
+//!     name: analyst
+//!     params:
+//!       tools: builtin
+//! ```
+
+use std::{fmt::Display, str::FromStr, sync::Arc};
+
+use arrow::array::RecordBatch;
+use async_openai::{
+    error::OpenAIError,
+    types::{
+        ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs,
+        ChatCompletionRequestToolMessageArgs, ChatCompletionTool, ChatCompletionToolArgs,
+        ChatCompletionToolType, CompletionUsage, CreateChatCompletionRequest,
+        CreateChatCompletionResponse, FunctionObjectArgs,
+    },
+};
+use datafusion::sql::TableReference;
+use futures::TryStreamExt;
+use llms::chat::Chat;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use snafu::prelude::*;
+
+use crate::{
+    auth::{access_control::Permission, Principal},
+    datafusion::{query::Protocol, DataFusion},
+    embeddings::vector_search::{RetrievalLimit, SearchMode, VectorSearch},
+};
+
+/// The maximum number of times the model is called for a single request, so a model that keeps
+/// calling tools can't loop forever.
+const MAX_TOOL_ROUNDS: usize = 10;
+
+/// The maximum number of rows returned to the model by the `sql` tool.
+const MAX_SQL_ROWS: usize = 100;
+
+const DEFAULT_SEARCH_LIMIT: usize = 3;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unknown tool {name}. Valid tools are: builtin, {}", BuiltinTool::ALL.map(|t| t.to_string()).join(", ")))]
+    UnknownTool { name: String },
+
+    #[snafu(display("Invalid arguments for tool {tool}: {source}"))]
+    InvalidArguments {
+        tool: BuiltinTool,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Access denied to table {table}"))]
+    AccessDenied { table: String },
+
+    #[snafu(display("Failed to run tool {tool}: {source}"))]
+    FailedToRunTool {
+        tool: BuiltinTool,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("Failed to run the chat model: {source}"))]
+    FailedToRunModel { source: OpenAIError },
+
+    #[snafu(display("The chat model kept calling tools after {MAX_TOOL_ROUNDS} rounds"))]
+    TooManyToolRounds,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinTool {
+    ListDatasets,
+    TableSchema,
+    Sql,
+    Search,
+}
+
+#[derive(Deserialize)]
+struct TableSchemaArgs {
+    table: String,
+}
+
+#[derive(Deserialize)]
+struct SqlArgs {
+    query: String,
+}
+
+#[derive(Deserialize)]
+struct SearchArgs {
+    text: String,
+    datasets: Vec<String>,
+    limit: Option<usize>,
+}
+
+impl BuiltinTool {
+    pub const ALL: [BuiltinTool; 4] = [
+        BuiltinTool::ListDatasets,
+        BuiltinTool::TableSchema,
+        BuiltinTool::Sql,
+        BuiltinTool::Search,
+    ];
+
+    /// Parses the `tools` parameter of a model: a comma-separated list of tool names, or
+    /// `builtin` for all of them.
+    pub fn parse_list(tools: &str) -> Result<Vec<Self>> {
+        let mut parsed = Vec::new();
+        for name in tools.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let tools = if name == "builtin" {
+                Self::ALL.to_vec()
+            } else {
+                vec![name.parse()?]
+            };
+            for tool in tools {
+                if !parsed.contains(&tool) {
+                    parsed.push(tool);
+                }
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            BuiltinTool::ListDatasets => "List the datasets that can be queried.",
+            BuiltinTool::TableSchema => "Get the schema of a dataset, as an Arrow schema in JSON.",
+            BuiltinTool::Sql => "Run a read-only SQL query (PostgreSQL dialect) and get the resulting rows as JSON. Results are limited to 100 rows.",
+            BuiltinTool::Search => "Search datasets with embeddings for the rows most similar to a text.",
+        }
+    }
+
+    fn parameters(self) -> Value {
+        match self {
+            BuiltinTool::ListDatasets => json!({
+                "type": "object",
+                "properties": {},
+            }),
+            BuiltinTool::TableSchema => json!({
+                "type": "object",
+                "properties": {
+                    "table": { "type": "string", "description": "The name of the dataset" },
+                },
+                "required": ["table"],
+            }),
+            BuiltinTool::Sql => json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "The SQL query to run" },
+                },
+                "required": ["query"],
+            }),
+            BuiltinTool::Search => json!({
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string", "description": "The text to search for" },
+                    "datasets": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "The datasets to search",
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "The maximum number of results per dataset. Defaults to 3.",
+                    },
+                },
+                "required": ["text", "datasets"],
+            }),
+        }
+    }
+
+    /// The definition of the tool offered to the model.
+    pub fn definition(self) -> Result<ChatCompletionTool, OpenAIError> {
+        ChatCompletionToolArgs::default()
+            .r#type(ChatCompletionToolType::Function)
+            .function(
+                FunctionObjectArgs::default()
+                    .name(self.to_string())
+                    .description(self.description())
+                    .parameters(self.parameters())
+                    .build()?,
+            )
+            .build()
+    }
+}
+
+impl Display for BuiltinTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuiltinTool::ListDatasets => write!(f, "list_datasets"),
+            BuiltinTool::TableSchema => write!(f, "table_schema"),
+            BuiltinTool::Sql => write!(f, "sql"),
+            BuiltinTool::Search => write!(f, "search"),
+        }
+    }
+}
+
+impl FromStr for BuiltinTool {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|tool| tool.to_string() == s)
+            .context(UnknownToolSnafu { name: s })
+    }
+}
+
+/// Runs the calls of built-in tools on behalf of a principal, who can only see the datasets
+/// they are allowed to read.
+pub struct ToolContext {
+    df: Arc<DataFusion>,
+    vector_search: Arc<VectorSearch>,
+    principal: Principal,
+}
+
+impl ToolContext {
+    #[must_use]
+    pub fn new(
+        df: Arc<DataFusion>,
+        vector_search: Arc<VectorSearch>,
+        principal: Principal,
+    ) -> Self {
+        Self {
+            df,
+            vector_search,
+            principal,
+        }
+    }
+
+    fn can_read(&self, table: &TableReference) -> bool {
+        self.df.access_control().map_or(true, |access_control| {
+            access_control.is_allowed(&self.principal, table, Permission::Read)
+        })
+    }
+
+    /// Calls a tool with its JSON arguments, returning the result for the model as text.
+    pub async fn call(&self, tool: BuiltinTool, arguments: &str) -> Result<String> {
+        // Models sometimes call tools without parameters with an empty string.
+        let arguments = if arguments.trim().is_empty() {
+            "{}"
+        } else {
+            arguments
+        };
+
+        match tool {
+            BuiltinTool::ListDatasets => {
+                let tables = self
+                    .df
+                    .get_public_table_names()
+                    .boxed()
+                    .context(FailedToRunToolSnafu { tool })?
+                    .into_iter()
+                    .filter(|t| self.can_read(&TableReference::bare(t.as_str())))
+                    .collect::<Vec<_>>();
+                Ok(json!(tables).to_string())
+            }
+            BuiltinTool::TableSchema => {
+                let args: TableSchemaArgs =
+                    serde_json::from_str(arguments).context(InvalidArgumentsSnafu { tool })?;
+                ensure!(
+                    self.can_read(&TableReference::from(args.table.as_str())),
+                    AccessDeniedSnafu { table: args.table }
+                );
+                let schema = self
+                    .df
+                    .get_arrow_schema(&args.table)
+                    .await
+                    .boxed()
+                    .context(FailedToRunToolSnafu { tool })?;
+                serde_json::to_string(&schema)
+                    .boxed()
+                    .context(FailedToRunToolSnafu { tool })
+            }
+            BuiltinTool::Sql => {
+                let args: SqlArgs =
+                    serde_json::from_str(arguments).context(InvalidArgumentsSnafu { tool })?;
+                self.sql(&args.query)
+                    .await
+                    .context(FailedToRunToolSnafu { tool })
+            }
+            BuiltinTool::Search => {
+                let args: SearchArgs =
+                    serde_json::from_str(arguments).context(InvalidArgumentsSnafu { tool })?;
+                let tables: Vec<TableReference> =
+                    args.datasets.iter().map(TableReference::from).collect();
+                if let Some(table) = tables.iter().find(|t| !self.can_read(t)) {
+                    return AccessDeniedSnafu {
+                        table: table.to_string(),
+                    }
+                    .fail();
+                }
+
+                let result = self
+                    .vector_search
+                    .search(
+                        args.text,
+                        tables,
+                        RetrievalLimit::TopN(args.limit.unwrap_or(DEFAULT_SEARCH_LIMIT)),
+                        SearchMode::Vector,
+                        None,
+                        Some(&self.principal),
+                    )
+                    .await
+                    .boxed()
+                    .context(FailedToRunToolSnafu { tool })?;
+                let results: serde_json::Map<String, Value> = result
+                    .retrieved_entries
+                    .into_iter()
+                    .map(|(table, entries)| {
+                        let distances = result
+                            .retrieved_distances
+                            .get(&table)
+                            .cloned()
+                            .unwrap_or_default();
+                        let matches = entries
+                            .into_iter()
+                            .zip(distances)
+                            .map(|(text, distance)| json!({ "text": text, "distance": distance }))
+                            .collect();
+                        (table.to_string(), Value::Array(matches))
+                    })
+                    .collect();
+                Ok(Value::Object(results).to_string())
+            }
+        }
+    }
+
+    /// Runs a read-only query as the principal, returning up to [`MAX_SQL_ROWS`] rows as JSON. The
+    /// restricted SQL options reject DDL and DML, so the model can't modify datasets.
+    async fn sql(&self, sql: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let query = self
+            .df
+            .query_builder(sql, Protocol::Http)
+            .use_restricted_sql_options()
+            .principal(self.principal.clone())
+            .build();
+
+        let mut data = query.run().await?.data;
+        let mut batches: Vec<RecordBatch> = Vec::new();
+        let mut num_rows = 0;
+        while num_rows < MAX_SQL_ROWS {
+            let Some(batch) = data.try_next().await? else {
+                break;
+            };
+            let batch = batch.slice(0, batch.num_rows().min(MAX_SQL_ROWS - num_rows));
+            num_rows += batch.num_rows();
+            batches.push(batch);
+        }
+
+        let mut writer = arrow_json::ArrayWriter::new(Vec::new());
+        writer.write_batches(&batches.iter().collect::<Vec<_>>())?;
+        writer.finish()?;
+        let rows = String::from_utf8(writer.into_inner())?;
+
+        // The writer outputs nothing for empty results.
+        Ok(if rows.is_empty() {
+            "[]".to_string()
+        } else {
+            rows
+        })
+    }
+}
+
+/// Offers the built-in `tools` to the model and runs the calls it makes to them, until it
+/// answers without calling any. Responses that call tools provided by the client are returned to
+/// the client to run them. The usage of the response covers every call to the model.
+pub async fn run_tool_loop(
+    model: &dyn Chat,
+    mut req: CreateChatCompletionRequest,
+    tools: &[BuiltinTool],
+    ctx: &ToolContext,
+) -> Result<CreateChatCompletionResponse> {
+    let definitions = tools
+        .iter()
+        .map(|tool| tool.definition())
+        .collect::<Result<Vec<_>, _>>()
+        .context(FailedToRunModelSnafu)?;
+    req.tools.get_or_insert_with(Vec::new).extend(definitions);
+    req.stream = None;
+
+    let mut usage: Option<CompletionUsage> = None;
+    for _ in 0..MAX_TOOL_ROUNDS {
+        let mut response = model
+            .chat_request(req.clone())
+            .await
+            .context(FailedToRunModelSnafu)?;
+        usage = add_usage(usage, response.usage.take());
+
+        let calls = response
+            .choices
+            .first()
+            .and_then(|choice| builtin_tool_calls(choice.message.tool_calls.as_ref()?, tools));
+        let Some(calls) = calls else {
+            response.usage = usage;
+            return Ok(response);
+        };
+
+        req.messages.push(
+            ChatCompletionRequestAssistantMessageArgs::default()
+                .tool_calls(
+                    calls
+                        .iter()
+                        .map(|(_, call)| call.clone())
+                        .collect::<Vec<_>>(),
+                )
+                .build()
+                .context(FailedToRunModelSnafu)?
+                .into(),
+        );
+        for (tool, call) in calls {
+            tracing::debug!("Calling tool {tool} with {}", call.function.arguments);
+            let content = match ctx.call(tool, &call.function.arguments).await {
+                Ok(content) => content,
+                Err(e) => {
+                    tracing::debug!("Tool {tool} failed: {e}");
+                    format!("Error: {e}")
+                }
+            };
+            req.messages.push(
+                ChatCompletionRequestToolMessageArgs::default()
+                    .content(content)
+                    .tool_call_id(call.id)
+                    .build()
+                    .context(FailedToRunModelSnafu)?
+                    .into(),
+            );
+        }
+    }
+
+    TooManyToolRoundsSnafu.fail()
+}
+
+/// Matches tool calls to the built-in tools, if they only call built-in tools.
+fn builtin_tool_calls(
+    calls: &[ChatCompletionMessageToolCall],
+    tools: &[BuiltinTool],
+) -> Option<Vec<(BuiltinTool, ChatCompletionMessageToolCall)>> {
+    if calls.is_empty() {
+        return None;
+    }
+
+    calls
+        .iter()
+        .map(|call| {
+            let tool = call.function.name.parse::<BuiltinTool>().ok()?;
+            tools.contains(&tool).then(|| (tool, call.clone()))
+        })
+        .collect()
+}
+
+fn add_usage(
+    total: Option<CompletionUsage>,
+    usage: Option<CompletionUsage>,
+) -> Option<CompletionUsage> {
+    match (total, usage) {
+        (Some(total), Some(usage)) => Some(CompletionUsage {
+            prompt_tokens: total.prompt_tokens + usage.prompt_tokens,
+            completion_tokens: total.completion_tokens + usage.completion_tokens,
+            total_tokens: total.total_tokens + usage.total_tokens,
+        }),
+        (total, usage) => total.or(usage),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use arrow::{
+        array::{Int64Array, StringArray},
+        datatypes::{DataType, Field, Schema},
+    };
+    use async_openai::types::{EmbeddingInput, FunctionCall};
+    use async_trait::async_trait;
+    use datafusion::datasource::MemTable;
+    use llms::embeddings::Embed;
+    use spicepod::component::dataset::policy::{ColumnMask, Policy};
+    use tokio::sync::RwLock;
+
+    use crate::embeddings::table::EmbeddingTable;
+
+    /// Embeds text by its length, which is enough to rank the rows of a test table.
+    struct LengthEmbed;
+
+    #[async_trait]
+    impl Embed for LengthEmbed {
+        #[allow(clippy::cast_precision_loss)]
+        async fn embed(
+            &mut self,
+            input: EmbeddingInput,
+        ) -> llms::embeddings::Result<Vec<Vec<f32>>> {
+            let texts = match input {
+                EmbeddingInput::String(text) => vec![text],
+                EmbeddingInput::StringArray(texts) => texts,
+                _ => vec![],
+            };
+            Ok(texts.iter().map(|t| vec![t.len() as f32, 1.0]).collect())
+        }
+
+        fn size(&self) -> i32 {
+            2
+        }
+    }
+
+    /// A `docs` table of two tenants, with an embedded `body` column.
+    async fn search_context(policy: Policy) -> ToolContext {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("tenant", DataType::Utf8, false),
+            Field::new("body", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(StringArray::from(vec!["acme", "globex"])),
+                Arc::new(StringArray::from(vec![
+                    "acme launch plan",
+                    "globex secret formula",
+                ])),
+            ],
+        )
+        .expect("valid batch");
+        let base_table = MemTable::try_new(schema, vec![vec![batch]]).expect("valid table");
+
+        let embedding_models = Arc::new(RwLock::new(HashMap::from([(
+            "length".to_string(),
+            RwLock::new(Box::new(LengthEmbed) as Box<dyn Embed>),
+        )])));
+        let table = EmbeddingTable::new(
+            Arc::new(base_table),
+            HashMap::from([("body".to_string(), "length".to_string())]),
+            Arc::clone(&embedding_models),
+            HashMap::new(),
+        )
+        .await;
+
+        let df = Arc::new(DataFusion::new());
+        let docs = TableReference::partial("public", "docs");
+        df.ctx
+            .register_table(docs.clone(), Arc::new(table))
+            .expect("table to register");
+        df.set_dataset_policy(&docs, Some(policy))
+            .expect("policy to set");
+
+        let vector_search = Arc::new(VectorSearch::new(
+            Arc::clone(&df),
+            embedding_models,
+            HashMap::new(),
+            HashMap::new(),
+        ));
+        ToolContext::new(df, vector_search, Principal::new("alice", vec![]))
+    }
+
+    async fn search(ctx: &ToolContext) -> String {
+        ctx.call(
+            BuiltinTool::Search,
+            r#"{"text": "formula", "datasets": ["docs"], "limit": 5}"#,
+        )
+        .await
+        .unwrap_or_else(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_parse_tool_list() {
+        assert_eq!(
+            BuiltinTool::parse_list("builtin").expect("valid tools"),
+            BuiltinTool::ALL.to_vec()
+        );
+        assert_eq!(
+            BuiltinTool::parse_list("sql, list_datasets,sql").expect("valid tools"),
+            vec![BuiltinTool::Sql, BuiltinTool::ListDatasets]
+        );
+        assert!(BuiltinTool::parse_list("sql,shell").is_err());
+    }
+
+    #[test]
+    fn test_builtin_tool_calls() {
+        let call = |name: &str| ChatCompletionMessageToolCall {
+            id: format!("call_{name}"),
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments: "{}".to_string(),
+            },
+        };
+
+        let calls = builtin_tool_calls(&[call("sql"), call("search")], &BuiltinTool::ALL)
+            .expect("built-in calls");
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].0, BuiltinTool::Sql);
+
+        // Tools that aren't enabled or that the client provided are left to the client.
+        assert!(builtin_tool_calls(&[call("sql")], &[BuiltinTool::Search]).is_none());
+        assert!(
+            builtin_tool_calls(&[call("sql"), call("get_weather")], &BuiltinTool::ALL).is_none()
+        );
+        assert!(builtin_tool_calls(&[], &BuiltinTool::ALL).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_search_tool_applies_dataset_policies() {
+        let row_filter = Policy {
+            row_filter: Some("tenant = 'acme'".to_string()),
+            column_masks: HashMap::new(),
+            exempt_roles: vec![],
+        };
+        let result = search(&search_context(row_filter).await).await;
+        assert!(result.contains("acme launch plan"), "{result}");
+        assert!(!result.contains("globex secret formula"), "{result}");
+
+        // The distances of a masked column's embeddings would reveal its values.
+        let column_mask = Policy {
+            row_filter: None,
+            column_masks: HashMap::from([("body".to_string(), ColumnMask::Redact)]),
+            exempt_roles: vec![],
+        };
+        let result = search(&search_context(column_mask).await).await;
+        assert!(result.contains("is masked"), "{result}");
+        assert!(!result.contains("secret formula"), "{result}");
+    }
+
+    #[tokio::test]
+    async fn test_sql_tool_is_read_only() {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![Arc::new(Int64Array::from(vec![1, 2]))],
+        )
+        .expect("valid batch");
+        let table = MemTable::try_new(schema, vec![vec![batch]]).expect("valid table");
+
+        // Runtime tables are writable.
+        let df = Arc::new(DataFusion::new());
+        df.register_runtime_table(TableReference::partial("runtime", "items"), Arc::new(table))
+            .expect("table to register");
+        let vector_search = Arc::new(VectorSearch::new(
+            Arc::clone(&df),
+            Arc::new(RwLock::new(HashMap::new())),
+            HashMap::new(),
+            HashMap::new(),
+        ));
+        let ctx = ToolContext::new(df, vector_search, Principal::new("alice", vec![]));
+
+        for sql in [
+            "DELETE FROM runtime.items",
+            "INSERT INTO runtime.items VALUES (3)",
+        ] {
+            let result = ctx
+                .call(BuiltinTool::Sql, &json!({ "query": sql }).to_string())
+                .await;
+            match result {
+                Ok(rows) => panic!("{sql} should be rejected, got {rows}"),
+                Err(e) => assert!(e.to_string().contains("DML not supported"), "{e}"),
+            }
+        }
+
+        let rows = ctx
+            .call(
+                BuiltinTool::Sql,
+                r#"{"query": "SELECT COUNT(*) AS count FROM runtime.items"}"#,
+            )
+            .await
+            .expect("query to run");
+        assert_eq!(rows, r#"[{"count":2}]"#);
+    }
+}
@@ -20,6 +20,7 @@ use std::{
 };
 
 use crate::init_tracing;
+use app::AppBuilder;
 use arrow_flight::{
     flight_service_client::FlightServiceClient,
     sql::{CommandStatementQuery, ProstMessageExt},
@@ -66,7 +67,10 @@ async fn test_tls_endpoints() -> Result<(), anyhow::Error> {
         .with_open_telemetry_bind_address(SocketAddr::new(LOCALHOST, otel_port));
     let tls_config = TlsConfig::try_new(cert_bytes.clone(), key_bytes).expect("valid TlsConfig");
 
+    // Without a spicepod, the endpoints reject all requests.
+    let app = AppBuilder::new("test_tls_endpoints").build();
     let rt = Runtime::builder()
+        .with_app(app)
         .with_metrics_server(SocketAddr::new(LOCALHOST, metrics_port), metrics_handle)
         .build()
         .await;
@@ -81,6 +81,12 @@ pub struct Dataset {
     #[serde(rename = "embeddings", default)]
     pub embeddings: Vec<ColumnEmbeddingConfig>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_text_search: Option<full_text_search::FullTextSearch>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<policy::Policy>,
+
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(rename = "dependsOn", default)]
     pub depends_on: Vec<String>,
@@ -106,6 +112,8 @@ impl Dataset {
             time_format: None,
             acceleration: None,
             embeddings: Vec::default(),
+            full_text_search: None,
+            policy: None,
             depends_on: Vec::default(),
         }
     }
@@ -124,6 +132,8 @@ impl WithDependsOn<Dataset> for Dataset {
             time_format: self.time_format.clone(),
             acceleration: self.acceleration.clone(),
             embeddings: self.embeddings.clone(),
+            full_text_search: self.full_text_search.clone(),
+            policy: self.policy.clone(),
             depends_on: depends_on.to_vec(),
         }
     }
@@ -326,3 +336,75 @@ pub mod replication {
         pub enabled: bool,
     }
 }
+
+pub mod full_text_search {
+    #[cfg(feature = "schemars")]
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    /// Enables BM25 full-text search over text columns of the dataset, on its own or combined
+    /// with vector search.
+    ///
+    /// ```yaml
+    /// full_text_search:
+    ///   columns:
+    ///     - title
+    ///     - body
+    /// ```
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    #[cfg_attr(feature = "schemars", derive(JsonSchema))]
+    pub struct FullTextSearch {
+        /// The columns whose text is indexed, as a single document per row.
+        pub columns: Vec<String>,
+    }
+}
+
+pub mod policy {
+    #[cfg(feature = "schemars")]
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    /// Restricts the rows and columns of a dataset that are visible to the caller of a query.
+    ///
+    /// ```yaml
+    /// policy:
+    ///   row_filter: tenant_id = $tenant_id
+    ///   column_masks:
+    ///     email: hash
+    ///     ssn: redact
+    ///   exempt_roles:
+    ///     - admin
+    /// ```
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    #[cfg_attr(feature = "schemars", derive(JsonSchema))]
+    #[serde(deny_unknown_fields)]
+    pub struct Policy {
+        /// A SQL predicate that rows must satisfy to be returned.
+        ///
+        /// `$principal` is replaced with the name of the authenticated principal, and any other `$name` placeholder
+        /// with the value of the `name` claim of the caller's JWT. Placeholders without a value are replaced with `NULL`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub row_filter: Option<String>,
+
+        /// Masks applied to the values of columns, keyed by column name.
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        pub column_masks: HashMap<String, ColumnMask>,
+
+        /// Principals with any of these roles see the dataset unfiltered.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub exempt_roles: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+    #[cfg_attr(feature = "schemars", derive(JsonSchema))]
+    #[serde(rename_all = "lowercase")]
+    pub enum ColumnMask {
+        /// Replaces string values with the hex encoded SHA-256 digest of the value. Other column types are masked as `null`.
+        Hash,
+        /// Replaces string values with `***`. Other column types are masked as `null`.
+        Redact,
+        /// Replaces values with `null`.
+        Null,
+    }
+}
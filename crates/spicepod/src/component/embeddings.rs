@@ -148,4 +148,53 @@ pub struct ColumnEmbeddingConfig {
 
     #[serde(rename = "column_pk", skip_serializing_if = "Option::is_none")]
     pub primary_keys: Option<Vec<String>>,
+
+    /// If set, the column is split into chunks that are embedded separately.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunking: Option<EmbeddingChunkConfig>,
+}
+
+/// Configuration for splitting a column's values into chunks before embedding.
+///
+/// ```yaml
+/// embeddings:
+///   - column: body
+///     use: openai
+///     chunking:
+///       strategy: characters
+///       target_chunk_size: 1000
+///       overlap_size: 100
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct EmbeddingChunkConfig {
+    #[serde(default)]
+    pub strategy: ChunkStrategy,
+
+    /// The maximum size of a chunk, in the unit of the [`ChunkStrategy`].
+    pub target_chunk_size: usize,
+
+    /// How much of the end of a chunk is repeated at the start of the next one, in the unit of the
+    /// [`ChunkStrategy`]. Unused for `sentences` and `paragraphs`.
+    #[serde(default)]
+    pub overlap_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkStrategy {
+    /// Fixed windows of `target_chunk_size` characters.
+    #[default]
+    Characters,
+
+    /// Fixed windows of `target_chunk_size` whitespace-separated tokens.
+    Tokens,
+
+    /// Whole sentences, packed into chunks of at most `target_chunk_size` characters.
+    Sentences,
+
+    /// Whole paragraphs, packed into chunks of at most `target_chunk_size` characters.
+    Paragraphs,
 }
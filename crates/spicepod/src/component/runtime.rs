@@ -27,8 +27,42 @@ pub struct Runtime {
 
     /// If set, the runtime will configure all endpoints to use TLS
     pub tls: Option<TlsConfig>,
+
+    /// If set, clients must authenticate before calling the HTTP and Flight endpoints
+    pub auth: Option<AuthConfig>,
+
+    /// Default limits applied to each query, which clients can override per request
+    #[serde(default)]
+    pub query: QueryConfig,
+}
+
+/// Example:
+/// ```yaml
+/// runtime:
+///   query:
+///     timeout: 30s
+///     memory_limit: 1GiB
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct QueryConfig {
+    /// The maximum time a query can run before it is cancelled, i.e. `30s`
+    pub timeout: Option<String>,
+
+    /// The maximum memory a single query can use for operations like sorts, joins and aggregations, i.e. `1GiB`
+    pub memory_limit: Option<String>,
 }
 
+/// Example:
+/// ```yaml
+/// runtime:
+///   results_cache:
+///     cache_type: file
+///     cache_dir: /var/cache/spice
+///     cache_max_size: 10GiB
+///     item_ttl: 10m
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
 pub struct ResultsCache {
@@ -37,6 +71,12 @@ pub struct ResultsCache {
     pub cache_max_size: Option<String>,
     pub item_ttl: Option<String>,
     pub eviction_policy: Option<String>,
+
+    /// Where cached results are stored: `memory` (default) or `file`
+    pub cache_type: Option<String>,
+
+    /// The directory of the `file` cache. Defaults to `.spice/cache/results`.
+    pub cache_dir: Option<String>,
 }
 
 const fn default_true() -> bool {
@@ -50,6 +90,8 @@ impl Default for ResultsCache {
             cache_max_size: None,
             item_ttl: None,
             eviction_policy: None,
+            cache_type: None,
+            cache_dir: None,
         }
     }
 }
@@ -73,3 +115,137 @@ pub struct TlsConfig {
     /// A PEM encoded private key
     pub key: Option<String>,
 }
+
+/// Authentication configuration for the runtime endpoints.
+///
+/// Example:
+/// ```yaml
+/// runtime:
+///   auth:
+///     api_key:
+///       keys:
+///         - key: ${ secrets:SPICE_API_KEY }
+///           principal: analytics
+///     basic:
+///       users:
+///         - username: admin
+///           password: ${ secrets:SPICE_ADMIN_PASSWORD }
+///     jwt:
+///       jwks_file: ./jwks.json
+///       issuer: https://auth.example.com
+///     grants:
+///       - principal: analytics
+///         read: [taxi_trips, "my_catalog.*"]
+///       - role: ingest
+///         write: [events]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct AuthConfig {
+    /// Static API keys, sent by clients as `Authorization: Bearer <key>`
+    pub api_key: Option<ApiKeyAuth>,
+
+    /// Username and password pairs, sent by clients as `Authorization: Basic <credentials>`
+    pub basic: Option<BasicAuth>,
+
+    /// JSON Web Tokens validated against a local JWKS file, sent by clients as `Authorization: Bearer <token>`
+    pub jwt: Option<JwtAuth>,
+
+    /// The datasets, views and catalogs each principal or role can read from and write to.
+    /// If empty, every authenticated principal has full access.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grants: Vec<Grant>,
+}
+
+/// Grants read and/or write access on a set of tables.
+///
+/// Tables are matched with glob patterns against their name, i.e. `taxi_trips`, `public.taxi_trips`, `my_catalog.*` or `*`.
+/// A grant that specifies neither `principal` nor `role` applies to every authenticated principal.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct Grant {
+    pub principal: Option<String>,
+
+    pub role: Option<String>,
+
+    /// Tables that can be queried with `SELECT`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub read: Vec<String>,
+
+    /// Tables that can be written to, i.e. with Flight `DoPut`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub write: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct ApiKeyAuth {
+    pub keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct ApiKey {
+    /// The API key, usually a secret reference like `${ secrets:SPICE_API_KEY }`
+    pub key: String,
+
+    /// The principal that requests using this key are identified as. Defaults to `api_key`.
+    pub principal: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct BasicAuth {
+    pub users: Vec<BasicAuthUser>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct BasicAuthUser {
+    pub username: String,
+
+    /// The password, usually a secret reference like `${ secrets:SPICE_ADMIN_PASSWORD }`
+    pub password: String,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct JwtAuth {
+    /// A filesystem path to a JSON Web Key Set used to verify token signatures
+    pub jwks_file: String,
+
+    /// If set, tokens must contain a matching `iss` claim
+    pub issuer: Option<String>,
+
+    /// If set, tokens must contain a matching `aud` claim
+    pub audience: Option<String>,
+
+    /// The claim used as the principal name. Defaults to `sub`.
+    #[serde(default = "default_principal_claim")]
+    pub principal_claim: String,
+
+    /// The claim listing the roles of the principal. Defaults to `roles`.
+    #[serde(default = "default_roles_claim")]
+    pub roles_claim: String,
+}
+
+fn default_principal_claim() -> String {
+    "sub".to_string()
+}
+
+fn default_roles_claim() -> String {
+    "roles".to_string()
+}